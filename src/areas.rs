@@ -0,0 +1,563 @@
+//! Build areas (polygons) from closed ways, and from multipolygon & boundary relations.
+//!
+//! The ways of a relation are joined end to end (by node id) into rings; rings which touch
+//! themselves (visit a node twice) are split there; then rings inside an odd number of others
+//! are inner rings (holes) of the smallest ring around them, and the rest are outer rings.
+//! Member roles are ignored, like osmium. Outer rings are counter-clockwise, inner rings
+//! clockwise, and every ring is closed (the first & last point are the same).
+//!
+//! ```
+//! use osmio::areas::assemble;
+//! use osmio::{Lat, Lon};
+//!
+//! let loc = |id: i64| match id {
+//!     1 => Some((Lat::from(0.), Lon::from(0.))),
+//!     2 => Some((Lat::from(0.), Lon::from(1.))),
+//!     3 => Some((Lat::from(1.), Lon::from(1.))),
+//!     4 => Some((Lat::from(1.), Lon::from(0.))),
+//!     _ => None,
+//! };
+//! // 2 ways, which make a square
+//! let polygons = assemble(&[vec![1, 2, 3], vec![3, 4, 1]], loc).unwrap();
+//! assert_eq!(polygons.len(), 1);
+//! assert_eq!(polygons[0].outer.len(), 5);
+//! assert!(polygons[0].inners.is_empty());
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+
+use formats::{read_from_path, OpenError};
+use nodestore::{LocationStore, WayStore};
+use {Lat, Lon, OSMObj, OSMObjBase, OSMObjectType, ObjId, ReadError, Relation, Way};
+
+/// Why an area couldn't be built
+#[derive(Debug, Clone, PartialEq)]
+pub enum AssemblyError {
+    /// This node's location isn't known
+    MissingNode(ObjId),
+    /// This member way isn't in the input
+    MissingWay(ObjId),
+    /// The ways don't join into closed rings. The ends (node ids) of one unclosed ring
+    OpenRing(ObjId, ObjId),
+    /// There are no rings (e.g. no member ways)
+    NoRings,
+}
+impl std::fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for AssemblyError {}
+
+/// An error when building the areas of a file
+#[derive(Debug)]
+pub enum AreaError {
+    Open(OpenError),
+    Read(ReadError),
+}
+impl std::fmt::Display for AreaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for AreaError {}
+
+impl From<OpenError> for AreaError {
+    fn from(err: OpenError) -> AreaError {
+        AreaError::Open(err)
+    }
+}
+impl From<ReadError> for AreaError {
+    fn from(err: ReadError) -> AreaError {
+        AreaError::Read(err)
+    }
+}
+impl From<::std::io::Error> for AreaError {
+    fn from(err: ::std::io::Error) -> AreaError {
+        AreaError::Read(ReadError::IOError(err))
+    }
+}
+
+/// One polygon: an outer ring, and its holes
+#[derive(Debug, Clone, PartialEq)]
+pub struct AreaPolygon {
+    pub outer: Vec<(Lat, Lon)>,
+    pub inners: Vec<Vec<(Lat, Lon)>>,
+}
+
+/// An area, from a closed way, or a relation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Area {
+    /// The type of object this area was built from, a way or relation
+    pub object_type: OSMObjectType,
+    pub id: ObjId,
+    pub tags: Vec<(String, String)>,
+    pub polygons: Vec<AreaPolygon>,
+}
+
+/// An object whose area couldn't be built
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidArea {
+    pub object_type: OSMObjectType,
+    pub id: ObjId,
+    pub error: AssemblyError,
+}
+
+#[cfg(feature = "geo")]
+impl Area {
+    /// This area as a polygon (if it has one) or multipolygon
+    pub fn to_geometry(&self) -> ::geo::Geometry {
+        let mut polygons: Vec<Vec<Vec<(Lat, Lon)>>> = self
+            .polygons
+            .iter()
+            .map(|p| {
+                let mut rings = vec![p.outer.clone()];
+                rings.extend(p.inners.iter().cloned());
+                rings
+            })
+            .collect();
+        if polygons.len() == 1 {
+            ::geo::Geometry::Polygon(polygons.remove(0))
+        } else {
+            ::geo::Geometry::MultiPolygon(polygons)
+        }
+    }
+}
+
+/// Twice the signed area of this closed ring, positive if it's counter-clockwise (with lon as x,
+/// lat as y)
+fn signed_area(ring: &[(Lat, Lon)]) -> f64 {
+    ring.windows(2)
+        .map(|w| {
+            let (lat1, lon1) = (w[0].0.degrees(), w[0].1.degrees());
+            let (lat2, lon2) = (w[1].0.degrees(), w[1].1.degrees());
+            lon1 * lat2 - lon2 * lat1
+        })
+        .sum()
+}
+
+/// True iff this point is inside this closed ring (even-odd rule)
+fn ring_contains(ring: &[(Lat, Lon)], (lat, lon): (Lat, Lon)) -> bool {
+    let (y, x) = (lat.degrees(), lon.degrees());
+    let mut inside = false;
+    for w in ring.windows(2) {
+        let (y1, x1) = (w[0].0.degrees(), w[0].1.degrees());
+        let (y2, x2) = (w[1].0.degrees(), w[1].1.degrees());
+        if (y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1 {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/// Join these node id chains end to end into closed rings. Also returns the ends of the chains
+/// which couldn't be closed.
+pub(crate) fn join_rings(chains: &[Vec<ObjId>]) -> (Vec<Vec<ObjId>>, Vec<(ObjId, ObjId)>) {
+    let mut rings = Vec::new();
+    let mut unclosed = Vec::new();
+    // The open chains, by both end nodes
+    let mut open: Vec<Option<Vec<ObjId>>> = Vec::new();
+    let mut by_end: HashMap<ObjId, Vec<usize>> = HashMap::new();
+    for chain in chains {
+        let mut chain = chain.clone();
+        chain.dedup();
+        if chain.len() < 2 {
+            continue;
+        }
+        if chain[0] == chain[chain.len() - 1] {
+            rings.push(chain);
+        } else {
+            by_end.entry(chain[0]).or_default().push(open.len());
+            by_end
+                .entry(chain[chain.len() - 1])
+                .or_default()
+                .push(open.len());
+            open.push(Some(chain));
+        }
+    }
+
+    'rings: for i in 0..open.len() {
+        let mut ring = match open[i].take() {
+            Some(chain) => chain,
+            None => continue,
+        };
+        // Extended from the end, then (reversed) from the start
+        let mut reversed = false;
+        while ring[0] != ring[ring.len() - 1] {
+            let end = ring[ring.len() - 1];
+            let next = by_end
+                .get(&end)
+                .and_then(|candidates| candidates.iter().find(|&&j| open[j].is_some()))
+                .cloned();
+            let mut chain = match next {
+                Some(j) => open[j].take().unwrap(),
+                None if !reversed => {
+                    ring.reverse();
+                    reversed = true;
+                    continue;
+                }
+                None => {
+                    unclosed.push((end, ring[0]));
+                    continue 'rings;
+                }
+            };
+            if chain[0] != end {
+                chain.reverse();
+            }
+            ring.extend_from_slice(&chain[1..]);
+        }
+        rings.push(ring);
+    }
+    (rings, unclosed)
+}
+
+/// Split rings which visit a node more than once into several rings
+fn split_self_touching(ring: Vec<ObjId>, rings: &mut Vec<Vec<ObjId>>) {
+    let mut seen: HashMap<ObjId, usize> = HashMap::new();
+    // Without the closing node
+    for (j, &id) in ring[..ring.len() - 1].iter().enumerate() {
+        if let Some(&i) = seen.get(&id) {
+            let inner: Vec<ObjId> = ring[i..=j].to_vec();
+            let mut rest: Vec<ObjId> = ring[..i].to_vec();
+            rest.extend_from_slice(&ring[j..]);
+            split_self_touching(inner, rings);
+            split_self_touching(rest, rings);
+            return;
+        }
+        seen.insert(id, j);
+    }
+    rings.push(ring);
+}
+
+/// Build the polygons made by these node id chains (e.g. the ways of a multipolygon), with
+/// `location` giving the location of every node.
+pub fn assemble(
+    chains: &[Vec<ObjId>],
+    location: impl Fn(ObjId) -> Option<(Lat, Lon)>,
+) -> Result<Vec<AreaPolygon>, AssemblyError> {
+    let (joined, unclosed) = join_rings(chains);
+    if let Some(&(start, end)) = unclosed.first() {
+        return Err(AssemblyError::OpenRing(start, end));
+    }
+    let mut rings = Vec::new();
+    for ring in joined {
+        split_self_touching(ring, &mut rings);
+    }
+    // Rings need at least 3 different nodes
+    rings.retain(|r| r.len() >= 4);
+    if rings.is_empty() {
+        return Err(AssemblyError::NoRings);
+    }
+
+    // The node ids (in order, & as a set), the points, & the signed area of each ring
+    let mut rings: Vec<(Vec<ObjId>, HashSet<ObjId>, Vec<(Lat, Lon)>, f64)> = rings
+        .into_iter()
+        .map(|ids| {
+            let points = ids
+                .iter()
+                .map(|&id| location(id).ok_or(AssemblyError::MissingNode(id)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let area = signed_area(&points);
+            let set = ids.iter().cloned().collect();
+            Ok((ids, set, points, area))
+        })
+        .collect::<Result<_, AssemblyError>>()?;
+    // Largest first, so the rings around a ring are before it
+    rings.sort_by(|a, b| b.3.abs().partial_cmp(&a.3.abs()).unwrap());
+
+    // For each ring, the smallest ring around it, and how many rings it's inside
+    let mut parents: Vec<(Option<usize>, usize)> = Vec::new();
+    for i in 0..rings.len() {
+        let mut parent = (None, 0);
+        for j in 0..i {
+            // A point of this ring which isn't on the other one
+            let point = rings[i]
+                .2
+                .iter()
+                .zip(rings[i].0.iter())
+                .find(|(_, id)| !rings[j].1.contains(id))
+                .map(|(p, _)| *p);
+            if point.map_or(false, |p| ring_contains(&rings[j].2, p)) {
+                parent = (Some(j), parents[j].1 + 1);
+            }
+        }
+        parents.push(parent);
+    }
+
+    let mut polygons: Vec<AreaPolygon> = Vec::new();
+    let mut polygon_of_ring: HashMap<usize, usize> = HashMap::new();
+    for (i, (_, _, points, area)) in rings.into_iter().enumerate() {
+        let (parent, depth) = parents[i];
+        let mut points = points;
+        if depth % 2 == 0 {
+            if area < 0. {
+                points.reverse();
+            }
+            polygon_of_ring.insert(i, polygons.len());
+            polygons.push(AreaPolygon {
+                outer: points,
+                inners: Vec::new(),
+            });
+        } else {
+            if area > 0. {
+                points.reverse();
+            }
+            let polygon = polygon_of_ring[&parent.unwrap()];
+            polygons[polygon].inners.push(points);
+        }
+    }
+    Ok(polygons)
+}
+
+/// True iff this relation is a multipolygon or boundary
+pub(crate) fn is_area_relation(relation: &impl Relation) -> bool {
+    match relation.tag("type") {
+        Some("multipolygon") | Some("boundary") => true,
+        _ => false,
+    }
+}
+
+/// True iff this closed way should be an area: it's not tagged `area=no`
+fn is_area_way(way: &impl Way) -> bool {
+    way.num_nodes() >= 4
+        && way.nodes().first() == way.nodes().last()
+        && way.tag("area") != Some("no")
+}
+
+fn tags(obj: &impl OSMObjBase) -> Vec<(String, String)> {
+    obj.tags()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+/// Builds all the areas of a file, from closed ways (unless tagged `area=no`), and multipolygon
+/// & boundary relations. The file is read twice, & must be sorted. Every node location is kept
+/// in memory, and the nodes of the member ways of relations.
+#[derive(Debug, Clone)]
+pub struct AreaAssembler {
+    ways: bool,
+    relations: bool,
+}
+
+impl Default for AreaAssembler {
+    fn default() -> Self {
+        AreaAssembler {
+            ways: true,
+            relations: true,
+        }
+    }
+}
+
+impl AreaAssembler {
+    pub fn new() -> Self {
+        AreaAssembler::default()
+    }
+
+    /// Build areas from closed ways. Default: true
+    pub fn ways(mut self, ways: bool) -> Self {
+        self.ways = ways;
+        self
+    }
+
+    /// Build areas from multipolygon & boundary relations. Default: true
+    pub fn relations(mut self, relations: bool) -> Self {
+        self.relations = relations;
+        self
+    }
+
+    /// Build the areas of this file, calling `f` with each area, or why it couldn't be built.
+    /// Returns how many areas were built.
+    pub fn run(
+        &self,
+        input: impl AsRef<Path>,
+        mut f: impl FnMut(Result<Area, InvalidArea>),
+    ) -> Result<u64, AreaError> {
+        let input = input.as_ref();
+        // The member ways of the area relations
+        let mut member_ways = HashSet::new();
+        if self.relations {
+            let mut reader = read_from_path(input)?;
+            while let Some(obj) = reader.try_next_obj()? {
+                if let Some(relation) = obj.as_relation() {
+                    if is_area_relation(relation) {
+                        member_ways.extend(
+                            relation
+                                .members()
+                                .filter(|m| m.mtype == OSMObjectType::Way)
+                                .map(|m| m.id),
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut locations = LocationStore::new();
+        let mut ways = WayStore::new();
+        let mut built = 0;
+        let mut emit = |result: Result<Area, InvalidArea>| {
+            if result.is_ok() {
+                built += 1;
+            }
+            f(result)
+        };
+        let mut reader = read_from_path(input)?;
+        while let Some(obj) = reader.try_next_obj()? {
+            if let Some(node) = obj.as_node() {
+                locations.add_node(node)?;
+            } else if let Some(way) = obj.as_way() {
+                if member_ways.contains(&way.id()) {
+                    ways.add_way(way);
+                }
+                if self.ways && is_area_way(way) {
+                    let result = assemble(&[way.nodes().to_vec()], |id| locations.get(id));
+                    emit(area(OSMObjectType::Way, &obj, result));
+                }
+            } else if let Some(relation) = obj.as_relation() {
+                if self.relations && is_area_relation(relation) {
+                    let chains = relation
+                        .members()
+                        .filter(|m| m.mtype == OSMObjectType::Way)
+                        .map(|m| ways.get(m.id).ok_or(AssemblyError::MissingWay(m.id)))
+                        .collect::<Result<Vec<_>, _>>();
+                    let result = chains.and_then(|c| assemble(&c, |id| locations.get(id)));
+                    emit(area(OSMObjectType::Relation, &obj, result));
+                }
+            }
+        }
+        Ok(built)
+    }
+}
+
+fn area(
+    object_type: OSMObjectType,
+    obj: &impl OSMObjBase,
+    polygons: Result<Vec<AreaPolygon>, AssemblyError>,
+) -> Result<Area, InvalidArea> {
+    match polygons {
+        Ok(polygons) => Ok(Area {
+            object_type: object_type,
+            id: obj.id(),
+            tags: tags(obj),
+            polygons: polygons,
+        }),
+        Err(error) => Err(InvalidArea {
+            object_type: object_type,
+            id: obj.id(),
+            error: error,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Nodes 1-16 on a 4x4 grid, (0, 0) to (3, 3), row by row
+    fn grid(id: ObjId) -> Option<(Lat, Lon)> {
+        if (1..=16).contains(&id) {
+            let i = id - 1;
+            Some((Lat::from((i / 4) as f64), Lon::from((i % 4) as f64)))
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn rings() {
+        // a square with a square hole, from 3 ways, one backwards
+        let polygons = assemble(
+            &[vec![1, 4, 16], vec![1, 13, 16], vec![6, 7, 11, 10, 6]],
+            grid,
+        )
+        .unwrap();
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].inners.len(), 1);
+        assert!(signed_area(&polygons[0].outer) > 0.);
+        assert!(signed_area(&polygons[0].inners[0]) < 0.);
+        assert_eq!(signed_area(&polygons[0].outer), 18.);
+
+        // 2 separate squares
+        let polygons = assemble(&[vec![1, 2, 6, 5, 1], vec![11, 12, 16, 15, 11]], grid).unwrap();
+        assert_eq!(polygons.len(), 2);
+        assert!(polygons.iter().all(|p| p.inners.is_empty()));
+
+        // a hole touching the outer ring at 2 nodes
+        let polygons = assemble(&[vec![1, 4, 16, 13, 1], vec![4, 7, 8, 4]], grid).unwrap();
+        assert_eq!(polygons.len(), 1);
+        assert_eq!(polygons[0].inners.len(), 1);
+
+        // a figure of 8, touching itself at 6, is split into 2
+        let polygons = assemble(&[vec![1, 2, 6, 11, 12, 8, 6, 5, 1]], grid).unwrap();
+        assert_eq!(polygons.len(), 2);
+
+        assert_eq!(
+            assemble(&[vec![1, 2, 3], vec![3, 7]], grid),
+            Err(AssemblyError::OpenRing(1, 7))
+        );
+        assert_eq!(
+            assemble(&[vec![1, 2, 99, 1]], grid),
+            Err(AssemblyError::MissingNode(99))
+        );
+        assert_eq!(assemble(&[], grid), Err(AssemblyError::NoRings));
+    }
+
+    #[test]
+    fn run() {
+        let path = ::std::env::temp_dir().join(format!("osmio-areas-{}.opl", ::std::process::id()));
+        let mut input = String::new();
+        for id in 1..=16 {
+            let (lat, lon) = grid(id).unwrap();
+            input.push_str(&format!("n{} x{} y{}\n", id, lon, lat));
+        }
+        input.push_str(
+            "w1 Tbuilding=yes Nn1,n2,n6,n5,n1\nw2 Tbarrier=fence,area=no Nn1,n2,n6,n5,n1\n\
+             w3 Nn3,n4,n8\nw4 Nn8,n7,n3\nw5 Nn9,n10,n14\n\
+             r1 Ttype=multipolygon,landuse=grass Mw3@outer,w4@outer\n\
+             r2 Ttype=multipolygon Mw5@outer\nr3 Ttype=boundary Mw99@outer\n",
+        );
+        fs::write(&path, input).unwrap();
+
+        let mut areas = Vec::new();
+        let mut invalid = Vec::new();
+        let built = AreaAssembler::new()
+            .run(&path, |result| match result {
+                Ok(area) => areas.push(area),
+                Err(e) => invalid.push(e),
+            })
+            .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(built, 2);
+        assert_eq!(areas[0].object_type, OSMObjectType::Way);
+        assert_eq!(areas[0].id, 1);
+        assert_eq!(areas[1].object_type, OSMObjectType::Relation);
+        assert_eq!(areas[1].id, 1);
+        assert_eq!(areas[1].polygons[0].outer.len(), 5);
+        let mut tags = areas[1].tags.clone();
+        tags.sort();
+        assert_eq!(
+            tags,
+            vec![
+                ("landuse".to_string(), "grass".to_string()),
+                ("type".to_string(), "multipolygon".to_string())
+            ]
+        );
+        // w5 isn't closed, & w99 doesn't exist
+        assert_eq!(
+            invalid,
+            vec![
+                InvalidArea {
+                    object_type: OSMObjectType::Relation,
+                    id: 2,
+                    error: AssemblyError::OpenRing(9, 14),
+                },
+                InvalidArea {
+                    object_type: OSMObjectType::Relation,
+                    id: 3,
+                    error: AssemblyError::MissingWay(99),
+                },
+            ]
+        );
+    }
+}
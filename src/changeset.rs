@@ -0,0 +1,69 @@
+//! Changesets, from changeset dumps (`changesets-latest.osm`) & API responses
+use obj_types::StringOSMObj;
+use {BBox, TimestampFormat};
+
+/// A changeset, the group of changes an editor uploads together
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Changeset {
+    pub id: u64,
+    pub created_at: Option<TimestampFormat>,
+    /// `None` while the changeset is open
+    pub closed_at: Option<TimestampFormat>,
+    pub open: bool,
+    /// The area of the changes. `None` for changesets without any changes
+    pub bbox: Option<BBox>,
+    pub uid: Option<u64>,
+    pub user: Option<String>,
+    /// How many objects were changed
+    pub num_changes: Option<u64>,
+    pub comments_count: Option<u64>,
+    pub tags: Vec<(String, String)>,
+}
+
+impl Changeset {
+    /// An open changeset with this id, and nothing else
+    pub fn new(id: u64) -> Self {
+        Changeset {
+            id: id,
+            open: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
+        let key = key.as_ref();
+        self.tags
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn has_tag(&self, key: impl AsRef<str>) -> bool {
+        self.tag(key).is_some()
+    }
+}
+
+/// One element of a file. Most files only have objects, changeset dumps only have changesets,
+/// but API responses can have both.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Element<O = StringOSMObj> {
+    Object(O),
+    Changeset(Changeset),
+}
+
+impl<O> Element<O> {
+    pub fn into_object(self) -> Option<O> {
+        match self {
+            Element::Object(o) => Some(o),
+            Element::Changeset(_) => None,
+        }
+    }
+
+    pub fn into_changeset(self) -> Option<Changeset> {
+        match self {
+            Element::Object(_) => None,
+            Element::Changeset(c) => Some(c),
+        }
+    }
+}
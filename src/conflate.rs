@@ -0,0 +1,366 @@
+//! Matching objects of two datasets by location & tags, the first step of conflating an external
+//! dataset (e.g. an import) with OSM data. One dataset is indexed (`Conflator::add`), then each
+//! object of the other is matched against it (`Conflator::matches`), giving the candidate pairs
+//! within a distance, scored by how close they are and how similar their tags are.
+//!
+//! The index is a grid (`SpatialIndex`), so there are no extra dependencies. Objects are points:
+//! nodes are their location, ways the centre of their bbox (`Feature::from_way`).
+//!
+//! ```
+//! use osmio::conflate::{Conflator, Feature};
+//! use osmio::opl::OPLReader;
+//! use osmio::{OSMObj, OSMReader};
+//!
+//! let osm = "n1 Tamenity=cafe,name=Bean x10 y50\nn2 Tamenity=bench x10.01 y50\n";
+//! let mut conflator = Conflator::new(50.).keys(vec!["amenity", "name"]);
+//! conflator.add_reader(&mut OPLReader::new(osm.as_bytes())).unwrap();
+//!
+//! let import = "n-1 Tamenity=cafe,name=Bean,source=survey x10 y50.0001\n";
+//! let import = OPLReader::new(import.as_bytes()).objects().next().unwrap();
+//! let import = Feature::from_node(import.as_node().unwrap()).unwrap();
+//! let matches = conflator.matches(&import);
+//! assert_eq!(matches.len(), 1);
+//! assert_eq!(matches[0].id, 1);
+//! assert_eq!(matches[0].similarity, 1.);
+//! ```
+use std::collections::{HashMap, HashSet};
+
+use diff::{distance, EARTH_RADIUS};
+use nodestore::NodeLocationStore;
+use {DynReader, Lat, Lon, Node, OSMObj, OSMObjBase, OSMObjectType, ObjId, ReadError, Way};
+
+/// Items at locations, in a grid of cells, to find the items near a location
+#[derive(Debug, Clone)]
+pub struct SpatialIndex<T> {
+    /// In degrees
+    cell_size: f64,
+    cells: HashMap<(i32, i32), Vec<((Lat, Lon), T)>>,
+    len: usize,
+}
+
+impl<T> SpatialIndex<T> {
+    /// An empty index, with cells of this many degrees. Cells about as big as the distances
+    /// searched are fastest.
+    pub fn new(cell_size: f64) -> Self {
+        SpatialIndex {
+            cell_size: cell_size,
+            cells: HashMap::new(),
+            len: 0,
+        }
+    }
+
+    fn cell(&self, (lat, lon): (f64, f64)) -> (i32, i32) {
+        (
+            (lat / self.cell_size).floor() as i32,
+            (lon / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Add an item at this location
+    pub fn insert(&mut self, location: (Lat, Lon), item: T) {
+        let cell = self.cell((location.0.degrees(), location.1.degrees()));
+        self.cells.entry(cell).or_default().push((location, item));
+        self.len += 1;
+    }
+
+    /// The items up to `max_distance` metres from this location, with their distance, nearest
+    /// first
+    pub fn within(&self, location: (Lat, Lon), max_distance: f64) -> Vec<(&T, f64)> {
+        let (lat, lon) = (location.0.degrees(), location.1.degrees());
+        let dlat = (max_distance / EARTH_RADIUS).to_degrees();
+        // Metres per degree of longitude shrink towards the poles
+        let dlon = (dlat / lat.to_radians().cos().max(1e-6)).min(180.);
+        // Longitude ranges, split at the antimeridian
+        let (west, east) = (lon - dlon, lon + dlon);
+        let ranges = if west < -180. {
+            vec![(west + 360., 180.), (-180., east)]
+        } else if east > 180. {
+            vec![(west, 180.), (-180., east - 360.)]
+        } else {
+            vec![(west, east)]
+        };
+
+        let mut found = Vec::new();
+        let mut seen = HashSet::new();
+        for (west, east) in ranges {
+            let (min_y, min_x) = self.cell((lat - dlat, west));
+            let (max_y, max_x) = self.cell((lat + dlat, east));
+            for cell in (min_y..=max_y).flat_map(|y| (min_x..=max_x).map(move |x| (y, x))) {
+                if !seen.insert(cell) {
+                    continue;
+                }
+                for (loc, item) in self.cells.get(&cell).into_iter().flatten() {
+                    let d = distance(location, *loc);
+                    if d <= max_distance {
+                        found.push((item, d));
+                    }
+                }
+            }
+        }
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        found
+    }
+
+    /// How many items there are
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// An object as a point with tags, to match
+#[derive(Debug, Clone, PartialEq)]
+pub struct Feature {
+    pub object_type: OSMObjectType,
+    pub id: ObjId,
+    pub location: (Lat, Lon),
+    pub tags: Vec<(String, String)>,
+}
+
+impl Feature {
+    fn new(object_type: OSMObjectType, obj: &impl OSMObjBase, location: (Lat, Lon)) -> Self {
+        Feature {
+            object_type: object_type,
+            id: obj.id(),
+            location: location,
+            tags: obj
+                .tags()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    /// This node, `None` if it has no location
+    pub fn from_node(node: &impl Node) -> Option<Self> {
+        Some(Feature::new(OSMObjectType::Node, node, node.lat_lon()?))
+    }
+
+    /// This way, at the centre of its bbox. `None` if none of its nodes are in `locations`
+    pub fn from_way(way: &impl Way, locations: &impl NodeLocationStore) -> Option<Self> {
+        let bbox = way.bbox(locations)?;
+        let centre = (
+            Lat::from_inner(
+                ((bbox.min_lat.inner() as i64 + bbox.max_lat.inner() as i64) / 2) as i32,
+            ),
+            Lon::from_inner(
+                ((bbox.min_lon.inner() as i64 + bbox.max_lon.inner() as i64) / 2) as i32,
+            ),
+        );
+        Some(Feature::new(OSMObjectType::Way, way, centre))
+    }
+}
+
+/// A feature of the index which matches, see `Conflator::matches`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub object_type: OSMObjectType,
+    pub id: ObjId,
+    /// In metres
+    pub distance: f64,
+    /// How similar the tags are, from 0 (nothing in common) to 1 (the same)
+    pub similarity: f64,
+    /// `similarity`, scaled down linearly to 0 at the maximum distance
+    pub score: f64,
+}
+
+/// Matches features against an index of others, by distance & tag similarity, see the module
+/// docs. Tag similarity is the Jaccard index of the tags (the tags both have, over the tags
+/// either has), only looking at some keys if `keys` is set.
+#[derive(Debug, Clone)]
+pub struct Conflator {
+    index: SpatialIndex<Feature>,
+    max_distance: f64,
+    min_similarity: f64,
+    keys: Option<Vec<String>>,
+}
+
+impl Conflator {
+    /// Match features up to `max_distance` metres apart
+    pub fn new(max_distance: f64) -> Self {
+        // About the max distance, but not too many cells
+        let cell_size = (max_distance / EARTH_RADIUS).to_degrees().max(0.001);
+        Conflator {
+            index: SpatialIndex::new(cell_size),
+            max_distance: max_distance,
+            min_similarity: 0.,
+            keys: None,
+        }
+    }
+
+    /// Only compare the tags with these keys
+    pub fn keys(mut self, keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.keys = Some(keys.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Only match features with at least this tag similarity. Default: 0, everything nearby
+    pub fn min_similarity(mut self, min_similarity: f64) -> Self {
+        self.min_similarity = min_similarity;
+        self
+    }
+
+    /// Index this feature
+    pub fn add(&mut self, feature: Feature) {
+        self.index.insert(feature.location, feature);
+    }
+
+    /// Index all the nodes (with a location) of this reader. Use `add` with `Feature::from_way`
+    /// for ways.
+    pub fn add_reader<R: DynReader + ?Sized>(&mut self, reader: &mut R) -> Result<(), ReadError> {
+        while let Some(obj) = reader.try_next_obj()? {
+            if let Some(feature) = obj.as_node().and_then(Feature::from_node) {
+                self.add(feature);
+            }
+        }
+        Ok(())
+    }
+
+    /// How many features are indexed
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// How similar the tags of these features are, see `Conflator`. 0 if neither has any
+    /// (compared) tags.
+    pub fn similarity(&self, a: &Feature, b: &Feature) -> f64 {
+        let compared = |f: &Feature| -> HashSet<(String, String)> {
+            f.tags
+                .iter()
+                .filter(|(k, _)| self.keys.as_ref().map_or(true, |keys| keys.contains(k)))
+                .cloned()
+                .collect()
+        };
+        let (a, b) = (compared(a), compared(b));
+        let union = a.union(&b).count();
+        if union == 0 {
+            return 0.;
+        }
+        a.intersection(&b).count() as f64 / union as f64
+    }
+
+    /// The indexed features which match this one, best first
+    pub fn matches(&self, feature: &Feature) -> Vec<Candidate> {
+        let mut candidates: Vec<Candidate> = self
+            .index
+            .within(feature.location, self.max_distance)
+            .into_iter()
+            .filter(|(other, _)| (other.object_type, other.id) != (feature.object_type, feature.id))
+            .map(|(other, distance)| {
+                let similarity = self.similarity(feature, other);
+                let closeness = if self.max_distance > 0. {
+                    1. - distance / self.max_distance
+                } else {
+                    1.
+                };
+                Candidate {
+                    object_type: other.object_type,
+                    id: other.id,
+                    distance: distance,
+                    similarity: similarity,
+                    score: similarity * closeness,
+                }
+            })
+            .filter(|c| c.similarity >= self.min_similarity)
+            .collect();
+        candidates.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap());
+        candidates
+    }
+
+    /// Match every node (with a location) of this reader, calling `f` with each one, and its
+    /// candidates (if it has any). Returns how many nodes had a candidate.
+    pub fn match_reader<R: DynReader + ?Sized>(
+        &self,
+        reader: &mut R,
+        mut f: impl FnMut(&Feature, Vec<Candidate>),
+    ) -> Result<u64, ReadError> {
+        let mut matched = 0;
+        while let Some(obj) = reader.try_next_obj()? {
+            if let Some(feature) = obj.as_node().and_then(Feature::from_node) {
+                let candidates = self.matches(&feature);
+                if !candidates.is_empty() {
+                    matched += 1;
+                    f(&feature, candidates);
+                }
+            }
+        }
+        Ok(matched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nodestore::LocationStore;
+    use obj_types::StringWay;
+    use opl::OPLReader;
+    use OSMReader;
+
+    #[test]
+    fn index() {
+        let mut index = SpatialIndex::new(0.01);
+        index.insert((Lat::from(0.), Lon::from(179.9999)), "east");
+        index.insert((Lat::from(0.), Lon::from(-179.9999)), "west");
+        index.insert((Lat::from(0.001), Lon::from(-179.9999)), "north");
+        index.insert((Lat::from(1.), Lon::from(0.)), "far");
+        assert_eq!(index.len(), 4);
+
+        let found: Vec<_> = index
+            .within((Lat::from(0.), Lon::from(180.)), 200.)
+            .into_iter()
+            .map(|(i, d)| (*i, d.round()))
+            .collect();
+        assert_eq!(found, vec![("east", 11.), ("west", 11.), ("north", 112.)]);
+        assert!(index
+            .within((Lat::from(0.), Lon::from(0.)), 1000.)
+            .is_empty());
+    }
+
+    #[test]
+    fn conflator() {
+        let osm = "n1 Tamenity=cafe,name=Bean x10 y50\nn2 Tamenity=cafe,name=Other x10.0001 y50\n\
+                   n3 Tamenity=bench x10 y50.0001\nn4 Tamenity=cafe x11 y50\n";
+        let mut conflator = Conflator::new(50.).keys(vec!["amenity", "name"]);
+        conflator
+            .add_reader(&mut OPLReader::new(osm.as_bytes()))
+            .unwrap();
+        assert_eq!(conflator.len(), 4);
+
+        let import = "n-1 Tamenity=cafe,name=Bean,source=import x10 y50.00001\nn-2 x0 y0\n";
+        let mut found = Vec::new();
+        let matched = conflator
+            .match_reader(&mut OPLReader::new(import.as_bytes()), |f, c| {
+                found.push((
+                    f.id,
+                    c.iter().map(|c| (c.id, c.similarity)).collect::<Vec<_>>(),
+                ))
+            })
+            .unwrap();
+        assert_eq!(matched, 1);
+        assert_eq!(found, vec![(-1, vec![(1, 1.), (2, 1. / 3.), (3, 0.)])]);
+
+        let mut strict = Conflator::new(50.).min_similarity(0.5);
+        strict
+            .add_reader(&mut OPLReader::new(osm.as_bytes()))
+            .unwrap();
+        let mut reader = OPLReader::new(import.as_bytes());
+        let feature =
+            Feature::from_node(reader.objects().next().unwrap().as_node().unwrap()).unwrap();
+        let candidates = strict.matches(&feature);
+        assert_eq!(candidates.len(), 1);
+        assert!(candidates[0].score < candidates[0].similarity);
+
+        let mut locations = LocationStore::new();
+        locations.set(1, (Lat::from(50.), Lon::from(10.))).unwrap();
+        locations.set(2, (Lat::from(52.), Lon::from(12.))).unwrap();
+        let way = Feature::from_way(&StringWay::new(5, vec![1, 2]), &locations).unwrap();
+        assert_eq!(way.location, (Lat::from(51.), Lon::from(11.)));
+        assert_eq!(way.object_type, OSMObjectType::Way);
+    }
+}
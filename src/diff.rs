@@ -0,0 +1,198 @@
+//! Compare two versions of the same object, field by field
+use {Lat, Lon, Member, Node, OSMObj, OSMObjBase, ObjId, Relation, Way};
+
+/// Mean radius of the earth, in metres
+pub(crate) const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// One difference between two versions of an object
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    TagAdded {
+        key: String,
+        value: String,
+    },
+    TagRemoved {
+        key: String,
+        value: String,
+    },
+    TagModified {
+        key: String,
+        old: String,
+        new: String,
+    },
+    Deleted {
+        old: bool,
+        new: bool,
+    },
+    /// The node location changed. See `distance()` for how far it moved
+    Moved {
+        old: Option<(Lat, Lon)>,
+        new: Option<(Lat, Lon)>,
+    },
+    NodesChanged {
+        old: Vec<ObjId>,
+        new: Vec<ObjId>,
+    },
+    MembersChanged {
+        old: Vec<Member>,
+        new: Vec<Member>,
+    },
+}
+
+impl Change {
+    /// For `Moved`, the distance in metres between the old & new location. `None` for other
+    /// changes, or if the node didn't have a location before (or after)
+    pub fn distance(&self) -> Option<f64> {
+        match self {
+            Change::Moved {
+                old: Some(old),
+                new: Some(new),
+            } => Some(distance(*old, *new)),
+            _ => None,
+        }
+    }
+}
+
+/// Great circle distance in metres, with the haversine formula
+pub(crate) fn distance(a: (Lat, Lon), b: (Lat, Lon)) -> f64 {
+    let (lat1, lon1) = (a.0.degrees().to_radians(), a.1.degrees().to_radians());
+    let (lat2, lon2) = (b.0.degrees().to_radians(), b.1.degrees().to_radians());
+    let h = ((lat2 - lat1) / 2.).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.).sin().powi(2);
+    2. * EARTH_RADIUS * h.sqrt().asin()
+}
+
+/// The tag changes from `old` to `new`. Removed tags are in the order of `old`, added &
+/// modified tags are in the order of `new`
+pub fn diff_tags(old: &impl OSMObjBase, new: &impl OSMObjBase) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (k, v) in old.tags() {
+        if !new.has_tag(k) {
+            changes.push(Change::TagRemoved {
+                key: k.to_string(),
+                value: v.to_string(),
+            });
+        }
+    }
+    for (k, v) in new.tags() {
+        match old.tag(k) {
+            None => changes.push(Change::TagAdded {
+                key: k.to_string(),
+                value: v.to_string(),
+            }),
+            Some(old_v) if old_v != v => changes.push(Change::TagModified {
+                key: k.to_string(),
+                old: old_v.to_string(),
+                new: v.to_string(),
+            }),
+            Some(_) => {}
+        }
+    }
+    changes
+}
+
+/// All the changes from `old` to `new`, ignoring the metadata (version, changeset, user &
+/// timestamp). Empty iff the objects are `semantic_eq`.
+pub fn diff<O: OSMObj>(old: &O, new: &O) -> Vec<Change> {
+    let mut changes = Vec::new();
+    if old.deleted() != new.deleted() {
+        changes.push(Change::Deleted {
+            old: old.deleted(),
+            new: new.deleted(),
+        });
+    }
+    changes.extend(diff_tags(old, new));
+
+    if let (Some(old), Some(new)) = (old.as_node(), new.as_node()) {
+        if old.lat_lon() != new.lat_lon() {
+            changes.push(Change::Moved {
+                old: old.lat_lon(),
+                new: new.lat_lon(),
+            });
+        }
+    }
+    if let (Some(old), Some(new)) = (old.as_way(), new.as_way()) {
+        if old.nodes() != new.nodes() {
+            changes.push(Change::NodesChanged {
+                old: old.nodes().to_vec(),
+                new: new.nodes().to_vec(),
+            });
+        }
+    }
+    if let (Some(old), Some(new)) = (old.as_relation(), new.as_relation()) {
+        if !old.members().eq(new.members()) {
+            changes.push(Change::MembersChanged {
+                old: old.members().map(|m| m.to_owned()).collect(),
+                new: new.members().map(|m| m.to_owned()).collect(),
+            });
+        }
+    }
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj_types::StringOSMObj;
+    use opl::OPLReader;
+    use OSMReader;
+
+    fn obj(line: &str) -> StringOSMObj {
+        OPLReader::new(line.as_bytes()).next().unwrap()
+    }
+
+    #[test]
+    fn node() {
+        let old = obj("n1 v1 c1 Tname=foo,amenity=cafe,old=yes x0 y0");
+        let new = obj("n1 v2 c2 Tname=bar,amenity=cafe,new=yes x0 y0.001");
+        let changes = diff(&old, &new);
+        assert_eq!(
+            changes[..3],
+            [
+                Change::TagRemoved {
+                    key: "old".to_string(),
+                    value: "yes".to_string()
+                },
+                Change::TagModified {
+                    key: "name".to_string(),
+                    old: "foo".to_string(),
+                    new: "bar".to_string()
+                },
+                Change::TagAdded {
+                    key: "new".to_string(),
+                    value: "yes".to_string()
+                },
+            ]
+        );
+        assert_eq!(changes.len(), 4);
+        let moved = changes[3].distance().unwrap();
+        assert!((moved - 111.2).abs() < 0.1, "{}", moved);
+
+        assert_eq!(diff(&old, &old), vec![]);
+        assert_eq!(changes[0].distance(), None);
+    }
+
+    #[test]
+    fn way_and_relation() {
+        let changes = diff(&obj("w1 v1 Nn1,n2"), &obj("w1 v2 dD Nn1,n2,n3"));
+        assert_eq!(
+            changes,
+            vec![
+                Change::Deleted {
+                    old: false,
+                    new: true
+                },
+                Change::NodesChanged {
+                    old: vec![1, 2],
+                    new: vec![1, 2, 3]
+                },
+            ]
+        );
+
+        let old = obj("r1 v1 Mw1@outer");
+        let new = obj("r1 v2 Mw1@inner");
+        assert!(!old.semantic_eq(&new));
+        assert_eq!(diff(&old, &new).len(), 1);
+    }
+}
@@ -0,0 +1,883 @@
+//! Cutting out the part of a file in an area, like `osmium extract`. The area is a bbox or a
+//! polygon (e.g. from a `.poly` or GeoJSON file, see `poly::read_polygon_file`). The input is
+//! read once per pass, so it must be a file, and it should be sorted (nodes, then ways, then
+//! relations).
+//!
+//! ```no_run
+//! use osmio::extract::{Extract, Strategy};
+//! use osmio::BBox;
+//!
+//! let mut output = osmio::write_to_path("city.osm").unwrap();
+//! Extract::new(BBox::new(51.4, -0.3, 51.6, 0.1))
+//!     .strategy(Strategy::Smart)
+//!     .run("region.osm.pbf", &mut output)
+//!     .unwrap();
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::path::Path;
+use std::str::FromStr;
+
+use filter::TagFilter;
+use formats::{read_from_path, OpenError};
+use obj_types::StringOSMObj;
+use poly::Polygon;
+use utils::type_idx;
+use {
+    BBox, DynWriter, Lat, Lon, Node, OSMObj, OSMObjBase, OSMObjectType, OSMWriteError, ObjId,
+    ReadError, Relation, Way,
+};
+
+/// Which objects are in an extract. In all of them, ways & relations aren't changed, so they can
+/// refer to objects which aren't in the extract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strategy {
+    /// One pass. The nodes in the area, the ways with at least one of those nodes, and the
+    /// relations with at least one of those nodes or ways as a member (or a relation member
+    /// which is earlier in the file).
+    Simple,
+    /// Two passes. Like `Simple`, but with all the nodes of the ways, so the ways are complete,
+    /// and the relations with any of the relations as a member. The default.
+    #[default]
+    CompleteWays,
+    /// Three passes. Like `CompleteWays`, but the multipolygon relations are complete too: all
+    /// their member ways, with all their nodes.
+    Smart,
+}
+
+/// An error when doing an extract
+#[derive(Debug)]
+pub enum ExtractError {
+    Open(OpenError),
+    Read(ReadError),
+    Write(OSMWriteError),
+}
+impl std::fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for ExtractError {}
+
+impl From<OpenError> for ExtractError {
+    fn from(err: OpenError) -> ExtractError {
+        ExtractError::Open(err)
+    }
+}
+impl From<ReadError> for ExtractError {
+    fn from(err: ReadError) -> ExtractError {
+        ExtractError::Read(err)
+    }
+}
+impl From<OSMWriteError> for ExtractError {
+    fn from(err: OSMWriteError) -> ExtractError {
+        ExtractError::Write(err)
+    }
+}
+
+/// The area of an extract
+#[derive(Debug, Clone, PartialEq)]
+pub enum Area {
+    BBox(BBox),
+    /// Nodes inside the polygon (see `Polygon::contains`) are in the area
+    Polygon(Polygon),
+}
+
+impl From<BBox> for Area {
+    fn from(bbox: BBox) -> Area {
+        Area::BBox(bbox)
+    }
+}
+
+impl From<Polygon> for Area {
+    fn from(polygon: Polygon) -> Area {
+        Area::Polygon(polygon)
+    }
+}
+
+impl Area {
+    /// The bbox of this area, `None` for a polygon without points
+    pub fn bounds(&self) -> Option<BBox> {
+        match self {
+            Area::BBox(bbox) => Some(*bbox),
+            Area::Polygon(polygon) => {
+                polygon
+                    .bounds()
+                    .map(|(min_lat, min_lon, max_lat, max_lon)| {
+                        BBox::new(min_lat, min_lon, max_lat, max_lon)
+                    })
+            }
+        }
+    }
+
+    /// True iff this location is in this area
+    pub fn contains(&self, lat: Lat, lon: Lon) -> bool {
+        match self {
+            Area::BBox(bbox) => bbox.contains(lat, lon),
+            Area::Polygon(polygon) => polygon.contains(lat.degrees(), lon.degrees()),
+        }
+    }
+}
+
+/// An extract of an area, with a strategy
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extract {
+    area: Area,
+    /// Of the area, to skip the polygon test for most nodes outside it
+    bounds: Option<BBox>,
+    strategy: Strategy,
+}
+
+/// The ids of the objects in the extract
+#[derive(Default)]
+struct Selection {
+    /// Nodes in the area
+    inside: HashSet<ObjId>,
+    /// Other nodes needed to complete the ways
+    extra_nodes: HashSet<ObjId>,
+    ways: HashSet<ObjId>,
+    relations: HashSet<ObjId>,
+    /// Relations not selected (yet) which have relation members, with those members
+    parents: Vec<(ObjId, Vec<ObjId>)>,
+    /// The member ways of every multipolygon, for `Smart`
+    multipolygons: HashMap<ObjId, Vec<ObjId>>,
+    /// The ways needed to complete the selected multipolygons
+    missing_ways: HashSet<ObjId>,
+}
+
+impl Selection {
+    fn contains(&self, obj: &StringOSMObj) -> bool {
+        let id = obj.id();
+        match obj.object_type() {
+            OSMObjectType::Node => self.inside.contains(&id) || self.extra_nodes.contains(&id),
+            OSMObjectType::Way => self.ways.contains(&id),
+            OSMObjectType::Relation => self.relations.contains(&id),
+        }
+    }
+
+    fn has_member(&self, relation: &impl Relation) -> bool {
+        relation.members().any(|m| match m.mtype {
+            OSMObjectType::Node => self.inside.contains(&m.id),
+            OSMObjectType::Way => self.ways.contains(&m.id),
+            OSMObjectType::Relation => self.relations.contains(&m.id),
+        })
+    }
+
+    /// Add this relation, and its node members
+    fn add_relation_nodes(&mut self, relation: &impl Relation) {
+        self.relations.insert(relation.id());
+        for m in relation.members() {
+            if m.mtype == OSMObjectType::Node && !self.inside.contains(&m.id) {
+                self.extra_nodes.insert(m.id);
+            }
+        }
+    }
+
+    fn add_way(&mut self, way: &impl Way) {
+        self.ways.insert(way.id());
+        for id in way.nodes() {
+            if !self.inside.contains(id) {
+                self.extra_nodes.insert(*id);
+            }
+        }
+    }
+}
+
+/// Call `f` on each object of the file
+fn read_all(
+    input: &Path,
+    mut f: impl FnMut(StringOSMObj) -> Result<(), ExtractError>,
+) -> Result<(), ExtractError> {
+    let mut reader = read_from_path(input)?;
+    while let Some(obj) = reader.try_next_obj()? {
+        f(obj)?;
+    }
+    Ok(())
+}
+
+impl Extract {
+    /// An extract of this area, with the default strategy
+    pub fn new(area: impl Into<Area>) -> Self {
+        let area = area.into();
+        Extract {
+            bounds: area.bounds(),
+            area: area,
+            strategy: Strategy::default(),
+        }
+    }
+
+    /// The area
+    pub fn area(&self) -> &Area {
+        &self.area
+    }
+
+    /// Use this strategy
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// True iff this node is in the area
+    pub fn contains(&self, node: &impl Node) -> bool {
+        match (node.lat_lon(), self.bounds) {
+            (Some((lat, lon)), Some(bounds)) => {
+                bounds.contains(lat, lon) && self.area.contains(lat, lon)
+            }
+            _ => false,
+        }
+    }
+
+    /// Write the objects of `input` in the extract to `output`, in the same order. Returns the
+    /// number of objects written. `output` isn't closed.
+    pub fn run(
+        &self,
+        input: impl AsRef<Path>,
+        output: &mut impl DynWriter,
+    ) -> Result<u64, ExtractError> {
+        let mut multi = MultiExtract::new();
+        multi.push(self.clone(), output);
+        Ok(multi.run(input)?[0])
+    }
+
+    /// Look at an object of the first pass. For `Simple`, true iff it's in the extract.
+    fn first_pass(&self, selection: &mut Selection, obj: &StringOSMObj) -> bool {
+        if let Some(node) = obj.as_node() {
+            if self.contains(node) {
+                selection.inside.insert(node.id());
+                return true;
+            }
+        } else if let Some(way) = obj.as_way() {
+            if way.nodes().iter().any(|id| selection.inside.contains(id)) {
+                if self.strategy == Strategy::Simple {
+                    selection.ways.insert(way.id());
+                } else {
+                    selection.add_way(way);
+                }
+                return true;
+            }
+        } else if let Some(relation) = obj.as_relation() {
+            if self.strategy == Strategy::Smart && relation.tag("type") == Some("multipolygon") {
+                let ways = relation
+                    .members()
+                    .filter(|m| m.mtype == OSMObjectType::Way)
+                    .map(|m| m.id)
+                    .collect();
+                selection.multipolygons.insert(relation.id(), ways);
+            }
+            if selection.has_member(relation) {
+                selection.relations.insert(relation.id());
+                return true;
+            } else if self.strategy != Strategy::Simple {
+                let members: Vec<_> = relation
+                    .members()
+                    .filter(|m| m.mtype == OSMObjectType::Relation)
+                    .map(|m| m.id)
+                    .collect();
+                if !members.is_empty() {
+                    selection.parents.push((relation.id(), members));
+                }
+            }
+        }
+        false
+    }
+}
+
+impl Selection {
+    /// After the first pass, add the parents of selected relations, and find the ways needed to
+    /// complete the multipolygons
+    fn finish_first_pass(&mut self) {
+        loop {
+            let before = self.parents.len();
+            let relations = &mut self.relations;
+            self.parents.retain(|(id, members)| {
+                if members.iter().any(|m| relations.contains(m)) {
+                    relations.insert(*id);
+                    false
+                } else {
+                    true
+                }
+            });
+            if self.parents.len() == before {
+                break;
+            }
+        }
+        self.parents = Vec::new();
+
+        let missing_ways = self
+            .multipolygons
+            .iter()
+            .filter(|(id, _)| self.relations.contains(id))
+            .flat_map(|(_, ways)| ways.iter().cloned())
+            .filter(|id| !self.ways.contains(id))
+            .collect();
+        self.missing_ways = missing_ways;
+        self.multipolygons = HashMap::new();
+    }
+}
+
+/// Several extracts of one file, each written to its own writer. Each pass reads the file once
+/// for all the extracts, so this is much faster than running them one after another. The
+/// number of passes is the most any of the strategies needs.
+///
+/// ```no_run
+/// use osmio::extract::{Extract, MultiExtract};
+/// use osmio::BBox;
+///
+/// let mut multi = MultiExtract::new();
+/// multi.push(
+///     Extract::new(BBox::new(51.4, -0.3, 51.6, 0.1)),
+///     osmio::write_to_path("london.osm").unwrap(),
+/// );
+/// multi.push(
+///     Extract::new(BBox::new(53.3, -6.4, 53.4, -6.1)),
+///     osmio::write_to_path("dublin.opl").unwrap(),
+/// );
+/// let written = multi.run("europe.osm.pbf").unwrap();
+/// ```
+pub struct MultiExtract<W> {
+    extracts: Vec<(Extract, W)>,
+}
+
+impl<W: DynWriter> MultiExtract<W> {
+    /// No extracts yet
+    pub fn new() -> Self {
+        MultiExtract {
+            extracts: Vec::new(),
+        }
+    }
+
+    /// Add an extract, which is written to `writer`
+    pub fn push(&mut self, extract: Extract, writer: W) {
+        self.extracts.push((extract, writer));
+    }
+
+    /// Number of extracts
+    pub fn len(&self) -> usize {
+        self.extracts.len()
+    }
+
+    /// True iff there are no extracts
+    pub fn is_empty(&self) -> bool {
+        self.extracts.is_empty()
+    }
+
+    /// The writers, in the order they were added
+    pub fn into_writers(self) -> Vec<W> {
+        self.extracts.into_iter().map(|(_, w)| w).collect()
+    }
+
+    /// Do all the extracts of `input`, see `Extract::run`. Returns the number of objects
+    /// written for each extract. The writers aren't closed.
+    pub fn run(&mut self, input: impl AsRef<Path>) -> Result<Vec<u64>, ExtractError> {
+        let input = input.as_ref();
+        let mut written = vec![0; self.extracts.len()];
+        let mut selections: Vec<Selection> =
+            self.extracts.iter().map(|_| Selection::default()).collect();
+
+        read_all(input, |obj| {
+            for (i, (extract, writer)) in self.extracts.iter_mut().enumerate() {
+                let keep = extract.first_pass(&mut selections[i], &obj);
+                if keep && extract.strategy == Strategy::Simple {
+                    writer.write_string_obj(&obj)?;
+                    written[i] += 1;
+                }
+            }
+            Ok(())
+        })?;
+        for selection in selections.iter_mut() {
+            selection.finish_first_pass();
+        }
+
+        if selections.iter().any(|s| !s.missing_ways.is_empty()) {
+            read_all(input, |obj| {
+                if let Some(way) = obj.as_way() {
+                    for selection in selections.iter_mut() {
+                        if selection.missing_ways.contains(&way.id()) {
+                            selection.add_way(way);
+                        }
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        if self
+            .extracts
+            .iter()
+            .any(|(e, _)| e.strategy != Strategy::Simple)
+        {
+            read_all(input, |obj| {
+                for (i, (extract, writer)) in self.extracts.iter_mut().enumerate() {
+                    if extract.strategy != Strategy::Simple && selections[i].contains(&obj) {
+                        writer.write_string_obj(&obj)?;
+                        written[i] += 1;
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        Ok(written)
+    }
+}
+
+impl<W: DynWriter> Default for MultiExtract<W> {
+    fn default() -> Self {
+        MultiExtract::new()
+    }
+}
+
+/// Writes some objects of a file, chosen by a function, with the objects they refer to, so the
+/// output is referentially complete: the nodes of the ways, and the node members of the
+/// relations. Optionally, the way & relation members of the relations too, recursively (with
+/// the nodes of those ways). Takes 2 passes over the input, or 3 with relation members. The
+/// input should be sorted.
+///
+/// ```no_run
+/// use osmio::extract::ReferenceCompletion;
+/// use osmio::filter::TagFilter;
+///
+/// let filter: TagFilter = "w/highway".parse().unwrap();
+/// let mut output = osmio::write_to_path("highways.osm").unwrap();
+/// ReferenceCompletion::new()
+///     .run("region.osm.pbf", |o| filter.matches(o), &mut output)
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ReferenceCompletion {
+    relation_members: bool,
+}
+
+impl ReferenceCompletion {
+    /// Add the nodes of the chosen ways & relations
+    pub fn new() -> Self {
+        ReferenceCompletion::default()
+    }
+
+    /// Also add the way & relation members of the relations, recursively. Default: false
+    pub fn relation_members(mut self, relation_members: bool) -> Self {
+        self.relation_members = relation_members;
+        self
+    }
+
+    /// Write the objects of `input` for which `select` is true, and the objects they refer to,
+    /// to `output`, in the same order. Returns the number of objects written. `output` isn't
+    /// closed.
+    pub fn run(
+        &self,
+        input: impl AsRef<Path>,
+        mut select: impl FnMut(&StringOSMObj) -> bool,
+        output: &mut impl DynWriter,
+    ) -> Result<u64, ExtractError> {
+        let input = input.as_ref();
+        let mut selection = Selection::default();
+        // The way & relation members of every relation
+        let mut members: HashMap<ObjId, Vec<(OSMObjectType, ObjId)>> = HashMap::new();
+        // Relations chosen by `select`, to start from when adding members
+        let mut chosen = Vec::new();
+        read_all(input, |obj| {
+            if let Some(relation) = obj.as_relation() {
+                if self.relation_members {
+                    let ms: Vec<_> = relation
+                        .members()
+                        .filter(|m| m.mtype != OSMObjectType::Node)
+                        .map(|m| (m.mtype, m.id))
+                        .collect();
+                    if !ms.is_empty() {
+                        members.insert(relation.id(), ms);
+                    }
+                }
+                if select(&obj) {
+                    selection.add_relation_nodes(relation);
+                    if self.relation_members {
+                        chosen.push(relation.id());
+                    }
+                }
+            } else if select(&obj) {
+                if let Some(way) = obj.as_way() {
+                    selection.add_way(way);
+                } else {
+                    selection.inside.insert(obj.id());
+                }
+            }
+            Ok(())
+        })?;
+
+        // Ways which are members of chosen relations, which weren't chosen themselves
+        let mut member_ways = HashSet::new();
+        let mut member_relations = false;
+        while let Some(id) = chosen.pop() {
+            for &(mtype, mid) in members.get(&id).into_iter().flatten() {
+                match mtype {
+                    OSMObjectType::Way if !selection.ways.contains(&mid) => {
+                        member_ways.insert(mid);
+                    }
+                    OSMObjectType::Relation if selection.relations.insert(mid) => {
+                        member_relations = true;
+                        chosen.push(mid);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        drop(members);
+        if !member_ways.is_empty() || member_relations {
+            // The node members of added relations are only known after reading them again
+            read_all(input, |obj| {
+                if let Some(way) = obj.as_way() {
+                    if member_ways.contains(&way.id()) {
+                        selection.add_way(way);
+                    }
+                } else if let Some(relation) = obj.as_relation() {
+                    if selection.relations.contains(&relation.id()) {
+                        selection.add_relation_nodes(relation);
+                    }
+                }
+                Ok(())
+            })?;
+        }
+
+        let mut written = 0;
+        read_all(input, |obj| {
+            if selection.contains(&obj) {
+                output.write_string_obj(&obj)?;
+                written += 1;
+            }
+            Ok(())
+        })?;
+        Ok(written)
+    }
+}
+
+/// Writes the objects with these ids, and everything they need, like `osmium getid -r`: the
+/// nodes of the ways, and the members of the relations, recursively (through relations of
+/// relations, at any depth). Uses `ReferenceCompletion`.
+///
+/// ```no_run
+/// use osmio::extract::GetId;
+///
+/// let mut output = osmio::write_to_path("relation.osm").unwrap();
+/// let written = "r62422"
+///     .parse::<GetId>()
+///     .unwrap()
+///     .run("planet.osm.pbf", &mut output)
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetId {
+    /// The node, way & relation ids
+    ids: [HashSet<ObjId>; 3],
+    recurse: bool,
+}
+
+impl Default for GetId {
+    fn default() -> Self {
+        GetId {
+            ids: Default::default(),
+            recurse: true,
+        }
+    }
+}
+
+/// Ids like osmium writes them, e.g. `n1 w2 r62422`, separated by whitespace or `,`
+impl FromStr for GetId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut getid = GetId::new();
+        for part in s.split(|c: char| c == ',' || c.is_whitespace()) {
+            if part.is_empty() {
+                continue;
+            }
+            let mut chars = part.chars();
+            let otype = OSMObjectType::try_from(chars.next().unwrap())?;
+            let id = chars
+                .as_str()
+                .parse()
+                .map_err(|_| format!("Invalid id {}", part))?;
+            getid.insert(otype, id);
+        }
+        Ok(getid)
+    }
+}
+
+impl GetId {
+    /// No ids yet
+    pub fn new() -> Self {
+        GetId::default()
+    }
+
+    /// Add an id
+    pub fn insert(&mut self, otype: OSMObjectType, id: ObjId) {
+        self.ids[type_idx(otype)].insert(id);
+    }
+
+    /// Add an id
+    pub fn with(mut self, otype: OSMObjectType, id: ObjId) -> Self {
+        self.insert(otype, id);
+        self
+    }
+
+    /// True iff this id was added
+    pub fn contains(&self, otype: OSMObjectType, id: ObjId) -> bool {
+        self.ids[type_idx(otype)].contains(&id)
+    }
+
+    /// Number of ids
+    pub fn len(&self) -> usize {
+        self.ids.iter().map(|ids| ids.len()).sum()
+    }
+
+    /// True iff there are no ids
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add the way & relation members of relations (recursively). If false, only the nodes of
+    /// the ways and the node members of the relations are added. Default: true
+    pub fn recurse(mut self, recurse: bool) -> Self {
+        self.recurse = recurse;
+        self
+    }
+
+    /// Write the objects to `output`, in the same order as `input`. Returns the number of objects
+    /// written. `output` isn't closed.
+    pub fn run(
+        &self,
+        input: impl AsRef<Path>,
+        output: &mut impl DynWriter,
+    ) -> Result<u64, ExtractError> {
+        ReferenceCompletion::new()
+            .relation_members(self.recurse)
+            .run(input, |o| self.contains(o.object_type(), o.id()), output)
+    }
+}
+
+/// Writes the objects of a file which match a filter, without the untagged nodes which nothing
+/// in the output refers to, for minimal extracts. Ways & relations are kept if they match, and
+/// tagged nodes if they match. Untagged nodes are only kept if a kept way or relation refers to
+/// them. Without a filter, everything except the orphan untagged nodes is kept. Uses
+/// `ReferenceCompletion`.
+///
+/// ```no_run
+/// use osmio::extract::DropOrphans;
+///
+/// let mut output = osmio::write_to_path("roads.osm").unwrap();
+/// DropOrphans::new()
+///     .filter("w/highway".parse().unwrap())
+///     .run("region.osm.pbf", &mut output)
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DropOrphans {
+    filter: Option<TagFilter>,
+    completion: ReferenceCompletion,
+}
+
+impl DropOrphans {
+    /// Keep every way, relation & tagged node
+    pub fn new() -> Self {
+        DropOrphans::default()
+    }
+
+    /// Only keep the ways, relations & tagged nodes which match this filter
+    pub fn filter(mut self, filter: TagFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Also keep the way & relation members of kept relations, recursively, see
+    /// `ReferenceCompletion::relation_members`. Default: false
+    pub fn relation_members(mut self, relation_members: bool) -> Self {
+        self.completion = self.completion.relation_members(relation_members);
+        self
+    }
+
+    /// True iff this object is kept for itself, not because another object refers to it
+    pub fn keeps(&self, obj: &impl OSMObj) -> bool {
+        (!obj.is_node() || obj.num_tags() > 0)
+            && self.filter.as_ref().map_or(true, |f| f.matches(obj))
+    }
+
+    /// Write the kept objects to `output`, in the same order as `input`. Returns the number of
+    /// objects written. `output` isn't closed.
+    pub fn run(
+        &self,
+        input: impl AsRef<Path>,
+        output: &mut impl DynWriter,
+    ) -> Result<u64, ExtractError> {
+        self.completion.run(input, |o| self.keeps(o), output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::OPLWriter;
+    use std::fs;
+    use OSMWriter;
+
+    fn extract(path: &Path, area: impl Into<Area>, strategy: Strategy) -> String {
+        let mut writer = OPLWriter::new(Vec::new());
+        let written = Extract::new(area)
+            .strategy(strategy)
+            .run(path, &mut writer)
+            .unwrap();
+        writer.close().unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(written as usize, output.lines().count());
+        ids(&output)
+    }
+
+    /// The type & id of each line of OPL
+    fn ids(output: &str) -> String {
+        output
+            .lines()
+            .map(|l| l.split(' ').next().unwrap())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn strategies() {
+        let path = std::env::temp_dir().join(format!("osmio-extract-{}.opl", std::process::id()));
+        // n1 & n2 are inside, n3, n4 & n5 outside
+        fs::write(
+            &path,
+            "n1 x0.5 y0.5\nn2 x0.6 y0.6\nn3 x2 y2\nn4 x3 y3\nn5 x4 y4\n\
+             w10 Nn1,n3\nw11 Nn4,n5\nw12 Nn3,n4\n\
+             r20 Ttype=multipolygon Mw10@outer,w11@outer\nr21 Mr22@\nr22 Mn2@\nr23 Mw12@\n",
+        )
+        .unwrap();
+
+        let bbox = BBox::new(0., 0., 1., 1.);
+        assert_eq!(extract(&path, bbox, Strategy::Simple), "n1 n2 w10 r20 r22");
+        assert_eq!(
+            extract(&path, bbox, Strategy::CompleteWays),
+            "n1 n2 n3 w10 r20 r21 r22"
+        );
+        assert_eq!(
+            extract(&path, bbox, Strategy::Smart),
+            "n1 n2 n3 n4 n5 w10 w11 r20 r21 r22"
+        );
+
+        // A triangle with n1 inside, n2 outside
+        let triangle: Polygon = "t\n1\n0 0\n1.1 0\n0 1.1\nEND\nEND\n".parse().unwrap();
+        assert_eq!(extract(&path, triangle, Strategy::Simple), "n1 w10 r20");
+
+        let mut multi = MultiExtract::new();
+        multi.push(
+            Extract::new(bbox).strategy(Strategy::Smart),
+            OPLWriter::new(Vec::new()),
+        );
+        multi.push(
+            Extract::new(BBox::new(2.5, 2.5, 3.5, 3.5)).strategy(Strategy::Simple),
+            OPLWriter::new(Vec::new()),
+        );
+        multi.push(
+            Extract::new(BBox::new(2.5, 2.5, 3.5, 3.5)),
+            OPLWriter::new(Vec::new()),
+        );
+        assert_eq!(multi.run(&path).unwrap(), vec![10, 5, 7]);
+        let outputs: Vec<_> = multi
+            .into_writers()
+            .into_iter()
+            .map(|w| ids(&String::from_utf8(w.into_inner()).unwrap()))
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![
+                "n1 n2 n3 n4 n5 w10 w11 r20 r21 r22",
+                "n4 w11 w12 r20 r23",
+                "n3 n4 n5 w11 w12 r20 r23"
+            ]
+        );
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn reference_completion() {
+        let path = std::env::temp_dir().join(format!("osmio-complete-{}.opl", std::process::id()));
+        fs::write(
+            &path,
+            "n1 x1 y1\nn2 x1 y1\nn3 x1 y1\nn4 x1 y1\nn5 x1 y1\n\
+             w10 Thighway=primary Nn1,n2\nw11 Nn3\n\
+             r20 Ttype=route Mn4@,w11@,r21@\nr21 Mn5@\nr22 Mn1@\n",
+        )
+        .unwrap();
+        let complete = |completion: ReferenceCompletion, filter: &str| {
+            let filter: TagFilter = filter.parse().unwrap();
+            let mut writer = OPLWriter::new(Vec::new());
+            completion
+                .run(&path, |o| filter.matches(o), &mut writer)
+                .unwrap();
+            writer.close().unwrap();
+            ids(&String::from_utf8(writer.into_inner()).unwrap())
+        };
+
+        assert_eq!(complete(ReferenceCompletion::new(), "highway"), "n1 n2 w10");
+        assert_eq!(complete(ReferenceCompletion::new(), "type"), "n4 r20");
+        assert_eq!(
+            complete(ReferenceCompletion::new().relation_members(true), "type"),
+            "n3 n4 n5 w11 r20 r21"
+        );
+
+        let getid = |getid: GetId| {
+            let mut writer = OPLWriter::new(Vec::new());
+            getid.run(&path, &mut writer).unwrap();
+            writer.close().unwrap();
+            ids(&String::from_utf8(writer.into_inner()).unwrap())
+        };
+        let ids: GetId = "r20, w10".parse().unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(getid(ids.clone()), "n1 n2 n3 n4 n5 w10 w11 r20 r21");
+        assert_eq!(getid(ids.recurse(false)), "n1 n2 n4 w10 r20");
+        assert_eq!(
+            getid(GetId::new().with(OSMObjectType::Relation, 21)),
+            "n5 r21"
+        );
+        assert!("x1".parse::<GetId>().is_err());
+        assert!("na".parse::<GetId>().is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn drop_orphans() {
+        let path = std::env::temp_dir().join(format!("osmio-orphans-{}.opl", std::process::id()));
+        fs::write(
+            &path,
+            "n1 x1 y1\nn2 x1 y1\nn3 x1 y1\nn4 Tamenity=bench x1 y1\nn5 x1 y1\nn6 Thighway=stop x1 y1\n\
+             w10 Thighway=primary Nn1,n2\nw11 Tbuilding=yes Nn3\nr20 Ttype=route Mn5@,w11@\n",
+        )
+        .unwrap();
+        let drop_orphans = |drop_orphans: DropOrphans| {
+            let mut writer = OPLWriter::new(Vec::new());
+            drop_orphans.run(&path, &mut writer).unwrap();
+            writer.close().unwrap();
+            ids(&String::from_utf8(writer.into_inner()).unwrap())
+        };
+
+        assert_eq!(
+            drop_orphans(DropOrphans::new()),
+            "n1 n2 n3 n4 n5 n6 w10 w11 r20"
+        );
+        let highways: TagFilter = "highway".parse().unwrap();
+        assert_eq!(
+            drop_orphans(DropOrphans::new().filter(highways.clone())),
+            "n1 n2 n6 w10"
+        );
+        assert_eq!(
+            drop_orphans(DropOrphans::new().filter("w/highway r/type".parse().unwrap())),
+            "n1 n2 n5 w10 r20"
+        );
+        assert_eq!(
+            drop_orphans(
+                DropOrphans::new()
+                    .filter("w/highway r/type".parse().unwrap())
+                    .relation_members(true)
+            ),
+            "n1 n2 n3 n5 w10 w11 r20"
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,727 @@
+//! Select objects by their tags, with osmium `tags-filter` style expressions.
+//!
+//! An expression is `[TYPES/]KEY[=VALUES]`, and a filter matches an object if any of its
+//! expressions do:
+//!
+//! * `TYPES` is some of `n`, `w` & `r` (e.g. `nw/`). Without it, all types match.
+//! * `KEY` alone matches objects with that tag, `!KEY` those without it.
+//! * `KEY=VALUE` matches objects with that tag value, `KEY!=VALUE` objects with the key, but a
+//!   different value.
+//! * `VALUES` can be several values separated by `,`.
+//! * Keys & values can start or end with `*` to match any prefix or suffix (e.g.
+//!   `name:*`, `highway=*_link`). A lone `*` is any key or value.
+//!
+//! ```
+//! use osmio::filter::TagFilter;
+//! use osmio::opl::OPLReader;
+//! use osmio::{OSMObjBase, OSMReader};
+//!
+//! let input = "n1 Tamenity=restaurant x1 y2\nn2 Tamenity=bench x1 y2\nw3 Tnote=x Nn1\n";
+//! let filter: TagFilter = "n/amenity=restaurant,cafe note".parse().unwrap();
+//! let mut reader = OPLReader::new(input.as_bytes());
+//! let ids: Vec<_> = filter.iter(reader.objects()).map(|o| o.id()).collect();
+//! assert_eq!(ids, vec![1, 3]);
+//! ```
+//!
+//! There are also filters by editor (`EditorFilter`) and by timestamp (`TimeFilter`), which
+//! work the same way.
+use std::collections::HashSet;
+use std::io::Write;
+use std::str::FromStr;
+
+use obj_types::StringOSMObj;
+use transform::{ObjTransform, TransformResult};
+use utils::type_idx;
+use {
+    DynWriter, HeaderField, OSMObj, OSMObjBase, OSMObjectType, OSMWriteError, OSMWriter,
+    TimestampFormat, Way,
+};
+
+/// Something which chooses objects, for `Filtered` & `FilterWriter`
+pub trait Filter {
+    /// True iff this object is chosen
+    fn matches(&self, obj: &impl OSMObj) -> bool;
+}
+
+/// An error when parsing a filter expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterParseError {
+    /// There was no expression
+    Empty,
+    /// The types before the `/` weren't some of `n`, `w` & `r`
+    InvalidType(String),
+    /// The key is empty
+    MissingKey(String),
+    /// A key which must be absent (`!KEY`) can't have a value
+    NegatedWithValue(String),
+}
+impl std::fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for FilterParseError {}
+
+/// A pattern for a key or a value
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    Any,
+    Exact(String),
+    Prefix(String),
+    Suffix(String),
+    Contains(String),
+}
+
+impl Pattern {
+    fn new(s: &str) -> Pattern {
+        if s == "*" {
+            return Pattern::Any;
+        }
+        match (s.strip_prefix('*'), s.strip_suffix('*')) {
+            (Some(rest), Some(_)) => Pattern::Contains(rest[..rest.len() - 1].to_string()),
+            (Some(rest), None) => Pattern::Suffix(rest.to_string()),
+            (None, Some(rest)) => Pattern::Prefix(rest.to_string()),
+            (None, None) => Pattern::Exact(s.to_string()),
+        }
+    }
+
+    fn matches(&self, s: &str) -> bool {
+        match self {
+            Pattern::Any => true,
+            Pattern::Exact(p) => s == p,
+            Pattern::Prefix(p) => s.starts_with(p.as_str()),
+            Pattern::Suffix(p) => s.ends_with(p.as_str()),
+            Pattern::Contains(p) => s.contains(p.as_str()),
+        }
+    }
+}
+
+/// What an expression requires of the value
+#[derive(Debug, Clone, PartialEq)]
+enum ValueTest {
+    /// Any value (`KEY`)
+    Any,
+    /// No tag with this key (`!KEY`)
+    Absent,
+    /// One of these values (`KEY=VALUES`)
+    OneOf(Vec<Pattern>),
+    /// None of these values (`KEY!=VALUES`)
+    NoneOf(Vec<Pattern>),
+}
+
+/// One compiled expression
+#[derive(Debug, Clone, PartialEq)]
+struct Expression {
+    /// Whether nodes, ways & relations (in that order) can match
+    types: [bool; 3],
+    key: Pattern,
+    value: ValueTest,
+}
+
+impl FromStr for Expression {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(FilterParseError::Empty);
+        }
+        let (types, rest) = match s.find('/') {
+            // A `/` after the `=` is part of the value
+            Some(idx) if s.find('=').is_none_or(|eq| idx < eq) => {
+                let mut types = [false; 3];
+                for c in s[..idx].chars() {
+                    match c {
+                        'n' => types[0] = true,
+                        'w' => types[1] = true,
+                        'r' => types[2] = true,
+                        _ => return Err(FilterParseError::InvalidType(s.to_string())),
+                    }
+                }
+                if types == [false; 3] {
+                    return Err(FilterParseError::InvalidType(s.to_string()));
+                }
+                (types, &s[idx + 1..])
+            }
+            _ => ([true; 3], s),
+        };
+
+        let (negated, rest) = match rest.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, rest),
+        };
+        let (key, value) = match rest.find('=') {
+            None => (rest, ValueTest::Any),
+            Some(idx) => {
+                let values = rest[idx + 1..].split(',').map(Pattern::new).collect();
+                if rest[..idx].ends_with('!') {
+                    (&rest[..idx - 1], ValueTest::NoneOf(values))
+                } else {
+                    (&rest[..idx], ValueTest::OneOf(values))
+                }
+            }
+        };
+        let value = match (negated, value) {
+            (false, value) => value,
+            (true, ValueTest::Any) => ValueTest::Absent,
+            (true, _) => return Err(FilterParseError::NegatedWithValue(s.to_string())),
+        };
+        if key.is_empty() {
+            return Err(FilterParseError::MissingKey(s.to_string()));
+        }
+
+        Ok(Expression {
+            types: types,
+            key: Pattern::new(key),
+            value: value,
+        })
+    }
+}
+
+impl Expression {
+    fn matches(&self, obj: &impl OSMObj) -> bool {
+        self.matches_as(obj.object_type(), obj)
+    }
+
+    /// Match this object, which is of this type
+    fn matches_as(&self, object_type: OSMObjectType, obj: &impl OSMObjBase) -> bool {
+        if !self.types[type_idx(object_type)] {
+            return false;
+        }
+        let mut tags = obj.tags().filter(|(k, _)| self.key.matches(k));
+        match &self.value {
+            ValueTest::Any => tags.next().is_some(),
+            ValueTest::Absent => tags.next().is_none(),
+            ValueTest::OneOf(values) => tags.any(|(_, v)| values.iter().any(|p| p.matches(v))),
+            ValueTest::NoneOf(values) => tags.any(|(_, v)| !values.iter().any(|p| p.matches(v))),
+        }
+    }
+}
+
+/// A compiled filter, which matches objects matching any of its expressions. Parse one from
+/// whitespace separated expressions with `str::parse`, or build it with `new`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TagFilter {
+    expressions: Vec<Expression>,
+    invert: bool,
+}
+
+impl FromStr for TagFilter {
+    type Err = FilterParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TagFilter::new(s.split_whitespace())
+    }
+}
+
+impl TagFilter {
+    /// A filter from these expressions. At least one is needed.
+    pub fn new(
+        expressions: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Result<Self, FilterParseError> {
+        let expressions = expressions
+            .into_iter()
+            .map(|e| e.as_ref().parse())
+            .collect::<Result<Vec<Expression>, _>>()?;
+        if expressions.is_empty() {
+            return Err(FilterParseError::Empty);
+        }
+        Ok(TagFilter {
+            expressions: expressions,
+            invert: false,
+        })
+    }
+
+    /// Match the objects this filter doesn't match instead (like `osmium tags-filter -i`)
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// Number of expressions
+    pub fn len(&self) -> usize {
+        self.expressions.len()
+    }
+
+    /// True iff there are no expressions (which only the `Default` filter has). This matches
+    /// everything, so e.g. `FilterWriter::new` writes every object.
+    pub fn is_empty(&self) -> bool {
+        self.expressions.is_empty()
+    }
+
+    /// True iff this object matches this filter
+    pub fn matches(&self, obj: &impl OSMObj) -> bool {
+        (self.is_empty() || self.expressions.iter().any(|e| e.matches(obj))) != self.invert
+    }
+
+    /// True iff this way matches this filter
+    pub fn matches_way(&self, way: &impl Way) -> bool {
+        (self.is_empty()
+            || self
+                .expressions
+                .iter()
+                .any(|e| e.matches_as(OSMObjectType::Way, way)))
+            != self.invert
+    }
+
+    /// Only the objects of this iterator which match
+    pub fn iter<I>(&self, iter: I) -> Filtered<I>
+    where
+        I: Iterator,
+        I::Item: OSMObj,
+    {
+        Filtered {
+            inner: iter,
+            filter: self.clone(),
+        }
+    }
+
+    /// Wrap this writer, so only the objects which match are written to it
+    pub fn writer<O>(&self, writer: O) -> FilterWriter<O> {
+        FilterWriter {
+            inner: writer,
+            filter: self.clone(),
+        }
+    }
+}
+
+impl Filter for TagFilter {
+    fn matches(&self, obj: &impl OSMObj) -> bool {
+        TagFilter::matches(self, obj)
+    }
+}
+
+/// Iterator of the objects which match a filter, see `TagFilter::iter`
+pub struct Filtered<I, F = TagFilter> {
+    inner: I,
+    filter: F,
+}
+
+impl<I, F> Iterator for Filtered<I, F>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+    F: Filter,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let filter = &self.filter;
+        self.inner.find(|o| filter.matches(o))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, self.inner.size_hint().1)
+    }
+}
+
+/// A writer which only writes the objects matching a filter, and drops the others, see
+/// `TagFilter::writer`. Created with `OSMWriter::new`, it has the default filter, which for
+/// `TagFilter` matches nothing.
+pub struct FilterWriter<O, F = TagFilter> {
+    inner: O,
+    filter: F,
+}
+
+impl<O, F> FilterWriter<O, F> {
+    /// The filter
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    /// The wrapped writer
+    pub fn writer(&self) -> &O {
+        &self.inner
+    }
+
+    /// Convert back to the wrapped writer
+    pub fn into_writer(self) -> O {
+        self.inner
+    }
+}
+
+impl<W: Write, O: OSMWriter<W>, F: Filter + Default> OSMWriter<W> for FilterWriter<O, F> {
+    fn new(writer: W) -> Self {
+        FilterWriter {
+            inner: O::new(writer),
+            filter: F::default(),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), OSMWriteError> {
+        self.inner.close()
+    }
+
+    fn is_open(&self) -> bool {
+        self.inner.is_open()
+    }
+
+    fn write_obj(&mut self, obj: &impl OSMObj) -> Result<(), OSMWriteError> {
+        if self.filter.matches(obj) {
+            self.inner.write_obj(obj)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn into_inner(self) -> W {
+        self.inner.into_inner()
+    }
+
+    fn set_header(&mut self, field: HeaderField) -> Result<(), OSMWriteError> {
+        self.inner.set_header(field)
+    }
+}
+
+impl<O: DynWriter, F: Filter> DynWriter for FilterWriter<O, F> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        if self.filter.matches(obj) {
+            self.inner.write_string_obj(obj)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        self.inner.finish()
+    }
+}
+
+/// Chooses the objects last edited by some users (by name or uid), or in some changesets. For
+/// vandalism analysis & import auditing. Objects match if any of these match, or, inverted,
+/// objects match unless any do.
+///
+/// ```
+/// use osmio::filter::EditorFilter;
+/// use osmio::opl::OPLReader;
+/// use osmio::{OSMObjBase, OSMReader};
+///
+/// let input = "n1 c10 i1 ualice x1 y2\nn2 c11 i2 ubob x1 y2\nn3 c12 i3 ucarol x1 y2\n";
+/// let filter = EditorFilter::new().user("alice").changeset(12);
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let ids: Vec<_> = filter.iter(reader.objects()).map(|o| o.id()).collect();
+/// assert_eq!(ids, vec![1, 3]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EditorFilter {
+    users: HashSet<String>,
+    uids: HashSet<u64>,
+    changesets: HashSet<u64>,
+    invert: bool,
+}
+
+impl EditorFilter {
+    /// Matches nothing, until users, uids or changesets are added
+    pub fn new() -> Self {
+        EditorFilter::default()
+    }
+
+    /// Match objects last edited by this user name
+    pub fn user(mut self, user: impl Into<String>) -> Self {
+        self.users.insert(user.into());
+        self
+    }
+
+    /// Match objects last edited by these user names
+    pub fn users(mut self, users: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.users.extend(users.into_iter().map(|u| u.into()));
+        self
+    }
+
+    /// Match objects last edited by this uid
+    pub fn uid(mut self, uid: u64) -> Self {
+        self.uids.insert(uid);
+        self
+    }
+
+    /// Match objects last edited by these uids
+    pub fn uids(mut self, uids: impl IntoIterator<Item = u64>) -> Self {
+        self.uids.extend(uids);
+        self
+    }
+
+    /// Match objects last edited in this changeset
+    pub fn changeset(mut self, changeset_id: u64) -> Self {
+        self.changesets.insert(changeset_id);
+        self
+    }
+
+    /// Match objects last edited in these changesets
+    pub fn changesets(mut self, changeset_ids: impl IntoIterator<Item = u64>) -> Self {
+        self.changesets.extend(changeset_ids);
+        self
+    }
+
+    /// Match the objects this filter doesn't match instead, to drop the objects of these
+    /// users or changesets
+    pub fn invert(mut self, invert: bool) -> Self {
+        self.invert = invert;
+        self
+    }
+
+    /// True iff this object matches this filter
+    pub fn matches(&self, obj: &impl OSMObj) -> bool {
+        let matched = obj.user().is_some_and(|u| self.users.contains(u))
+            || obj.uid().is_some_and(|u| self.uids.contains(&u))
+            || obj
+                .changeset_id()
+                .is_some_and(|c| self.changesets.contains(&c));
+        matched != self.invert
+    }
+
+    /// Only the objects of this iterator which match
+    pub fn iter<I>(&self, iter: I) -> Filtered<I, EditorFilter>
+    where
+        I: Iterator,
+        I::Item: OSMObj,
+    {
+        Filtered {
+            inner: iter,
+            filter: self.clone(),
+        }
+    }
+
+    /// Wrap this writer, so only the objects which match are written to it
+    pub fn writer<O>(&self, writer: O) -> FilterWriter<O, EditorFilter> {
+        FilterWriter {
+            inner: writer,
+            filter: self.clone(),
+        }
+    }
+}
+
+impl Filter for EditorFilter {
+    fn matches(&self, obj: &impl OSMObj) -> bool {
+        EditorFilter::matches(self, obj)
+    }
+}
+
+/// Chooses the objects last edited inside a time window, from `since` (inclusive) up to
+/// `before` (exclusive). Without a bound, that side of the window is open. Objects without a valid
+/// timestamp never match.
+///
+/// ```
+/// use osmio::filter::TimeFilter;
+/// use osmio::opl::OPLReader;
+/// use osmio::{OSMObjBase, OSMReader, TimestampFormat};
+///
+/// let input = "n1 t2012-12-31T23:59:59Z x1 y2\nn2 t2013-01-15T00:00:00Z x1 y2\nn3 t2013-02-01T00:00:00Z x1 y2\n";
+/// let since: TimestampFormat = "2013-01-01T00:00:00Z".parse().unwrap();
+/// let before: TimestampFormat = "2013-02-01T00:00:00Z".parse().unwrap();
+/// let filter = TimeFilter::new().since(since).before(before);
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let ids: Vec<_> = filter.iter(reader.objects()).map(|o| o.id()).collect();
+/// assert_eq!(ids, vec![2]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct TimeFilter {
+    /// Unix epochs
+    since: Option<i64>,
+    before: Option<i64>,
+}
+
+impl TimeFilter {
+    /// Matches every object with a timestamp, until bounds are set
+    pub fn new() -> Self {
+        TimeFilter::default()
+    }
+
+    /// Only match objects edited at, or after, this time
+    pub fn since(mut self, since: impl Into<TimestampFormat>) -> Self {
+        self.since = Some(since.into().to_epoch_number());
+        self
+    }
+
+    /// Only match objects edited before this time
+    pub fn before(mut self, before: impl Into<TimestampFormat>) -> Self {
+        self.before = Some(before.into().to_epoch_number());
+        self
+    }
+
+    /// True iff this object was edited inside this window
+    pub fn matches(&self, obj: &impl OSMObj) -> bool {
+        let t = match obj.timestamp().as_ref().and_then(|t| t.to_datetime()) {
+            None => return false,
+            Some(t) => t.timestamp(),
+        };
+        self.since.is_none_or(|s| s <= t) && self.before.is_none_or(|b| t < b)
+    }
+
+    /// Only the objects of this iterator which match
+    pub fn iter<I>(&self, iter: I) -> Filtered<I, TimeFilter>
+    where
+        I: Iterator,
+        I::Item: OSMObj,
+    {
+        Filtered {
+            inner: iter,
+            filter: self.clone(),
+        }
+    }
+
+    /// Wrap this writer, so only the objects which match are written to it
+    pub fn writer<O>(&self, writer: O) -> FilterWriter<O, TimeFilter> {
+        FilterWriter {
+            inner: writer,
+            filter: self.clone(),
+        }
+    }
+}
+
+impl Filter for TimeFilter {
+    fn matches(&self, obj: &impl OSMObj) -> bool {
+        TimeFilter::matches(self, obj)
+    }
+}
+
+/// Filters are transforms, which drop the objects which don't match
+macro_rules! impl_filter_transform {
+    ($($ty:ident),*) => {
+        $(
+            impl<T: OSMObj> ObjTransform<T> for $ty {
+                fn apply(&mut self, obj: T) -> TransformResult<T> {
+                    if self.matches(&obj) {
+                        TransformResult::Keep(obj)
+                    } else {
+                        TransformResult::Drop
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_filter_transform!(TagFilter, EditorFilter, TimeFilter);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::{OPLReader, OPLWriter};
+    use {OSMObjBase, OSMReader};
+
+    fn ids(filter: &str, input: &str) -> Vec<i64> {
+        let filter: TagFilter = filter.parse().unwrap();
+        let mut reader = OPLReader::new(input.as_bytes());
+        filter.iter(reader.objects()).map(|o| o.id()).collect()
+    }
+
+    #[test]
+    fn expressions() {
+        let input = "n1 Thighway=primary x1 y2\nw2 Thighway=primary_link,name:en=A Nn1\n\
+                     w3 Tbuilding=yes Nn1\nr4 Ttype=route,url=http://x/y M\n";
+        assert_eq!(ids("highway", input), vec![1, 2]);
+        assert_eq!(ids("w/highway", input), vec![2]);
+        assert_eq!(ids("nr/highway", input), vec![1]);
+        assert_eq!(ids("highway=primary", input), vec![1]);
+        assert_eq!(ids("highway=*_link", input), vec![2]);
+        assert_eq!(ids("highway=prim*", input), vec![1, 2]);
+        assert_eq!(ids("highway!=primary", input), vec![2]);
+        assert_eq!(ids("highway=footway,primary", input), vec![1]);
+        assert_eq!(ids("name:*", input), vec![2]);
+        assert_eq!(ids("w/!highway", input), vec![3]);
+        assert_eq!(ids("building highway=primary", input), vec![1, 3]);
+        assert_eq!(ids("url=http://x/y", input), vec![4]);
+        assert_eq!(ids("*=yes", input), vec![3]);
+
+        let filter: TagFilter = "highway".parse().unwrap();
+        let filter = filter.invert(true);
+        let mut reader = OPLReader::new(input.as_bytes());
+        let ids: Vec<_> = filter.iter(reader.objects()).map(|o| o.id()).collect();
+        assert_eq!(ids, vec![3, 4]);
+
+        assert_eq!("".parse::<TagFilter>(), Err(FilterParseError::Empty));
+        assert!(matches!(
+            "x/highway".parse::<TagFilter>(),
+            Err(FilterParseError::InvalidType(_))
+        ));
+        assert!(matches!(
+            "n/=x".parse::<TagFilter>(),
+            Err(FilterParseError::MissingKey(_))
+        ));
+        assert!(matches!(
+            "!highway=x".parse::<TagFilter>(),
+            Err(FilterParseError::NegatedWithValue(_))
+        ));
+    }
+
+    #[test]
+    fn writer() {
+        let input = "n1 Tamenity=cafe x1 y2\nn2 Tamenity=bench x1 y2\n";
+        let filter = TagFilter::new(vec!["amenity=cafe"]).unwrap();
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut writer = filter.writer(OPLWriter::new(Vec::new()));
+        writer.write_objs(reader.objects()).unwrap();
+        writer.close().unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "n1 dV Tamenity=cafe x1 y2\n"
+        );
+
+        // `OSMWriter::new` has the default filter, which writes everything
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut writer: FilterWriter<OPLWriter<_>> = FilterWriter::new(Vec::new());
+        writer.write_objs(reader.objects()).unwrap();
+        writer.close().unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "n1 dV Tamenity=cafe x1 y2\nn2 dV Tamenity=bench x1 y2\n"
+        );
+        let way = ::obj_types::StringWay::new(1, vec![1]);
+        assert!(TagFilter::default().matches_way(&way));
+        assert!(!TagFilter::default().invert(true).matches_way(&way));
+    }
+
+    #[test]
+    fn editors() {
+        let input =
+            "n1 c10 i1 ualice x1 y2\nn2 c11 i2 ubob x1 y2\nn3 x1 y2\nn4 c12 i1 ualice2 x1 y2\n";
+        let ids = |filter: EditorFilter| {
+            let mut reader = OPLReader::new(input.as_bytes());
+            filter
+                .iter(reader.objects())
+                .map(|o| o.id())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(EditorFilter::new()), Vec::<i64>::new());
+        assert_eq!(ids(EditorFilter::new().user("alice")), vec![1]);
+        assert_eq!(ids(EditorFilter::new().uid(1)), vec![1, 4]);
+        assert_eq!(
+            ids(EditorFilter::new().changesets(vec![11, 12])),
+            vec![2, 4]
+        );
+        assert_eq!(
+            ids(EditorFilter::new().users(vec!["bob"]).invert(true)),
+            vec![1, 3, 4]
+        );
+
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut writer = EditorFilter::new()
+            .uids(vec![2])
+            .writer(OPLWriter::new(Vec::new()));
+        writer.write_objs(reader.objects()).unwrap();
+        writer.close().unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "n2 dV c11 i2 ubob T x1 y2\n"
+        );
+    }
+
+    #[test]
+    fn times() {
+        let input = "n1 t2012-12-31T23:59:59Z x1 y2\nn2 t2013-01-01T00:00:00Z x1 y2\nn3 x1 y2\nn4 t2013-02-01T00:00:00Z x1 y2\n";
+        let ids = |filter: TimeFilter| {
+            let mut reader = OPLReader::new(input.as_bytes());
+            filter
+                .iter(reader.objects())
+                .map(|o| o.id())
+                .collect::<Vec<_>>()
+        };
+        let jan: TimestampFormat = "2013-01-01T00:00:00Z".parse().unwrap();
+        let feb: TimestampFormat = "2013-02-01T00:00:00Z".parse().unwrap();
+        assert_eq!(ids(TimeFilter::new()), vec![1, 2, 4]);
+        assert_eq!(ids(TimeFilter::new().since(jan.clone())), vec![2, 4]);
+        assert_eq!(ids(TimeFilter::new().before(jan.clone())), vec![1]);
+        assert_eq!(ids(TimeFilter::new().since(jan).before(feb)), vec![2]);
+        assert_eq!(ids(TimeFilter::new().since(1359676800)), vec![4]);
+    }
+}
@@ -0,0 +1,821 @@
+//! Detecting file formats from filenames, and converting between them
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use bzip2::read::MultiBzDecoder;
+use bzip2::write::BzEncoder;
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+
+use obj_types::StringOSMObj;
+use opl::{OPLReader, OPLWriter};
+use osc::{OSCReader, OSCWriter};
+use pbf::PBFReader;
+use xml::{XMLReader, XMLWriter};
+use {
+    DynReader, DynWriter, OSMObj, OSMObjBase, OSMObjectType, OSMReader, OSMWriteError, OSMWriter,
+    ObjId, ReadError, WriterOptions,
+};
+
+/// The OSM file formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileFormat {
+    PBF,
+    XML,
+    OSC,
+    OPL,
+}
+
+/// The compression (if any) applied to a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    /// Requires the `xz` feature
+    Xz,
+    /// Requires the `zstd` feature
+    Zstd,
+}
+
+impl FileFormat {
+    /// Detect the format & compression from the filename, e.g. `planet.osm.bz2` is bzip2
+    /// compressed XML. `None` if the extension isn't known.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<(FileFormat, Compression)> {
+        let filename = path.as_ref().file_name()?.to_str()?.to_lowercase();
+        let (filename, compression) = if filename.ends_with(".gz") {
+            (&filename[..filename.len() - 3], Compression::Gzip)
+        } else if filename.ends_with(".bz2") {
+            (&filename[..filename.len() - 4], Compression::Bzip2)
+        } else if filename.ends_with(".xz") {
+            (&filename[..filename.len() - 3], Compression::Xz)
+        } else if filename.ends_with(".zst") {
+            (&filename[..filename.len() - 4], Compression::Zstd)
+        } else {
+            (filename.as_str(), Compression::None)
+        };
+
+        let format = match Path::new(filename).extension()?.to_str()? {
+            "pbf" => FileFormat::PBF,
+            "osm" | "xml" => FileFormat::XML,
+            "osc" => FileFormat::OSC,
+            "opl" => FileFormat::OPL,
+            _ => return None,
+        };
+
+        Some((format, compression))
+    }
+}
+
+/// Detect the compression from the first bytes of a file
+fn sniff_compression(start: &[u8]) -> Compression {
+    if start.starts_with(&[0x1f, 0x8b]) {
+        Compression::Gzip
+    } else if start.starts_with(b"BZh") {
+        Compression::Bzip2
+    } else if start.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0]) {
+        Compression::Xz
+    } else if start.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Compression::Zstd
+    } else {
+        Compression::None
+    }
+}
+
+/// Detect the (uncompressed) file format from the first bytes of a file
+fn sniff_format(start: &[u8]) -> Option<FileFormat> {
+    let text = String::from_utf8_lossy(&start[..start.len().min(1024)]);
+    if text.trim_start().starts_with('<') {
+        if text.contains("<osmChange") {
+            Some(FileFormat::OSC)
+        } else {
+            Some(FileFormat::XML)
+        }
+    } else if start.len() >= 4 && start[4..].windows(9).any(|w| w == b"OSMHeader") {
+        // PBF files start with the length of the first BlobHeader, which is a `OSMHeader`
+        Some(FileFormat::PBF)
+    } else {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some('n'), Some(c)) | (Some('w'), Some(c)) | (Some('r'), Some(c))
+                if c.is_ascii_digit() || c == '-' =>
+            {
+                Some(FileFormat::OPL)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// An error when trying to open a file as an OSM reader or writer
+#[derive(Debug)]
+pub enum OpenError {
+    IOError(::std::io::Error),
+    /// The format can't be detected from this path
+    UnknownFormat(PathBuf),
+    /// osmio can't read this format
+    CannotRead(FileFormat),
+    /// osmio can't write this format
+    CannotWrite(FileFormat),
+    /// This compression needs a cargo feature which isn't enabled
+    CompressionNotEnabled(Compression),
+    /// This format can't be appended to (`WriterOptions::append`), as it has a footer
+    CannotAppend(FileFormat),
+}
+impl std::fmt::Display for OpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for OpenError {}
+
+impl From<std::io::Error> for OpenError {
+    fn from(err: std::io::Error) -> OpenError {
+        OpenError::IOError(err)
+    }
+}
+
+/// An error when converting a file
+#[derive(Debug)]
+pub enum ConvertError {
+    Open(OpenError),
+    /// The input can't be decoded
+    Read(ReadError),
+    Write(OSMWriteError),
+    /// The input was checked for sortedness, and these two objects (type, id, version) are out
+    /// of order
+    NotSorted(
+        (OSMObjectType, ObjId, Option<u32>),
+        (OSMObjectType, ObjId, Option<u32>),
+    ),
+}
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for ConvertError {}
+
+impl From<OpenError> for ConvertError {
+    fn from(err: OpenError) -> ConvertError {
+        ConvertError::Open(err)
+    }
+}
+impl From<ReadError> for ConvertError {
+    fn from(err: ReadError) -> ConvertError {
+        ConvertError::Read(err)
+    }
+}
+impl From<OSMWriteError> for ConvertError {
+    fn from(err: OSMWriteError) -> ConvertError {
+        ConvertError::Write(err)
+    }
+}
+
+/// Options for `convert_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Fail with `ConvertError::NotSorted` if the input isn't sorted by type, then id, then
+    /// version.
+    pub check_sorted: bool,
+}
+
+fn decompress(
+    reader: impl BufRead + Send + 'static,
+    compression: Compression,
+) -> Result<Box<dyn Read + Send>, OpenError> {
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+        #[cfg(feature = "xz")]
+        Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+        #[allow(unreachable_patterns)]
+        c => return Err(OpenError::CompressionNotEnabled(c)),
+    })
+}
+
+pub(crate) fn open_read(
+    path: &Path,
+    compression: Compression,
+) -> Result<Box<dyn Read + Send>, OpenError> {
+    decompress(BufReader::new(File::open(path)?), compression)
+}
+
+/// A file being written, maybe compressed. It's buffered, so `finish` has to be called at the end
+/// to write all of it, & see any errors.
+pub(crate) enum FileWriter {
+    Plain(BufWriter<File>),
+    Gzip(BufWriter<GzEncoder<BufWriter<File>>>),
+    Bzip2(BufWriter<BzEncoder<BufWriter<File>>>),
+    #[cfg(feature = "xz")]
+    Xz(BufWriter<xz2::write::XzEncoder<BufWriter<File>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(BufWriter<zstd::stream::write::Encoder<'static, BufWriter<File>>>),
+}
+
+impl FileWriter {
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            FileWriter::Plain(w) => w,
+            FileWriter::Gzip(w) => w,
+            FileWriter::Bzip2(w) => w,
+            #[cfg(feature = "xz")]
+            FileWriter::Xz(w) => w,
+            #[cfg(feature = "zstd")]
+            FileWriter::Zstd(w) => w,
+        }
+    }
+
+    /// Write everything that's buffered, and the end of the compression
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        self.flush()?;
+        match self {
+            FileWriter::Plain(_) => Ok(()),
+            FileWriter::Gzip(w) => {
+                w.get_mut().try_finish()?;
+                w.get_mut().get_mut().flush()
+            }
+            FileWriter::Bzip2(w) => {
+                w.get_mut().try_finish()?;
+                w.get_mut().get_mut().flush()
+            }
+            #[cfg(feature = "xz")]
+            FileWriter::Xz(w) => {
+                w.get_mut().try_finish()?;
+                w.get_mut().get_mut().flush()
+            }
+            #[cfg(feature = "zstd")]
+            FileWriter::Zstd(w) => {
+                w.get_mut().do_finish()?;
+                w.get_mut().get_mut().flush()
+            }
+        }
+    }
+}
+
+impl Write for FileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer().flush()
+    }
+}
+
+pub(crate) fn open_write(
+    path: &Path,
+    compression: Compression,
+    options: &WriterOptions,
+) -> Result<FileWriter, OpenError> {
+    match compression {
+        Compression::Xz if !cfg!(feature = "xz") => {
+            return Err(OpenError::CompressionNotEnabled(compression))
+        }
+        Compression::Zstd if !cfg!(feature = "zstd") => {
+            return Err(OpenError::CompressionNotEnabled(compression))
+        }
+        _ => {}
+    }
+    let size = options.buffer_size;
+    let file = if options.append {
+        OpenOptions::new().append(true).create(true).open(path)?
+    } else {
+        File::create(path)?
+    };
+    let file = BufWriter::with_capacity(size, file);
+    // The writers make many small writes, so collect them before compressing too
+    Ok(match compression {
+        Compression::None => FileWriter::Plain(file),
+        Compression::Gzip => FileWriter::Gzip(BufWriter::with_capacity(
+            size,
+            GzEncoder::new(file, flate2::Compression::default()),
+        )),
+        Compression::Bzip2 => FileWriter::Bzip2(BufWriter::with_capacity(
+            size,
+            BzEncoder::new(file, bzip2::Compression::default()),
+        )),
+        #[cfg(feature = "xz")]
+        Compression::Xz => FileWriter::Xz(BufWriter::with_capacity(
+            size,
+            xz2::write::XzEncoder::new(file, 6),
+        )),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => FileWriter::Zstd(BufWriter::with_capacity(
+            size,
+            zstd::stream::write::Encoder::new(file, 0)?,
+        )),
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    })
+}
+
+/// An `OSMWriter` of a `FileWriter`
+trait FileOSMWriter: OSMWriter<FileWriter> {
+    fn file(&mut self) -> &mut FileWriter;
+}
+
+impl FileOSMWriter for XMLWriter<FileWriter> {
+    fn file(&mut self) -> &mut FileWriter {
+        self.get_mut()
+    }
+}
+
+impl FileOSMWriter for OSCWriter<FileWriter> {
+    fn file(&mut self) -> &mut FileWriter {
+        self.get_mut()
+    }
+}
+
+impl FileOSMWriter for OPLWriter<FileWriter> {
+    fn file(&mut self) -> &mut FileWriter {
+        self.get_mut()
+    }
+}
+
+/// Close the writer, then finish the file
+fn close_file(writer: &mut impl FileOSMWriter) -> Result<(), OSMWriteError> {
+    writer.close()?;
+    writer.file().finish().map_err(OSMWriteError::FileWrite)
+}
+
+/// The writer from `write_to_path`, which finishes the file when it's finished
+struct PathWriter<O: FileOSMWriter> {
+    writer: O,
+}
+
+impl<O: FileOSMWriter> DynWriter for PathWriter<O> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        self.writer.write_obj(obj)
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        close_file(&mut self.writer)
+    }
+}
+
+fn detect(path: &Path) -> Result<(FileFormat, Compression), OpenError> {
+    FileFormat::from_path(path).ok_or_else(|| OpenError::UnknownFormat(path.to_path_buf()))
+}
+
+fn boxed_reader(format: FileFormat, reader: Box<dyn Read + Send>) -> Box<dyn DynReader + Send> {
+    match format {
+        FileFormat::PBF => Box::new(PBFReader::new(reader)),
+        FileFormat::XML => Box::new(XMLReader::new(reader)),
+        FileFormat::OSC => Box::new(OSCReader::new(reader)),
+        FileFormat::OPL => Box::new(OPLReader::new(reader)),
+    }
+}
+
+/// Open a file for reading, whatever the format. The format & compression are detected from the
+/// filename, or if that's unknown, from the first bytes of the file. The reader is `Send`, so it
+/// can be moved to another thread.
+///
+/// ```no_run
+/// for obj in osmio::read_from_path("region.osc.gz").unwrap() {
+///     println!("{:?}", obj);
+/// }
+/// ```
+pub fn read_from_path(path: impl AsRef<Path>) -> Result<Box<dyn DynReader + Send>, OpenError> {
+    let path = path.as_ref();
+    if let Some((format, compression)) = FileFormat::from_path(path) {
+        return Ok(boxed_reader(format, open_read(path, compression)?));
+    }
+
+    let mut file = BufReader::new(File::open(path)?);
+    let compression = sniff_compression(file.fill_buf()?);
+    let mut reader = BufReader::new(decompress(file, compression)?);
+    match sniff_format(reader.fill_buf()?) {
+        Some(format) => Ok(boxed_reader(format, Box::new(reader))),
+        None => Err(OpenError::UnknownFormat(path.to_path_buf())),
+    }
+}
+
+/// Create a file for writing. The format & compression are detected from the filename, e.g.
+/// `out.osc.gz` is a gzip compressed osmChange file.
+///
+/// ```no_run
+/// let mut writer = osmio::write_to_path("out.osm.bz2").unwrap();
+/// for obj in osmio::read_from_path("in.osm.pbf").unwrap() {
+///     writer.write_string_obj(&obj).unwrap();
+/// }
+/// writer.finish().unwrap();
+/// ```
+pub fn write_to_path(path: impl AsRef<Path>) -> Result<Box<dyn DynWriter>, OpenError> {
+    write_to_path_with_options(path, &WriterOptions::default())
+}
+
+/// `write_to_path`, with these options, e.g. a bigger buffer
+///
+/// ```no_run
+/// let options = osmio::WriterOptions {
+///     buffer_size: 16 * 1024 * 1024,
+///     ..Default::default()
+/// };
+/// let mut writer = osmio::write_to_path_with_options("out.opl", &options).unwrap();
+/// ```
+pub fn write_to_path_with_options(
+    path: impl AsRef<Path>,
+    options: &WriterOptions,
+) -> Result<Box<dyn DynWriter>, OpenError> {
+    let path = path.as_ref();
+    let (format, compression) = detect(path)?;
+    if options.append && format != FileFormat::OPL {
+        return Err(OpenError::CannotAppend(format));
+    }
+    let open = || open_write(path, compression, options);
+    match format {
+        FileFormat::XML => Ok(Box::new(PathWriter {
+            writer: XMLWriter::new(open()?),
+        })),
+        FileFormat::OSC => Ok(Box::new(PathWriter {
+            writer: OSCWriter::new(open()?),
+        })),
+        FileFormat::OPL => Ok(Box::new(PathWriter {
+            writer: OPLWriter::new(open()?),
+        })),
+        f => Err(OpenError::CannotWrite(f)),
+    }
+}
+
+fn copy_objects<R, O>(
+    reader: &mut R,
+    writer: &mut O,
+    options: &ConvertOptions,
+) -> Result<u64, ConvertError>
+where
+    R: OSMReader,
+    O: FileOSMWriter,
+{
+    if let Some(mut header) = reader.try_header()? {
+        // this program is writing the new file
+        header.generator = None;
+        writer.set_headers(&header)?;
+    }
+
+    let mut last_key = None;
+    let mut count = 0;
+    for obj in reader.try_objects() {
+        let obj = obj?;
+        if options.check_sorted {
+            let key = (obj.object_type(), obj.id(), obj.version());
+            if let Some(last_key) = last_key {
+                if key <= last_key {
+                    return Err(ConvertError::NotSorted(last_key, key));
+                }
+            }
+            last_key = Some(key);
+        }
+        writer.write_obj(&obj)?;
+        count += 1;
+    }
+    close_file(writer)?;
+    Ok(count)
+}
+
+fn convert_from<R: OSMReader>(
+    mut reader: R,
+    output: &Path,
+    options: &ConvertOptions,
+) -> Result<u64, ConvertError> {
+    let (format, compression) = detect(output)?;
+    let output = open_write(output, compression, &WriterOptions::default())?;
+    match format {
+        FileFormat::XML => copy_objects(&mut reader, &mut XMLWriter::new(output), options),
+        FileFormat::OSC => copy_objects(&mut reader, &mut OSCWriter::new(output), options),
+        FileFormat::OPL => copy_objects(&mut reader, &mut OPLWriter::new(output), options),
+        f => Err(OpenError::CannotWrite(f).into()),
+    }
+}
+
+/// Read all the objects from `input` and write them to `output`, detecting the file formats and
+/// compression from the filenames. Returns the number of objects written.
+pub fn convert(input: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<u64, ConvertError> {
+    convert_with_options(input, output, &ConvertOptions::default())
+}
+
+/// Like `convert`, but with options
+pub fn convert_with_options(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    options: &ConvertOptions,
+) -> Result<u64, ConvertError> {
+    let input = input.as_ref();
+    let output = output.as_ref();
+    let (format, compression) = detect(input)?;
+    // check the output format before doing anything else
+    detect(output)?;
+    let reader = open_read(input, compression)?;
+    match format {
+        FileFormat::PBF => convert_from(PBFReader::new(reader), output, options),
+        FileFormat::XML => convert_from(XMLReader::new(reader), output, options),
+        FileFormat::OSC => convert_from(OSCReader::new(reader), output, options),
+        FileFormat::OPL => convert_from(OPLReader::new(reader), output, options),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use Lon;
+
+    macro_rules! assert_detect {
+        ( $name:ident, $input:expr, $output:expr ) => {
+            #[test]
+            fn $name() {
+                assert_eq!(FileFormat::from_path($input), $output);
+            }
+        };
+    }
+
+    assert_detect!(
+        detect1,
+        "planet.osm.pbf",
+        Some((FileFormat::PBF, Compression::None))
+    );
+    assert_detect!(
+        detect2,
+        "foo/bar.osm",
+        Some((FileFormat::XML, Compression::None))
+    );
+    assert_detect!(
+        detect3,
+        "history.OSM.BZ2",
+        Some((FileFormat::XML, Compression::Bzip2))
+    );
+    assert_detect!(
+        detect4,
+        "123.osc.gz",
+        Some((FileFormat::OSC, Compression::Gzip))
+    );
+    assert_detect!(
+        detect5,
+        "data.opl",
+        Some((FileFormat::OPL, Compression::None))
+    );
+    assert_detect!(
+        detect8,
+        "history.osm.xz",
+        Some((FileFormat::XML, Compression::Xz))
+    );
+    assert_detect!(
+        detect9,
+        "history.osm.zst",
+        Some((FileFormat::XML, Compression::Zstd))
+    );
+    assert_detect!(detect6, "data.txt", None);
+    assert_detect!(detect7, "osm", None);
+
+    const XML: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<osm version=\"0.6\">\n\t<node id=\"1\" version=\"1\" lat=\"1\" lon=\"2\"/>\n\t<node id=\"2\" version=\"1\" lat=\"1\" lon=\"2\"/>\n\t<way id=\"1\" version=\"1\"><nd ref=\"1\"/><nd ref=\"2\"/><tag k=\"highway\" v=\"road\"/></way>\n</osm>";
+
+    fn tmp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("osmio-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn convert_xml_to_compressed() {
+        let input = tmp_path("convert.osm");
+        fs::write(&input, XML).unwrap();
+
+        for output_name in &["convert-out.osm.gz", "convert-out.osm.bz2"] {
+            let output = tmp_path(output_name);
+            assert_eq!(convert(&input, &output).unwrap(), 3);
+            let (_, compression) = FileFormat::from_path(&output).unwrap();
+            let mut reader = XMLReader::new(open_read(&output, compression).unwrap());
+            assert_eq!(reader.objects().count(), 3);
+            fs::remove_file(&output).unwrap();
+        }
+
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn convert_keeps_header() {
+        let input = tmp_path("header.osm");
+        let output = tmp_path("header-out.osm");
+        let bounds = "<bounds minlat=\"1\" minlon=\"2\" maxlat=\"3\" maxlon=\"4\"/>";
+        fs::write(
+            &input,
+            XML.replace(
+                "<osm version=\"0.6\">",
+                &format!("<osm version=\"0.6\" generator=\"other\">{}", bounds),
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(convert(&input, &output).unwrap(), 3);
+        let mut reader = XMLReader::new(fs::File::open(&output).unwrap());
+        let header = reader.header().unwrap();
+        assert!(header.generator.unwrap().starts_with("osmio/"));
+        assert_eq!(header.bbox.unwrap().max_lon, Lon::from(4.));
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn convert_sorted_check() {
+        let input = tmp_path("unsorted.osm");
+        let output = tmp_path("unsorted-out.osm");
+        fs::write(&input, XML.replace("id=\"2\"", "id=\"0\"")).unwrap();
+
+        let options = ConvertOptions { check_sorted: true };
+        match convert_with_options(&input, &output, &options) {
+            Err(ConvertError::NotSorted((_, 1, _), (_, 0, _))) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert_eq!(convert(&input, &output).unwrap(), 3);
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&output).unwrap();
+    }
+
+    #[test]
+    fn sniff() {
+        assert_eq!(sniff_compression(&[0x1f, 0x8b, 8]), Compression::Gzip);
+        assert_eq!(sniff_compression(b"BZh91AY"), Compression::Bzip2);
+        assert_eq!(sniff_compression(b"<?xml"), Compression::None);
+        assert_eq!(sniff_format(XML.as_bytes()), Some(FileFormat::XML));
+        assert_eq!(
+            sniff_format(b"<?xml version=\"1.0\"?>\n<osmChange version=\"0.6\">"),
+            Some(FileFormat::OSC)
+        );
+        assert_eq!(
+            sniff_format(b"\0\0\0\x0d\x0a\x09OSMHeader\x18"),
+            Some(FileFormat::PBF)
+        );
+        assert_eq!(sniff_format(b"n1 v1 x1 y2\n"), Some(FileFormat::OPL));
+        assert_eq!(sniff_format(b"hello"), None);
+    }
+
+    #[test]
+    fn read_from_paths() {
+        let input = tmp_path("read.osm");
+        fs::write(&input, XML).unwrap();
+        assert_eq!(read_from_path(&input).unwrap().count(), 3);
+
+        // no extension, so the format & compression have to be detected from the contents
+        let output = tmp_path("read-no-extension.osm.bz2");
+        convert(&input, &output).unwrap();
+        let renamed = tmp_path("read-no-extension");
+        fs::rename(&output, &renamed).unwrap();
+        let ways: Vec<_> = read_from_path(&renamed)
+            .unwrap()
+            .filter(|o| o.is_way())
+            .collect();
+        assert_eq!(ways.len(), 1);
+        assert_eq!(ways[0].tag("highway"), Some("road"));
+
+        fs::remove_file(&input).unwrap();
+        fs::remove_file(&renamed).unwrap();
+    }
+
+    #[test]
+    fn write_to_paths() {
+        let input = tmp_path("write.osm");
+        fs::write(&input, XML).unwrap();
+
+        for output_name in &["write-out.opl", "write-out.osc.gz", "write-out.osm.bz2"] {
+            let output = tmp_path(output_name);
+            let mut writer = write_to_path(&output).unwrap();
+            for obj in read_from_path(&input).unwrap() {
+                writer.write_any(obj).unwrap();
+            }
+            writer.finish().unwrap();
+            drop(writer);
+            assert_eq!(read_from_path(&output).unwrap().count(), 3);
+            fs::remove_file(&output).unwrap();
+        }
+
+        // All the output is written, whatever the buffer size
+        for &buffer_size in &[1, 100, 1 << 24] {
+            let output = tmp_path("write-buffered.opl.gz");
+            let options = WriterOptions {
+                buffer_size: buffer_size,
+                ..Default::default()
+            };
+            let mut writer = write_to_path_with_options(&output, &options).unwrap();
+            for obj in read_from_path(&input).unwrap() {
+                writer.write_any(obj).unwrap();
+            }
+            writer.finish().unwrap();
+            drop(writer);
+            assert_eq!(read_from_path(&output).unwrap().count(), 3);
+            fs::remove_file(&output).unwrap();
+        }
+
+        // Appending to OPL adds another (gzip) stream, and both are read
+        let output = tmp_path("write-append.opl.gz");
+        let options = WriterOptions {
+            append: true,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            let mut writer = write_to_path_with_options(&output, &options).unwrap();
+            for obj in read_from_path(&input).unwrap() {
+                writer.write_any(obj).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        assert_eq!(read_from_path(&output).unwrap().count(), 6);
+        fs::remove_file(&output).unwrap();
+        match write_to_path_with_options(tmp_path("write-append.osm"), &options) {
+            Err(OpenError::CannotAppend(FileFormat::XML)) => {}
+            _ => panic!(),
+        }
+
+        match write_to_path(tmp_path("write-out.osm.pbf")) {
+            Err(OpenError::CannotWrite(FileFormat::PBF)) => {}
+            _ => panic!(),
+        }
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finish_errors() {
+        // Writing to /dev/full fails, but only when the buffers are written
+        let input = tmp_path("full-in.osm");
+        fs::write(&input, XML).unwrap();
+        let mut output_names = vec!["full.osm", "full.osc", "full.opl.gz", "full.osm.bz2"];
+        if cfg!(feature = "xz") {
+            output_names.push("full.osm.xz");
+        }
+        if cfg!(feature = "zstd") {
+            output_names.push("full.osm.zst");
+        }
+        for output_name in output_names {
+            let output = tmp_path(output_name);
+            std::os::unix::fs::symlink("/dev/full", &output).unwrap();
+            let mut writer = write_to_path(&output).unwrap();
+            for obj in read_from_path(&input).unwrap() {
+                writer.write_any(obj).unwrap();
+            }
+            assert!(writer.finish().is_err(), "{}", output_name);
+            drop(writer);
+
+            match convert(&input, &output) {
+                Err(ConvertError::Write(_)) => {}
+                r => panic!("unexpected result {:?}", r),
+            }
+            fs::remove_file(&output).unwrap();
+        }
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn xz_and_zstd() {
+        let input = tmp_path("xz.osm");
+        fs::write(&input, XML).unwrap();
+
+        for (output_name, enabled) in &[
+            ("xz-out.osm.xz", cfg!(feature = "xz")),
+            ("xz-out.osm.zst", cfg!(feature = "zstd")),
+        ] {
+            let output = tmp_path(output_name);
+            if *enabled {
+                assert_eq!(convert(&input, &output).unwrap(), 3);
+                assert_eq!(read_from_path(&output).unwrap().count(), 3);
+                fs::remove_file(&output).unwrap();
+            } else {
+                match convert(&input, &output) {
+                    Err(ConvertError::Open(OpenError::CompressionNotEnabled(_))) => {}
+                    r => panic!("unexpected result {:?}", r),
+                }
+                assert!(!output.exists());
+            }
+        }
+        fs::remove_file(&input).unwrap();
+    }
+
+    #[test]
+    fn convert_errors() {
+        match convert("foo.txt", "bar.osm") {
+            Err(ConvertError::Open(OpenError::UnknownFormat(_))) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        match convert(tmp_path("doesnt-exist.osm"), "bar.osm") {
+            Err(ConvertError::Open(OpenError::IOError(_))) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn convert_truncated() {
+        let input = tmp_path("truncated.osm");
+        let output = tmp_path("truncated-out.opl");
+        fs::write(&input, &XML[..XML.len() / 2]).unwrap();
+        match convert(&input, &output) {
+            Err(ConvertError::Read(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        fs::remove_file(&input).unwrap();
+        let _ = fs::remove_file(&output);
+    }
+}
@@ -0,0 +1,202 @@
+//! Geometries of OSM objects, as WKT or WKB
+//!
+//! Only nodes know their own location, so way geometries are built with a lookup function which
+//! returns the location of a node id.
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fmt::Write as FmtWrite;
+
+use super::{Lat, Lon, Node, ObjId, Way};
+
+/// A simple feature geometry. Coordinates are `(lat, lon)`, like elsewhere in osmio, but are
+/// written as `lon lat` (x y) in WKT/WKB.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Geometry {
+    Point((Lat, Lon)),
+    LineString(Vec<(Lat, Lon)>),
+    /// The first ring is the outer ring, all others are holes
+    Polygon(Vec<Vec<(Lat, Lon)>>),
+    MultiPolygon(Vec<Vec<Vec<(Lat, Lon)>>>),
+}
+
+const WKB_POINT: u32 = 1;
+const WKB_LINESTRING: u32 = 2;
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTIPOLYGON: u32 = 6;
+
+fn wkt_points(out: &mut String, points: &[(Lat, Lon)]) {
+    out.push('(');
+    for (i, (lat, lon)) in points.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write!(out, "{} {}", lon, lat).unwrap();
+    }
+    out.push(')');
+}
+
+fn wkt_rings(out: &mut String, rings: &[Vec<(Lat, Lon)>]) {
+    out.push('(');
+    for (i, ring) in rings.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        wkt_points(out, ring);
+    }
+    out.push(')');
+}
+
+fn wkb_header(out: &mut Vec<u8>, geom_type: u32) {
+    // 1 = little endian
+    out.push(1);
+    out.write_u32::<LittleEndian>(geom_type).unwrap();
+}
+
+fn wkb_points(out: &mut Vec<u8>, points: &[(Lat, Lon)]) {
+    out.write_u32::<LittleEndian>(points.len() as u32).unwrap();
+    for (lat, lon) in points {
+        out.write_f64::<LittleEndian>(lon.degrees()).unwrap();
+        out.write_f64::<LittleEndian>(lat.degrees()).unwrap();
+    }
+}
+
+fn wkb_rings(out: &mut Vec<u8>, rings: &[Vec<(Lat, Lon)>]) {
+    out.write_u32::<LittleEndian>(rings.len() as u32).unwrap();
+    for ring in rings {
+        wkb_points(out, ring);
+    }
+}
+
+impl Geometry {
+    /// This geometry as Well Known Text, e.g. `POINT(0.1 51.5)`
+    pub fn to_wkt(&self) -> String {
+        let mut out = String::new();
+        match self {
+            Geometry::Point((lat, lon)) => {
+                write!(out, "POINT({} {})", lon, lat).unwrap();
+            }
+            Geometry::LineString(points) => {
+                out.push_str("LINESTRING");
+                wkt_points(&mut out, points);
+            }
+            Geometry::Polygon(rings) => {
+                out.push_str("POLYGON");
+                wkt_rings(&mut out, rings);
+            }
+            Geometry::MultiPolygon(polygons) => {
+                out.push_str("MULTIPOLYGON(");
+                for (i, rings) in polygons.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str(", ");
+                    }
+                    wkt_rings(&mut out, rings);
+                }
+                out.push(')');
+            }
+        }
+        out
+    }
+
+    /// This geometry as (little endian) Well Known Binary
+    pub fn to_wkb(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        match self {
+            Geometry::Point((lat, lon)) => {
+                wkb_header(&mut out, WKB_POINT);
+                out.write_f64::<LittleEndian>(lon.degrees()).unwrap();
+                out.write_f64::<LittleEndian>(lat.degrees()).unwrap();
+            }
+            Geometry::LineString(points) => {
+                wkb_header(&mut out, WKB_LINESTRING);
+                wkb_points(&mut out, points);
+            }
+            Geometry::Polygon(rings) => {
+                wkb_header(&mut out, WKB_POLYGON);
+                wkb_rings(&mut out, rings);
+            }
+            Geometry::MultiPolygon(polygons) => {
+                wkb_header(&mut out, WKB_MULTIPOLYGON);
+                out.write_u32::<LittleEndian>(polygons.len() as u32)
+                    .unwrap();
+                for rings in polygons {
+                    wkb_header(&mut out, WKB_POLYGON);
+                    wkb_rings(&mut out, rings);
+                }
+            }
+        }
+        out
+    }
+}
+
+/// The point geometry of this node. `None` if it has no location (e.g. deleted)
+pub fn node_geometry(node: &impl Node) -> Option<Geometry> {
+    node.lat_lon().map(Geometry::Point)
+}
+
+/// The linestring geometry of this way. `location` is called for every node id of this way.
+/// `None` if any node location is unknown, or there are less than 2 nodes.
+pub fn way_geometry(
+    way: &impl Way,
+    location: impl Fn(ObjId) -> Option<(Lat, Lon)>,
+) -> Option<Geometry> {
+    if way.num_nodes() < 2 {
+        return None;
+    }
+    let points: Option<Vec<_>> = way.nodes().iter().map(|&nid| location(nid)).collect();
+    points.map(Geometry::LineString)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj_types::{StringNodeBuilder, StringWayBuilder};
+    use std::collections::HashMap;
+
+    fn ll(lat: f64, lon: f64) -> (Lat, Lon) {
+        (lat.into(), lon.into())
+    }
+
+    #[test]
+    fn point() {
+        let n = StringNodeBuilder::default()
+            ._id(1)
+            ._lat_lon((Lat::from(51.5), Lon::from(-0.25)))
+            .build()
+            .unwrap();
+        let g = node_geometry(&n).unwrap();
+        assert_eq!(g.to_wkt(), "POINT(-0.25 51.5)");
+
+        let mut wkb = vec![1, 1, 0, 0, 0];
+        wkb.write_f64::<LittleEndian>(-0.25).unwrap();
+        wkb.write_f64::<LittleEndian>(51.5).unwrap();
+        assert_eq!(g.to_wkb(), wkb);
+    }
+
+    #[test]
+    fn way() {
+        let w = StringWayBuilder::default()
+            ._id(1)
+            ._nodes(vec![1, 2])
+            .build()
+            .unwrap();
+        let mut locations = HashMap::new();
+        locations.insert(1, ll(1., 2.));
+        assert_eq!(way_geometry(&w, |nid| locations.get(&nid).cloned()), None);
+
+        locations.insert(2, ll(3., 4.));
+        let g = way_geometry(&w, |nid| locations.get(&nid).cloned()).unwrap();
+        assert_eq!(g.to_wkt(), "LINESTRING(2 1, 4 3)");
+        assert_eq!(g.to_wkb().len(), 1 + 4 + 4 + 2 * 16);
+    }
+
+    #[test]
+    fn polygons() {
+        let ring = vec![ll(0., 0.), ll(0., 1.), ll(1., 1.), ll(0., 0.)];
+        let g = Geometry::Polygon(vec![ring.clone()]);
+        assert_eq!(g.to_wkt(), "POLYGON((0 0, 1 0, 1 1, 0 0))");
+        let g = Geometry::MultiPolygon(vec![vec![ring.clone()], vec![ring]]);
+        assert_eq!(
+            g.to_wkt(),
+            "MULTIPOLYGON(((0 0, 1 0, 1 1, 0 0)), ((0 0, 1 0, 1 1, 0 0)))"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! A graph of which nodes are connected by ways, for routing & network analysis.
+//!
+//! Every pair of consecutive nodes of a way is an edge, in both directions. `GraphBuilder`
+//! collects the ways (optionally only those matching a `TagFilter`), then builds a compact
+//! `Graph`, with all the edges of a node stored next to each other.
+//!
+//! ```
+//! use osmio::graph::GraphBuilder;
+//! use osmio::opl::OPLReader;
+//! use osmio::OSMReader;
+//!
+//! let input = "w1 Thighway=primary Nn1,n2,n3\nw2 Thighway=service Nn2,n4\nw3 Tbuilding=yes Nn5,n6\n";
+//! let mut reader = OPLReader::new(input.as_bytes());
+//! let mut builder = GraphBuilder::new().filter("w/highway".parse().unwrap());
+//! builder.add_reader(&mut reader).unwrap();
+//! let graph = builder.build();
+//!
+//! assert_eq!(graph.num_nodes(), 4);
+//! let neighbours: Vec<_> = graph.neighbours(2).iter().map(|e| (e.to, e.way)).collect();
+//! assert_eq!(neighbours, vec![(1, 1), (3, 1), (4, 2)]);
+//! ```
+use filter::TagFilter;
+use {DynReader, OSMObj, ObjId, ReadError, Way};
+
+/// An edge from a node, see `Graph::neighbours`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    /// The node this edge goes to
+    pub to: ObjId,
+    /// The way this edge is part of
+    pub way: ObjId,
+    /// The position, in the way's node list, of the first node of this segment
+    pub index: u32,
+    /// True iff this edge goes in the direction of the way (e.g. for `oneway`s)
+    pub forward: bool,
+}
+
+/// Collects ways, to build a `Graph`
+#[derive(Debug, Clone, Default)]
+pub struct GraphBuilder {
+    filter: Option<TagFilter>,
+    edges: Vec<(ObjId, Edge)>,
+}
+
+impl GraphBuilder {
+    /// Add every way
+    pub fn new() -> Self {
+        GraphBuilder::default()
+    }
+
+    /// Only add the ways which match this filter
+    pub fn filter(mut self, filter: TagFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Add the edges of this way, if it matches the filter. Repeated nodes (`a,a`) aren't an edge.
+    pub fn add_way(&mut self, way: &impl Way) {
+        if let Some(ref filter) = self.filter {
+            if !filter.matches_way(way) {
+                return;
+            }
+        }
+        for (i, pair) in way.nodes().windows(2).enumerate() {
+            if pair[0] == pair[1] {
+                continue;
+            }
+            let edge = |to, forward| Edge {
+                to: to,
+                way: way.id(),
+                index: i as u32,
+                forward: forward,
+            };
+            self.edges.push((pair[0], edge(pair[1], true)));
+            self.edges.push((pair[1], edge(pair[0], false)));
+        }
+    }
+
+    /// Add all the ways of this reader
+    pub fn add_reader<R: DynReader + ?Sized>(&mut self, reader: &mut R) -> Result<(), ReadError> {
+        while let Some(obj) = reader.try_next_obj()? {
+            if let Some(way) = obj.as_way() {
+                self.add_way(way);
+            }
+        }
+        Ok(())
+    }
+
+    /// The graph of all the ways added
+    pub fn build(mut self) -> Graph {
+        // Stable, so each node's edges are in the order they were added
+        self.edges.sort_by_key(|&(from, _)| from);
+        let mut nodes = Vec::new();
+        let mut offsets = Vec::new();
+        for (i, &(from, _)) in self.edges.iter().enumerate() {
+            if nodes.last() != Some(&from) {
+                nodes.push(from);
+                offsets.push(i);
+            }
+        }
+        offsets.push(self.edges.len());
+
+        Graph {
+            nodes: nodes,
+            offsets: offsets,
+            edges: self.edges.into_iter().map(|(_, edge)| edge).collect(),
+        }
+    }
+}
+
+/// Which nodes are connected, and by which ways, see the module docs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Graph {
+    /// Every node with an edge, sorted
+    nodes: Vec<ObjId>,
+    /// The edges of `nodes[i]` are `edges[offsets[i]..offsets[i + 1]]`
+    offsets: Vec<usize>,
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    /// How many nodes have an edge
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// How many edges there are. Each segment of a way is 2, one in each direction.
+    pub fn num_edges(&self) -> usize {
+        self.edges.len()
+    }
+
+    /// Every node with an edge, sorted by id
+    pub fn nodes(&self) -> &[ObjId] {
+        &self.nodes
+    }
+
+    /// True iff this node has an edge
+    pub fn contains_node(&self, node: ObjId) -> bool {
+        self.nodes.binary_search(&node).is_ok()
+    }
+
+    /// The edges from this node, in the order the ways were added. Empty if it has none.
+    pub fn neighbours(&self, node: ObjId) -> &[Edge] {
+        match self.nodes.binary_search(&node) {
+            Ok(i) => &self.edges[self.offsets[i]..self.offsets[i + 1]],
+            Err(_) => &[],
+        }
+    }
+
+    /// How many edges this node has
+    pub fn degree(&self, node: ObjId) -> usize {
+        self.neighbours(node).len()
+    }
+
+    /// Every edge, with the node it's from
+    pub fn edges(&self) -> impl Iterator<Item = (ObjId, &Edge)> + '_ {
+        self.nodes.iter().enumerate().flat_map(move |(i, &from)| {
+            self.edges[self.offsets[i]..self.offsets[i + 1]]
+                .iter()
+                .map(move |edge| (from, edge))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::OPLReader;
+    use OSMReader;
+
+    #[test]
+    fn graph() {
+        let input = "n1 x1 y2\nw1 Thighway=primary Nn3,n1,n2,n2\nw2 Thighway=path Nn1,n4,n1\n\
+                     w3 Tbuilding=yes Nn1,n5\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut builder = GraphBuilder::new().filter("highway".parse().unwrap());
+        builder.add_reader(&mut reader).unwrap();
+        let graph = builder.build();
+
+        assert_eq!(graph.nodes(), &[1, 2, 3, 4]);
+        assert_eq!(graph.num_edges(), 8);
+        assert!(!graph.contains_node(5));
+        assert_eq!(graph.neighbours(5), &[]);
+        assert_eq!(graph.degree(4), 2);
+        assert_eq!(
+            graph.neighbours(1),
+            &[
+                Edge {
+                    to: 3,
+                    way: 1,
+                    index: 0,
+                    forward: false
+                },
+                Edge {
+                    to: 2,
+                    way: 1,
+                    index: 1,
+                    forward: true
+                },
+                Edge {
+                    to: 4,
+                    way: 2,
+                    index: 0,
+                    forward: true
+                },
+                Edge {
+                    to: 4,
+                    way: 2,
+                    index: 1,
+                    forward: false
+                },
+            ]
+        );
+        assert_eq!(graph.edges().count(), 8);
+        assert_eq!(graph.edges().next(), Some((1, &graph.neighbours(1)[0])));
+
+        let mut builder = GraphBuilder::new();
+        builder
+            .add_reader(&mut OPLReader::new(input.as_bytes()))
+            .unwrap();
+        assert!(builder.build().contains_node(5));
+    }
+}
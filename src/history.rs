@@ -0,0 +1,534 @@
+//! Working with full-history files, which have every version of every object, sorted by type,
+//! then id, then version. `Versions` groups the versions of each object, `Snapshot` gives
+//! the data at a point in time, and `Thinned` drops old versions. `ByChangeset` groups the
+//! objects by changeset instead, for data sorted by changeset.
+//!
+//! ```
+//! use osmio::history::Versions;
+//! use osmio::opl::OPLReader;
+//! use osmio::{OSMObjBase, OSMReader};
+//!
+//! let input = "n1 v1 x1 y2\nn1 v2 dD\nn2 v1 x1 y2\n";
+//! let mut reader = OPLReader::new(input.as_bytes());
+//! let versions: Vec<Vec<_>> = Versions::new(reader.objects()).collect();
+//! assert_eq!(versions.len(), 2);
+//! assert_eq!(versions[0].len(), 2);
+//! assert!(versions[0][1].deleted());
+//! ```
+use std::collections::{HashMap, VecDeque};
+
+use {BBox, Changeset, Node, OSMObj, OSMObjBase, TimestampFormat};
+
+/// Iterator adapter which returns all the versions of one object at a time, in the order
+/// they're in, as a `Vec`. The versions of each object must be next to each other.
+pub struct Versions<I: Iterator> {
+    inner: I,
+    /// The first version of the next object
+    pending: Option<I::Item>,
+}
+
+impl<I> Versions<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    pub fn new(inner: I) -> Self {
+        Versions {
+            inner: inner,
+            pending: None,
+        }
+    }
+}
+
+impl<I> Iterator for Versions<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Vec<I::Item>> {
+        let first = match self.pending.take() {
+            Some(obj) => obj,
+            None => self.inner.next()?,
+        };
+        let key = (first.object_type(), first.id());
+        let mut versions = vec![first];
+        for obj in self.inner.by_ref() {
+            if (obj.object_type(), obj.id()) != key {
+                self.pending = Some(obj);
+                break;
+            }
+            versions.push(obj);
+        }
+        Some(versions)
+    }
+}
+
+/// Iterator adapter which turns a full-history file into a snapshot of the data at a time: for
+/// each object, the last version at or before that time, unless it's deleted. Versions without
+/// a valid timestamp are skipped. The versions of each object must be next to each other, and
+/// in order.
+///
+/// ```
+/// use osmio::history::Snapshot;
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::{OSMReader, OSMWriter, TimestampFormat};
+///
+/// let input = "n1 v1 t2010-01-01T00:00:00Z x1 y2\n\
+///              n1 v2 t2015-01-01T00:00:00Z x3 y4\n\
+///              n2 v1 t2010-01-01T00:00:00Z x1 y2\n\
+///              n2 v2 dD t2012-01-01T00:00:00Z\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let at: TimestampFormat = "2013-01-01T00:00:00Z".parse().unwrap();
+/// let snapshot = Snapshot::new(reader.objects(), at);
+/// let mut writer = OPLWriter::new(Vec::new());
+/// writer.write_objs(snapshot).unwrap();
+/// writer.close().unwrap();
+/// assert_eq!(
+///     String::from_utf8(writer.into_inner()).unwrap(),
+///     "n1 v1 dV t2010-01-01T00:00:00Z T x1 y2\n"
+/// );
+/// ```
+pub struct Snapshot<I: Iterator> {
+    versions: Versions<I>,
+    /// Unix epoch
+    at: i64,
+}
+
+impl<I> Snapshot<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    /// The snapshot at this time
+    pub fn new(inner: I, at: impl Into<TimestampFormat>) -> Self {
+        Snapshot {
+            versions: Versions::new(inner),
+            at: at.into().to_epoch_number(),
+        }
+    }
+}
+
+impl<I> Iterator for Snapshot<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let at = self.at;
+        for versions in self.versions.by_ref() {
+            let current = versions.into_iter().rev().find(|o| {
+                o.timestamp()
+                    .as_ref()
+                    .and_then(|t| t.to_datetime())
+                    .map_or(false, |t| t.timestamp() <= at)
+            });
+            match current {
+                Some(obj) if !obj.deleted() => return Some(obj),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+/// Iterator adapter which thins out a full-history file, keeping only the most recent versions
+/// of each object: the last `latest` versions, and/or those newer than a cutoff (versions without
+/// a valid timestamp are dropped then). With both, versions must pass both. The versions of each
+/// object must be next to each other, and in order.
+///
+/// ```
+/// use osmio::history::Thinned;
+/// use osmio::opl::OPLReader;
+/// use osmio::{OSMObjBase, OSMReader};
+///
+/// let input = "n1 v1 x1 y2\nn1 v2 x1 y3\nn1 v3 x1 y4\nn2 v1 x1 y2\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let kept: Vec<_> = Thinned::new(reader.objects())
+///     .latest(2)
+///     .map(|o| (o.id(), o.version().unwrap()))
+///     .collect();
+/// assert_eq!(kept, vec![(1, 2), (1, 3), (2, 1)]);
+/// ```
+pub struct Thinned<I: Iterator> {
+    versions: Versions<I>,
+    latest: Option<usize>,
+    /// Unix epoch
+    newer_than: Option<i64>,
+    /// The kept versions of this object, still to be returned
+    current: ::std::vec::IntoIter<I::Item>,
+}
+
+impl<I> Thinned<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    /// Keeps every version, until `latest` or `newer_than` are set
+    pub fn new(inner: I) -> Self {
+        Thinned {
+            versions: Versions::new(inner),
+            latest: None,
+            newer_than: None,
+            current: Vec::new().into_iter(),
+        }
+    }
+
+    /// Only keep the last `n` versions of each object
+    pub fn latest(mut self, n: usize) -> Self {
+        self.latest = Some(n);
+        self
+    }
+
+    /// Only keep the versions with a timestamp after this time
+    pub fn newer_than(mut self, cutoff: impl Into<TimestampFormat>) -> Self {
+        self.newer_than = Some(cutoff.into().to_epoch_number());
+        self
+    }
+}
+
+impl<I> Iterator for Thinned<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        loop {
+            if let Some(obj) = self.current.next() {
+                return Some(obj);
+            }
+            let mut versions = self.versions.next()?;
+            if let Some(n) = self.latest {
+                let skip = versions.len().saturating_sub(n);
+                versions.drain(..skip);
+            }
+            if let Some(cutoff) = self.newer_than {
+                versions.retain(|o| {
+                    o.timestamp()
+                        .as_ref()
+                        .and_then(|t| t.to_datetime())
+                        .map_or(false, |t| t.timestamp() > cutoff)
+                });
+            }
+            self.current = versions.into_iter();
+        }
+    }
+}
+
+/// The objects of one changeset, see `ByChangeset`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangesetGroup<T> {
+    /// `None` for the objects without a changeset id
+    pub changeset_id: Option<u64>,
+    pub objects: Vec<T>,
+    bbox: Option<BBox>,
+    /// Unix epochs
+    min_timestamp: Option<i64>,
+    max_timestamp: Option<i64>,
+}
+
+impl<T: OSMObj> ChangesetGroup<T> {
+    fn new(changeset_id: Option<u64>) -> Self {
+        ChangesetGroup {
+            changeset_id: changeset_id,
+            objects: Vec::new(),
+            bbox: None,
+            min_timestamp: None,
+            max_timestamp: None,
+        }
+    }
+
+    fn push(&mut self, obj: T) {
+        if let Some(t) = obj.timestamp().as_ref().and_then(|t| t.to_datetime()) {
+            let t = t.timestamp();
+            self.min_timestamp = Some(self.min_timestamp.map_or(t, |m| m.min(t)));
+            self.max_timestamp = Some(self.max_timestamp.map_or(t, |m| m.max(t)));
+        }
+        if let Some((lat, lon)) = obj.as_node().and_then(|n| n.lat_lon()) {
+            self.bbox = Some(match self.bbox {
+                None => BBox::point(lat, lon),
+                Some(b) => b.extended(lat, lon),
+            });
+        }
+        self.objects.push(obj);
+    }
+
+    /// The bbox of the nodes (with a location) of this changeset
+    pub fn bbox(&self) -> Option<BBox> {
+        self.bbox
+    }
+
+    /// The earliest (valid) timestamp
+    pub fn start(&self) -> Option<TimestampFormat> {
+        self.min_timestamp.map(TimestampFormat::EpochNunber)
+    }
+
+    /// The latest (valid) timestamp
+    pub fn end(&self) -> Option<TimestampFormat> {
+        self.max_timestamp.map(TimestampFormat::EpochNunber)
+    }
+
+    /// The changeset, as far as it can be told from these objects: closed, from the start to the
+    /// end, by the user of the first object. `None` for the objects without a changeset id.
+    pub fn to_changeset(&self) -> Option<Changeset> {
+        let first = self.objects.first();
+        Some(Changeset {
+            id: self.changeset_id?,
+            created_at: self.start(),
+            closed_at: self.end(),
+            open: false,
+            bbox: self.bbox,
+            uid: first.and_then(|o| o.uid()),
+            user: first.and_then(|o| o.user()).map(|u| u.to_string()),
+            num_changes: Some(self.objects.len() as u64),
+            ..Default::default()
+        })
+    }
+}
+
+/// Iterator adapter which returns the objects of one changeset at a time, for changeset
+/// analytics on full-history data sorted (or nearly sorted) by changeset. Up to `window`
+/// changesets are collected at once, so a changeset's objects don't have to be next to each
+/// other, only within `window` changesets of each other; if they're further apart, that changeset
+/// is returned more than once. The changesets are returned in the order they're first seen.
+///
+/// ```
+/// use osmio::history::ByChangeset;
+/// use osmio::opl::OPLReader;
+/// use osmio::OSMReader;
+///
+/// let input = "n1 c10 t2020-01-01T00:00:00Z x1 y2\nn2 c11 x1 y2\nw3 c10 t2020-01-01T00:05:00Z Nn1\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let changesets: Vec<_> = ByChangeset::new(reader.objects()).window(2).collect();
+/// assert_eq!(changesets.len(), 2);
+/// assert_eq!(changesets[0].changeset_id, Some(10));
+/// assert_eq!(changesets[0].objects.len(), 2);
+/// assert_eq!(
+///     changesets[0].end().unwrap().to_epoch_number() - changesets[0].start().unwrap().to_epoch_number(),
+///     300
+/// );
+/// ```
+pub struct ByChangeset<I: Iterator> {
+    inner: I,
+    window: usize,
+    groups: HashMap<Option<u64>, ChangesetGroup<I::Item>>,
+    /// The changeset ids in `groups`, in the order they were first seen
+    order: VecDeque<Option<u64>>,
+}
+
+impl<I> ByChangeset<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    /// Each changeset's objects must be next to each other, until `window` is set
+    pub fn new(inner: I) -> Self {
+        ByChangeset {
+            inner: inner,
+            window: 1,
+            groups: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// How many changesets to collect at once. Default: 1
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    /// Remove the changeset which was first seen
+    fn pop_oldest(&mut self) -> Option<ChangesetGroup<I::Item>> {
+        let id = self.order.pop_front()?;
+        self.groups.remove(&id)
+    }
+}
+
+impl<I> Iterator for ByChangeset<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    type Item = ChangesetGroup<I::Item>;
+
+    fn next(&mut self) -> Option<ChangesetGroup<I::Item>> {
+        loop {
+            let obj = match self.inner.next() {
+                Some(obj) => obj,
+                None => return self.pop_oldest(),
+            };
+            let id = obj.changeset_id();
+            if !self.groups.contains_key(&id) {
+                self.order.push_back(id);
+            }
+            self.groups
+                .entry(id)
+                .or_insert_with(|| ChangesetGroup::new(id))
+                .push(obj);
+            if self.groups.len() > self.window {
+                return self.pop_oldest();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::OPLReader;
+    use {OSMObjBase, OSMObjectType, OSMReader};
+
+    #[test]
+    fn versions() {
+        let input = "n1 v1 x1 y2\nn1 v2 x1 y3\nn1 v3 dD\nw1 v1 Nn1\nr1 v1 M\nr1 v2 M\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut versions = Versions::new(reader.objects());
+        let summary: Vec<_> = versions
+            .by_ref()
+            .map(|v| {
+                (
+                    v[0].object_type(),
+                    v[0].id(),
+                    v.iter().map(|o| o.version().unwrap()).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (OSMObjectType::Node, 1, vec![1, 2, 3]),
+                (OSMObjectType::Way, 1, vec![1]),
+                (OSMObjectType::Relation, 1, vec![1, 2]),
+            ]
+        );
+        assert!(versions.next().is_none());
+    }
+
+    #[test]
+    fn snapshot() {
+        let input = "n1 v1 t2010-01-01T00:00:00Z x1 y2\nn1 v2 t2015-01-01T00:00:00Z x3 y4\n\
+                     n2 v1 t2014-01-01T00:00:00Z x1 y2\nn3 v1 x1 y2\n\
+                     w1 v1 t2010-01-01T00:00:00Z Nn1\nw1 v2 dD t2011-01-01T00:00:00Z\n\
+                     w1 v3 t2012-01-01T00:00:00Z Nn1,n2\n";
+        let snapshot = |at: &str| {
+            let mut reader = OPLReader::new(input.as_bytes());
+            Snapshot::new(reader.objects(), at.parse::<TimestampFormat>().unwrap())
+                .map(|o| (o.object_type(), o.id(), o.version().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(snapshot("2000-01-01T00:00:00Z"), vec![]);
+        assert_eq!(
+            snapshot("2010-06-01T00:00:00Z"),
+            vec![(OSMObjectType::Node, 1, 1), (OSMObjectType::Way, 1, 1)]
+        );
+        assert_eq!(
+            snapshot("2011-06-01T00:00:00Z"),
+            vec![(OSMObjectType::Node, 1, 1)]
+        );
+        assert_eq!(
+            snapshot("2020-01-01T00:00:00Z"),
+            vec![
+                (OSMObjectType::Node, 1, 2),
+                (OSMObjectType::Node, 2, 1),
+                (OSMObjectType::Way, 1, 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn thinned() {
+        let input = "n1 v1 t2010-01-01T00:00:00Z x1 y2\nn1 v2 t2012-01-01T00:00:00Z x1 y3\n\
+                     n1 v3 t2014-01-01T00:00:00Z x1 y4\nn2 v1 x1 y2\n\
+                     w1 v1 t2011-01-01T00:00:00Z Nn1\nw1 v2 t2013-01-01T00:00:00Z Nn1,n2\n";
+        let kept = |latest: Option<usize>, newer_than: Option<&str>| {
+            let mut reader = OPLReader::new(input.as_bytes());
+            let mut thinned = Thinned::new(reader.objects());
+            if let Some(n) = latest {
+                thinned = thinned.latest(n);
+            }
+            if let Some(t) = newer_than {
+                thinned = thinned.newer_than(t.parse::<TimestampFormat>().unwrap());
+            }
+            thinned
+                .map(|o| (o.object_type(), o.id(), o.version().unwrap()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kept(None, None).len(), 6);
+        assert_eq!(
+            kept(Some(1), None),
+            vec![
+                (OSMObjectType::Node, 1, 3),
+                (OSMObjectType::Node, 2, 1),
+                (OSMObjectType::Way, 1, 2)
+            ]
+        );
+        assert_eq!(kept(Some(0), None), vec![]);
+        assert_eq!(
+            kept(None, Some("2012-06-01T00:00:00Z")),
+            vec![(OSMObjectType::Node, 1, 3), (OSMObjectType::Way, 1, 2)]
+        );
+        assert_eq!(
+            kept(Some(2), Some("2013-06-01T00:00:00Z")),
+            vec![(OSMObjectType::Node, 1, 3)]
+        );
+    }
+
+    #[test]
+    fn by_changeset() {
+        let input = "n1 c1 i5 ua t2020-01-01T00:00:00Z x1 y2\nn2 c1 t2020-01-01T01:00:00Z x3 y-4\n\
+                     n3 c2 x1 y2\nw1 c1 Nn1\nn4\nr1 c2 M\nw2 c3 Nn1\nw3 c1 Nn1\n";
+        let summary = |window| {
+            let mut reader = OPLReader::new(input.as_bytes());
+            ByChangeset::new(reader.objects())
+                .window(window)
+                .map(|c| (c.changeset_id, c.objects.len()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(1),
+            vec![
+                (Some(1), 2),
+                (Some(2), 1),
+                (Some(1), 1),
+                (None, 1),
+                (Some(2), 1),
+                (Some(3), 1),
+                (Some(1), 1)
+            ]
+        );
+        assert_eq!(
+            summary(2),
+            vec![
+                (Some(1), 3),
+                (Some(2), 2),
+                (None, 1),
+                (Some(3), 1),
+                (Some(1), 1)
+            ]
+        );
+        assert_eq!(
+            summary(4),
+            vec![(Some(1), 4), (Some(2), 2), (None, 1), (Some(3), 1)]
+        );
+
+        let mut reader = OPLReader::new(input.as_bytes());
+        let first = ByChangeset::new(reader.objects()).next().unwrap();
+        assert_eq!(first.bbox(), Some(BBox::new(-4., 1., 2., 3.)));
+        let changeset = first.to_changeset().unwrap();
+        assert_eq!(changeset.id, 1);
+        assert_eq!(changeset.uid, Some(5));
+        assert_eq!(changeset.user.as_deref(), Some("a"));
+        assert_eq!(changeset.num_changes, Some(2));
+        assert_eq!(changeset.created_at.unwrap().to_epoch_number(), 1577836800);
+        assert_eq!(changeset.closed_at.unwrap().to_epoch_number(), 1577840400);
+        assert_eq!(
+            ChangesetGroup::<::obj_types::StringOSMObj>::new(None).to_changeset(),
+            None
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Maps from old to new object ids, per object type. For renumbering files, or for uploads,
+//! where the API gives the placeholder (negative) ids of new objects real ids.
+//!
+//! ```
+//! use osmio::idmap::IdMap;
+//! use osmio::obj_types::{StringOSMObj, StringWay};
+//! use osmio::{OSMObj, OSMObjBase, OSMObjectType, Way};
+//!
+//! let mut ids = IdMap::new();
+//! ids.insert(OSMObjectType::Node, -1, 100);
+//! ids.insert(OSMObjectType::Way, -1, 200);
+//!
+//! let mut way: StringOSMObj = StringWay::new(-1, vec![-1, 5]).into();
+//! ids.apply(&mut way);
+//! assert_eq!(way.id(), 200);
+//! assert_eq!(way.as_way().unwrap().nodes(), [100, 5]);
+//! ```
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use utils::{read_varint, type_idx, write_varint};
+use {Member, OSMObj, OSMObjectType, ObjId, Relation, Way};
+
+const SAVED_MAGIC: &[u8; 8] = b"osmioids";
+const SAVED_VERSION: u8 = 1;
+
+/// Not mapped, in a `DenseIdMap`
+const UNMAPPED: ObjId = ObjId::MIN;
+
+/// A `DenseIdMap`'s arrays grow by this many ids at a time (8 MiB)
+const CHUNK: usize = 1 << 20;
+
+/// Roughly how many bytes an id takes in a `HashMap`
+const SPARSE_BYTES_PER_ID: u64 = 32;
+
+const TYPES: [OSMObjectType; 3] = [
+    OSMObjectType::Node,
+    OSMObjectType::Way,
+    OSMObjectType::Relation,
+];
+
+/// Change the id, way nodes & relation members of this object with `map`. Ids which aren't
+/// mapped are kept.
+fn apply_map(obj: &mut impl OSMObj, map: impl Fn(OSMObjectType, ObjId) -> Option<ObjId>) {
+    let otype = obj.object_type();
+    if let Some(new) = map(otype, obj.id()) {
+        obj.set_id(new);
+    }
+    if let Some(way) = obj.as_way_mut() {
+        if way
+            .nodes()
+            .iter()
+            .any(|&n| map(OSMObjectType::Node, n).is_some())
+        {
+            let nodes: Vec<ObjId> = way
+                .nodes()
+                .iter()
+                .map(|&n| map(OSMObjectType::Node, n).unwrap_or(n))
+                .collect();
+            way.set_nodes(nodes);
+        }
+    }
+    if let Some(rel) = obj.as_relation_mut() {
+        if rel.members().any(|m| map(m.mtype, m.id).is_some()) {
+            let members: Vec<Member> = rel
+                .members()
+                .map(|m| {
+                    let id = map(m.mtype, m.id).unwrap_or(m.id);
+                    Member::new(m.mtype, id, m.role.to_string())
+                })
+                .collect();
+            rel.set_members(members);
+        }
+    }
+}
+
+// The saved format: the magic bytes, a version byte, then for nodes, ways & relations: the
+// number of ids (u64, big endian), and each old & new id, sorted by the old id, as the
+// differences from the previous ones as zigzag varints.
+fn write_saved(
+    fp: &mut impl Write,
+    lens: [usize; 3],
+    iter: impl Fn(OSMObjectType) -> Vec<(ObjId, ObjId)>,
+) -> io::Result<()> {
+    fp.write_all(SAVED_MAGIC)?;
+    fp.write_u8(SAVED_VERSION)?;
+    for (&otype, &len) in TYPES.iter().zip(lens.iter()) {
+        fp.write_u64::<BigEndian>(len as u64)?;
+        let (mut last_old, mut last_new) = (0i64, 0i64);
+        for (old, new) in iter(otype) {
+            write_varint(fp, old.wrapping_sub(last_old))?;
+            write_varint(fp, new.wrapping_sub(last_new))?;
+            last_old = old;
+            last_new = new;
+        }
+    }
+    Ok(())
+}
+
+fn read_saved(
+    mut fp: impl Read,
+    mut insert: impl FnMut(OSMObjectType, ObjId, ObjId),
+) -> io::Result<()> {
+    let mut magic = [0; 8];
+    fp.read_exact(&mut magic)?;
+    if &magic != SAVED_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a saved IdMap",
+        ));
+    }
+    let version = fp.read_u8()?;
+    if version != SAVED_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported IdMap version {}", version),
+        ));
+    }
+    for &otype in TYPES.iter() {
+        let count = fp.read_u64::<BigEndian>()?;
+        let (mut old, mut new) = (0i64, 0i64);
+        for _ in 0..count {
+            old = old.wrapping_add(read_varint(&mut fp)?);
+            new = new.wrapping_add(read_varint(&mut fp)?);
+            insert(otype, old, new);
+        }
+    }
+    Ok(())
+}
+
+/// Old to new ids, in a `HashMap` per object type
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdMap {
+    maps: [HashMap<ObjId, ObjId>; 3],
+}
+
+impl IdMap {
+    pub fn new() -> Self {
+        IdMap::default()
+    }
+
+    /// Map this old id to `new`, returning the id it was mapped to before
+    pub fn insert(&mut self, otype: OSMObjectType, old: ObjId, new: ObjId) -> Option<ObjId> {
+        self.maps[type_idx(otype)].insert(old, new)
+    }
+
+    /// The new id of this old id
+    pub fn get(&self, otype: OSMObjectType, old: ObjId) -> Option<ObjId> {
+        self.maps[type_idx(otype)].get(&old).cloned()
+    }
+
+    /// Number of ids mapped, of all types
+    pub fn len(&self) -> usize {
+        self.maps.iter().map(|m| m.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Change the id, and the ids of the way nodes or relation members, of this object to the
+    /// new ones. Ids which aren't mapped are kept.
+    pub fn apply(&self, obj: &mut impl OSMObj) {
+        apply_map(obj, |otype, id| self.get(otype, id))
+    }
+
+    /// Save the ids to this file, to `load_from` later
+    pub fn save_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut fp = BufWriter::new(fs::File::create(path)?);
+        let lens = [self.maps[0].len(), self.maps[1].len(), self.maps[2].len()];
+        write_saved(&mut fp, lens, |otype| {
+            let mut ids: Vec<_> = self.maps[type_idx(otype)]
+                .iter()
+                .map(|(&old, &new)| (old, new))
+                .collect();
+            ids.sort();
+            ids
+        })?;
+        fp.flush()
+    }
+
+    /// Load the ids saved with `save_to` (of either kind of map)
+    pub fn load_from(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut map = IdMap::new();
+        read_saved(BufReader::new(fs::File::open(path)?), |otype, old, new| {
+            map.insert(otype, old, new);
+        })?;
+        Ok(map)
+    }
+}
+
+/// Old to new ids, in an array per object type indexed by the old id, for renumbering whole
+/// planets: 8 bytes for every id up to the largest. The arrays grow a chunk at a time, once
+/// there are enough ids to fill it. Until then, and for negative ids, or the odd id far beyond
+/// the rest, the ids are kept in a `HashMap`.
+#[derive(Debug, Clone, Default)]
+pub struct DenseIdMap {
+    maps: [Vec<ObjId>; 3],
+    /// Ids the arrays can't have (negative ones), or shouldn't (ones far beyond the others,
+    /// which would make them huge). The positive ones are after the end of their array.
+    outliers: IdMap,
+    /// Number of ids mapped, per type
+    lens: [usize; 3],
+}
+
+impl DenseIdMap {
+    pub fn new() -> Self {
+        DenseIdMap::default()
+    }
+
+    /// Map this old id to `new`, returning the id it was mapped to before
+    pub fn insert(&mut self, otype: OSMObjectType, old: ObjId, new: ObjId) -> Option<ObjId> {
+        let t = type_idx(otype);
+        if old >= 0 && old as usize >= self.maps[t].len() {
+            let len = (old as usize / CHUNK + 1) * CHUNK;
+            if (len as u64).saturating_mul(8) <= (self.lens[t] as u64 + 1) * SPARSE_BYTES_PER_ID {
+                self.grow(t, len);
+            }
+        }
+        let prev = match self.maps[t].get_mut(old as usize) {
+            Some(slot) if old >= 0 => Some(std::mem::replace(slot, new)).filter(|&p| p != UNMAPPED),
+            _ => self.outliers.insert(otype, old, new),
+        };
+        if prev.is_none() {
+            self.lens[t] += 1;
+        }
+        prev
+    }
+
+    /// Grow the array of this type to `len` ids, and move the outliers which now fit into it
+    fn grow(&mut self, t: usize, len: usize) {
+        let map = &mut self.maps[t];
+        // Exactly, as doubling a planet sized array could waste gigabytes
+        map.reserve_exact(len - map.len());
+        map.resize(len, UNMAPPED);
+        let outliers = &mut self.outliers.maps[t];
+        let moved: Vec<_> = outliers
+            .keys()
+            .filter(|&&old| old >= 0 && (old as usize) < len)
+            .cloned()
+            .collect();
+        for old in moved {
+            map[old as usize] = outliers.remove(&old).unwrap();
+        }
+    }
+
+    /// The new id of this old id
+    pub fn get(&self, otype: OSMObjectType, old: ObjId) -> Option<ObjId> {
+        match self.maps[type_idx(otype)].get(old as usize) {
+            Some(&new) if old >= 0 => Some(new).filter(|&new| new != UNMAPPED),
+            _ => self.outliers.get(otype, old),
+        }
+    }
+
+    /// Number of ids mapped, of all types
+    pub fn len(&self) -> usize {
+        self.lens.iter().sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Change the id, and the ids of the way nodes or relation members, of this object to the
+    /// new ones. Ids which aren't mapped are kept.
+    pub fn apply(&self, obj: &mut impl OSMObj) {
+        apply_map(obj, |otype, id| self.get(otype, id))
+    }
+
+    /// The mapped ids of this type, sorted by the old id
+    fn sorted(&self, otype: OSMObjectType) -> Vec<(ObjId, ObjId)> {
+        let mut ids: Vec<_> = self.outliers.maps[type_idx(otype)]
+            .iter()
+            .map(|(&old, &new)| (old, new))
+            .collect();
+        ids.sort();
+        let after = ids.split_off(ids.partition_point(|&(old, _)| old < 0));
+        ids.extend(
+            self.maps[type_idx(otype)]
+                .iter()
+                .enumerate()
+                .filter(|&(_, &new)| new != UNMAPPED)
+                .map(|(old, &new)| (old as ObjId, new)),
+        );
+        ids.extend(after);
+        ids
+    }
+
+    /// Save the ids to this file, to `load_from` later. The format is the same as `IdMap`'s
+    pub fn save_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut fp = BufWriter::new(fs::File::create(path)?);
+        let sorted: Vec<_> = TYPES.iter().map(|&t| self.sorted(t)).collect();
+        let lens = [sorted[0].len(), sorted[1].len(), sorted[2].len()];
+        write_saved(&mut fp, lens, |otype| sorted[type_idx(otype)].clone())?;
+        fp.flush()
+    }
+
+    /// Load the ids saved with `save_to` (of either kind of map)
+    pub fn load_from(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut map = DenseIdMap::new();
+        read_saved(BufReader::new(fs::File::open(path)?), |otype, old, new| {
+            map.insert(otype, old, new);
+        })?;
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj_types::{StringOSMObj, StringRelation};
+    use OSMObjBase;
+
+    #[test]
+    fn maps() {
+        let path = std::env::temp_dir().join(format!("osmio-idmap-{}", std::process::id()));
+        let mut sparse = IdMap::new();
+        let mut dense = DenseIdMap::new();
+        for &(otype, old, new) in &[
+            (OSMObjectType::Node, -1, 10),
+            (OSMObjectType::Node, 3, 11),
+            (OSMObjectType::Way, 3, 12),
+            (OSMObjectType::Relation, -2, 13),
+        ] {
+            assert_eq!(sparse.insert(otype, old, new), None);
+            assert_eq!(dense.insert(otype, old, new), None);
+        }
+        assert_eq!(dense.insert(OSMObjectType::Node, 3, 14), Some(11));
+        sparse.insert(OSMObjectType::Node, 3, 14);
+        assert_eq!(dense.len(), 4);
+        assert_eq!(sparse.len(), 4);
+
+        for (old, new) in vec![(-1, Some(10)), (3, Some(14)), (2, None), (100, None)] {
+            assert_eq!(sparse.get(OSMObjectType::Node, old), new);
+            assert_eq!(dense.get(OSMObjectType::Node, old), new);
+        }
+        assert_eq!(dense.get(OSMObjectType::Way, 3), Some(12));
+        assert_eq!(dense.get(OSMObjectType::Relation, 3), None);
+
+        let mut rel: StringOSMObj = StringRelation::new(
+            -2,
+            vec![
+                (OSMObjectType::Node, -1, "a"),
+                (OSMObjectType::Way, 3, "b"),
+                (OSMObjectType::Relation, 3, "c"),
+            ],
+        )
+        .into();
+        dense.apply(&mut rel);
+        assert_eq!(rel.id(), 13);
+        assert_eq!(
+            rel.as_relation().unwrap().members().collect::<Vec<_>>(),
+            vec![
+                Member::new(OSMObjectType::Node, 10, "a"),
+                Member::new(OSMObjectType::Way, 12, "b"),
+                Member::new(OSMObjectType::Relation, 3, "c"),
+            ]
+        );
+
+        dense.save_to(&path).unwrap();
+        assert_eq!(IdMap::load_from(&path).unwrap(), sparse);
+        sparse.save_to(&path).unwrap();
+        let loaded = DenseIdMap::load_from(&path).unwrap();
+        assert_eq!(loaded.len(), dense.len());
+        assert_eq!(
+            loaded.sorted(OSMObjectType::Node),
+            dense.sorted(OSMObjectType::Node)
+        );
+        assert_eq!(loaded.get(OSMObjectType::Relation, -2), Some(13));
+
+        // An id far beyond the others doesn't grow the array, until there are enough ids
+        let mut dense = DenseIdMap::new();
+        dense.insert(OSMObjectType::Way, 1 << 40, 1);
+        assert_eq!(dense.maps[1].len(), 0);
+        let far = 2 * CHUNK as ObjId;
+        for old in (0..CHUNK as ObjId / 2).chain(Some(far)) {
+            assert_eq!(dense.insert(OSMObjectType::Way, old, old + 10), None);
+        }
+        assert_eq!(dense.maps[1].len(), CHUNK);
+        assert_eq!(dense.outliers.len(), 2);
+        assert_eq!(dense.len(), CHUNK / 2 + 2);
+        assert_eq!(dense.get(OSMObjectType::Way, 5), Some(15));
+        assert_eq!(dense.get(OSMObjectType::Way, far), Some(far + 10));
+        assert_eq!(dense.insert(OSMObjectType::Way, far, 2), Some(far + 10));
+        let sorted = dense.sorted(OSMObjectType::Way);
+        assert_eq!(sorted.len(), dense.len());
+        assert_eq!(sorted[sorted.len() - 2..], [(far, 2), (1 << 40, 1)]);
+
+        std::fs::write(&path, b"nonsense").unwrap();
+        assert!(IdMap::load_from(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
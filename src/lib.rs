@@ -1,6 +1,7 @@
 //! Read and write OpenStreetMap fileformats
 //!
 extern crate byteorder;
+extern crate bzip2;
 extern crate chrono;
 extern crate flate2;
 extern crate protobuf;
@@ -8,8 +9,31 @@ extern crate quick_xml;
 extern crate xml as xml_rs;
 #[macro_use]
 extern crate derive_builder;
+#[cfg(feature = "parquet")]
+extern crate arrow_array;
+#[cfg(feature = "parquet")]
+extern crate arrow_schema;
+#[cfg(feature = "mmap")]
+extern crate libc;
+#[cfg(feature = "parquet")]
+extern crate parquet as parquet_rs;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "geojson")]
+extern crate serde_json;
+#[cfg(feature = "sled")]
+extern crate sled;
+#[cfg(feature = "compact")]
+extern crate smallvec;
+#[cfg(feature = "compact")]
+extern crate smol_str;
+#[cfg(feature = "xz")]
+extern crate xz2;
+#[cfg(feature = "zstd")]
+extern crate zstd;
 
-use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Debug;
@@ -20,28 +44,211 @@ use utils::{epoch_to_iso, iso_to_epoch};
 #[macro_use]
 pub mod utils;
 
+#[macro_use]
+pub mod macros;
+
+pub mod filter;
+pub mod idmap;
 pub mod nodestore;
+pub mod poly;
+pub mod sort;
+pub use sort::sort;
+pub mod stats;
+pub use stats::{file_bbox, stats};
 
+pub mod opl;
+pub mod osc;
+#[cfg(feature = "parquet")]
+pub mod parquet;
 pub mod pbf;
 pub mod xml;
-//pub mod opl;
-pub mod osc;
 
 pub mod obj_types;
 
+pub mod changeset;
+pub use changeset::{Changeset, Element};
+
+pub mod areas;
+pub mod conflate;
+pub mod diff;
+pub mod extract;
+
+pub mod formats;
+pub use formats::{
+    convert, convert_with_options, read_from_path, write_to_path, write_to_path_with_options,
+};
+
+pub mod graph;
+pub mod history;
+
+pub mod pipeline;
+pub mod split;
+pub mod transform;
+pub mod validate;
+
+#[cfg(feature = "geo")]
+pub mod geo;
+
 #[cfg(test)]
 mod tests;
 
 /// OSM id of object
 pub type ObjId = i64;
 
-/// Latitude
-pub type Lat = f32;
+/// Number of fixed-point units in one degree, OSM stores coordinates to 7 decimal places
+pub const COORD_PRECISION: i32 = 10_000_000;
+
+macro_rules! coord_type {
+    ($(#[$attr:meta])* $name:ident, $max:expr) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+        #[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+        #[cfg_attr(feature = "serde", serde(transparent))]
+        pub struct $name(i32);
+
+        impl $name {
+            /// From the fixed-point value, in units of 1e-7 degrees
+            pub fn from_inner(inner: i32) -> Self {
+                $name(inner)
+            }
+
+            /// The fixed-point value, in units of 1e-7 degrees
+            pub fn inner(&self) -> i32 {
+                self.0
+            }
+
+            /// From a value in degrees, rounded to the nearest 1e-7 degree
+            pub fn from_degrees(degrees: f64) -> Self {
+                $name((degrees * COORD_PRECISION as f64).round() as i32)
+            }
+
+            /// The value in degrees
+            pub fn degrees(&self) -> f64 {
+                self.0 as f64 / COORD_PRECISION as f64
+            }
+
+            /// From a value in degrees, `None` if it's out of range or NaN
+            pub fn try_from_degrees(degrees: f64) -> Option<Self> {
+                if degrees.abs() <= $max {
+                    Some($name::from_degrees(degrees))
+                } else {
+                    None
+                }
+            }
+
+            /// From a value in degrees, clamped to the range. `None` if it's NaN
+            pub fn from_degrees_clamped(degrees: f64) -> Option<Self> {
+                if degrees.is_nan() {
+                    None
+                } else {
+                    Some($name::from_degrees(degrees.max(-$max).min($max)))
+                }
+            }
+
+            /// True iff this is in range
+            pub fn is_valid(&self) -> bool {
+                (self.0 as i64).abs() <= $max as i64 * COORD_PRECISION as i64
+            }
+        }
+
+        impl From<f64> for $name {
+            fn from(degrees: f64) -> Self {
+                $name::from_degrees(degrees)
+            }
+        }
+
+        impl From<f32> for $name {
+            fn from(degrees: f32) -> Self {
+                $name::from_degrees(degrees as f64)
+            }
+        }
+
+        impl From<$name> for f64 {
+            fn from(val: $name) -> f64 {
+                val.degrees()
+            }
+        }
+
+        /// Printed exactly, in degrees, without trailing zeros
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let abs = (self.0 as i64).abs();
+                if self.0 < 0 {
+                    write!(f, "-")?;
+                }
+                write!(f, "{}", abs / COORD_PRECISION as i64)?;
+                let frac = abs % COORD_PRECISION as i64;
+                if frac != 0 {
+                    let frac = format!("{:07}", frac);
+                    write!(f, ".{}", frac.trim_end_matches('0'))?;
+                }
+                Ok(())
+            }
+        }
+
+        impl std::str::FromStr for $name {
+            type Err = std::num::ParseFloatError;
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse::<f64>().map($name::from_degrees)
+            }
+        }
+    };
+}
+
+coord_type!(
+    /// Latitude, stored as a fixed-point number of 1e-7 degrees
+    Lat,
+    90.
+);
+coord_type!(
+    /// Longitude, stored as a fixed-point number of 1e-7 degrees
+    Lon,
+    180.
+);
+
+/// What to do with coordinates which are out of range, see `Node::try_set_lat_lon_with`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoordPolicy {
+    /// Return an error
+    Reject,
+    /// Move them to the nearest valid value. NaN is still an error
+    Clamp,
+}
+
+impl Default for CoordPolicy {
+    fn default() -> Self {
+        CoordPolicy::Reject
+    }
+}
 
-/// Longitude
-pub type Lon = f32;
+/// A location with a latitude outside ±90, a longitude outside ±180, or NaN
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidLocation {
+    pub lat: f64,
+    pub lon: f64,
+}
+impl std::fmt::Display for InvalidLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "invalid location lat {} lon {}", self.lat, self.lon)
+    }
+}
+impl std::error::Error for InvalidLocation {}
+
+/// The location from degrees, following `policy` for invalid coordinates
+pub fn checked_lat_lon(
+    lat: f64,
+    lon: f64,
+    policy: CoordPolicy,
+) -> Result<(Lat, Lon), InvalidLocation> {
+    let loc = match policy {
+        CoordPolicy::Reject => Lat::try_from_degrees(lat).zip(Lon::try_from_degrees(lon)),
+        CoordPolicy::Clamp => Lat::from_degrees_clamped(lat).zip(Lon::from_degrees_clamped(lon)),
+    };
+    loc.ok_or(InvalidLocation { lat: lat, lon: lon })
+}
 
-#[derive(Debug, Clone, Eq, Ord)]
+#[derive(Debug, Clone, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum TimestampFormat {
     ISOString(String),
     EpochNunber(i64),
@@ -51,16 +258,34 @@ impl TimestampFormat {
     pub fn to_iso_string(&self) -> String {
         match self {
             &TimestampFormat::ISOString(ref s) => s.clone(),
-            &TimestampFormat::EpochNunber(ref t) => epoch_to_iso(*t as i32),
+            &TimestampFormat::EpochNunber(ref t) => {
+                epoch_to_iso(*t).unwrap_or_else(|| t.to_string())
+            }
         }
     }
 
     pub fn to_epoch_number(&self) -> i64 {
         match self {
-            &TimestampFormat::ISOString(ref s) => iso_to_epoch(s) as i64,
+            &TimestampFormat::ISOString(ref s) => iso_to_epoch(s),
             &TimestampFormat::EpochNunber(t) => t,
         }
     }
+
+    /// OSM timestamps have whole second precision, so anything smaller is dropped
+    pub fn from_datetime<Tz: chrono::TimeZone>(dt: &chrono::DateTime<Tz>) -> Self {
+        TimestampFormat::EpochNunber(dt.timestamp())
+    }
+
+    /// This timestamp as a `chrono` UTC date. `None` if it's an invalid ISO string, or out of
+    /// range
+    pub fn to_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        match self {
+            &TimestampFormat::ISOString(ref s) => chrono::DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|d| d.with_timezone(&chrono::Utc)),
+            &TimestampFormat::EpochNunber(t) => chrono::DateTime::from_timestamp(t, 0),
+        }
+    }
 }
 
 impl<T> From<T> for TimestampFormat
@@ -89,25 +314,151 @@ impl fmt::Display for TimestampFormat {
     }
 }
 
-impl std::cmp::PartialOrd for TimestampFormat {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+impl TimestampFormat {
+    /// What timestamps are compared by: the epoch number, then the string of invalid ISO
+    /// strings, which would otherwise all be equal to the epoch number 0.
+    fn cmp_key(&self) -> (i64, Option<&str>) {
+        match self {
+            TimestampFormat::ISOString(s) => match chrono::DateTime::parse_from_rfc3339(s) {
+                Ok(d) => (d.timestamp(), None),
+                Err(_) => (0, Some(s)),
+            },
+            TimestampFormat::EpochNunber(t) => (*t, None),
+        }
+    }
+}
+
+/// Ordered by the time, so an ISO string & an epoch number can be equal
+impl std::cmp::Ord for TimestampFormat {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match (self, other) {
-            (TimestampFormat::ISOString(a), TimestampFormat::ISOString(b)) => a.partial_cmp(b),
-            (TimestampFormat::EpochNunber(a), TimestampFormat::EpochNunber(b)) => a.partial_cmp(b),
-            (a, b) => a.to_epoch_number().partial_cmp(&b.to_epoch_number()),
+            (TimestampFormat::EpochNunber(a), TimestampFormat::EpochNunber(b)) => a.cmp(b),
+            (a, b) => a.cmp_key().cmp(&b.cmp_key()),
         }
     }
 }
+impl std::cmp::PartialOrd for TimestampFormat {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+/// Hashed as the epoch number, since an ISO string & an epoch number can be equal
+impl std::hash::Hash for TimestampFormat {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.to_epoch_number().hash(state)
+    }
+}
 impl std::cmp::PartialEq for TimestampFormat {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (TimestampFormat::ISOString(a), TimestampFormat::ISOString(b)) => a.eq(b),
-            (TimestampFormat::EpochNunber(a), TimestampFormat::EpochNunber(b)) => a.eq(b),
-            (a, b) => a.to_epoch_number().eq(&b.to_epoch_number()),
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+/// What `OSMObjBase::merge_tags` does with a key the object already has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// Change it to the new value
+    Overwrite,
+    /// Keep the current value
+    Keep,
+}
+
+impl Default for OverwritePolicy {
+    fn default() -> Self {
+        OverwritePolicy::Overwrite
+    }
+}
+
+/// One tag of an object, a key & a value. `OSMObjBase::tag_items` iterates over these instead of
+/// `(key, value)` tuples, and tuples convert to & from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tag<'a> {
+    pub key: &'a str,
+    pub value: &'a str,
+}
+
+impl<'a> Tag<'a> {
+    pub fn new(key: &'a str, value: &'a str) -> Self {
+        Tag {
+            key: key,
+            value: value,
+        }
+    }
+
+    /// True iff the value is `yes`, `true` or `1`, what OSM uses for true
+    pub fn is_yes(&self) -> bool {
+        match self.value {
+            "yes" | "true" | "1" => true,
+            _ => false,
         }
     }
+
+    /// True iff the value is `no`, `false` or `0`
+    pub fn is_no(&self) -> bool {
+        match self.value {
+            "no" | "false" | "0" => true,
+            _ => false,
+        }
+    }
+
+    /// Parse the value, e.g. `tag.parse::<u32>()` for `lanes`
+    pub fn parse<T: std::str::FromStr>(&self) -> Result<T, T::Err> {
+        self.value.parse()
+    }
+}
+
+impl<'a> From<(&'a str, &'a str)> for Tag<'a> {
+    fn from((key, value): (&'a str, &'a str)) -> Self {
+        Tag::new(key, value)
+    }
+}
+
+impl<'a> From<Tag<'a>> for (&'a str, &'a str) {
+    fn from(tag: Tag<'a>) -> Self {
+        (tag.key, tag.value)
+    }
+}
+
+impl<'a> fmt::Display for Tag<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.key, self.value)
+    }
+}
+
+/// Iterator over the `(key, value)` tags of an object which keeps them in a slice, without the
+/// allocation of the boxed `OSMObjBase::tags`. Returned by `tags_iter` on the object types.
+#[derive(Debug, Clone)]
+pub struct TagIter<'a, S: 'a> {
+    inner: std::slice::Iter<'a, (S, S)>,
+}
+
+impl<'a, S> TagIter<'a, S> {
+    pub fn new(tags: &'a [(S, S)]) -> Self {
+        TagIter { inner: tags.iter() }
+    }
+}
+
+impl<'a, S: std::ops::Deref<Target = str>> Iterator for TagIter<'a, S> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        self.inner.next().map(|(k, v)| (&**k, &**v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, S: std::ops::Deref<Target = str>> DoubleEndedIterator for TagIter<'a, S> {
+    fn next_back(&mut self) -> Option<(&'a str, &'a str)> {
+        self.inner.next_back().map(|(k, v)| (&**k, &**v))
+    }
 }
 
+impl<'a, S: std::ops::Deref<Target = str>> ExactSizeIterator for TagIter<'a, S> {}
+impl<'a, S: std::ops::Deref<Target = str>> std::iter::FusedIterator for TagIter<'a, S> {}
+
 /// The basic metadata fields all OSM objects share
 pub trait OSMObjBase: PartialEq + Debug + Clone {
     fn id(&self) -> ObjId;
@@ -116,16 +467,29 @@ pub trait OSMObjBase: PartialEq + Debug + Clone {
     fn set_version(&mut self, val: impl Into<Option<u32>>);
     fn deleted(&self) -> bool;
     fn set_deleted(&mut self, val: bool);
-    fn changeset_id(&self) -> Option<u32>;
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>);
+    fn changeset_id(&self) -> Option<u64>;
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>);
     fn timestamp(&self) -> &Option<TimestampFormat>;
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>);
-    fn uid(&self) -> Option<u32>;
-    fn set_uid(&mut self, val: impl Into<Option<u32>>);
+    fn uid(&self) -> Option<u64>;
+    fn set_uid(&mut self, val: impl Into<Option<u64>>);
     fn user(&self) -> Option<&str>;
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>);
 
+    /// The tags of this object, in the order they were read or set. In hot loops, the unboxed
+    /// `tags_iter` of the object types saves an allocation per object.
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a>;
+    /// The tags of this object sorted by key, for stable output regardless of insertion order
+    fn tags_sorted(&self) -> Vec<(&str, &str)> {
+        let mut tags: Vec<_> = self.tags().collect();
+        tags.sort();
+        tags
+    }
+    /// The tags as `Tag`s, e.g. `obj.tag_items().filter(|t| t.is_yes())`. (`TagIter`s convert
+    /// with `.map(Tag::from)`)
+    fn tag_items<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = Tag<'a>> + 'a> {
+        Box::new(self.tags().map(Tag::from))
+    }
     fn tag(&self, key: impl AsRef<str>) -> Option<&str>;
     fn has_tag(&self, key: impl AsRef<str>) -> bool {
         self.tag(key).is_some()
@@ -146,6 +510,65 @@ pub trait OSMObjBase: PartialEq + Debug + Clone {
     fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>);
     fn unset_tag(&mut self, key: impl AsRef<str>);
 
+    /// Replace all the tags with these `(key, value)` pairs
+    fn set_tags<K: AsRef<str>, V: Into<String>>(&mut self, tags: impl IntoIterator<Item = (K, V)>) {
+        let old_keys: Vec<String> = self.tags().map(|(k, _)| k.to_string()).collect();
+        for k in old_keys {
+            self.unset_tag(k);
+        }
+        for (k, v) in tags {
+            self.set_tag(k, v);
+        }
+    }
+
+    /// Keep only the tags for which `pred(key, value)` is true
+    fn retain_tags(&mut self, mut pred: impl FnMut(&str, &str) -> bool) {
+        let remove: Vec<String> = self
+            .tags()
+            .filter(|(k, v)| !pred(k, v))
+            .map(|(k, _)| k.to_string())
+            .collect();
+        for k in remove {
+            self.unset_tag(k);
+        }
+    }
+
+    /// Change the key of the `old` tag to `new`, replacing any `new` tag. Returns false (and
+    /// changes nothing) if there's no `old` tag.
+    fn rename_tag_key(&mut self, old: impl AsRef<str>, new: impl AsRef<str>) -> bool {
+        let (old, new) = (old.as_ref(), new.as_ref());
+        let value = match self.tag(old) {
+            None => return false,
+            Some(v) => v.to_string(),
+        };
+        if old != new {
+            self.unset_tag(old);
+            self.set_tag(new, value);
+        }
+        true
+    }
+
+    /// Add these `(key, value)` pairs to the tags. `policy` decides what happens to keys the
+    /// object already has.
+    fn merge_tags<K: AsRef<str>, V: Into<String>>(
+        &mut self,
+        tags: impl IntoIterator<Item = (K, V)>,
+        policy: OverwritePolicy,
+    ) {
+        for (k, v) in tags {
+            if policy == OverwritePolicy::Overwrite || !self.has_tag(k.as_ref()) {
+                self.set_tag(k, v);
+            }
+        }
+    }
+
+    /// True iff the objects are the same, ignoring the metadata (version, changeset, user &
+    /// timestamp). Tags are compared in any order, along with the node location, way nodes, or
+    /// relation members.
+    fn semantic_eq(&self, other: &Self) -> bool {
+        obj_types::base_semantic_eq(self, other)
+    }
+
     fn strip_metadata(&mut self) {
         self.set_uid(None);
         self.set_user(None);
@@ -160,7 +583,47 @@ pub trait Node: OSMObjBase {
         self.lat_lon().is_some()
     }
 
+    /// The latitude in degrees
+    fn lat(&self) -> Option<f64> {
+        self.lat_lon().map(|(lat, _)| lat.degrees())
+    }
+    /// The longitude in degrees
+    fn lon(&self) -> Option<f64> {
+        self.lat_lon().map(|(_, lon)| lon.degrees())
+    }
+    /// The location in degrees, as `(lat, lon)`
+    fn lat_lon_f64(&self) -> Option<(f64, f64)> {
+        self.lat_lon()
+            .map(|(lat, lon)| (lat.degrees(), lon.degrees()))
+    }
+
     fn set_lat_lon(&mut self, loc: impl Into<Option<(Lat, Lon)>>);
+
+    /// Set the location from degrees, or return an error (and leave the location alone) if the
+    /// latitude isn't in ±90, the longitude isn't in ±180, or either is NaN. `set_lat_lon`
+    /// doesn't check, and NaN becomes 0.
+    fn try_set_lat_lon(&mut self, lat: f64, lon: f64) -> Result<(), InvalidLocation> {
+        self.try_set_lat_lon_with(lat, lon, CoordPolicy::Reject)
+    }
+
+    /// `try_set_lat_lon`, with a choice of what to do with out of range coordinates
+    fn try_set_lat_lon_with(
+        &mut self,
+        lat: f64,
+        lon: f64,
+        policy: CoordPolicy,
+    ) -> Result<(), InvalidLocation> {
+        let loc = checked_lat_lon(lat, lon, policy)?;
+        self.set_lat_lon(loc);
+        Ok(())
+    }
+
+    /// This node as one line of OPL, e.g. for logging or tests
+    fn to_opl_line(&self) -> String {
+        let mut line = String::new();
+        opl::encode_node(&mut line, self);
+        line
+    }
 }
 
 /// A Way
@@ -169,20 +632,217 @@ pub trait Way: OSMObjBase {
     fn num_nodes(&self) -> usize;
     fn node(&self, idx: usize) -> Option<ObjId>;
     fn set_nodes(&mut self, nodes: impl IntoIterator<Item = impl Into<ObjId>>);
+
+    // The editing methods have defaults which rebuild the node list with `set_nodes`, the
+    // object types in osmio (and `impl_way!`) change it in place.
+
+    /// Add a node to the end
+    fn push_node(&mut self, id: impl Into<ObjId>) {
+        let mut nodes = self.nodes().to_vec();
+        nodes.push(id.into());
+        self.set_nodes(nodes);
+    }
+
+    /// Insert a node at `idx`, moving the later nodes along. Panics if `idx > num_nodes()`
+    fn insert_node(&mut self, idx: usize, id: impl Into<ObjId>) {
+        let mut nodes = self.nodes().to_vec();
+        nodes.insert(idx, id.into());
+        self.set_nodes(nodes);
+    }
+
+    /// Remove & return the node at `idx`, `None` if there isn't one
+    fn remove_node(&mut self, idx: usize) -> Option<ObjId> {
+        if idx >= self.num_nodes() {
+            return None;
+        }
+        let mut nodes = self.nodes().to_vec();
+        let removed = nodes.remove(idx);
+        self.set_nodes(nodes);
+        Some(removed)
+    }
+
+    /// Reverse the order of the nodes
+    fn reverse(&mut self) {
+        let mut nodes = self.nodes().to_vec();
+        nodes.reverse();
+        self.set_nodes(nodes);
+    }
+
+    /// Replace every `old` node with `new`, returning how many were replaced (e.g. 2 for the
+    /// first node of a closed way)
+    fn replace_node(&mut self, old: ObjId, new: ObjId) -> usize {
+        let count = self.nodes().iter().filter(|&&n| n == old).count();
+        if count > 0 {
+            let nodes: Vec<ObjId> = self
+                .nodes()
+                .iter()
+                .map(|&n| if n == old { new } else { n })
+                .collect();
+            self.set_nodes(nodes);
+        }
+        count
+    }
+
+    /// This way as one line of OPL, e.g. for logging or tests
+    fn to_opl_line(&self) -> String {
+        let mut line = String::new();
+        opl::encode_way(&mut line, self);
+        line
+    }
+
+    /// The bbox of the nodes of this way whose location is in `locations`. `None` if none are
+    fn bbox(&self, locations: &impl nodestore::NodeLocationStore) -> Option<BBox> {
+        BBox::around(self.nodes().iter().filter_map(|&id| locations.location(id)))
+    }
+}
+
+/// A member of a relation. `R` is the role, often a `&str` when borrowed from a relation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct Member<R = String> {
+    pub mtype: OSMObjectType,
+    pub id: ObjId,
+    pub role: R,
+}
+
+impl<R> Member<R> {
+    pub fn new(mtype: OSMObjectType, id: ObjId, role: R) -> Self {
+        Member {
+            mtype: mtype,
+            id: id,
+            role: role,
+        }
+    }
+}
+
+impl<'a> Member<&'a str> {
+    /// Convert to a member which owns its role
+    pub fn to_owned(&self) -> Member<String> {
+        Member::new(self.mtype, self.id, self.role.to_string())
+    }
+}
+
+/// The old tuple form of a member
+impl<R> From<(OSMObjectType, ObjId, R)> for Member<R> {
+    fn from((mtype, id, role): (OSMObjectType, ObjId, R)) -> Self {
+        Member::new(mtype, id, role)
+    }
+}
+
+impl<R> From<Member<R>> for (OSMObjectType, ObjId, R) {
+    fn from(m: Member<R>) -> Self {
+        (m.mtype, m.id, m.role)
+    }
 }
 
 /// A Relation
 pub trait Relation: OSMObjBase {
-    fn members<'a>(
+    fn members<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = Member<&'a str>> + 'a>;
+
+    /// The bbox of the node members of this relation whose location is in `locations`. `None`
+    /// if none are. See `bbox_with_ways` to include the member ways.
+    fn bbox(&self, locations: &impl nodestore::NodeLocationStore) -> Option<BBox> {
+        BBox::around(
+            self.members()
+                .filter(|m| m.mtype == OSMObjectType::Node)
+                .filter_map(|m| locations.location(m.id)),
+        )
+    }
+
+    /// The bbox of the node members, and of the nodes of the member ways in `ways`, whose
+    /// location is in `locations`. Member relations aren't included.
+    fn bbox_with_ways(
+        &self,
+        locations: &impl nodestore::NodeLocationStore,
+        ways: &nodestore::WayStore,
+    ) -> Option<BBox> {
+        let nodes = self.members().flat_map(|m| match m.mtype {
+            OSMObjectType::Node => vec![m.id],
+            OSMObjectType::Way => ways.get(m.id).unwrap_or_default(),
+            OSMObjectType::Relation => vec![],
+        });
+        BBox::around(nodes.filter_map(|id| locations.location(id)))
+    }
+
+    /// Replace the members. Accepts `Member`s, or `(OSMObjectType, ObjId, role)` tuples.
+    fn set_members<M, R>(&mut self, members: impl IntoIterator<Item = M>)
+    where
+        M: Into<Member<R>>,
+        R: Into<String>;
+
+    // Like `Way`, the editing methods have defaults which rebuild the members with
+    // `set_members`, and the object types in osmio change them in place.
+
+    /// Add a member to the end. Accepts a `Member`, or a `(OSMObjectType, ObjId, role)` tuple.
+    fn push_member<R: Into<String>>(&mut self, member: impl Into<Member<R>>) {
+        let mut members: Vec<Member> = self.members().map(|m| m.to_owned()).collect();
+        let m = member.into();
+        members.push(Member::new(m.mtype, m.id, m.role.into()));
+        self.set_members(members);
+    }
+
+    /// Remove & return the member at `idx`, `None` if there isn't one
+    fn remove_member(&mut self, idx: usize) -> Option<Member> {
+        let mut members: Vec<Member> = self.members().map(|m| m.to_owned()).collect();
+        if idx >= members.len() {
+            return None;
+        }
+        let removed = members.remove(idx);
+        self.set_members(members);
+        Some(removed)
+    }
+
+    /// Keep only the members for which `pred` is true
+    fn retain_members(&mut self, mut pred: impl FnMut(&Member<&str>) -> bool) {
+        let members: Vec<Member> = self
+            .members()
+            .filter(|m| pred(m))
+            .map(|m| m.to_owned())
+            .collect();
+        self.set_members(members);
+    }
+
+    /// Change the id of every member of this type & id to `new`, keeping the roles. Returns how
+    /// many were changed.
+    fn replace_member_id(&mut self, mtype: OSMObjectType, old: ObjId, new: ObjId) -> usize {
+        let count = self
+            .members()
+            .filter(|m| m.mtype == mtype && m.id == old)
+            .count();
+        if count > 0 {
+            let members: Vec<Member> = self
+                .members()
+                .map(|m| {
+                    let id = if m.mtype == mtype && m.id == old {
+                        new
+                    } else {
+                        m.id
+                    };
+                    Member::new(m.mtype, id, m.role.to_string())
+                })
+                .collect();
+            self.set_members(members);
+        }
+        count
+    }
+
+    #[deprecated(note = "members() returns Member structs now")]
+    fn member_tuples<'a>(
         &'a self,
-    ) -> Box<dyn ExactSizeIterator<Item = (OSMObjectType, ObjId, &'a str)> + 'a>;
-    fn set_members(
-        &mut self,
-        members: impl IntoIterator<Item = (OSMObjectType, ObjId, impl Into<String>)>,
-    );
+    ) -> Box<dyn ExactSizeIterator<Item = (OSMObjectType, ObjId, &'a str)> + 'a> {
+        Box::new(self.members().map(|m| m.into()))
+    }
+
+    /// This relation as one line of OPL, e.g. for logging or tests
+    fn to_opl_line(&self) -> String {
+        let mut line = String::new();
+        opl::encode_relation(&mut line, self);
+        line
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum OSMObjectType {
     Node,
     Way,
@@ -263,6 +923,243 @@ pub trait OSMObj: OSMObjBase {
     fn is_relation(&self) -> bool {
         self.object_type() == OSMObjectType::Relation
     }
+
+    /// Call the function for this object type, saving a `match` with `into_node().unwrap()` etc.
+    fn visit<T>(
+        self,
+        on_node: impl FnOnce(Self::Node) -> T,
+        on_way: impl FnOnce(Self::Way) -> T,
+        on_relation: impl FnOnce(Self::Relation) -> T,
+    ) -> T {
+        match self.object_type() {
+            OSMObjectType::Node => on_node(self.into_node().unwrap()),
+            OSMObjectType::Way => on_way(self.into_way().unwrap()),
+            OSMObjectType::Relation => on_relation(self.into_relation().unwrap()),
+        }
+    }
+
+    /// `visit`, by reference
+    fn visit_ref<'a, T>(
+        &'a self,
+        on_node: impl FnOnce(&'a Self::Node) -> T,
+        on_way: impl FnOnce(&'a Self::Way) -> T,
+        on_relation: impl FnOnce(&'a Self::Relation) -> T,
+    ) -> T {
+        match self.object_type() {
+            OSMObjectType::Node => on_node(self.as_node().unwrap()),
+            OSMObjectType::Way => on_way(self.as_way().unwrap()),
+            OSMObjectType::Relation => on_relation(self.as_relation().unwrap()),
+        }
+    }
+
+    /// This object as one line of OPL, e.g. for logging or tests. This is also the `Display`
+    /// of the object types in osmio.
+    fn to_opl_line(&self) -> String {
+        self.visit_ref(
+            |n| n.to_opl_line(),
+            |w| w.to_opl_line(),
+            |r| r.to_opl_line(),
+        )
+    }
+}
+
+/// An area, between 2 latitudes & longitudes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct BBox {
+    pub min_lat: Lat,
+    pub min_lon: Lon,
+    pub max_lat: Lat,
+    pub max_lon: Lon,
+}
+
+impl BBox {
+    pub fn new(
+        min_lat: impl Into<Lat>,
+        min_lon: impl Into<Lon>,
+        max_lat: impl Into<Lat>,
+        max_lon: impl Into<Lon>,
+    ) -> Self {
+        BBox {
+            min_lat: min_lat.into(),
+            min_lon: min_lon.into(),
+            max_lat: max_lat.into(),
+            max_lon: max_lon.into(),
+        }
+    }
+
+    /// The box of just this location
+    pub fn point(lat: Lat, lon: Lon) -> Self {
+        BBox::new(lat, lon, lat, lon)
+    }
+
+    /// The smallest box around all these locations, `None` if there are none
+    pub fn around(locations: impl IntoIterator<Item = (Lat, Lon)>) -> Option<Self> {
+        let mut locations = locations.into_iter();
+        let (lat, lon) = locations.next()?;
+        Some(locations.fold(BBox::point(lat, lon), |b, (lat, lon)| b.extended(lat, lon)))
+    }
+
+    /// True iff this location is inside this box (or on its edge)
+    pub fn contains(&self, lat: Lat, lon: Lon) -> bool {
+        self.min_lat <= lat && lat <= self.max_lat && self.min_lon <= lon && lon <= self.max_lon
+    }
+
+    /// The smallest box around this one, and this location
+    pub fn extended(&self, lat: Lat, lon: Lon) -> Self {
+        BBox::new(
+            self.min_lat.min(lat),
+            self.min_lon.min(lon),
+            self.max_lat.max(lat),
+            self.max_lon.max(lon),
+        )
+    }
+
+    /// The smallest box around this one, and `other`
+    pub fn union(&self, other: &BBox) -> Self {
+        self.extended(other.min_lat, other.min_lon)
+            .extended(other.max_lat, other.max_lon)
+    }
+}
+
+/// One field of a file header, see `OSMWriter::set_header`
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderField {
+    /// The area the file covers
+    Bbox(BBox),
+    /// The program which wrote the file. Writers default to `osmio/<version>`
+    Generator(String),
+    /// How up to date the data is
+    ReplicationTimestamp(TimestampFormat),
+    /// The sequence number of the replication diff this data is up to date with
+    ReplicationSequenceNumber(u64),
+    /// Where to get replication diffs to update this data
+    ReplicationBaseUrl(String),
+    /// Any other field, as a key & value. e.g. an attribute of the root element in XML
+    Other(String, String),
+}
+
+/// The header of a file. Readers can return it, and writers can store it (see
+/// `OSMWriter::set_headers`), so it's kept when converting between formats.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Header {
+    /// The area the file covers
+    pub bbox: Option<BBox>,
+    /// The program which wrote the file
+    pub generator: Option<String>,
+    /// How up to date the data is, i.e. the replication timestamp
+    pub timestamp: Option<TimestampFormat>,
+    pub replication_sequence_number: Option<u64>,
+    pub replication_base_url: Option<String>,
+    /// Features a program needs to understand to read the file, e.g. `DenseNodes` in PBF
+    pub required_features: Vec<String>,
+    /// Features the file has, which a program can ignore, e.g. `Sort.Type_then_ID` in PBF
+    pub optional_features: Vec<String>,
+    /// Any other fields, see `HeaderField::Other`
+    pub other: Vec<(String, String)>,
+}
+
+impl Header {
+    /// The fields which are set, to pass to `OSMWriter::set_header`. The features aren't
+    /// included, since they depend on how a file is written, not what's in it.
+    pub fn fields(&self) -> Vec<HeaderField> {
+        let mut fields = Vec::new();
+        if let Some(bbox) = self.bbox {
+            fields.push(HeaderField::Bbox(bbox));
+        }
+        if let Some(ref generator) = self.generator {
+            fields.push(HeaderField::Generator(generator.clone()));
+        }
+        if let Some(ref timestamp) = self.timestamp {
+            fields.push(HeaderField::ReplicationTimestamp(timestamp.clone()));
+        }
+        if let Some(seq) = self.replication_sequence_number {
+            fields.push(HeaderField::ReplicationSequenceNumber(seq));
+        }
+        if let Some(ref url) = self.replication_base_url {
+            fields.push(HeaderField::ReplicationBaseUrl(url.clone()));
+        }
+        fields.extend(
+            self.other
+                .iter()
+                .map(|(k, v)| HeaderField::Other(k.clone(), v.clone())),
+        );
+        fields
+    }
+
+    /// Set one field, replacing the current value
+    pub fn set(&mut self, field: HeaderField) {
+        match field {
+            HeaderField::Bbox(bbox) => self.bbox = Some(bbox),
+            HeaderField::Generator(generator) => self.generator = Some(generator),
+            HeaderField::ReplicationTimestamp(t) => self.timestamp = Some(t),
+            HeaderField::ReplicationSequenceNumber(seq) => {
+                self.replication_sequence_number = Some(seq)
+            }
+            HeaderField::ReplicationBaseUrl(url) => self.replication_base_url = Some(url),
+            HeaderField::Other(key, value) => {
+                self.other.retain(|(k, _)| *k != key);
+                self.other.push((key, value));
+            }
+        }
+    }
+}
+
+/// Options for creating a reader, see `OSMReader::new_with_options`. Not all formats use all the
+/// options.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReaderOptions {
+    /// How many threads to decode the file with (PBF only). Default: 1
+    pub threads: usize,
+    /// If true, invalid objects are errors. If false, they're skipped (XML, OSC & OPL). Default:
+    /// true
+    pub strict: bool,
+    /// See `OSMReader::set_sorted_assumption`. Default: false
+    pub sorted_assumption: bool,
+    /// If false, the changeset, timestamp, uid & user of objects are not read. Default: true
+    pub decode_metadata: bool,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        ReaderOptions {
+            threads: 1,
+            strict: true,
+            sorted_assumption: false,
+            decode_metadata: true,
+        }
+    }
+}
+
+/// Options for creating a writer, see `write_to_path_with_options`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriterOptions {
+    /// How many bytes to collect before writing them to the file. Compressed files also collect
+    /// this much before compressing it. Default: 1 MiB
+    pub buffer_size: usize,
+    /// Add to the end of the file (creating it if need be), rather than replacing it. Only OPL
+    /// files can be appended to, as the other formats have a footer. Compressed files get
+    /// another compressed stream, which `read_from_path` reads as one. Default: false
+    pub append: bool,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        WriterOptions {
+            buffer_size: 1024 * 1024,
+            append: false,
+        }
+    }
+}
+
+impl ReaderOptions {
+    /// Remove the metadata from this object, if `decode_metadata` is off
+    pub(crate) fn apply_decode_metadata(&self, obj: &mut impl OSMObjBase) {
+        if !self.decode_metadata {
+            obj.strip_metadata();
+            obj.set_timestamp(None);
+        }
+    }
 }
 
 /// A Generic reader that reads OSM objects
@@ -272,6 +1169,16 @@ pub trait OSMReader {
 
     fn new(Self::R) -> Self;
 
+    /// Create a reader with these options
+    fn new_with_options(reader: Self::R, options: ReaderOptions) -> Self
+    where
+        Self: Sized,
+    {
+        let mut reader = Self::new(reader);
+        reader.set_sorted_assumption(options.sorted_assumption);
+        reader
+    }
+
     #[allow(unused_variables)]
     fn set_sorted_assumption(&mut self, sorted_assumption: bool) {}
     fn get_sorted_assumption(&mut self) -> bool {
@@ -290,8 +1197,51 @@ pub trait OSMReader {
 
     fn inner(&self) -> &Self::R;
 
+    /// The file header, if this format has one (PBF & XML). This can read the start of the
+    /// file, but no objects are lost. The readers in osmio panic if the header can't be
+    /// decoded, use `try_header` to handle those errors.
+    fn header(&mut self) -> Option<Header> {
+        self.try_header().unwrap()
+    }
+
+    /// The file header, or an error if it can't be decoded. `Ok(None)` by default
+    fn try_header(&mut self) -> Result<Option<Header>, ReadError> {
+        Ok(None)
+    }
+
+    /// The area this file covers, from the header
+    fn bounds(&mut self) -> Option<BBox> {
+        self.header().and_then(|h| h.bbox)
+    }
+
+    /// The next object. The readers in osmio panic if the file can't be decoded, use `try_next`
+    /// to handle those errors.
     fn next(&mut self) -> Option<Self::Obj>;
 
+    /// The next object, `Ok(None)` at the end of the file, or an error if the file can't be
+    /// decoded.
+    fn try_next(&mut self) -> Result<Option<Self::Obj>, ReadError> {
+        Ok(self.next())
+    }
+
+    /// Read the next object into `obj`, reusing its allocations (e.g. the tags, user & way nodes)
+    /// where the reader supports it, rather than allocating a new object. Returns false at the
+    /// end of the file, when the contents of `obj` are unspecified.
+    fn next_into(&mut self, obj: &mut Self::Obj) -> bool {
+        self.try_next_into(obj).unwrap()
+    }
+
+    /// `next_into`, or an error if the file can't be decoded, see `try_next`
+    fn try_next_into(&mut self, obj: &mut Self::Obj) -> Result<bool, ReadError> {
+        match self.try_next()? {
+            Some(next) => {
+                *obj = next;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     fn objects<'a>(&'a mut self) -> OSMObjectIterator<'a, Self>
     where
         Self: Sized,
@@ -299,29 +1249,123 @@ pub trait OSMReader {
         OSMObjectIterator { inner: self }
     }
 
-    //fn nodes<'a, N: Node>(&'a mut self) -> Box<dyn Iterator<Item=N>+'a> where Self:Sized {
-    //    if self.get_sorted_assumption() {
-    //        Box::new(self.objects().take_while(|o| o.is_node()).filter_map(|o| o.into_node()))
-    //    } else {
-    //        Box::new(self.objects().filter_map(|o| o.into_node()))
-    //    }
-    //}
+    /// Parallel iterator over the objects of this reader. The file is still decoded in order
+    /// (on the calling thread), and the objects are handed out to the rayon thread pool in
+    /// batches. Like with `par_bridge`, the order of the objects is not kept.
+    #[cfg(feature = "rayon")]
+    fn par_objects<'a>(&'a mut self) -> impl rayon::iter::ParallelIterator<Item = Self::Obj> + 'a
+    where
+        Self: Sized + Send,
+        Self::Obj: Send,
+    {
+        use rayon::iter::{ParallelBridge, ParallelIterator};
+        OSMObjectBatchIterator { inner: self }
+            .par_bridge()
+            .flat_map_iter(|batch| batch)
+    }
+
+    /// Iterator over the objects (or errors) of this reader. Ends after the first error.
+    fn try_objects<'a>(&'a mut self) -> OSMObjectTryIterator<'a, Self>
+    where
+        Self: Sized,
+    {
+        OSMObjectTryIterator {
+            inner: self,
+            errored: false,
+        }
+    }
+
+    /// Iterator over just the nodes. If the sorted assumption is on, this stops at the first
+    /// non-node, which is consumed (and lost).
+    fn nodes<'a>(&'a mut self) -> Box<dyn Iterator<Item = <Self::Obj as OSMObj>::Node> + 'a>
+    where
+        Self: Sized,
+    {
+        if self.get_sorted_assumption() {
+            Box::new(
+                self.objects()
+                    .take_while(|o| o.is_node())
+                    .filter_map(|o| o.into_node()),
+            )
+        } else {
+            Box::new(self.objects().filter_map(|o| o.into_node()))
+        }
+    }
+
+    /// Iterator over just the ways. If the sorted assumption is on, this stops at the first
+    /// relation, which is consumed (and lost).
+    fn ways<'a>(&'a mut self) -> Box<dyn Iterator<Item = <Self::Obj as OSMObj>::Way> + 'a>
+    where
+        Self: Sized,
+    {
+        if self.get_sorted_assumption() {
+            Box::new(
+                self.objects()
+                    .take_while(|o| o.is_node() || o.is_way())
+                    .filter_map(|o| o.into_way()),
+            )
+        } else {
+            Box::new(self.objects().filter_map(|o| o.into_way()))
+        }
+    }
+
+    /// Iterator over just the relations
+    fn relations<'a>(&'a mut self) -> Box<dyn Iterator<Item = <Self::Obj as OSMObj>::Relation> + 'a>
+    where
+        Self: Sized,
+    {
+        Box::new(self.objects().filter_map(|o| o.into_relation()))
+    }
+}
+
+/// A reader of any file format, see `read_from_path`.
+///
+/// Unlike `OSMReader`, this can be used as a trait object, and so all objects are converted to
+/// `StringOSMObj`. `Box<dyn DynReader>` (and `Box<dyn DynReader + Send>`) is an iterator of
+/// objects.
+pub trait DynReader {
+    /// The next object, if any
+    fn next_obj(&mut self) -> Option<obj_types::StringOSMObj>;
+
+    /// The next object, or an error, see `OSMReader::try_next`
+    fn try_next_obj(&mut self) -> Result<Option<obj_types::StringOSMObj>, ReadError>;
+
+    /// The file header, see `OSMReader::try_header`
+    fn file_header(&mut self) -> Result<Option<Header>, ReadError>;
+}
+
+impl<R> DynReader for R
+where
+    R: OSMReader,
+    R::Obj: Into<obj_types::StringOSMObj>,
+{
+    fn next_obj(&mut self) -> Option<obj_types::StringOSMObj> {
+        self.next().map(|o| o.into())
+    }
+
+    fn try_next_obj(&mut self) -> Result<Option<obj_types::StringOSMObj>, ReadError> {
+        self.try_next().map(|o| o.map(|o| o.into()))
+    }
+
+    fn file_header(&mut self) -> Result<Option<Header>, ReadError> {
+        self.try_header()
+    }
+}
+
+impl Iterator for Box<dyn DynReader> {
+    type Item = obj_types::StringOSMObj;
 
-    //fn nodes_locations<'a>(&'a mut self) -> Box<Iterator<Item=(ObjId, Lat, Lon)>+'a> where Self:Sized {
-    //    Box::new(self.nodes().filter_map(|n| if n.deleted || n.lat.is_none() { None } else { Some((n.id, n.lat.unwrap(), n.lon.unwrap())) } ))
-    //}
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_obj()
+    }
+}
 
-    //fn ways<'a>(&'a mut self) -> Box<Iterator<Item=Way>+'a> where Self:Sized {
-    //    if self.get_sorted_assumption() {
-    //        Box::new(self.objects().take_while(|o| (o.is_node() || o.is_way())).filter_map(|o| o.into_way()))
-    //    } else {
-    //        Box::new(self.objects().filter_map(|o| o.into_way()))
-    //    }
-    //}
+impl Iterator for Box<dyn DynReader + Send> {
+    type Item = obj_types::StringOSMObj;
 
-    //fn relations<'a>(&'a mut self) -> Box<Iterator<Item=Relation>+'a> where Self:Sized {
-    //    Box::new(self.objects().filter_map(|o| o.into_relation()))
-    //}
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_obj()
+    }
 }
 
 // FIXME does this have to be public? Can I make it private?
@@ -352,15 +1396,129 @@ where
     }
 }
 
+/// How many objects are given to a rayon thread at a time
+#[cfg(feature = "rayon")]
+const PAR_BATCH_SIZE: usize = 1024;
+
+/// Iterator over batches of objects, for `par_objects`
+#[cfg(feature = "rayon")]
+struct OSMObjectBatchIterator<'a, R>
+where
+    R: OSMReader + 'a,
+{
+    inner: &'a mut R,
+}
+
+#[cfg(feature = "rayon")]
+impl<'a, R> Iterator for OSMObjectBatchIterator<'a, R>
+where
+    R: OSMReader,
+{
+    type Item = Vec<R::Obj>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut batch = Vec::with_capacity(PAR_BATCH_SIZE);
+        while batch.len() < PAR_BATCH_SIZE {
+            match self.inner.next() {
+                Some(obj) => batch.push(obj),
+                None => break,
+            }
+        }
+        if batch.is_empty() {
+            None
+        } else {
+            Some(batch)
+        }
+    }
+}
+
+pub struct OSMObjectTryIterator<'a, R>
+where
+    R: OSMReader + 'a,
+{
+    inner: &'a mut R,
+    errored: bool,
+}
+
+impl<'a, R> Iterator for OSMObjectTryIterator<'a, R>
+where
+    R: OSMReader,
+{
+    type Item = Result<R::Obj, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.errored {
+            return None;
+        }
+        match self.inner.try_next() {
+            Ok(o) => o.map(Ok),
+            Err(e) => {
+                self.errored = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// An error when trying to read from an OSMReader
+#[derive(Debug)]
+pub enum ReadError {
+    IOError(::std::io::Error),
+    XMLParseError(xml_rs::reader::Error),
+    /// An XML element (of this name) for an object is missing attributes, or has invalid ones
+    XMLInvalidObject(String),
+    PBFDecodeError(protobuf::ProtobufError),
+    /// A PBF blob is compressed with something osmio doesn't support
+    PBFUnsupportedCompression(&'static str),
+    /// A PBF block decoded, but its contents are invalid, e.g. a string table index is out of
+    /// range
+    PBFInvalidData(&'static str),
+    /// This line (1-based line number, and line) of an OPL file is invalid
+    OPLInvalidLine(usize, String),
+    /// These two objects (type, id, version) are out of order, see `sort::SortCheckedReader`
+    NotSorted(
+        (OSMObjectType, ObjId, Option<u32>),
+        (OSMObjectType, ObjId, Option<u32>),
+    ),
+}
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for ReadError {}
+
+impl From<::std::io::Error> for ReadError {
+    fn from(err: ::std::io::Error) -> ReadError {
+        ReadError::IOError(err)
+    }
+}
+impl From<xml_rs::reader::Error> for ReadError {
+    fn from(err: xml_rs::reader::Error) -> ReadError {
+        ReadError::XMLParseError(err)
+    }
+}
+impl From<protobuf::ProtobufError> for ReadError {
+    fn from(err: protobuf::ProtobufError) -> ReadError {
+        ReadError::PBFDecodeError(err)
+    }
+}
+
 /// An error when trying to write from an OSMWriter
 #[derive(Debug)]
 pub enum OSMWriteError {
     FormatDoesntSupportHeaders,
+    /// This format has headers, but can't store this field
+    UnsupportedHeaderField(HeaderField),
     AlreadyStarted,
     AlreadyClosed,
     OPLWrite(::std::io::Error),
     XMLWriteXMLError(quick_xml::Error),
     XMLWriteIOError(::std::io::Error),
+    /// Writing the end of a file from `write_to_path` failed, e.g. the disk is full
+    FileWrite(::std::io::Error),
+    #[cfg(feature = "parquet")]
+    ParquetWrite(parquet_rs::errors::ParquetError),
 }
 impl std::fmt::Display for OSMWriteError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -393,25 +1551,125 @@ pub trait OSMWriter<W: Write> {
     /// Convert back to the underlying writer object
     fn into_inner(self) -> W;
 
-    fn set_header(&mut self, _key_value: (&str, &str)) -> Result<(), OSMWriteError> {
-        todo!("set_header not done yet")
+    /// Set a header field. This has to be done before writing any objects. Setting the same
+    /// field again replaces it.
+    ///
+    /// The default is `Err(FormatDoesntSupportHeaders)`, for formats without a header, e.g. OPL.
+    /// Formats with a header return `Err(UnsupportedHeaderField)` for fields they can't store.
+    fn set_header(&mut self, _field: HeaderField) -> Result<(), OSMWriteError> {
+        Err(OSMWriteError::FormatDoesntSupportHeaders)
+    }
+
+    /// Set all the fields of this header which this format can store, e.g. the header from a
+    /// reader of another format. Unlike `set_header`, it's not an error if the format doesn't
+    /// support some (or any) of the fields.
+    fn set_headers(&mut self, header: &Header) -> Result<(), OSMWriteError> {
+        for field in header.fields() {
+            match self.set_header(field) {
+                Ok(()) | Err(OSMWriteError::UnsupportedHeaderField(_)) => {}
+                Err(OSMWriteError::FormatDoesntSupportHeaders) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Write all these objects, stopping at the first error
+    fn write_objs<O: OSMObj>(
+        &mut self,
+        objs: impl IntoIterator<Item = O>,
+    ) -> Result<(), OSMWriteError>
+    where
+        Self: Sized,
+    {
+        for obj in objs {
+            self.write_obj(&obj)?;
+        }
+        Ok(())
     }
 
     /// Create a new OSMWriter, consume all the objects from an OSMObj iterator source, and then
     /// close this source. Returns this OSMWriter.
+    ///
+    /// # Panics
+    /// If writing fails. See `try_from_iter` to get the error instead.
     fn from_iter<I: Iterator<Item = impl OSMObj>>(writer: W, iter: I) -> Self
+    where
+        Self: Sized,
+    {
+        Self::try_from_iter(writer, iter).unwrap()
+    }
+
+    /// `from_iter`, which returns the first write error
+    fn try_from_iter<I: Iterator<Item = impl OSMObj>>(
+        writer: W,
+        iter: I,
+    ) -> Result<Self, OSMWriteError>
     where
         Self: Sized,
     {
         let mut writer = Self::new(writer);
+        writer.write_objs(iter)?;
+        writer.close()?;
+
+        Ok(writer)
+    }
+}
 
-        // FIXME return the results of these operations?
-        for obj in iter {
-            writer.write_obj(&obj).unwrap();
+/// A writer of any file format, see `write_to_path`.
+///
+/// Unlike `OSMWriter`, this can be used as a trait object.
+pub trait DynWriter {
+    /// Write an object
+    fn write_string_obj(&mut self, obj: &obj_types::StringOSMObj) -> Result<(), OSMWriteError>;
+
+    /// Write many objects, stopping at the first error. On a `Box<dyn DynWriter>` this is one
+    /// dynamic call for the whole batch, rather than one per object.
+    fn write_string_objs(&mut self, objs: &[obj_types::StringOSMObj]) -> Result<(), OSMWriteError> {
+        for obj in objs {
+            self.write_string_obj(obj)?;
         }
-        writer.close().unwrap();
+        Ok(())
+    }
+
+    /// Close this writer, see `OSMWriter::close`. Closing is also done when the writer is
+    /// dropped, but then errors cause a panic.
+    fn finish(&mut self) -> Result<(), OSMWriteError>;
+
+    /// Write an object from any object family, converting it to a `StringOSMObj`
+    fn write_any(&mut self, obj: impl Into<obj_types::StringOSMObj>) -> Result<(), OSMWriteError>
+    where
+        Self: Sized,
+    {
+        self.write_string_obj(&obj.into())
+    }
+}
+
+impl<T: DynWriter + ?Sized> DynWriter for Box<T> {
+    fn write_string_obj(&mut self, obj: &obj_types::StringOSMObj) -> Result<(), OSMWriteError> {
+        (**self).write_string_obj(obj)
+    }
+
+    fn write_string_objs(&mut self, objs: &[obj_types::StringOSMObj]) -> Result<(), OSMWriteError> {
+        (**self).write_string_objs(objs)
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        (**self).finish()
+    }
+}
+
+impl<'a, T: DynWriter + ?Sized> DynWriter for &'a mut T {
+    fn write_string_obj(&mut self, obj: &obj_types::StringOSMObj) -> Result<(), OSMWriteError> {
+        (**self).write_string_obj(obj)
+    }
+
+    fn write_string_objs(&mut self, objs: &[obj_types::StringOSMObj]) -> Result<(), OSMWriteError> {
+        (**self).write_string_objs(objs)
+    }
 
-        writer
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        (**self).finish()
     }
 }
 
@@ -0,0 +1,393 @@
+//! Macros to implement the object traits for your own types.
+//!
+//! Each macro takes the struct, and which of its fields hold the id, metadata, tags, and
+//! location/nodes/members. The metadata fields are optional (but have to be in this order). When
+//! a metadata field is left out, the getter always returns `None` (or `false`) and the setter
+//! does nothing, so compact types can skip what they don't need.
+//!
+//! The field types are the same as `StringNode`: `id: ObjId`, `version: Option<u32>`,
+//! `deleted: bool`, `changeset_id: Option<u64>`, `timestamp: Option<TimestampFormat>`,
+//! `uid: Option<u64>`, `user: Option<String>`, `tags: Vec<(String, String)>`,
+//! `lat_lon: Option<(Lat, Lon)>`, `nodes: Vec<ObjId>` & `members: Vec<Member>`. Instead of
+//! `String`, any string type which derefs to `str`, and converts from `&str` & `String`, can be
+//! used, and `nodes` can be anything with the same methods as `Vec`.
+//!
+//! ```
+//! #[macro_use]
+//! extern crate osmio;
+//! use osmio::{Lat, Lon, Node, OSMObjBase, ObjId};
+//!
+//! #[derive(PartialEq, Debug, Clone)]
+//! struct SmallNode {
+//!     id: ObjId,
+//!     tags: Vec<(String, String)>,
+//!     loc: Option<(Lat, Lon)>,
+//! }
+//!
+//! impl_node!(SmallNode { id: id, tags: tags, lat_lon: loc });
+//!
+//! # fn main() {
+//! let mut n = SmallNode { id: 1, tags: vec![], loc: None };
+//! n.set_tag("name", "foo");
+//! n.set_version(2);
+//! assert_eq!(n.tag("name"), Some("foo"));
+//! assert_eq!(n.version(), None);
+//! # }
+//! ```
+
+/// Implement `OSMObjBase` for a struct. Usually you want `impl_node!`, `impl_way!` or
+/// `impl_relation!` instead.
+#[macro_export]
+macro_rules! impl_osm_obj_base {
+    ($ty:ty {
+        id: $id:ident,
+        $(version: $version:ident,)*
+        $(deleted: $deleted:ident,)*
+        $(changeset_id: $changeset_id:ident,)*
+        $(timestamp: $timestamp:ident,)*
+        $(uid: $uid:ident,)*
+        $(user: $user:ident,)*
+        tags: $tags:ident
+        $(, semantic_eq: $semantic_eq:path)*
+        $(,)*
+    }) => {
+        impl $crate::OSMObjBase for $ty {
+            $(
+            fn semantic_eq(&self, other: &Self) -> bool {
+                $semantic_eq(self, other)
+            }
+            )*
+
+            fn id(&self) -> $crate::ObjId {
+                self.$id
+            }
+            fn version(&self) -> Option<u32> {
+                __osmio_field!(get self [$($version)*] None)
+            }
+            fn deleted(&self) -> bool {
+                __osmio_field!(get self [$($deleted)*] false)
+            }
+            fn changeset_id(&self) -> Option<u64> {
+                __osmio_field!(get self [$($changeset_id)*] None)
+            }
+            fn timestamp(&self) -> &Option<$crate::TimestampFormat> {
+                __osmio_field!(get_ref self [$($timestamp)*] &None)
+            }
+            fn uid(&self) -> Option<u64> {
+                __osmio_field!(get self [$($uid)*] None)
+            }
+            fn user(&self) -> Option<&str> {
+                __osmio_field!(get_str self [$($user)*])
+            }
+
+            fn set_id(&mut self, val: impl Into<$crate::ObjId>) {
+                self.$id = val.into();
+            }
+            fn set_version(&mut self, val: impl Into<Option<u32>>) {
+                let val: Option<u32> = val.into();
+                __osmio_field!(set self [$($version)*] val);
+            }
+            fn set_deleted(&mut self, val: bool) {
+                __osmio_field!(set self [$($deleted)*] val);
+            }
+            fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
+                let val: Option<u64> = val.into();
+                __osmio_field!(set self [$($changeset_id)*] val);
+            }
+            fn set_timestamp(&mut self, val: impl Into<Option<$crate::TimestampFormat>>) {
+                let val: Option<$crate::TimestampFormat> = val.into();
+                __osmio_field!(set self [$($timestamp)*] val);
+            }
+            fn set_uid(&mut self, val: impl Into<Option<u64>>) {
+                let val: Option<u64> = val.into();
+                __osmio_field!(set self [$($uid)*] val);
+            }
+            fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
+                __osmio_field!(set_str self [$($user)*] val);
+            }
+
+            fn tags<'a>(
+                &'a self,
+            ) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
+                Box::new($crate::TagIter::new(&self.$tags))
+            }
+
+            fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
+                let key = key.as_ref();
+                self.$tags
+                    .iter()
+                    .find(|(k, _)| &**k == key)
+                    .map(|(_, v)| &**v)
+            }
+
+            fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
+                let key = key.as_ref();
+                let value = value.into();
+                match self.$tags.iter_mut().find(|(k, _)| &**k == key) {
+                    Some(tag) => tag.1 = value.into(),
+                    None => self.$tags.push((key.into(), value.into())),
+                }
+            }
+
+            fn unset_tag(&mut self, key: impl AsRef<str>) {
+                let key = key.as_ref();
+                self.$tags.retain(|(k, _)| &**k != key);
+            }
+        }
+    };
+}
+
+/// Get or set an optional field, or the default if the type doesn't have that field
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __osmio_field {
+    (get $slf:ident [$field:ident] $default:expr) => {
+        $slf.$field
+    };
+    (get $slf:ident [] $default:expr) => {
+        $default
+    };
+    (get_ref $slf:ident [$field:ident] $default:expr) => {
+        &$slf.$field
+    };
+    (get_ref $slf:ident [] $default:expr) => {
+        $default
+    };
+    (get_str $slf:ident [$field:ident]) => {
+        $slf.$field.as_ref().map(|s| &**s)
+    };
+    (get_str $slf:ident []) => {
+        None
+    };
+    (set $slf:ident [$field:ident] $val:ident) => {
+        $slf.$field = $val
+    };
+    (set $slf:ident [] $val:ident) => {
+        let _ = $val;
+    };
+    (set_str $slf:ident [$field:ident] $val:ident) => {
+        $slf.$field = $val.into().map(Into::into)
+    };
+    (set_str $slf:ident [] $val:ident) => {
+        let _ = $val;
+    };
+}
+
+/// Implement `OSMObjBase` & `Node` for a struct. See the module docs for the fields.
+#[macro_export]
+macro_rules! impl_node {
+    ($ty:ty {
+        id: $id:ident,
+        $(version: $version:ident,)*
+        $(deleted: $deleted:ident,)*
+        $(changeset_id: $changeset_id:ident,)*
+        $(timestamp: $timestamp:ident,)*
+        $(uid: $uid:ident,)*
+        $(user: $user:ident,)*
+        tags: $tags:ident,
+        lat_lon: $lat_lon:ident
+        $(,)*
+    }) => {
+        impl_osm_obj_base!($ty {
+            id: $id,
+            $(version: $version,)*
+            $(deleted: $deleted,)*
+            $(changeset_id: $changeset_id,)*
+            $(timestamp: $timestamp,)*
+            $(uid: $uid,)*
+            $(user: $user,)*
+            tags: $tags,
+            semantic_eq: $crate::obj_types::node_semantic_eq,
+        });
+
+        impl $crate::Node for $ty {
+            fn lat_lon(&self) -> Option<($crate::Lat, $crate::Lon)> {
+                self.$lat_lon
+            }
+
+            fn set_lat_lon(&mut self, loc: impl Into<Option<($crate::Lat, $crate::Lon)>>) {
+                self.$lat_lon = loc.into();
+            }
+        }
+    };
+}
+
+/// Implement `OSMObjBase` & `Way` for a struct. See the module docs for the fields.
+#[macro_export]
+macro_rules! impl_way {
+    ($ty:ty {
+        id: $id:ident,
+        $(version: $version:ident,)*
+        $(deleted: $deleted:ident,)*
+        $(changeset_id: $changeset_id:ident,)*
+        $(timestamp: $timestamp:ident,)*
+        $(uid: $uid:ident,)*
+        $(user: $user:ident,)*
+        tags: $tags:ident,
+        nodes: $nodes:ident
+        $(,)*
+    }) => {
+        impl_osm_obj_base!($ty {
+            id: $id,
+            $(version: $version,)*
+            $(deleted: $deleted,)*
+            $(changeset_id: $changeset_id,)*
+            $(timestamp: $timestamp,)*
+            $(uid: $uid,)*
+            $(user: $user,)*
+            tags: $tags,
+            semantic_eq: $crate::obj_types::way_semantic_eq,
+        });
+
+        impl $crate::Way for $ty {
+            fn num_nodes(&self) -> usize {
+                self.$nodes.len()
+            }
+            fn nodes(&self) -> &[$crate::ObjId] {
+                self.$nodes.as_ref()
+            }
+            fn node(&self, idx: usize) -> Option<$crate::ObjId> {
+                self.$nodes.get(idx).cloned()
+            }
+            fn set_nodes(&mut self, nodes: impl IntoIterator<Item = impl Into<$crate::ObjId>>) {
+                self.$nodes.truncate(0);
+                self.$nodes.extend(nodes.into_iter().map(|i| i.into()));
+            }
+
+            __osmio_way_edit!($nodes);
+        }
+    };
+}
+
+/// The `Way` editing methods, which change a `Vec`-like `nodes` field in place
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __osmio_way_edit {
+    ($nodes:ident) => {
+        fn push_node(&mut self, id: impl Into<$crate::ObjId>) {
+            self.$nodes.push(id.into());
+        }
+        fn insert_node(&mut self, idx: usize, id: impl Into<$crate::ObjId>) {
+            self.$nodes.insert(idx, id.into());
+        }
+        fn remove_node(&mut self, idx: usize) -> Option<$crate::ObjId> {
+            if idx < self.$nodes.len() {
+                Some(self.$nodes.remove(idx))
+            } else {
+                None
+            }
+        }
+        fn reverse(&mut self) {
+            self.$nodes.reverse();
+        }
+        fn replace_node(&mut self, old: $crate::ObjId, new: $crate::ObjId) -> usize {
+            let mut count = 0;
+            for n in self.$nodes.iter_mut().filter(|n| **n == old) {
+                *n = new;
+                count += 1;
+            }
+            count
+        }
+    };
+}
+
+/// Implement `OSMObjBase` & `Relation` for a struct. See the module docs for the fields.
+#[macro_export]
+macro_rules! impl_relation {
+    ($ty:ty {
+        id: $id:ident,
+        $(version: $version:ident,)*
+        $(deleted: $deleted:ident,)*
+        $(changeset_id: $changeset_id:ident,)*
+        $(timestamp: $timestamp:ident,)*
+        $(uid: $uid:ident,)*
+        $(user: $user:ident,)*
+        tags: $tags:ident,
+        members: $members:ident
+        $(,)*
+    }) => {
+        impl_osm_obj_base!($ty {
+            id: $id,
+            $(version: $version,)*
+            $(deleted: $deleted,)*
+            $(changeset_id: $changeset_id,)*
+            $(timestamp: $timestamp,)*
+            $(uid: $uid,)*
+            $(user: $user,)*
+            tags: $tags,
+            semantic_eq: $crate::obj_types::relation_semantic_eq,
+        });
+
+        impl $crate::Relation for $ty {
+            fn members<'a>(
+                &'a self,
+            ) -> Box<dyn ExactSizeIterator<Item = $crate::Member<&'a str>> + 'a> {
+                Box::new(
+                    self.$members
+                        .iter()
+                        .map(|m| $crate::Member::new(m.mtype, m.id, &*m.role)),
+                )
+            }
+
+            fn set_members<M, R>(&mut self, members: impl IntoIterator<Item = M>)
+            where
+                M: Into<$crate::Member<R>>,
+                R: Into<String>,
+            {
+                self.$members.truncate(0);
+
+                self.$members.extend(members.into_iter().map(|m| {
+                    let m = m.into();
+                    let role: String = m.role.into();
+                    $crate::Member::new(m.mtype, m.id, role.into())
+                }));
+            }
+
+            __osmio_relation_edit!($members);
+        }
+    };
+}
+
+/// The `Relation` editing methods, which change a `Vec` of `Member`s in place. The role type
+/// has to deref to `str` and convert from `String`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __osmio_relation_edit {
+    ($members:ident) => {
+        fn push_member<R: Into<String>>(&mut self, member: impl Into<$crate::Member<R>>) {
+            let m = member.into();
+            let role: String = m.role.into();
+            self.$members
+                .push($crate::Member::new(m.mtype, m.id, role.into()));
+        }
+        fn remove_member(&mut self, idx: usize) -> Option<$crate::Member> {
+            if idx < self.$members.len() {
+                let m = self.$members.remove(idx);
+                Some($crate::Member::new(m.mtype, m.id, String::from(&*m.role)))
+            } else {
+                None
+            }
+        }
+        fn retain_members(&mut self, mut pred: impl FnMut(&$crate::Member<&str>) -> bool) {
+            self.$members
+                .retain(|m| pred(&$crate::Member::new(m.mtype, m.id, &*m.role)));
+        }
+        fn replace_member_id(
+            &mut self,
+            mtype: $crate::OSMObjectType,
+            old: $crate::ObjId,
+            new: $crate::ObjId,
+        ) -> usize {
+            let mut count = 0;
+            for m in self
+                .$members
+                .iter_mut()
+                .filter(|m| m.mtype == mtype && m.id == old)
+            {
+                m.id = new;
+                count += 1;
+            }
+            count
+        }
+    };
+}
@@ -1,15 +1,25 @@
 use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
 use std::fs;
+use std::io;
 use std::io::{BufReader, BufWriter};
-use std::io::{Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+#[cfg(all(feature = "mmap", unix))]
+use std::path::PathBuf;
+
+use utils::{read_varint, write_varint};
+use {Lat, Lon, Node, OSMObj, OSMReader, ObjId, ReadError, TimestampFormat, Way};
 
 pub struct NodeStoreWriter {
     max_node_id: u64,
     fp: BufWriter<fs::File>,
 }
 
+/// Reads the locations of a `NodeStoreWriter` file. `get` only needs `&self`, so one reader
+/// can be shared between threads.
 pub struct NodeStoreReader {
-    fp: BufReader<fs::File>,
+    fp: fs::File,
 }
 
 impl NodeStoreWriter {
@@ -39,14 +49,16 @@ impl NodeStoreWriter {
 
 impl NodeStoreReader {
     pub fn open(filename: &str) -> Self {
-        let fp = BufReader::new(fs::File::open(filename).unwrap());
+        let fp = fs::File::open(filename).unwrap();
         NodeStoreReader { fp: fp }
     }
 
-    pub fn get(&mut self, node_id: &u64) -> Option<(f32, f32)> {
-        self.fp.seek(SeekFrom::Start(node_id * 8)).unwrap();
-        let lat = self.fp.read_f32::<BigEndian>().unwrap();
-        let lon = self.fp.read_f32::<BigEndian>().unwrap();
+    pub fn get(&self, node_id: &u64) -> Option<(f32, f32)> {
+        let mut bytes = [0; 8];
+        read_exact_at(&self.fp, &mut bytes, node_id * 8).unwrap();
+        let mut bytes = &bytes[..];
+        let lat = bytes.read_f32::<BigEndian>().unwrap();
+        let lon = bytes.read_f32::<BigEndian>().unwrap();
         if lat == 200f32 || lon == 200f32 {
             None
         } else {
@@ -54,3 +66,1966 @@ impl NodeStoreReader {
         }
     }
 }
+
+/// Read from this offset of the file, without moving the file position, so it works with a
+/// shared `&File`
+#[cfg(unix)]
+fn read_exact_at(fp: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    fp.read_exact_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(fp: &fs::File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match fp.seek_read(buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+// The dense stores keep a location in a u64, the latitude in the high half. Nothing stored (or
+// a fresh, sparse, page of a file) is 0, and a latitude of i32::MIN isn't valid, so the
+// latitude is xor'ed with i32::MIN to make 0 mean "no location".
+fn encode(loc: (Lat, Lon)) -> u64 {
+    (((loc.0.inner() ^ i32::MIN) as u32 as u64) << 32) | (loc.1.inner() as u32 as u64)
+}
+
+fn decode(val: u64) -> Option<(Lat, Lon)> {
+    if val == 0 {
+        None
+    } else {
+        Some((
+            Lat::from_inner(((val >> 32) as u32 as i32) ^ i32::MIN),
+            Lon::from_inner(val as u32 as i32),
+        ))
+    }
+}
+
+const SAVED_MAGIC: &[u8; 8] = b"osmioloc";
+const SAVED_VERSION: u8 = 1;
+
+/// How much a store holds, & how much memory it uses, to log or to choose a strategy
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StoreStats {
+    /// Number of nodes (or ways) stored
+    pub len: usize,
+    /// Roughly how many bytes are allocated. For a store in a file, the size of the file (most of
+    /// which can be sparse, and not on disk)
+    pub bytes: u64,
+    /// For dense stores, the fraction of the slots (node ids up to the largest) which are used
+    pub fill_ratio: Option<f64>,
+}
+
+/// Roughly how many bytes a `HashMap` of this capacity uses, with one control byte per slot
+fn hashmap_bytes<K, V>(map: &HashMap<K, V>) -> u64 {
+    (map.capacity() * (std::mem::size_of::<(K, V)>() + 1)) as u64
+}
+
+/// Below this many nodes, a `LocationStore` is always sparse
+const SMALL_STORE: usize = 1 << 16;
+/// Roughly how many bytes a `HashMap` needs per node, vs. 8 for the dense stores
+const SPARSE_BYTES_PER_NODE: u64 = 24;
+
+enum Store {
+    Sparse(HashMap<ObjId, (Lat, Lon)>),
+    Dense(Vec<u64>),
+    #[cfg(all(feature = "mmap", unix))]
+    File(DenseLocationStore),
+}
+
+/// Node locations, e.g. to assemble way geometries, which picks how to store them. Small
+/// extracts (or ones with ids spread far apart) are kept in a `HashMap`. Once there are enough
+/// nodes that an array indexed by node id is smaller, they're moved to one: in memory, or, with
+/// `with_dense_file` (`mmap` feature), a `DenseLocationStore`. `with_size_hint` picks up front.
+/// Negative ids, and the odd id far beyond the rest, stay in a `HashMap` beside the dense store.
+///
+/// `get` only needs `&self`, and the store is `Sync`, so once it's filled, worker threads can
+/// share it (e.g. in an `Arc`) without a lock.
+///
+/// ```
+/// use osmio::nodestore::LocationStore;
+/// use osmio::{Lat, Lon};
+///
+/// let mut store = LocationStore::new();
+/// store.set(1, (Lat::from(51.5), Lon::from(-0.25))).unwrap();
+/// assert_eq!(store.get(1).unwrap().0.degrees(), 51.5);
+/// assert_eq!(store.get(2), None);
+/// assert!(!store.is_dense());
+/// ```
+pub struct LocationStore {
+    store: Store,
+    /// Ids a dense store can't have (negative ones, from unuploaded edits), or shouldn't (ones
+    /// far beyond the others, like placeholders, which would make it huge) go here. The positive
+    /// ones are always after the end of the dense store.
+    outliers: HashMap<ObjId, (Lat, Lon)>,
+    len: usize,
+    max_id: ObjId,
+    #[cfg(all(feature = "mmap", unix))]
+    dense_file: Option<PathBuf>,
+    /// The keys of the tags to keep, see `with_tags`
+    tag_keys: Vec<String>,
+    /// The kept tags of each node, as indexes into `tag_keys` & values
+    tags: HashMap<ObjId, Vec<(usize, String)>>,
+}
+
+/// Something which knows the locations of nodes, e.g. to get the bbox of a way (`Way::bbox`)
+pub trait NodeLocationStore {
+    /// The location of this node, `None` if it's not known
+    fn location(&self, id: ObjId) -> Option<(Lat, Lon)>;
+}
+
+impl NodeLocationStore for LocationStore {
+    fn location(&self, id: ObjId) -> Option<(Lat, Lon)> {
+        self.get(id)
+    }
+}
+
+impl NodeLocationStore for CompressedLocationStore {
+    fn location(&self, id: ObjId) -> Option<(Lat, Lon)> {
+        self.get(id)
+    }
+}
+
+impl NodeLocationStore for HashMap<ObjId, (Lat, Lon)> {
+    fn location(&self, id: ObjId) -> Option<(Lat, Lon)> {
+        self.get(&id).cloned()
+    }
+}
+
+impl Default for LocationStore {
+    fn default() -> Self {
+        LocationStore::new()
+    }
+}
+
+impl LocationStore {
+    /// An empty store, which starts sparse & switches to dense when the node ids are dense
+    /// enough
+    pub fn new() -> Self {
+        LocationStore {
+            store: Store::Sparse(HashMap::new()),
+            outliers: HashMap::new(),
+            len: 0,
+            max_id: 0,
+            #[cfg(all(feature = "mmap", unix))]
+            dense_file: None,
+            tag_keys: Vec::new(),
+            tags: HashMap::new(),
+        }
+    }
+
+    /// An empty store, which is dense from the start if this many nodes, with ids up to
+    /// `max_id`, would be smaller that way. (e.g. from the PBF header, or a previous run)
+    pub fn with_size_hint(max_id: ObjId, num_nodes: usize) -> Self {
+        let mut store = LocationStore::new();
+        store.max_id = max_id;
+        if store.dense_is_smaller(max_id, num_nodes) {
+            store.store = Store::Dense(Vec::new());
+        }
+        store
+    }
+
+    /// When switching to dense, store the locations in this file (a `DenseLocationStore`)
+    /// instead of memory. Call it before adding nodes.
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn with_dense_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.dense_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Also keep the tags with these keys (e.g. `highway` for crossings), from the nodes added
+    /// with `add_node`. Only the nodes which have one of these tags take up extra space. The tags
+    /// aren't saved by `save_to`.
+    pub fn with_tags(mut self, keys: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.tag_keys.extend(keys.into_iter().map(Into::into));
+        self
+    }
+
+    /// Store the location of this node (if it has one), and the tags chosen with `with_tags`
+    pub fn add_node(&mut self, node: &impl Node) -> io::Result<()> {
+        if let Some(loc) = node.lat_lon() {
+            self.set(node.id(), loc)?;
+        }
+        if !self.tag_keys.is_empty() {
+            let kept: Vec<(usize, String)> = self
+                .tag_keys
+                .iter()
+                .enumerate()
+                .filter_map(|(i, k)| node.tag(k).map(|v| (i, v.to_string())))
+                .collect();
+            if kept.is_empty() {
+                self.tags.remove(&node.id());
+            } else {
+                self.tags.insert(node.id(), kept);
+            }
+        }
+        Ok(())
+    }
+
+    /// Add all the nodes of this reader (with `add_node`), and return how many were read. With
+    /// the sorted assumption on, this stops at the first way or relation, which is consumed (and
+    /// lost), like `OSMReader::nodes`. Otherwise the whole file is read.
+    pub fn fill_from(&mut self, reader: &mut impl OSMReader) -> Result<usize, ReadError> {
+        let sorted = reader.get_sorted_assumption();
+        let mut count = 0;
+        while let Some(obj) = reader.try_next()? {
+            match obj.as_node() {
+                Some(node) => {
+                    self.add_node(node)?;
+                    count += 1;
+                }
+                None if sorted => break,
+                None => {}
+            }
+        }
+        Ok(count)
+    }
+
+    /// The value of this kept tag of this node, see `with_tags`
+    pub fn tag(&self, id: ObjId, key: impl AsRef<str>) -> Option<&str> {
+        let key = key.as_ref();
+        self.tags
+            .get(&id)?
+            .iter()
+            .find(|(i, _)| self.tag_keys[*i] == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The kept tags of this node, see `with_tags`
+    pub fn tags<'a>(&'a self, id: ObjId) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.tags
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .map(move |(i, v)| (self.tag_keys[*i].as_str(), v.as_str()))
+    }
+
+    /// Number of nodes with a location
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How many nodes are stored, and how much memory that takes
+    pub fn stats(&self) -> StoreStats {
+        let (bytes, slots) = match self.store {
+            Store::Sparse(ref locs) => (hashmap_bytes(locs), None),
+            Store::Dense(ref locs) => ((locs.capacity() * 8) as u64, Some(locs.len() as u64)),
+            #[cfg(all(feature = "mmap", unix))]
+            Store::File(ref locs) => (locs.capacity() * 8, Some(locs.capacity())),
+        };
+        let tag_bytes: u64 = hashmap_bytes(&self.tags)
+            + self
+                .tags
+                .values()
+                .map(|t| {
+                    (t.capacity() * std::mem::size_of::<(usize, String)>()) as u64
+                        + t.iter().map(|(_, v)| v.capacity() as u64).sum::<u64>()
+                })
+                .sum::<u64>();
+        let dense_len = self.len - self.outliers.len();
+        StoreStats {
+            len: self.len,
+            bytes: bytes + hashmap_bytes(&self.outliers) + tag_bytes,
+            fill_ratio: slots.map(|slots| {
+                if slots == 0 {
+                    0.
+                } else {
+                    dense_len as f64 / slots as f64
+                }
+            }),
+        }
+    }
+
+    /// True iff the locations are in a dense (memory or file) store
+    pub fn is_dense(&self) -> bool {
+        match self.store {
+            Store::Sparse(_) => false,
+            _ => true,
+        }
+    }
+
+    /// Store the location of this node. Errors are from the dense file.
+    pub fn set(&mut self, id: ObjId, loc: (Lat, Lon)) -> io::Result<()> {
+        let outlier = self.is_dense() && !self.dense_fits(id);
+        if id > self.max_id && !outlier {
+            self.max_id = id;
+        }
+        if !outlier && self.outliers.remove(&id).is_some() {
+            // It's only an outlier until the dense store is big enough
+            self.len -= 1;
+        }
+        let dense_len = self.dense_len();
+        let new = match self.store {
+            _ if outlier => self.outliers.insert(id, loc).is_none(),
+            Store::Sparse(ref mut locs) => locs.insert(id, loc).is_none(),
+            Store::Dense(ref mut locs) => {
+                let id = id as usize;
+                if id >= locs.len() {
+                    let len = std::cmp::max(id + 1, locs.len() * 2);
+                    locs.resize(len, 0);
+                }
+                let new = locs[id] == 0;
+                locs[id] = encode(loc);
+                new
+            }
+            #[cfg(all(feature = "mmap", unix))]
+            Store::File(ref mut locs) => {
+                let new = locs.get(id).is_none();
+                locs.set(id, loc)?;
+                new
+            }
+        };
+        if self.dense_len() > dense_len && !self.outliers.is_empty() {
+            self.move_outliers()?;
+        }
+        if new {
+            self.len += 1;
+            if !self.is_dense() && self.dense_is_smaller(self.max_id, self.len) {
+                self.make_dense()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// True iff this id can go in the dense store: it's positive, and the store already has
+    /// room for it, or it's worth growing the store to have room for it.
+    fn dense_fits(&self, id: ObjId) -> bool {
+        id >= 0 && (id <= self.max_id || self.dense_is_smaller(id, self.len + 1))
+    }
+
+    /// How many ids the dense store has room for, 0 if it's sparse
+    fn dense_len(&self) -> u64 {
+        match self.store {
+            Store::Sparse(_) => 0,
+            Store::Dense(ref locs) => locs.len() as u64,
+            #[cfg(all(feature = "mmap", unix))]
+            Store::File(ref locs) => locs.capacity(),
+        }
+    }
+
+    /// Move the outliers which now fit into the dense store (after it grew) into it
+    fn move_outliers(&mut self) -> io::Result<()> {
+        let dense_len = self.dense_len();
+        let moved: Vec<_> = self
+            .outliers
+            .keys()
+            .filter(|&&id| id >= 0 && (id as u64) < dense_len)
+            .cloned()
+            .collect();
+        for id in moved {
+            let loc = self.outliers.remove(&id).unwrap();
+            self.len -= 1;
+            self.set(id, loc)?;
+        }
+        Ok(())
+    }
+
+    /// The location of this node, `None` if it wasn't set
+    pub fn get(&self, id: ObjId) -> Option<(Lat, Lon)> {
+        match self.store {
+            Store::Sparse(ref locs) => locs.get(&id).cloned(),
+            _ if id < 0 => self.outliers.get(&id).cloned(),
+            Store::Dense(ref locs) => match locs.get(id as usize) {
+                Some(&v) => decode(v),
+                None => self.outliers.get(&id).cloned(),
+            },
+            #[cfg(all(feature = "mmap", unix))]
+            Store::File(ref locs) => locs.get(id).or_else(|| self.outliers.get(&id).cloned()),
+        }
+    }
+
+    /// All the node locations, sorted by id
+    pub fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (ObjId, (Lat, Lon))> + 'a> {
+        let dense: Box<dyn Iterator<Item = (ObjId, (Lat, Lon))> + 'a> = match self.store {
+            Store::Sparse(ref locs) => {
+                let mut locs: Vec<_> = locs.iter().map(|(&id, &loc)| (id, loc)).collect();
+                locs.sort_by_key(|&(id, _)| id);
+                return Box::new(locs.into_iter());
+            }
+            Store::Dense(ref locs) => Box::new(
+                locs.iter()
+                    .enumerate()
+                    .filter_map(|(id, &v)| decode(v).map(|loc| (id as ObjId, loc))),
+            ),
+            #[cfg(all(feature = "mmap", unix))]
+            Store::File(ref locs) => Box::new(locs.iter()),
+        };
+        let mut outliers: Vec<_> = self.outliers.iter().map(|(&id, &loc)| (id, loc)).collect();
+        outliers.sort_by_key(|&(id, _)| id);
+        let after = outliers.split_off(outliers.partition_point(|&(id, _)| id < 0));
+        Box::new(outliers.into_iter().chain(dense).chain(after))
+    }
+
+    /// Save all the locations to this file, to `load_from` later. The file has a version
+    /// number, and is delta compressed: about 5 bytes per node for a planet.
+    pub fn save_to(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut fp = BufWriter::new(fs::File::create(path)?);
+        self.write_saved(&mut fp)?;
+        fp.flush()
+    }
+
+    /// Load the locations saved with `save_to`
+    pub fn load_from(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut store = LocationStore::new();
+        store.read_saved(BufReader::new(fs::File::open(path)?))?;
+        Ok(store)
+    }
+
+    /// Add the locations saved with `save_to` to this store, e.g. to load them into a dense
+    /// file with `LocationStore::new().with_dense_file(…)`
+    pub fn add_saved(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.read_saved(BufReader::new(fs::File::open(path)?))
+    }
+
+    // The saved format: the magic bytes, a version byte, the number of nodes (u64, big endian),
+    // then for each node, in id order, the differences of the id, latitude & longitude (as
+    // `i32`s of 1e-7 degrees) from the previous node, as zigzag varints.
+    fn write_saved(&self, fp: &mut impl Write) -> io::Result<()> {
+        fp.write_all(SAVED_MAGIC)?;
+        fp.write_u8(SAVED_VERSION)?;
+        fp.write_u64::<BigEndian>(self.len as u64)?;
+        let (mut last_id, mut last_lat, mut last_lon) = (0i64, 0i64, 0i64);
+        for (id, (lat, lon)) in self.iter() {
+            let (lat, lon) = (lat.inner() as i64, lon.inner() as i64);
+            write_varint(fp, id.wrapping_sub(last_id))?;
+            write_varint(fp, lat - last_lat)?;
+            write_varint(fp, lon - last_lon)?;
+            last_id = id;
+            last_lat = lat;
+            last_lon = lon;
+        }
+        Ok(())
+    }
+
+    fn read_saved(&mut self, mut fp: impl Read) -> io::Result<()> {
+        let mut magic = [0; 8];
+        fp.read_exact(&mut magic)?;
+        if &magic != SAVED_MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a saved LocationStore",
+            ));
+        }
+        let version = fp.read_u8()?;
+        if version != SAVED_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported LocationStore version {}", version),
+            ));
+        }
+        let count = fp.read_u64::<BigEndian>()?;
+        let (mut id, mut lat, mut lon) = (0i64, 0i64, 0i64);
+        let overflow = || io::Error::new(io::ErrorKind::InvalidData, "invalid saved location");
+        for _ in 0..count {
+            id = id.wrapping_add(read_varint(&mut fp)?);
+            lat = lat
+                .checked_add(read_varint(&mut fp)?)
+                .ok_or_else(overflow)?;
+            lon = lon
+                .checked_add(read_varint(&mut fp)?)
+                .ok_or_else(overflow)?;
+            self.set(
+                id,
+                (Lat::from_inner(lat as i32), Lon::from_inner(lon as i32)),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// True iff this many nodes, with ids up to `max_id`, take less space in a dense store
+    fn dense_is_smaller(&self, max_id: ObjId, num_nodes: usize) -> bool {
+        num_nodes >= SMALL_STORE
+            && (max_id.max(0) as u64 + 1).saturating_mul(8)
+                <= num_nodes as u64 * SPARSE_BYTES_PER_NODE
+    }
+
+    fn make_dense(&mut self) -> io::Result<()> {
+        let sparse = match self.store {
+            Store::Sparse(ref mut locs) => std::mem::replace(locs, HashMap::new()),
+            _ => return Ok(()),
+        };
+        #[cfg(all(feature = "mmap", unix))]
+        {
+            if let Some(ref path) = self.dense_file {
+                self.store = Store::File(DenseLocationStore::create(path)?);
+            }
+        }
+        if !self.is_dense() {
+            self.store = Store::Dense(vec![0; self.max_id.max(0) as usize + 1]);
+        }
+        self.len = 0;
+        for (id, loc) in sparse {
+            self.set(id, loc)?;
+        }
+        Ok(())
+    }
+}
+
+/// Nodes per block of a `CompressedLocationStore`
+const BLOCK_SIZE: usize = 128;
+
+/// Node locations in memory, delta compressed, for files sorted by node id. Nodes are grouped
+/// into blocks of 128, and each one is stored as the differences of the id, latitude & longitude
+/// from the previous node as varints. That's about 4–6 bytes per node for a planet, between a
+/// `HashMap` and a `DenseLocationStore` file. Lookups decode (part of) one block.
+///
+/// Nodes have to be added in order of id.
+///
+/// ```
+/// use osmio::nodestore::CompressedLocationStore;
+/// use osmio::{Lat, Lon};
+///
+/// let mut store = CompressedLocationStore::new();
+/// store.set(1, (Lat::from(51.5), Lon::from(-0.25))).unwrap();
+/// store.set(3, (Lat::from(51.6), Lon::from(-0.24))).unwrap();
+/// assert!(store.set(2, (Lat::from(0.), Lon::from(0.))).is_err());
+/// assert_eq!(store.get(3).unwrap().0.degrees(), 51.6);
+/// assert_eq!(store.get(2), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CompressedLocationStore {
+    /// The first id of each full block
+    block_ids: Vec<ObjId>,
+    /// Offset of each full block in `data`
+    block_offsets: Vec<usize>,
+    data: Vec<u8>,
+    /// The nodes after the last full block
+    pending: Vec<(ObjId, (Lat, Lon))>,
+    last_id: Option<ObjId>,
+    len: usize,
+}
+
+impl CompressedLocationStore {
+    pub fn new() -> Self {
+        CompressedLocationStore::default()
+    }
+
+    /// Number of nodes
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Add the location of this node. The id has to be larger than all the ids before it,
+    /// otherwise this is an error (and nothing is stored).
+    pub fn set(&mut self, id: ObjId, loc: (Lat, Lon)) -> io::Result<()> {
+        if self.last_id.map_or(false, |last| id <= last) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "CompressedLocationStore needs the nodes sorted by id",
+            ));
+        }
+        self.pending.push((id, loc));
+        self.last_id = Some(id);
+        self.len += 1;
+        if self.pending.len() == BLOCK_SIZE {
+            self.block_ids.push(self.pending[0].0);
+            self.block_offsets.push(self.data.len());
+            let (mut last_id, mut last_lat, mut last_lon) = (self.pending[0].0, 0i64, 0i64);
+            for &(id, (lat, lon)) in self.pending.iter() {
+                let (lat, lon) = (lat.inner() as i64, lon.inner() as i64);
+                // Writing to a Vec can't fail
+                write_varint(&mut self.data, id.wrapping_sub(last_id)).unwrap();
+                write_varint(&mut self.data, lat - last_lat).unwrap();
+                write_varint(&mut self.data, lon - last_lon).unwrap();
+                last_id = id;
+                last_lat = lat;
+                last_lon = lon;
+            }
+            self.pending.clear();
+        }
+        Ok(())
+    }
+
+    /// The location of this node, `None` if it wasn't set
+    pub fn get(&self, id: ObjId) -> Option<(Lat, Lon)> {
+        if self
+            .pending
+            .first()
+            .map_or(false, |&(first, _)| id >= first)
+        {
+            return self
+                .pending
+                .binary_search_by_key(&id, |&(id, _)| id)
+                .ok()
+                .map(|i| self.pending[i].1);
+        }
+        let block = match self.block_ids.binary_search(&id) {
+            Ok(block) => block,
+            Err(0) => return None,
+            Err(next) => next - 1,
+        };
+        let mut data = &self.data[self.block_offsets[block]..];
+        let (mut node_id, mut lat, mut lon) = (self.block_ids[block], 0i64, 0i64);
+        for _ in 0..BLOCK_SIZE {
+            node_id = node_id.wrapping_add(read_varint(&mut data).unwrap());
+            lat += read_varint(&mut data).unwrap();
+            lon += read_varint(&mut data).unwrap();
+            if node_id == id {
+                return Some((Lat::from_inner(lat as i32), Lon::from_inner(lon as i32)));
+            } else if node_id > id {
+                break;
+            }
+        }
+        None
+    }
+
+    /// How many nodes are stored, and how much memory that takes
+    pub fn stats(&self) -> StoreStats {
+        StoreStats {
+            len: self.len,
+            bytes: (self.block_ids.capacity() * 8
+                + self.block_offsets.capacity() * std::mem::size_of::<usize>()
+                + self.data.capacity()
+                + self.pending.capacity() * std::mem::size_of::<(ObjId, (Lat, Lon))>())
+                as u64,
+            fill_ratio: None,
+        }
+    }
+}
+
+/// No entry, in the linked list of `LruLocationCache`
+const NO_ENTRY: usize = usize::MAX;
+
+#[derive(Debug, Clone)]
+struct LruEntry {
+    id: ObjId,
+    loc: (Lat, Lon),
+    /// The entries used more & less recently
+    newer: usize,
+    older: usize,
+}
+
+/// The locations of the most recently used nodes, up to a fixed number. When full, adding a node
+/// removes the least recently used (set or got) one. For small machines, when ways mostly use
+/// nodes which were read shortly before, like in a sorted file. It counts how many lookups
+/// found a location, to tune the capacity.
+///
+/// ```
+/// use osmio::nodestore::LruLocationCache;
+/// use osmio::{Lat, Lon};
+///
+/// let mut cache = LruLocationCache::new(2);
+/// cache.set(1, (Lat::from(1.), Lon::from(1.)));
+/// cache.set(2, (Lat::from(2.), Lon::from(2.)));
+/// cache.get(1);
+/// cache.set(3, (Lat::from(3.), Lon::from(3.)));
+/// assert!(cache.get(2).is_none());
+/// assert!(cache.get(1).is_some());
+/// assert_eq!((cache.hits(), cache.misses()), (2, 1));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LruLocationCache {
+    capacity: usize,
+    /// Where each node is in `entries`
+    index: HashMap<ObjId, usize>,
+    entries: Vec<LruEntry>,
+    newest: usize,
+    oldest: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl LruLocationCache {
+    /// An empty cache, for up to `capacity` nodes
+    pub fn new(capacity: usize) -> Self {
+        LruLocationCache {
+            capacity: capacity,
+            index: HashMap::with_capacity(capacity),
+            entries: Vec::with_capacity(capacity),
+            newest: NO_ENTRY,
+            oldest: NO_ENTRY,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Number of nodes in the cache
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// How many times `get` found a location
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// How many times `get` didn't find a location
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// Add (or update) the location of this node, removing the least recently used node if the
+    /// cache is full
+    pub fn set(&mut self, id: ObjId, loc: (Lat, Lon)) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some(&i) = self.index.get(&id) {
+            self.entries[i].loc = loc;
+            self.make_newest(i);
+            return;
+        }
+        let i = if self.entries.len() < self.capacity {
+            self.entries.push(LruEntry {
+                id: id,
+                loc: loc,
+                newer: NO_ENTRY,
+                older: NO_ENTRY,
+            });
+            self.entries.len() - 1
+        } else {
+            // Reuse the oldest entry
+            let i = self.oldest;
+            self.unlink(i);
+            self.index.remove(&self.entries[i].id);
+            self.entries[i].id = id;
+            self.entries[i].loc = loc;
+            i
+        };
+        self.index.insert(id, i);
+        self.link_newest(i);
+    }
+
+    /// The location of this node, if it's in the cache. Counts as a use of the node, and as a
+    /// hit or a miss.
+    pub fn get(&mut self, id: ObjId) -> Option<(Lat, Lon)> {
+        match self.index.get(&id) {
+            Some(&i) => {
+                self.hits += 1;
+                self.make_newest(i);
+                Some(self.entries[i].loc)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// The location of this node, if it's in the cache, without counting as a use
+    pub fn peek(&self, id: ObjId) -> Option<(Lat, Lon)> {
+        self.index.get(&id).map(|&i| self.entries[i].loc)
+    }
+
+    /// How many nodes are stored, and how much memory that takes. `fill_ratio` is how full
+    /// the cache is.
+    pub fn stats(&self) -> StoreStats {
+        StoreStats {
+            len: self.len(),
+            bytes: hashmap_bytes(&self.index)
+                + (self.entries.capacity() * std::mem::size_of::<LruEntry>()) as u64,
+            fill_ratio: if self.capacity == 0 {
+                None
+            } else {
+                Some(self.len() as f64 / self.capacity as f64)
+            },
+        }
+    }
+
+    fn make_newest(&mut self, i: usize) {
+        if self.newest != i {
+            self.unlink(i);
+            self.link_newest(i);
+        }
+    }
+
+    fn unlink(&mut self, i: usize) {
+        let (newer, older) = (self.entries[i].newer, self.entries[i].older);
+        if newer == NO_ENTRY {
+            self.newest = older;
+        } else {
+            self.entries[newer].older = older;
+        }
+        if older == NO_ENTRY {
+            self.oldest = newer;
+        } else {
+            self.entries[older].newer = newer;
+        }
+    }
+
+    fn link_newest(&mut self, i: usize) {
+        self.entries[i].newer = NO_ENTRY;
+        self.entries[i].older = self.newest;
+        if self.newest != NO_ENTRY {
+            self.entries[self.newest].newer = i;
+        }
+        self.newest = i;
+        if self.oldest == NO_ENTRY {
+            self.oldest = i;
+        }
+    }
+}
+
+/// One version of a node in a `HistoryLocationStore`
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NodeVersion {
+    /// Seconds since the epoch
+    timestamp: i64,
+    version: Option<u32>,
+    /// `None` for deleted versions
+    loc: Option<(Lat, Lon)>,
+}
+
+/// All the versions of node locations, from a full history file, to build way geometries as
+/// they were at some time. Deleted versions are kept, so a node has no location from when it
+/// was deleted until it's undeleted.
+///
+/// ```
+/// use osmio::nodestore::HistoryLocationStore;
+/// use osmio::{Lat, Lon};
+///
+/// let mut store = HistoryLocationStore::new();
+/// store.set(1, 1, 1000, Some((Lat::from(1.), Lon::from(2.))));
+/// store.set(1, 2, 2000, None);
+/// assert_eq!(store.get_at(1, 500), None);
+/// assert_eq!(store.get_at(1, 1500).unwrap().1.degrees(), 2.);
+/// assert_eq!(store.get_at(1, 2500), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct HistoryLocationStore {
+    /// The versions of each node, sorted by timestamp
+    versions: HashMap<ObjId, Vec<NodeVersion>>,
+    len: usize,
+}
+
+impl HistoryLocationStore {
+    pub fn new() -> Self {
+        HistoryLocationStore::default()
+    }
+
+    /// Number of nodes (not versions)
+    pub fn len(&self) -> usize {
+        self.versions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty()
+    }
+
+    /// Number of node versions
+    pub fn num_versions(&self) -> usize {
+        self.len
+    }
+
+    /// Store a version of this node, from this time on. `loc` is `None` for deleted versions.
+    pub fn set(
+        &mut self,
+        id: ObjId,
+        version: impl Into<Option<u32>>,
+        timestamp: impl Into<TimestampFormat>,
+        loc: Option<(Lat, Lon)>,
+    ) {
+        let new = NodeVersion {
+            timestamp: timestamp.into().to_epoch_number(),
+            version: version.into(),
+            loc: loc,
+        };
+        let versions = self.versions.entry(id).or_default();
+        // History files are sorted by version, so this is usually the end
+        let idx = versions
+            .iter()
+            .rposition(|v| (v.timestamp, v.version) <= (new.timestamp, new.version))
+            .map_or(0, |i| i + 1);
+        versions.insert(idx, new);
+        self.len += 1;
+    }
+
+    /// Store this version of the node. Returns false (and stores nothing) if it has no valid
+    /// timestamp.
+    pub fn add_node(&mut self, node: &impl Node) -> bool {
+        let timestamp = match node.timestamp().as_ref().and_then(|t| t.to_datetime()) {
+            Some(t) => t.timestamp(),
+            None => return false,
+        };
+        let loc = if node.deleted() { None } else { node.lat_lon() };
+        self.set(node.id(), node.version(), timestamp, loc);
+        true
+    }
+
+    /// The location of this node at this time, `None` if it didn't exist yet, or was deleted
+    pub fn get_at(&self, id: ObjId, timestamp: impl Into<TimestampFormat>) -> Option<(Lat, Lon)> {
+        let timestamp = timestamp.into().to_epoch_number();
+        let versions = self.versions.get(&id)?;
+        let idx = versions.iter().rposition(|v| v.timestamp <= timestamp)?;
+        versions[idx].loc
+    }
+
+    /// The location of this version of the node, `None` if it's not stored, or deleted
+    pub fn get_version(&self, id: ObjId, version: u32) -> Option<(Lat, Lon)> {
+        self.versions
+            .get(&id)?
+            .iter()
+            .find(|v| v.version == Some(version))?
+            .loc
+    }
+
+    /// The location of the last version of this node, `None` if it's deleted
+    pub fn get_latest(&self, id: ObjId) -> Option<(Lat, Lon)> {
+        self.versions.get(&id)?.last()?.loc
+    }
+}
+
+/// The node lists of ways, e.g. to assemble relations from their member ways without reading
+/// the file again. Each list is delta encoded (as zigzag varints) into one buffer, so a way
+/// takes about 2 bytes per node, and the index 16 bytes per way. Setting a way again leaves the
+/// old list in the buffer.
+///
+/// ```
+/// use osmio::nodestore::WayStore;
+///
+/// let mut ways = WayStore::new();
+/// ways.set(10, &[1, 2, 3, 1]);
+/// assert_eq!(ways.get(10), Some(vec![1, 2, 3, 1]));
+/// assert_eq!(ways.nodes(10).unwrap().len(), 4);
+/// assert_eq!(ways.get(11), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WayStore {
+    /// Offset of each way's list in `data`
+    index: HashMap<ObjId, usize>,
+    data: Vec<u8>,
+}
+
+impl WayStore {
+    pub fn new() -> Self {
+        WayStore::default()
+    }
+
+    /// Number of ways
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    pub fn contains(&self, id: ObjId) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    /// How many ways are stored, and how much memory that takes
+    pub fn stats(&self) -> StoreStats {
+        StoreStats {
+            len: self.len(),
+            bytes: hashmap_bytes(&self.index) + self.data.capacity() as u64,
+            fill_ratio: None,
+        }
+    }
+
+    /// Store the node list of this way
+    pub fn set(&mut self, id: ObjId, nodes: &[ObjId]) {
+        self.index.insert(id, self.data.len());
+        // Writing to a Vec can't fail
+        write_varint(&mut self.data, nodes.len() as i64).unwrap();
+        let mut last = 0i64;
+        for &nid in nodes {
+            write_varint(&mut self.data, nid.wrapping_sub(last)).unwrap();
+            last = nid;
+        }
+    }
+
+    /// Store the node list of this way object
+    pub fn add_way(&mut self, way: &impl Way) {
+        self.set(way.id(), way.nodes());
+    }
+
+    /// The node ids of this way, decoded as they're iterated. `None` if it's not stored
+    pub fn nodes(&self, id: ObjId) -> Option<WayNodes<'_>> {
+        let mut data = &self.data[*self.index.get(&id)?..];
+        let remaining = read_varint(&mut data).unwrap() as usize;
+        Some(WayNodes {
+            data: data,
+            remaining: remaining,
+            last: 0,
+        })
+    }
+
+    /// The node ids of this way, `None` if it's not stored
+    pub fn get(&self, id: ObjId) -> Option<Vec<ObjId>> {
+        self.nodes(id).map(|nodes| nodes.collect())
+    }
+}
+
+/// Iterator over the node ids of one way in a `WayStore`
+#[derive(Debug, Clone)]
+pub struct WayNodes<'a> {
+    data: &'a [u8],
+    remaining: usize,
+    last: ObjId,
+}
+
+impl<'a> Iterator for WayNodes<'a> {
+    type Item = ObjId;
+
+    fn next(&mut self) -> Option<ObjId> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.last = self.last.wrapping_add(read_varint(&mut self.data).unwrap());
+        Some(self.last)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for WayNodes<'a> {}
+
+/// A way, with the location of each of its nodes
+#[derive(Debug, Clone, PartialEq)]
+pub struct WayWithGeometry<W> {
+    pub way: W,
+    /// The location of each node of the way, in order. `None` for nodes without a known location
+    pub locations: Vec<Option<(Lat, Lon)>>,
+}
+
+impl<W: Way> WayWithGeometry<W> {
+    /// This way, with the node locations from this store
+    pub fn new(way: W, store: &LocationStore) -> Self {
+        let locations = way.nodes().iter().map(|&n| store.get(n)).collect();
+        WayWithGeometry {
+            way: way,
+            locations: locations,
+        }
+    }
+
+    /// True iff every node has a location
+    pub fn is_complete(&self) -> bool {
+        self.locations.iter().all(|l| l.is_some())
+    }
+
+    /// Each node id, and its location
+    pub fn nodes_with_locations<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = (ObjId, Option<(Lat, Lon)>)> + 'a {
+        self.way
+            .nodes()
+            .iter()
+            .cloned()
+            .zip(self.locations.iter().cloned())
+    }
+}
+
+/// An object from `objects_with_locations`: ways come with their node locations
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocatedObj<O: OSMObj> {
+    Node(O::Node),
+    Way(WayWithGeometry<O::Way>),
+    Relation(O::Relation),
+}
+
+/// Iterator of the objects of a reader, with locations on the ways, see `objects_with_locations`
+pub struct WithLocations<'a, R: OSMReader + 'a> {
+    reader: &'a mut R,
+    store: &'a mut LocationStore,
+}
+
+/// The objects of this reader, with every way's node locations attached, in one pass. Nodes are
+/// added to `store` as they're read, so for a sorted input (nodes first), every way's nodes can
+/// be found. For other inputs, fill `store` first (e.g. with `LocationStore::fill_from`).
+///
+/// ```
+/// use osmio::nodestore::{objects_with_locations, LocatedObj, LocationStore};
+/// use osmio::opl::OPLReader;
+/// use osmio::OSMReader;
+///
+/// let input = "n1 x1 y2\nn2 x3 y4\nw3 Nn1,n2,n9\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let mut store = LocationStore::new();
+/// for obj in objects_with_locations(&mut reader, &mut store) {
+///     if let LocatedObj::Way(way) = obj {
+///         assert_eq!(way.locations[1].unwrap().0.degrees(), 4.);
+///         assert_eq!(way.locations[2], None);
+///     }
+/// }
+/// ```
+pub fn objects_with_locations<'a, R: OSMReader>(
+    reader: &'a mut R,
+    store: &'a mut LocationStore,
+) -> WithLocations<'a, R> {
+    WithLocations {
+        reader: reader,
+        store: store,
+    }
+}
+
+impl<'a, R: OSMReader> WithLocations<'a, R> {
+    /// The next object, or an error
+    pub fn try_next(&mut self) -> Result<Option<LocatedObj<R::Obj>>, ReadError> {
+        let obj = match self.reader.try_next()? {
+            None => return Ok(None),
+            Some(obj) => obj,
+        };
+        if let Some(node) = obj.as_node() {
+            self.store.add_node(node)?;
+        }
+        Ok(Some(if obj.is_node() {
+            LocatedObj::Node(obj.into_node().unwrap())
+        } else if obj.is_way() {
+            LocatedObj::Way(WayWithGeometry::new(obj.into_way().unwrap(), self.store))
+        } else {
+            LocatedObj::Relation(obj.into_relation().unwrap())
+        }))
+    }
+}
+
+impl<'a, R: OSMReader> Iterator for WithLocations<'a, R> {
+    type Item = LocatedObj<R::Obj>;
+
+    /// Panics on errors, see `try_next`
+    fn next(&mut self) -> Option<Self::Item> {
+        self.try_next().unwrap()
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+pub use self::dense::DenseLocationStore;
+
+#[cfg(all(feature = "mmap", unix))]
+mod dense {
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+    use std::ptr;
+
+    use libc;
+
+    use super::{decode, encode};
+    use {Lat, Lon, ObjId};
+
+    /// Bytes per node, the latitude & longitude as `i32`s
+    const ENTRY_SIZE: u64 = 8;
+    /// The file grows by at least this many nodes at a time
+    const MIN_GROWTH: u64 = 1 << 20;
+
+    /// Node locations in a memory mapped file, indexed directly by node id (like osmium's
+    /// `dense_mmap_array`). Every id up to the largest one takes 8 bytes, but the file is sparse,
+    /// so the OS only stores (and keeps in memory) the pages which have nodes. A full planet
+    /// needs about 100GB of disk, and not much RAM. Negative ids can't be stored.
+    ///
+    /// Like `LocationStore`, it can be shared between threads for reading. Only with the `mmap`
+    /// feature, on unix.
+    pub struct DenseLocationStore {
+        file: File,
+        map: *mut u8,
+        /// Number of nodes the file & map have space for
+        capacity: u64,
+        writable: bool,
+    }
+
+    unsafe impl Send for DenseLocationStore {}
+    unsafe impl Sync for DenseLocationStore {}
+
+    impl DenseLocationStore {
+        /// Create a new, empty store in this file, replacing any existing file
+        pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)?;
+            Ok(DenseLocationStore {
+                file: file,
+                map: ptr::null_mut(),
+                capacity: 0,
+                writable: true,
+            })
+        }
+
+        /// Open a store which was written before, read only
+        pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+            let file = File::open(path)?;
+            let capacity = file.metadata()?.len() / ENTRY_SIZE;
+            let mut store = DenseLocationStore {
+                file: file,
+                map: ptr::null_mut(),
+                capacity: 0,
+                writable: false,
+            };
+            store.map(capacity)?;
+            Ok(store)
+        }
+
+        /// Number of nodes the file has space for, i.e. one more than the largest id set
+        pub fn capacity(&self) -> u64 {
+            self.capacity
+        }
+
+        /// Store the location of this node. Errors for negative ids, if the store was opened
+        /// read only, or if the file can't be grown.
+        pub fn set(&mut self, id: ObjId, loc: (Lat, Lon)) -> io::Result<()> {
+            if !self.writable {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "DenseLocationStore was opened read only",
+                ));
+            }
+            if id < 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "DenseLocationStore can't store negative ids",
+                ));
+            }
+            let id = id as u64;
+            if id >= self.capacity {
+                let capacity = std::cmp::max(id + 1, self.capacity * 2).max(MIN_GROWTH);
+                let len = capacity.checked_mul(ENTRY_SIZE).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "node id too large for a DenseLocationStore",
+                    )
+                })?;
+                self.file.set_len(len)?;
+                self.map(capacity)?;
+            }
+            self.slice_mut()[id as usize * 8..id as usize * 8 + 8]
+                .copy_from_slice(&encode(loc).to_ne_bytes());
+            Ok(())
+        }
+
+        /// The location of this node, `None` if it wasn't set
+        pub fn get(&self, id: ObjId) -> Option<(Lat, Lon)> {
+            if id < 0 || id as u64 >= self.capacity {
+                return None;
+            }
+            let id = id as usize;
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&self.slice()[id * 8..id * 8 + 8]);
+            decode(u64::from_ne_bytes(bytes))
+        }
+
+        /// All the node locations, by id
+        pub fn iter<'a>(&'a self) -> impl Iterator<Item = (ObjId, (Lat, Lon))> + 'a {
+            self.slice()
+                .chunks(8)
+                .enumerate()
+                .filter_map(|(id, chunk)| {
+                    let mut bytes = [0; 8];
+                    bytes.copy_from_slice(chunk);
+                    decode(u64::from_ne_bytes(bytes)).map(|loc| (id as ObjId, loc))
+                })
+        }
+
+        /// Write the changes to the file
+        pub fn flush(&self) -> io::Result<()> {
+            if self.map.is_null() {
+                return Ok(());
+            }
+            let res = unsafe {
+                libc::msync(
+                    self.map as *mut libc::c_void,
+                    (self.capacity * ENTRY_SIZE) as usize,
+                    libc::MS_SYNC,
+                )
+            };
+            if res == 0 {
+                Ok(())
+            } else {
+                Err(io::Error::last_os_error())
+            }
+        }
+
+        fn slice(&self) -> &[u8] {
+            if self.map.is_null() {
+                return &[];
+            }
+            unsafe { std::slice::from_raw_parts(self.map, (self.capacity * ENTRY_SIZE) as usize) }
+        }
+
+        fn slice_mut(&mut self) -> &mut [u8] {
+            if self.map.is_null() {
+                return &mut [];
+            }
+            unsafe {
+                std::slice::from_raw_parts_mut(self.map, (self.capacity * ENTRY_SIZE) as usize)
+            }
+        }
+
+        /// (Re)map the first `capacity` nodes of the file
+        fn map(&mut self, capacity: u64) -> io::Result<()> {
+            self.unmap();
+            if capacity == 0 {
+                return Ok(());
+            }
+            let prot = if self.writable {
+                libc::PROT_READ | libc::PROT_WRITE
+            } else {
+                libc::PROT_READ
+            };
+            let map = unsafe {
+                libc::mmap(
+                    ptr::null_mut(),
+                    (capacity * ENTRY_SIZE) as usize,
+                    prot,
+                    libc::MAP_SHARED,
+                    self.file.as_raw_fd(),
+                    0,
+                )
+            };
+            if map == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            self.map = map as *mut u8;
+            self.capacity = capacity;
+            Ok(())
+        }
+
+        fn unmap(&mut self) {
+            if !self.map.is_null() {
+                unsafe {
+                    libc::munmap(
+                        self.map as *mut libc::c_void,
+                        (self.capacity * ENTRY_SIZE) as usize,
+                    );
+                }
+                self.map = ptr::null_mut();
+                self.capacity = 0;
+            }
+        }
+    }
+
+    impl Drop for DenseLocationStore {
+        fn drop(&mut self) {
+            self.unmap();
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn dense_store() {
+            let path = std::env::temp_dir().join(format!("osmio-dense-{}", std::process::id()));
+            let loc = (Lat::from(51.5), Lon::from(-0.25));
+            {
+                let mut store = DenseLocationStore::create(&path).unwrap();
+                assert_eq!(store.get(1), None);
+                store.set(1, loc).unwrap();
+                store.set(0, (Lat::from(0.), Lon::from(0.))).unwrap();
+                store
+                    .set(3_000_000, (Lat::from(-90.), Lon::from(180.)))
+                    .unwrap();
+                assert!(store.set(-1, loc).is_err());
+                assert!(store.set(ObjId::MAX, loc).is_err());
+                assert_eq!(store.get(1), Some(loc));
+                assert_eq!(store.get(2), None);
+                assert_eq!(store.get(-1), None);
+                assert_eq!(store.get(10_000_000), None);
+                store.flush().unwrap();
+            }
+            let mut store = DenseLocationStore::open(&path).unwrap();
+            assert!(store.capacity() > 3_000_000);
+            assert_eq!(store.get(1), Some(loc));
+            assert_eq!(store.get(0), Some((Lat::from(0.), Lon::from(0.))));
+            assert_eq!(
+                store.get(3_000_000),
+                Some((Lat::from(-90.), Lon::from(180.)))
+            );
+            assert!(store.set(2, loc).is_err());
+            drop(store);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use self::kv::KVLocationStore;
+
+#[cfg(feature = "sled")]
+mod kv {
+    use std::io;
+    use std::path::Path;
+
+    use sled;
+
+    use super::{decode, encode, NodeLocationStore};
+    use {Lat, Lon, Node, ObjId};
+
+    /// Node locations in an embedded key-value database (sled), for a store which lives between
+    /// runs and gets random updates, e.g. to keep it up to date by applying diffs. Every change
+    /// is durable (after `flush`), at the cost of being much slower than the other stores. Only
+    /// with the `sled` feature.
+    ///
+    /// ```no_run
+    /// # use osmio::nodestore::KVLocationStore;
+    /// # use osmio::{Lat, Lon};
+    /// let mut store = KVLocationStore::open("nodes.db").unwrap();
+    /// store.set(1, (Lat::from(51.5), Lon::from(-0.25))).unwrap();
+    /// store.remove(2).unwrap();
+    /// store.flush().unwrap();
+    /// ```
+    pub struct KVLocationStore {
+        db: sled::Db,
+    }
+
+    /// The database key of this id. Big endian, with the sign bit flipped, so the keys sort like
+    /// the ids.
+    fn key(id: ObjId) -> [u8; 8] {
+        ((id as u64) ^ (1 << 63)).to_be_bytes()
+    }
+
+    fn id(key: &[u8]) -> io::Result<ObjId> {
+        let mut bytes = [0; 8];
+        if key.len() != 8 {
+            return Err(invalid());
+        }
+        bytes.copy_from_slice(key);
+        Ok((u64::from_be_bytes(bytes) ^ (1 << 63)) as ObjId)
+    }
+
+    fn location(value: &[u8]) -> io::Result<(Lat, Lon)> {
+        let mut bytes = [0; 8];
+        if value.len() != 8 {
+            return Err(invalid());
+        }
+        bytes.copy_from_slice(value);
+        decode(u64::from_be_bytes(bytes)).ok_or_else(invalid)
+    }
+
+    fn invalid() -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, "invalid KVLocationStore entry")
+    }
+
+    impl KVLocationStore {
+        /// Open the database in this directory, creating it if it doesn't exist
+        pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+            Ok(KVLocationStore {
+                db: sled::open(path)?,
+            })
+        }
+
+        /// A new store which is deleted when it's dropped, e.g. for tests
+        pub fn temporary() -> io::Result<Self> {
+            Ok(KVLocationStore {
+                db: sled::Config::new().temporary(true).open()?,
+            })
+        }
+
+        /// Store the location of this node. Unlike the dense stores, any id (incl. negative ones)
+        /// can be stored.
+        pub fn set(&mut self, id: ObjId, loc: (Lat, Lon)) -> io::Result<()> {
+            self.db.insert(key(id), &encode(loc).to_be_bytes())?;
+            Ok(())
+        }
+
+        /// Remove the location of this node (e.g. a deleted node), returning it
+        pub fn remove(&mut self, id: ObjId) -> io::Result<Option<(Lat, Lon)>> {
+            match self.db.remove(key(id))? {
+                Some(value) => location(&value).map(Some),
+                None => Ok(None),
+            }
+        }
+
+        /// The location of this node, `None` if it wasn't set
+        pub fn get(&self, id: ObjId) -> io::Result<Option<(Lat, Lon)>> {
+            match self.db.get(key(id))? {
+                Some(value) => location(&value).map(Some),
+                None => Ok(None),
+            }
+        }
+
+        /// Apply this node from a diff: store its location, or remove it if it's deleted
+        pub fn apply_node(&mut self, node: &impl Node) -> io::Result<()> {
+            match node.lat_lon() {
+                Some(loc) if !node.deleted() => self.set(node.id(), loc),
+                _ => self.remove(node.id()).map(|_| ()),
+            }
+        }
+
+        /// Number of nodes stored. This has to go through the whole database.
+        pub fn len(&self) -> usize {
+            self.db.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.db.is_empty()
+        }
+
+        /// All the node locations, sorted by id
+        pub fn iter<'a>(&'a self) -> impl Iterator<Item = io::Result<(ObjId, (Lat, Lon))>> + 'a {
+            self.db.iter().map(|entry| {
+                let (key, value) = entry?;
+                Ok((id(&key)?, location(&value)?))
+            })
+        }
+
+        /// Write all the changes to disk
+        pub fn flush(&self) -> io::Result<()> {
+            self.db.flush()?;
+            Ok(())
+        }
+    }
+
+    /// Panics on database errors, see `get`
+    impl NodeLocationStore for KVLocationStore {
+        fn location(&self, id: ObjId) -> Option<(Lat, Lon)> {
+            self.get(id).unwrap()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use obj_types::StringNodeBuilder;
+
+        #[test]
+        fn kv_store() {
+            let path = std::env::temp_dir().join(format!("osmio-kv-{}", std::process::id()));
+            let loc = (Lat::from(51.5), Lon::from(-0.25));
+            {
+                let mut store = KVLocationStore::open(&path).unwrap();
+                assert!(store.is_empty());
+                store.set(1, loc).unwrap();
+                store.set(-5, (Lat::from(-90.), Lon::from(180.))).unwrap();
+                store.set(1 << 40, (Lat::from(0.), Lon::from(0.))).unwrap();
+                assert_eq!(store.get(1).unwrap(), Some(loc));
+                assert_eq!(store.location(2), None);
+                store.flush().unwrap();
+            }
+
+            // Applying a diff to the store from the last run
+            let mut store = KVLocationStore::open(&path).unwrap();
+            assert_eq!(store.len(), 3);
+            assert_eq!(store.remove(1).unwrap(), Some(loc));
+            assert_eq!(store.remove(2).unwrap(), None);
+            let moved = StringNodeBuilder::default()
+                ._id(-5)
+                ._lat_lon(loc)
+                .build()
+                .unwrap();
+            store.apply_node(&moved).unwrap();
+            let deleted = StringNodeBuilder::default()
+                ._id(1 << 40)
+                ._deleted(true)
+                .build()
+                .unwrap();
+            store.apply_node(&deleted).unwrap();
+            store.set(7, loc).unwrap();
+            assert_eq!(
+                store.iter().map(|e| e.unwrap()).collect::<Vec<_>>(),
+                vec![(-5, loc), (7, loc)]
+            );
+            drop(store);
+            std::fs::remove_dir_all(&path).unwrap();
+
+            let store = KVLocationStore::temporary().unwrap();
+            assert_eq!(store.get(1).unwrap(), None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loc(id: ObjId) -> (Lat, Lon) {
+        (Lat::from_inner(id as i32), Lon::from_inner(-id as i32))
+    }
+
+    #[test]
+    fn location_store() {
+        let mut store = LocationStore::new();
+        for id in -10..SMALL_STORE as ObjId + 10 {
+            store.set(id, loc(id)).unwrap();
+        }
+        assert!(store.is_dense());
+        assert_eq!(store.len(), SMALL_STORE + 20);
+        store.set(5, loc(6)).unwrap();
+        assert_eq!(store.len(), SMALL_STORE + 20);
+        assert_eq!(store.get(5), Some(loc(6)));
+        assert_eq!(store.get(-3), Some(loc(-3)));
+        assert_eq!(store.get(1000), Some(loc(1000)));
+        assert_eq!(store.get(0), Some(loc(0)));
+        assert_eq!(store.get(1 << 40), None);
+
+        // An id far beyond the others doesn't make the dense store huge
+        store.set(1 << 40, loc(40)).unwrap();
+        assert!(store.is_dense());
+        assert!(store.stats().bytes < 100 * SMALL_STORE as u64);
+        assert_eq!(store.get(1 << 40), Some(loc(40)));
+        assert_eq!(store.len(), SMALL_STORE + 21);
+        // Until there are enough nodes that it's dense enough
+        let far = 4 * SMALL_STORE as ObjId;
+        store.set(far, loc(far)).unwrap();
+        assert_eq!(store.get(far), Some(loc(far)));
+        for id in SMALL_STORE as ObjId + 10..far {
+            store.set(id, loc(id)).unwrap();
+        }
+        assert_eq!(store.len(), far as usize + 12);
+        assert_eq!(store.get(far), Some(loc(far)));
+        assert!(store.outliers.keys().all(|&id| id < 0 || id == 1 << 40));
+        let ids: Vec<_> = store.iter().map(|(id, _)| id).collect();
+        assert_eq!(ids.len(), store.len());
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(ids.last(), Some(&(1 << 40)));
+
+        // Ids far apart stay in the HashMap
+        let mut store = LocationStore::new();
+        for id in 0..SMALL_STORE as ObjId + 10 {
+            store.set(id * 1000, loc(id)).unwrap();
+        }
+        assert!(!store.is_dense());
+        assert_eq!(store.get(5000), Some(loc(5)));
+        assert_eq!(store.get(5001), None);
+
+        assert!(LocationStore::with_size_hint(1_000_000, 900_000).is_dense());
+        assert!(!LocationStore::with_size_hint(1_000_000, 1000).is_dense());
+        assert!(!LocationStore::with_size_hint(1 << 40, 900_000).is_dense());
+    }
+
+    #[test]
+    fn kept_tags() {
+        use obj_types::StringNode;
+        use OSMObjBase;
+
+        let mut store = LocationStore::new().with_tags(vec!["highway", "crossing"]);
+        let mut node = StringNode::new(1, loc(1));
+        node.set_tags(vec![
+            ("highway", "crossing"),
+            ("crossing", "zebra"),
+            ("name", "x"),
+        ]);
+        store.add_node(&node).unwrap();
+        store.add_node(&StringNode::new(2, loc(2))).unwrap();
+        assert_eq!(store.get(1), Some(loc(1)));
+        assert_eq!(store.get(2), Some(loc(2)));
+        assert_eq!(store.tag(1, "highway"), Some("crossing"));
+        assert_eq!(store.tag(1, "name"), None);
+        assert_eq!(store.tag(2, "highway"), None);
+        assert_eq!(
+            store.tags(1).collect::<Vec<_>>(),
+            vec![("highway", "crossing"), ("crossing", "zebra")]
+        );
+        assert_eq!(store.tags(2).count(), 0);
+
+        // Adding it again replaces the tags
+        node.set_tags(vec![("highway", "traffic_signals")]);
+        store.add_node(&node).unwrap();
+        assert_eq!(store.tag(1, "highway"), Some("traffic_signals"));
+        assert_eq!(store.tag(1, "crossing"), None);
+
+        let mut plain = LocationStore::new();
+        plain.add_node(&node).unwrap();
+        assert_eq!(plain.get(1), Some(loc(1)));
+        assert_eq!(plain.tag(1, "highway"), None);
+    }
+
+    #[test]
+    fn fill_from() {
+        use opl::OPLReader;
+        use OSMObjBase;
+
+        let input = "n1 x1 y2\nn2 x3 y4\nw3 Nn1,n2\nn4 x5 y6\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut store = LocationStore::new();
+        assert_eq!(store.fill_from(&mut reader).unwrap(), 3);
+        assert_eq!(store.len(), 3);
+        assert_eq!(store.get(4).unwrap().1.degrees(), 5.);
+
+        let mut reader = OPLReader::new(input.as_bytes());
+        reader.assume_sorted();
+        let mut store = LocationStore::new();
+        assert_eq!(store.fill_from(&mut reader).unwrap(), 2);
+        assert_eq!(store.get(2).unwrap().0.degrees(), 4.);
+        assert_eq!(store.get(4), None);
+        assert_eq!(reader.next().unwrap().id(), 4);
+    }
+
+    #[test]
+    fn stats() {
+        let mut store = LocationStore::new();
+        assert_eq!(store.stats().len, 0);
+        store.set(10, loc(10)).unwrap();
+        let stats = store.stats();
+        assert_eq!(stats.len, 1);
+        assert!(stats.bytes > 0);
+        assert_eq!(stats.fill_ratio, None);
+
+        for id in 0..SMALL_STORE as ObjId {
+            store.set(id * 2, loc(id)).unwrap();
+        }
+        store.set(-1, loc(1)).unwrap();
+        assert!(store.is_dense());
+        let stats = store.stats();
+        assert_eq!(stats.len, SMALL_STORE + 1);
+        assert!(stats.bytes >= 2 * SMALL_STORE as u64 * 8);
+        let fill = stats.fill_ratio.unwrap();
+        assert!(fill > 0.4 && fill < 0.51, "{}", fill);
+
+        let mut ways = WayStore::new();
+        ways.set(1, &[1, 2, 3]);
+        assert_eq!(ways.stats().len, 1);
+        assert!(ways.stats().bytes >= 4);
+    }
+
+    #[test]
+    fn compressed_store() {
+        let mut store = CompressedLocationStore::new();
+        let ids: Vec<ObjId> = (0..1000).map(|i| i * 3 - 10).collect();
+        for &id in &ids {
+            store.set(id, loc(id)).unwrap();
+        }
+        assert!(store.set(5, loc(5)).is_err());
+        assert_eq!(store.len(), ids.len());
+        for &id in &ids {
+            assert_eq!(store.get(id), Some(loc(id)));
+            assert_eq!(store.get(id + 1), None);
+        }
+        assert_eq!(store.get(-100), None);
+        assert_eq!(store.get(10_000), None);
+        store.set(1 << 40, loc(7)).unwrap();
+        assert_eq!(store.get(1 << 40), Some(loc(7)));
+        assert!(store.stats().bytes < 10 * ids.len() as u64);
+    }
+
+    #[test]
+    fn lru_cache() {
+        let mut cache = LruLocationCache::new(3);
+        for id in 1..=3 {
+            cache.set(id, loc(id));
+        }
+        assert_eq!(cache.get(1), Some(loc(1)));
+        cache.set(4, loc(4));
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.peek(2), None);
+        assert_eq!(cache.peek(3), Some(loc(3)));
+        // Updating counts as a use
+        cache.set(3, loc(30));
+        cache.set(5, loc(5));
+        assert_eq!(cache.peek(1), None);
+        assert_eq!(cache.get(3), Some(loc(30)));
+        assert_eq!(cache.get(4), Some(loc(4)));
+        assert_eq!(cache.get(5), Some(loc(5)));
+        assert_eq!(cache.get(1), None);
+        assert_eq!((cache.hits(), cache.misses()), (4, 1));
+        assert_eq!(cache.stats().fill_ratio, Some(1.));
+
+        let mut empty = LruLocationCache::new(0);
+        empty.set(1, loc(1));
+        assert_eq!(empty.get(1), None);
+        let mut one = LruLocationCache::new(1);
+        one.set(1, loc(1));
+        one.set(2, loc(2));
+        assert_eq!((one.peek(1), one.peek(2)), (None, Some(loc(2))));
+    }
+
+    #[test]
+    fn history() {
+        use opl::OPLReader;
+        use OSMReader;
+
+        let input = "n1 v1 dV t2020-01-01T00:00:00Z x1 y1\n\
+                     n1 v2 dV t2021-01-01T00:00:00Z x2 y2\n\
+                     n1 v3 dD t2022-01-01T00:00:00Z\n\
+                     n1 v4 dV t2023-01-01T00:00:00Z x4 y4\n\
+                     n2 v1 dV x5 y5\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut store = HistoryLocationStore::new();
+        let added: Vec<bool> = reader.nodes().map(|n| store.add_node(&n)).collect();
+        assert_eq!(added, vec![true, true, true, true, false]);
+        assert_eq!((store.len(), store.num_versions()), (1, 4));
+
+        let at = |date: &str| store.get_at(1, date.parse::<TimestampFormat>().unwrap());
+        assert_eq!(at("2019-06-01T00:00:00Z"), None);
+        assert_eq!(at("2020-01-01T00:00:00Z").unwrap().0.degrees(), 1.);
+        assert_eq!(at("2021-06-01T00:00:00Z").unwrap().0.degrees(), 2.);
+        assert_eq!(at("2022-06-01T00:00:00Z"), None);
+        assert_eq!(at("2024-06-01T00:00:00Z").unwrap().0.degrees(), 4.);
+        assert_eq!(store.get_version(1, 2).unwrap().1.degrees(), 2.);
+        assert_eq!(store.get_version(1, 3), None);
+        assert_eq!(store.get_latest(1).unwrap().1.degrees(), 4.);
+        assert_eq!(store.get_at(2, 0), None);
+
+        // Out of order versions are sorted by time
+        store.set(3, 2, 20, Some(loc(2)));
+        store.set(3, 1, 10, Some(loc(1)));
+        assert_eq!(store.get_at(3, 15), Some(loc(1)));
+        assert_eq!(store.get_latest(3), Some(loc(2)));
+    }
+
+    #[test]
+    fn way_store() {
+        let mut ways = WayStore::new();
+        let long: Vec<ObjId> = vec![i64::MAX, -1, 0, 1 << 40, 5, 6, 7];
+        ways.set(1, &long);
+        ways.set(2, &[]);
+        let way = ::obj_types::StringWay::new(3, vec![100, 101, 102]);
+        ways.add_way(&way);
+        assert_eq!(ways.len(), 3);
+        assert_eq!(ways.get(1), Some(long));
+        assert_eq!(ways.get(2), Some(vec![]));
+        assert_eq!(ways.nodes(3).unwrap().len(), 3);
+        assert!(ways.nodes(3).unwrap().eq(way.nodes().iter().cloned()));
+        assert!(!ways.contains(4));
+
+        ways.set(3, &[1]);
+        assert_eq!(ways.get(3), Some(vec![1]));
+        assert_eq!(ways.len(), 3);
+    }
+
+    #[test]
+    fn shared_reads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<LocationStore>();
+        assert_send_sync::<NodeStoreReader>();
+        #[cfg(all(feature = "mmap", unix))]
+        assert_send_sync::<DenseLocationStore>();
+
+        let mut store = LocationStore::new();
+        for id in 0..SMALL_STORE as ObjId {
+            store.set(id, loc(id)).unwrap();
+        }
+        let store = &store;
+        std::thread::scope(|s| {
+            let threads: Vec<_> = (0..4)
+                .map(|t| {
+                    s.spawn(move || {
+                        (t..SMALL_STORE as ObjId)
+                            .step_by(4)
+                            .all(|id| store.get(id) == Some(loc(id)))
+                    })
+                })
+                .collect();
+            assert!(threads.into_iter().all(|t| t.join().unwrap()));
+        });
+
+        let path = std::env::temp_dir().join(format!("osmio-nodestore-{}", std::process::id()));
+        {
+            let mut writer = NodeStoreWriter::create(path.to_str().unwrap());
+            writer.set(3, 1.5, 2.5);
+        }
+        let reader = NodeStoreReader::open(path.to_str().unwrap());
+        std::thread::scope(|s| {
+            let reader = &reader;
+            let a = s.spawn(move || reader.get(&3));
+            let b = s.spawn(move || reader.get(&1));
+            assert_eq!(a.join().unwrap(), Some((1.5, 2.5)));
+            assert_eq!(b.join().unwrap(), None);
+        });
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("osmio-saved-{}", std::process::id()));
+        for &step in &[1, 1000] {
+            let mut store = LocationStore::new();
+            for id in -5..SMALL_STORE as ObjId + 5 {
+                store.set(id * step, loc(id)).unwrap();
+            }
+            if !store.is_dense() {
+                store
+                    .set(i64::MAX, (Lat::from(-90.), Lon::from(-180.)))
+                    .unwrap();
+            }
+            store.save_to(&path).unwrap();
+            let loaded = LocationStore::load_from(&path).unwrap();
+            assert_eq!(loaded.len(), store.len());
+            assert!(loaded.iter().eq(store.iter()));
+            assert_eq!(loaded.get(-5 * step), Some(loc(-5)));
+            assert_eq!(loaded.get(7 * step), Some(loc(7)));
+        }
+        // Saved ids are sorted, so the deltas are small
+        assert!(std::fs::metadata(&path).unwrap().len() < 10 * SMALL_STORE as u64);
+
+        std::fs::write(&path, b"osmioloc\x02").unwrap();
+        assert_eq!(
+            LocationStore::load_from(&path).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        // Latitude deltas which overflow
+        let mut saved = SAVED_MAGIC.to_vec();
+        saved.write_u8(SAVED_VERSION).unwrap();
+        saved.write_u64::<BigEndian>(2).unwrap();
+        for &delta in &[1, i64::MAX, 0, 1, 1, 0] {
+            write_varint(&mut saved, delta).unwrap();
+        }
+        std::fs::write(&path, saved).unwrap();
+        assert_eq!(
+            LocationStore::load_from(&path).err().unwrap().kind(),
+            io::ErrorKind::InvalidData
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(all(feature = "mmap", unix))]
+    #[test]
+    fn dense_file() {
+        let path = std::env::temp_dir().join(format!("osmio-store-{}", std::process::id()));
+        {
+            let mut store = LocationStore::new().with_dense_file(&path);
+            for id in 0..SMALL_STORE as ObjId {
+                store.set(id, loc(id)).unwrap();
+            }
+            assert!(store.is_dense());
+            assert_eq!(store.get(123), Some(loc(123)));
+        }
+        assert_eq!(
+            DenseLocationStore::open(&path).unwrap().get(456),
+            Some(loc(456))
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn objects_with_locations() {
+        use opl::OPLReader;
+
+        let input = "n1 x1 y2\nn2 x3 y4\nw3 Nn1,n2\nw4 Nn2,n5\nr6 Mw3@\nn5 x5 y6\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut store = LocationStore::new();
+        let objs: Vec<_> = super::objects_with_locations(&mut reader, &mut store).collect();
+        assert_eq!(objs.len(), 6);
+        match objs[2] {
+            LocatedObj::Way(ref way) => {
+                assert!(way.is_complete());
+                assert_eq!(
+                    way.nodes_with_locations().collect::<Vec<_>>(),
+                    vec![
+                        (1, Some((Lat::from(2.), Lon::from(1.)))),
+                        (2, Some((Lat::from(4.), Lon::from(3.))))
+                    ]
+                );
+            }
+            _ => panic!("not a way"),
+        }
+        match objs[3] {
+            // n5 is after the way
+            LocatedObj::Way(ref way) => assert!(!way.is_complete()),
+            _ => panic!("not a way"),
+        }
+        assert!(matches!(objs[4], LocatedObj::Relation(_)));
+        assert!(matches!(objs[5], LocatedObj::Node(_)));
+        assert_eq!(store.len(), 3);
+    }
+}
@@ -1,3 +1,4 @@
+use super::{node_semantic_eq, relation_semantic_eq, way_semantic_eq};
 use std::sync::Arc;
 use *;
 
@@ -21,49 +22,52 @@ macro_rules! func_call_inner_set {
     };
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 pub struct ArcNode {
     pub(crate) _id: ObjId,
     pub(crate) _version: Option<u32>,
     pub(crate) _deleted: bool,
-    pub(crate) _changeset_id: Option<u32>,
+    pub(crate) _changeset_id: Option<u64>,
     pub(crate) _timestamp: Option<TimestampFormat>,
-    pub(crate) _uid: Option<u32>,
+    pub(crate) _uid: Option<u64>,
     pub(crate) _user: Option<Arc<str>>,
     pub(crate) _tags: Option<Vec<(Arc<str>, Arc<str>)>>,
 
     pub(crate) _lat_lon: Option<(Lat, Lon)>,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 pub struct ArcWay {
     pub(crate) _id: ObjId,
     pub(crate) _version: Option<u32>,
     pub(crate) _deleted: bool,
-    pub(crate) _changeset_id: Option<u32>,
+    pub(crate) _changeset_id: Option<u64>,
     pub(crate) _timestamp: Option<TimestampFormat>,
-    pub(crate) _uid: Option<u32>,
+    pub(crate) _uid: Option<u64>,
     pub(crate) _user: Option<Arc<str>>,
     pub(crate) _tags: Vec<(Arc<str>, Arc<str>)>,
 
     pub(crate) _nodes: Vec<ObjId>,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 pub struct ArcRelation {
     pub(crate) _id: ObjId,
     pub(crate) _version: Option<u32>,
     pub(crate) _deleted: bool,
-    pub(crate) _changeset_id: Option<u32>,
+    pub(crate) _changeset_id: Option<u64>,
     pub(crate) _timestamp: Option<TimestampFormat>,
-    pub(crate) _uid: Option<u32>,
+    pub(crate) _uid: Option<u64>,
     pub(crate) _user: Option<Arc<str>>,
     pub(crate) _tags: Vec<(Arc<str>, Arc<str>)>,
 
-    pub(crate) _members: Vec<(OSMObjectType, ObjId, Arc<str>)>,
+    pub(crate) _members: Vec<Member<Arc<str>>>,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+/// An object with `Arc<str>` strings, which is `Send + Sync` and cheap to clone, so objects
+/// can be shared between, & cached across, threads without copying the tags. `PBFReader`
+/// returns these.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
 pub enum ArcOSMObj {
     Node(ArcNode),
     Way(ArcWay),
@@ -71,6 +75,15 @@ pub enum ArcOSMObj {
 }
 
 impl OSMObjBase for ArcOSMObj {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ArcOSMObj::Node(a), ArcOSMObj::Node(b)) => a.semantic_eq(b),
+            (ArcOSMObj::Way(a), ArcOSMObj::Way(b)) => a.semantic_eq(b),
+            (ArcOSMObj::Relation(a), ArcOSMObj::Relation(b)) => a.semantic_eq(b),
+            _ => false,
+        }
+    }
+
     fn id(&self) -> ObjId {
         func_call_inner_get!(self, id)
     }
@@ -80,13 +93,13 @@ impl OSMObjBase for ArcOSMObj {
     fn deleted(&self) -> bool {
         func_call_inner_get!(self, deleted)
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         func_call_inner_get!(self, changeset_id)
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         func_call_inner_get!(self, timestamp)
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         func_call_inner_get!(self, uid)
     }
     fn user(&self) -> Option<&str> {
@@ -102,13 +115,13 @@ impl OSMObjBase for ArcOSMObj {
     fn set_deleted(&mut self, val: bool) {
         func_call_inner_set!(self, set_deleted, val);
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         func_call_inner_set!(self, set_changeset_id, val);
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         func_call_inner_set!(self, set_timestamp, val);
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         func_call_inner_set!(self, set_uid, val);
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -116,11 +129,7 @@ impl OSMObjBase for ArcOSMObj {
     }
 
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        match self {
-            ArcOSMObj::Node(x) => x.tags(),
-            ArcOSMObj::Way(x) => x.tags(),
-            ArcOSMObj::Relation(x) => x.tags(),
-        }
+        Box::new(self.tags_iter())
     }
 
     fn num_tags(&self) -> usize {
@@ -250,6 +259,10 @@ impl OSMObj for ArcOSMObj {
 }
 
 impl OSMObjBase for ArcNode {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        node_semantic_eq(self, other)
+    }
+
     fn id(&self) -> ObjId {
         self._id
     }
@@ -259,13 +272,13 @@ impl OSMObjBase for ArcNode {
     fn deleted(&self) -> bool {
         self._deleted
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         self._changeset_id
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         &self._timestamp
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         self._uid
     }
     fn user(&self) -> Option<&str> {
@@ -284,13 +297,13 @@ impl OSMObjBase for ArcNode {
     fn set_deleted(&mut self, val: bool) {
         self._deleted = val.into();
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         self._changeset_id = val.into();
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         self._timestamp = val.into();
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         self._uid = val.into();
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -298,10 +311,7 @@ impl OSMObjBase for ArcNode {
     }
 
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        match self._tags {
-            None => Box::new(std::iter::empty()),
-            Some(ref t) => Box::new(t.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))),
-        }
+        Box::new(self.tags_iter())
     }
 
     fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
@@ -366,6 +376,10 @@ impl Node for ArcNode {
 }
 
 impl OSMObjBase for ArcWay {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        way_semantic_eq(self, other)
+    }
+
     fn id(&self) -> ObjId {
         self._id
     }
@@ -375,13 +389,13 @@ impl OSMObjBase for ArcWay {
     fn deleted(&self) -> bool {
         self._deleted
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         self._changeset_id
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         &self._timestamp
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         self._uid
     }
     fn user(&self) -> Option<&str> {
@@ -400,13 +414,13 @@ impl OSMObjBase for ArcWay {
     fn set_deleted(&mut self, val: bool) {
         self._deleted = val.into();
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         self._changeset_id = val.into();
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         self._timestamp = val.into();
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         self._uid = val.into();
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -414,7 +428,7 @@ impl OSMObjBase for ArcWay {
     }
 
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        Box::new(self._tags.iter().map(|(k, v)| (k.as_ref(), v.as_ref())))
+        Box::new(self.tags_iter())
     }
 
     fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
@@ -476,9 +490,15 @@ impl Way for ArcWay {
         self._nodes.truncate(0);
         self._nodes.extend(nodes.into_iter().map(|i| i.into()));
     }
+
+    __osmio_way_edit!(_nodes);
 }
 
 impl OSMObjBase for ArcRelation {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        relation_semantic_eq(self, other)
+    }
+
     fn id(&self) -> ObjId {
         self._id
     }
@@ -488,13 +508,13 @@ impl OSMObjBase for ArcRelation {
     fn deleted(&self) -> bool {
         self._deleted
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         self._changeset_id
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         &self._timestamp
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         self._uid
     }
     fn user(&self) -> Option<&str> {
@@ -513,13 +533,13 @@ impl OSMObjBase for ArcRelation {
     fn set_deleted(&mut self, val: bool) {
         self._deleted = val.into();
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         self._changeset_id = val.into();
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         self._timestamp = val.into();
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         self._uid = val.into();
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -527,7 +547,7 @@ impl OSMObjBase for ArcRelation {
     }
 
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        Box::new(self._tags.iter().map(|(k, v)| (k.as_ref(), v.as_ref())))
+        Box::new(self.tags_iter())
     }
 
     fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
@@ -574,26 +594,26 @@ impl OSMObjBase for ArcRelation {
 }
 
 impl Relation for ArcRelation {
-    fn members<'a>(
-        &'a self,
-    ) -> Box<dyn ExactSizeIterator<Item = (OSMObjectType, ObjId, &'a str)> + 'a> {
+    fn members<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = Member<&'a str>> + 'a> {
         Box::new(
             self._members
                 .iter()
-                .map(|(t, o, r)| (t.clone(), o.clone(), r.as_ref())),
+                .map(|m| Member::new(m.mtype, m.id, m.role.as_ref())),
         )
     }
 
-    fn set_members(
-        &mut self,
-        members: impl IntoIterator<Item = (OSMObjectType, ObjId, impl Into<String>)>,
-    ) {
+    fn set_members<M, R>(&mut self, members: impl IntoIterator<Item = M>)
+    where
+        M: Into<Member<R>>,
+        R: Into<String>,
+    {
         self._members.truncate(0);
 
-        self._members.extend(
-            members
-                .into_iter()
-                .map(|(t, i, r)| (t, i, Arc::from(r.into()))),
-        );
+        self._members.extend(members.into_iter().map(|m| {
+            let m = m.into();
+            Member::new(m.mtype, m.id, Arc::from(m.role.into()))
+        }));
     }
+
+    __osmio_relation_edit!(_members);
 }
@@ -0,0 +1,336 @@
+//! Objects for keeping lots of data in memory. Strings of up to 23 bytes (nearly all tag keys &
+//! values, users & roles) are stored inline without a separate allocation, and short way node
+//! lists are stored inline too.
+use smallvec::SmallVec;
+use smol_str::SmolStr;
+use *;
+
+macro_rules! func_call_inner_get {
+    ($slf:ident, $name:ident) => {
+        match $slf {
+            CompactOSMObj::Node(x) => x.$name(),
+            CompactOSMObj::Way(x) => x.$name(),
+            CompactOSMObj::Relation(x) => x.$name(),
+        }
+    };
+}
+
+macro_rules! func_call_inner_set {
+    ($slf:ident, $name:ident, $val:ident) => {
+        match $slf {
+            CompactOSMObj::Node(x) => x.$name($val),
+            CompactOSMObj::Way(x) => x.$name($val),
+            CompactOSMObj::Relation(x) => x.$name($val),
+        };
+    };
+}
+
+/// Way node lists up to this length are stored inline
+const INLINE_NODES: usize = 4;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+pub struct CompactNode {
+    pub(crate) _id: ObjId,
+    pub(crate) _version: Option<u32>,
+    pub(crate) _deleted: bool,
+    pub(crate) _changeset_id: Option<u64>,
+    pub(crate) _timestamp: Option<TimestampFormat>,
+    pub(crate) _uid: Option<u64>,
+    pub(crate) _user: Option<SmolStr>,
+    pub(crate) _tags: Vec<(SmolStr, SmolStr)>,
+    pub(crate) _lat_lon: Option<(Lat, Lon)>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+pub struct CompactWay {
+    pub(crate) _id: ObjId,
+    pub(crate) _version: Option<u32>,
+    pub(crate) _deleted: bool,
+    pub(crate) _changeset_id: Option<u64>,
+    pub(crate) _timestamp: Option<TimestampFormat>,
+    pub(crate) _uid: Option<u64>,
+    pub(crate) _user: Option<SmolStr>,
+    pub(crate) _tags: Vec<(SmolStr, SmolStr)>,
+    pub(crate) _nodes: SmallVec<[ObjId; INLINE_NODES]>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+pub struct CompactRelation {
+    pub(crate) _id: ObjId,
+    pub(crate) _version: Option<u32>,
+    pub(crate) _deleted: bool,
+    pub(crate) _changeset_id: Option<u64>,
+    pub(crate) _timestamp: Option<TimestampFormat>,
+    pub(crate) _uid: Option<u64>,
+    pub(crate) _user: Option<SmolStr>,
+    pub(crate) _tags: Vec<(SmolStr, SmolStr)>,
+    pub(crate) _members: Vec<Member<SmolStr>>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+pub enum CompactOSMObj {
+    Node(CompactNode),
+    Way(CompactWay),
+    Relation(CompactRelation),
+}
+
+impl From<CompactNode> for CompactOSMObj {
+    fn from(n: CompactNode) -> Self {
+        CompactOSMObj::Node(n)
+    }
+}
+impl From<CompactWay> for CompactOSMObj {
+    fn from(w: CompactWay) -> Self {
+        CompactOSMObj::Way(w)
+    }
+}
+impl From<CompactRelation> for CompactOSMObj {
+    fn from(r: CompactRelation) -> Self {
+        CompactOSMObj::Relation(r)
+    }
+}
+
+impl OSMObjBase for CompactOSMObj {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (CompactOSMObj::Node(a), CompactOSMObj::Node(b)) => a.semantic_eq(b),
+            (CompactOSMObj::Way(a), CompactOSMObj::Way(b)) => a.semantic_eq(b),
+            (CompactOSMObj::Relation(a), CompactOSMObj::Relation(b)) => a.semantic_eq(b),
+            _ => false,
+        }
+    }
+
+    fn id(&self) -> ObjId {
+        func_call_inner_get!(self, id)
+    }
+    fn version(&self) -> Option<u32> {
+        func_call_inner_get!(self, version)
+    }
+    fn deleted(&self) -> bool {
+        func_call_inner_get!(self, deleted)
+    }
+    fn changeset_id(&self) -> Option<u64> {
+        func_call_inner_get!(self, changeset_id)
+    }
+    fn timestamp(&self) -> &Option<TimestampFormat> {
+        func_call_inner_get!(self, timestamp)
+    }
+    fn uid(&self) -> Option<u64> {
+        func_call_inner_get!(self, uid)
+    }
+    fn user(&self) -> Option<&str> {
+        func_call_inner_get!(self, user)
+    }
+
+    fn set_id(&mut self, val: impl Into<ObjId>) {
+        func_call_inner_set!(self, set_id, val);
+    }
+    fn set_version(&mut self, val: impl Into<Option<u32>>) {
+        func_call_inner_set!(self, set_version, val);
+    }
+    fn set_deleted(&mut self, val: bool) {
+        func_call_inner_set!(self, set_deleted, val);
+    }
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
+        func_call_inner_set!(self, set_changeset_id, val);
+    }
+    fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
+        func_call_inner_set!(self, set_timestamp, val);
+    }
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
+        func_call_inner_set!(self, set_uid, val);
+    }
+    fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
+        func_call_inner_set!(self, set_user, val);
+    }
+
+    fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
+        func_call_inner_get!(self, tags)
+    }
+
+    fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
+        match self {
+            CompactOSMObj::Node(x) => x.tag(key),
+            CompactOSMObj::Way(x) => x.tag(key),
+            CompactOSMObj::Relation(x) => x.tag(key),
+        }
+    }
+
+    fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
+        match self {
+            CompactOSMObj::Node(x) => x.set_tag(key, value),
+            CompactOSMObj::Way(x) => x.set_tag(key, value),
+            CompactOSMObj::Relation(x) => x.set_tag(key, value),
+        }
+    }
+
+    fn unset_tag(&mut self, key: impl AsRef<str>) {
+        match self {
+            CompactOSMObj::Node(x) => x.unset_tag(key),
+            CompactOSMObj::Way(x) => x.unset_tag(key),
+            CompactOSMObj::Relation(x) => x.unset_tag(key),
+        }
+    }
+}
+
+impl OSMObj for CompactOSMObj {
+    type Node = CompactNode;
+    type Way = CompactWay;
+    type Relation = CompactRelation;
+
+    fn object_type(&self) -> OSMObjectType {
+        match self {
+            CompactOSMObj::Node(_) => OSMObjectType::Node,
+            CompactOSMObj::Way(_) => OSMObjectType::Way,
+            CompactOSMObj::Relation(_) => OSMObjectType::Relation,
+        }
+    }
+
+    fn into_node(self) -> Option<CompactNode> {
+        if let CompactOSMObj::Node(n) = self {
+            Some(n)
+        } else {
+            None
+        }
+    }
+
+    fn into_way(self) -> Option<CompactWay> {
+        if let CompactOSMObj::Way(w) = self {
+            Some(w)
+        } else {
+            None
+        }
+    }
+
+    fn into_relation(self) -> Option<CompactRelation> {
+        if let CompactOSMObj::Relation(r) = self {
+            Some(r)
+        } else {
+            None
+        }
+    }
+
+    fn as_node(&self) -> Option<&CompactNode> {
+        if let CompactOSMObj::Node(n) = self {
+            Some(&n)
+        } else {
+            None
+        }
+    }
+
+    fn as_way(&self) -> Option<&CompactWay> {
+        if let CompactOSMObj::Way(w) = self {
+            Some(&w)
+        } else {
+            None
+        }
+    }
+
+    fn as_relation(&self) -> Option<&CompactRelation> {
+        if let CompactOSMObj::Relation(r) = self {
+            Some(&r)
+        } else {
+            None
+        }
+    }
+
+    fn as_node_mut(&mut self) -> Option<&mut CompactNode> {
+        if let CompactOSMObj::Node(n) = self {
+            Some(n)
+        } else {
+            None
+        }
+    }
+
+    fn as_way_mut(&mut self) -> Option<&mut CompactWay> {
+        if let CompactOSMObj::Way(w) = self {
+            Some(w)
+        } else {
+            None
+        }
+    }
+
+    fn as_relation_mut(&mut self) -> Option<&mut CompactRelation> {
+        if let CompactOSMObj::Relation(r) = self {
+            Some(r)
+        } else {
+            None
+        }
+    }
+}
+
+impl_node!(CompactNode {
+    id: _id,
+    version: _version,
+    deleted: _deleted,
+    changeset_id: _changeset_id,
+    timestamp: _timestamp,
+    uid: _uid,
+    user: _user,
+    tags: _tags,
+    lat_lon: _lat_lon,
+});
+
+impl_way!(CompactWay {
+    id: _id,
+    version: _version,
+    deleted: _deleted,
+    changeset_id: _changeset_id,
+    timestamp: _timestamp,
+    uid: _uid,
+    user: _user,
+    tags: _tags,
+    nodes: _nodes,
+});
+
+impl_relation!(CompactRelation {
+    id: _id,
+    version: _version,
+    deleted: _deleted,
+    changeset_id: _changeset_id,
+    timestamp: _timestamp,
+    uid: _uid,
+    user: _user,
+    tags: _tags,
+    members: _members,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::OPLReader;
+
+    #[test]
+    fn no_allocations_for_typical_objects() {
+        let input = "n1 v3 uSomeone Thighway=traffic_signals,crossing=traffic_signals x1 y2\n\
+                     w2 v1 Thighway=residential,name=Main%20%Street Nn1,n3,n4,n1\n\
+                     r3 v1 Ttype=multipolygon Mw2@outer\n";
+        let objs: Vec<CompactOSMObj> = OPLReader::new(input.as_bytes())
+            .objects()
+            .map(Into::into)
+            .collect();
+
+        let node = objs[0].as_node().unwrap();
+        assert_eq!(node.user(), Some("Someone"));
+        assert_eq!(node.tag("crossing"), Some("traffic_signals"));
+        assert!(!node._user.as_ref().unwrap().is_heap_allocated());
+        assert!(node
+            ._tags
+            .iter()
+            .all(|(k, v)| !k.is_heap_allocated() && !v.is_heap_allocated()));
+
+        let way = objs[1].as_way().unwrap();
+        assert_eq!(way.nodes(), &[1, 3, 4, 1]);
+        assert!(!way._nodes.spilled());
+        assert_eq!(way.tag("name"), Some("Main Street"));
+
+        let relation = objs[2].as_relation().unwrap();
+        assert_eq!(
+            relation.members().collect::<Vec<_>>(),
+            vec![Member::new(OSMObjectType::Way, 2, "outer")]
+        );
+
+        let string: Vec<obj_types::StringOSMObj> = objs.into_iter().map(Into::into).collect();
+        assert_eq!(string[1].tag("highway"), Some("residential"));
+    }
+}
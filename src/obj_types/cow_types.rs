@@ -0,0 +1,231 @@
+//! The object traits for `Cow`, which borrows an object until it's changed. For pipelines which
+//! only change a few of the objects, this saves cloning all the others.
+//!
+//! ```
+//! use osmio::opl::OPLReader;
+//! use osmio::{OSMObjBase, OSMReader};
+//! use std::borrow::Cow;
+//!
+//! let objs: Vec<_> = OPLReader::new("n1 Tname=a x1 y2\nn2 Tname=b x1 y2\n".as_bytes())
+//!     .objects()
+//!     .collect();
+//! let mut changed = 0;
+//! for obj in objs.iter() {
+//!     let mut obj = Cow::Borrowed(obj);
+//!     if obj.tag("name") == Some("b") {
+//!         obj.set_tag("name", "c");
+//!     }
+//!     if let Cow::Owned(_) = obj {
+//!         changed += 1;
+//!     }
+//! }
+//! assert_eq!(changed, 1);
+//! ```
+use std::borrow::Cow;
+
+use {
+    Lat, Lon, Member, Node, OSMObj, OSMObjBase, OSMObjectType, ObjId, Relation, TimestampFormat,
+    Way,
+};
+
+impl<'a, T: OSMObjBase> OSMObjBase for Cow<'a, T> {
+    fn id(&self) -> ObjId {
+        (**self).id()
+    }
+    fn version(&self) -> Option<u32> {
+        (**self).version()
+    }
+    fn deleted(&self) -> bool {
+        (**self).deleted()
+    }
+    fn changeset_id(&self) -> Option<u64> {
+        (**self).changeset_id()
+    }
+    fn timestamp(&self) -> &Option<TimestampFormat> {
+        (**self).timestamp()
+    }
+    fn uid(&self) -> Option<u64> {
+        (**self).uid()
+    }
+    fn user(&self) -> Option<&str> {
+        (**self).user()
+    }
+
+    fn set_id(&mut self, val: impl Into<ObjId>) {
+        self.to_mut().set_id(val)
+    }
+    fn set_version(&mut self, val: impl Into<Option<u32>>) {
+        self.to_mut().set_version(val)
+    }
+    fn set_deleted(&mut self, val: bool) {
+        self.to_mut().set_deleted(val)
+    }
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
+        self.to_mut().set_changeset_id(val)
+    }
+    fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
+        self.to_mut().set_timestamp(val)
+    }
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
+        self.to_mut().set_uid(val)
+    }
+    fn set_user<'b>(&mut self, val: impl Into<Option<&'b str>>) {
+        self.to_mut().set_user(val)
+    }
+
+    fn tags<'b>(&'b self) -> Box<dyn ExactSizeIterator<Item = (&'b str, &'b str)> + 'b> {
+        (**self).tags()
+    }
+
+    fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
+        (**self).tag(key)
+    }
+
+    /// Only clones the object if the value changes
+    fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
+        let value = value.into();
+        if self.tag(key.as_ref()) != Some(value.as_str()) {
+            self.to_mut().set_tag(key, value);
+        }
+    }
+
+    /// Only clones the object if it has this tag
+    fn unset_tag(&mut self, key: impl AsRef<str>) {
+        if self.has_tag(key.as_ref()) {
+            self.to_mut().unset_tag(key);
+        }
+    }
+
+    fn semantic_eq(&self, other: &Self) -> bool {
+        (**self).semantic_eq(&**other)
+    }
+}
+
+impl<'a, T: Node> Node for Cow<'a, T> {
+    fn lat_lon(&self) -> Option<(Lat, Lon)> {
+        (**self).lat_lon()
+    }
+
+    fn set_lat_lon(&mut self, loc: impl Into<Option<(Lat, Lon)>>) {
+        self.to_mut().set_lat_lon(loc)
+    }
+}
+
+impl<'a, T: Way> Way for Cow<'a, T> {
+    fn nodes(&self) -> &[ObjId] {
+        (**self).nodes()
+    }
+    fn num_nodes(&self) -> usize {
+        (**self).num_nodes()
+    }
+    fn node(&self, idx: usize) -> Option<ObjId> {
+        (**self).node(idx)
+    }
+    fn set_nodes(&mut self, nodes: impl IntoIterator<Item = impl Into<ObjId>>) {
+        self.to_mut().set_nodes(nodes)
+    }
+    fn push_node(&mut self, id: impl Into<ObjId>) {
+        self.to_mut().push_node(id)
+    }
+    fn insert_node(&mut self, idx: usize, id: impl Into<ObjId>) {
+        self.to_mut().insert_node(idx, id)
+    }
+    /// Only clones the way if there's a node at `idx`
+    fn remove_node(&mut self, idx: usize) -> Option<ObjId> {
+        if idx < self.num_nodes() {
+            self.to_mut().remove_node(idx)
+        } else {
+            None
+        }
+    }
+    fn reverse(&mut self) {
+        self.to_mut().reverse()
+    }
+    /// Only clones the way if it has the `old` node
+    fn replace_node(&mut self, old: ObjId, new: ObjId) -> usize {
+        if self.nodes().contains(&old) {
+            self.to_mut().replace_node(old, new)
+        } else {
+            0
+        }
+    }
+}
+
+impl<'a, T: Relation> Relation for Cow<'a, T> {
+    fn members<'b>(&'b self) -> Box<dyn ExactSizeIterator<Item = Member<&'b str>> + 'b> {
+        (**self).members()
+    }
+
+    fn set_members<M, R>(&mut self, members: impl IntoIterator<Item = M>)
+    where
+        M: Into<Member<R>>,
+        R: Into<String>,
+    {
+        self.to_mut().set_members(members)
+    }
+    fn push_member<R: Into<String>>(&mut self, member: impl Into<Member<R>>) {
+        self.to_mut().push_member(member)
+    }
+    /// Only clones the relation if there's a member at `idx`
+    fn remove_member(&mut self, idx: usize) -> Option<Member> {
+        if idx < self.members().len() {
+            self.to_mut().remove_member(idx)
+        } else {
+            None
+        }
+    }
+    /// Only clones the relation if a member is removed
+    fn retain_members(&mut self, mut pred: impl FnMut(&Member<&str>) -> bool) {
+        if !self.members().all(|m| pred(&m)) {
+            self.to_mut().retain_members(pred)
+        }
+    }
+    /// Only clones the relation if it has this member
+    fn replace_member_id(&mut self, mtype: OSMObjectType, old: ObjId, new: ObjId) -> usize {
+        if self.members().any(|m| m.mtype == mtype && m.id == old) {
+            self.to_mut().replace_member_id(mtype, old, new)
+        } else {
+            0
+        }
+    }
+}
+
+impl<'a, O: OSMObj> OSMObj for Cow<'a, O> {
+    type Node = O::Node;
+    type Way = O::Way;
+    type Relation = O::Relation;
+
+    fn object_type(&self) -> OSMObjectType {
+        (**self).object_type()
+    }
+
+    fn into_node(self) -> Option<O::Node> {
+        self.into_owned().into_node()
+    }
+    fn into_way(self) -> Option<O::Way> {
+        self.into_owned().into_way()
+    }
+    fn into_relation(self) -> Option<O::Relation> {
+        self.into_owned().into_relation()
+    }
+
+    fn as_node(&self) -> Option<&O::Node> {
+        (**self).as_node()
+    }
+    fn as_way(&self) -> Option<&O::Way> {
+        (**self).as_way()
+    }
+    fn as_relation(&self) -> Option<&O::Relation> {
+        (**self).as_relation()
+    }
+
+    fn as_node_mut(&mut self) -> Option<&mut O::Node> {
+        self.to_mut().as_node_mut()
+    }
+    fn as_way_mut(&mut self) -> Option<&mut O::Way> {
+        self.to_mut().as_way_mut()
+    }
+    fn as_relation_mut(&mut self) -> Option<&mut O::Relation> {
+        self.to_mut().as_relation_mut()
+    }
+}
@@ -0,0 +1,744 @@
+//! Objects which store their strings as `Symbol`s in a shared `StringPool`. Tag keys & values,
+//! users & roles repeat a lot, so each distinct string is only stored once, and comparing tags
+//! is comparing integers.
+use super::{copy_node, copy_relation, copy_way};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, OnceLock};
+use *;
+
+macro_rules! func_call_inner_get {
+    ($slf:ident, $name:ident) => {
+        match $slf {
+            InternedOSMObj::Node(x) => x.$name(),
+            InternedOSMObj::Way(x) => x.$name(),
+            InternedOSMObj::Relation(x) => x.$name(),
+        }
+    };
+}
+
+macro_rules! func_call_inner_set {
+    ($slf:ident, $name:ident, $val:ident) => {
+        match $slf {
+            InternedOSMObj::Node(x) => x.$name($val),
+            InternedOSMObj::Way(x) => x.$name($val),
+            InternedOSMObj::Relation(x) => x.$name($val),
+        };
+    };
+}
+
+/// A string in a `StringPool`. Only meaningful for the pool that created it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+/// Size of the first chunk of strings. Each chunk is twice the size of the previous one
+const FIRST_CHUNK: usize = 64;
+/// Enough chunks for `u32::MAX` strings
+const NUM_CHUNKS: usize = 27;
+
+/// Which chunk, & where in that chunk, a symbol is
+fn locate(sym: Symbol) -> (usize, usize) {
+    let idx = sym.0 as usize;
+    let n = idx / FIRST_CHUNK + 1;
+    let chunk = (usize::BITS - 1 - n.leading_zeros()) as usize;
+    (chunk, idx - FIRST_CHUNK * ((1 << chunk) - 1))
+}
+
+fn hash_str(s: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    s.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Default)]
+struct PoolIndex {
+    len: u32,
+    /// The first string with each hash
+    by_hash: HashMap<u64, Symbol>,
+    /// Strings whose hash is the same as an earlier, different, string
+    collisions: HashMap<Box<str>, Symbol>,
+}
+
+/// Interns strings. Strings are never removed, so looking up a `Symbol` doesn't need a lock, and
+/// the `&str` lives as long as the pool. It can be shared between threads (& readers) in an `Arc`.
+pub struct StringPool {
+    index: Mutex<PoolIndex>,
+    chunks: [OnceLock<Box<[OnceLock<Box<str>>]>>; NUM_CHUNKS],
+}
+
+impl StringPool {
+    pub fn new() -> Self {
+        StringPool {
+            index: Mutex::new(PoolIndex::default()),
+            chunks: std::array::from_fn(|_| OnceLock::new()),
+        }
+    }
+
+    /// The symbol for this string, adding it to the pool if needed
+    pub fn intern(&self, s: &str) -> Symbol {
+        let hash = hash_str(s);
+        let mut index = self.index.lock().unwrap();
+        match index.by_hash.get(&hash) {
+            None => {
+                let sym = self.push(&mut index, s);
+                index.by_hash.insert(hash, sym);
+                sym
+            }
+            Some(&sym) if self.resolve(sym) == s => sym,
+            Some(_) => match index.collisions.get(s) {
+                Some(&sym) => sym,
+                None => {
+                    let sym = self.push(&mut index, s);
+                    index.collisions.insert(s.into(), sym);
+                    sym
+                }
+            },
+        }
+    }
+
+    fn push(&self, index: &mut PoolIndex, s: &str) -> Symbol {
+        let sym = Symbol(index.len);
+        index.len = index.len.checked_add(1).expect("StringPool is full");
+        let (chunk, offset) = locate(sym);
+        let chunk = self.chunks[chunk]
+            .get_or_init(|| (0..FIRST_CHUNK << chunk).map(|_| OnceLock::new()).collect());
+        // Only called with the index locked, so nothing else sets this slot
+        let _ = chunk[offset].set(s.into());
+        sym
+    }
+
+    /// The symbol for this string, if it's in the pool
+    pub fn get(&self, s: &str) -> Option<Symbol> {
+        let index = self.index.lock().unwrap();
+        match index.by_hash.get(&hash_str(s)) {
+            Some(&sym) if self.resolve(sym) == s => Some(sym),
+            Some(_) => index.collisions.get(s).cloned(),
+            None => None,
+        }
+    }
+
+    /// The string for this symbol.
+    ///
+    /// # Panics
+    /// If the symbol isn't from this pool
+    pub fn resolve(&self, sym: Symbol) -> &str {
+        let (chunk, offset) = locate(sym);
+        self.chunks[chunk]
+            .get()
+            .and_then(|chunk| chunk[offset].get())
+            .expect("Symbol isn't from this StringPool")
+    }
+
+    /// Number of distinct strings in the pool
+    pub fn len(&self) -> usize {
+        self.index.lock().unwrap().len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Default for StringPool {
+    fn default() -> Self {
+        StringPool::new()
+    }
+}
+
+impl fmt::Debug for StringPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StringPool({} strings)", self.len())
+    }
+}
+
+/// Pools are only equal to themselves, so objects from different pools are never `==`. (Use
+/// `semantic_eq`, or convert them, to compare those)
+impl PartialEq for StringPool {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Iterator over the tags of an interned object, resolved in its pool. The unboxed version of
+/// `tags()`, returned by `tags_iter`.
+#[derive(Debug, Clone)]
+pub struct InternedTagIter<'a> {
+    pool: &'a StringPool,
+    inner: std::slice::Iter<'a, (Symbol, Symbol)>,
+}
+
+impl<'a> Iterator for InternedTagIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<(&'a str, &'a str)> {
+        let pool = self.pool;
+        self.inner
+            .next()
+            .map(|(k, v)| (pool.resolve(*k), pool.resolve(*v)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a> DoubleEndedIterator for InternedTagIter<'a> {
+    fn next_back(&mut self) -> Option<(&'a str, &'a str)> {
+        let pool = self.pool;
+        self.inner
+            .next_back()
+            .map(|(k, v)| (pool.resolve(*k), pool.resolve(*v)))
+    }
+}
+
+impl<'a> ExactSizeIterator for InternedTagIter<'a> {}
+impl<'a> std::iter::FusedIterator for InternedTagIter<'a> {}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct InternedNode {
+    pub(crate) _pool: Arc<StringPool>,
+    pub(crate) _id: ObjId,
+    pub(crate) _version: Option<u32>,
+    pub(crate) _deleted: bool,
+    pub(crate) _changeset_id: Option<u64>,
+    pub(crate) _timestamp: Option<TimestampFormat>,
+    pub(crate) _uid: Option<u64>,
+    pub(crate) _user: Option<Symbol>,
+    pub(crate) _tags: Vec<(Symbol, Symbol)>,
+    pub(crate) _lat_lon: Option<(Lat, Lon)>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct InternedWay {
+    pub(crate) _pool: Arc<StringPool>,
+    pub(crate) _id: ObjId,
+    pub(crate) _version: Option<u32>,
+    pub(crate) _deleted: bool,
+    pub(crate) _changeset_id: Option<u64>,
+    pub(crate) _timestamp: Option<TimestampFormat>,
+    pub(crate) _uid: Option<u64>,
+    pub(crate) _user: Option<Symbol>,
+    pub(crate) _tags: Vec<(Symbol, Symbol)>,
+    pub(crate) _nodes: Vec<ObjId>,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct InternedRelation {
+    pub(crate) _pool: Arc<StringPool>,
+    pub(crate) _id: ObjId,
+    pub(crate) _version: Option<u32>,
+    pub(crate) _deleted: bool,
+    pub(crate) _changeset_id: Option<u64>,
+    pub(crate) _timestamp: Option<TimestampFormat>,
+    pub(crate) _uid: Option<u64>,
+    pub(crate) _user: Option<Symbol>,
+    pub(crate) _tags: Vec<(Symbol, Symbol)>,
+    pub(crate) _members: Vec<Member<Symbol>>,
+}
+
+macro_rules! impl_interned {
+    ($ty:ident, $extra:ident : $extra_ty:ty, $semantic_eq:ident) => {
+        impl $ty {
+            /// An empty object, with strings in this pool
+            pub fn new_in(pool: &Arc<StringPool>, id: ObjId) -> Self {
+                $ty {
+                    _pool: Arc::clone(pool),
+                    _id: id,
+                    _version: None,
+                    _deleted: false,
+                    _changeset_id: None,
+                    _timestamp: None,
+                    _uid: None,
+                    _user: None,
+                    _tags: Vec::new(),
+                    $extra: <$extra_ty>::default(),
+                }
+            }
+
+            pub fn pool(&self) -> &Arc<StringPool> {
+                &self._pool
+            }
+
+            /// The tags, as symbols in `pool()`
+            pub fn tag_symbols(&self) -> &[(Symbol, Symbol)] {
+                &self._tags
+            }
+
+            /// The tags, like `OSMObjBase::tags` without boxing the iterator
+            pub fn tags_iter(&self) -> InternedTagIter<'_> {
+                InternedTagIter {
+                    pool: &self._pool,
+                    inner: self._tags.iter(),
+                }
+            }
+        }
+
+        impl OSMObjBase for $ty {
+            fn semantic_eq(&self, other: &Self) -> bool {
+                super::$semantic_eq(self, other)
+            }
+
+            fn id(&self) -> ObjId {
+                self._id
+            }
+            fn version(&self) -> Option<u32> {
+                self._version
+            }
+            fn deleted(&self) -> bool {
+                self._deleted
+            }
+            fn changeset_id(&self) -> Option<u64> {
+                self._changeset_id
+            }
+            fn timestamp(&self) -> &Option<TimestampFormat> {
+                &self._timestamp
+            }
+            fn uid(&self) -> Option<u64> {
+                self._uid
+            }
+            fn user(&self) -> Option<&str> {
+                self._user.map(|u| self._pool.resolve(u))
+            }
+
+            fn set_id(&mut self, val: impl Into<ObjId>) {
+                self._id = val.into();
+            }
+            fn set_version(&mut self, val: impl Into<Option<u32>>) {
+                self._version = val.into();
+            }
+            fn set_deleted(&mut self, val: bool) {
+                self._deleted = val;
+            }
+            fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
+                self._changeset_id = val.into();
+            }
+            fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
+                self._timestamp = val.into();
+            }
+            fn set_uid(&mut self, val: impl Into<Option<u64>>) {
+                self._uid = val.into();
+            }
+            fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
+                self._user = val.into().map(|u| self._pool.intern(u));
+            }
+
+            fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
+                Box::new(self.tags_iter())
+            }
+
+            fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
+                let key = self._pool.get(key.as_ref())?;
+                self._tags
+                    .iter()
+                    .find(|(k, _)| *k == key)
+                    .map(|(_, v)| self._pool.resolve(*v))
+            }
+
+            fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
+                let key = self._pool.intern(key.as_ref());
+                let value = self._pool.intern(&value.into());
+                match self._tags.iter_mut().find(|(k, _)| *k == key) {
+                    Some(tag) => tag.1 = value,
+                    None => self._tags.push((key, value)),
+                }
+            }
+
+            fn unset_tag(&mut self, key: impl AsRef<str>) {
+                if let Some(key) = self._pool.get(key.as_ref()) {
+                    self._tags.retain(|(k, _)| *k != key);
+                }
+            }
+        }
+    };
+}
+
+impl_interned!(InternedNode, _lat_lon: Option<(Lat, Lon)>, node_semantic_eq);
+impl_interned!(InternedWay, _nodes: Vec<ObjId>, way_semantic_eq);
+impl_interned!(InternedRelation, _members: Vec<Member<Symbol>>, relation_semantic_eq);
+
+impl Node for InternedNode {
+    fn lat_lon(&self) -> Option<(Lat, Lon)> {
+        self._lat_lon
+    }
+
+    fn set_lat_lon(&mut self, loc: impl Into<Option<(Lat, Lon)>>) {
+        self._lat_lon = loc.into();
+    }
+}
+
+impl Way for InternedWay {
+    fn num_nodes(&self) -> usize {
+        self._nodes.len()
+    }
+    fn nodes(&self) -> &[ObjId] {
+        &self._nodes
+    }
+    fn node(&self, idx: usize) -> Option<ObjId> {
+        self._nodes.get(idx).cloned()
+    }
+    fn set_nodes(&mut self, nodes: impl IntoIterator<Item = impl Into<ObjId>>) {
+        self._nodes.truncate(0);
+        self._nodes.extend(nodes.into_iter().map(|i| i.into()));
+    }
+
+    __osmio_way_edit!(_nodes);
+}
+
+impl Relation for InternedRelation {
+    fn members<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = Member<&'a str>> + 'a> {
+        let pool = &self._pool;
+        Box::new(
+            self._members
+                .iter()
+                .map(move |m| Member::new(m.mtype, m.id, pool.resolve(m.role))),
+        )
+    }
+
+    fn set_members<M, R>(&mut self, members: impl IntoIterator<Item = M>)
+    where
+        M: Into<Member<R>>,
+        R: Into<String>,
+    {
+        self._members.truncate(0);
+
+        let pool = &self._pool;
+        self._members.extend(members.into_iter().map(|m| {
+            let m = m.into();
+            Member::new(m.mtype, m.id, pool.intern(&m.role.into()))
+        }));
+    }
+
+    fn push_member<R: Into<String>>(&mut self, member: impl Into<Member<R>>) {
+        let m = member.into();
+        let role = self._pool.intern(&m.role.into());
+        self._members.push(Member::new(m.mtype, m.id, role));
+    }
+
+    fn remove_member(&mut self, idx: usize) -> Option<Member> {
+        if idx < self._members.len() {
+            let m = self._members.remove(idx);
+            Some(Member::new(
+                m.mtype,
+                m.id,
+                self._pool.resolve(m.role).to_string(),
+            ))
+        } else {
+            None
+        }
+    }
+
+    fn retain_members(&mut self, mut pred: impl FnMut(&Member<&str>) -> bool) {
+        let pool = &self._pool;
+        self._members
+            .retain(|m| pred(&Member::new(m.mtype, m.id, pool.resolve(m.role))));
+    }
+
+    fn replace_member_id(&mut self, mtype: OSMObjectType, old: ObjId, new: ObjId) -> usize {
+        let mut count = 0;
+        for m in self
+            ._members
+            .iter_mut()
+            .filter(|m| m.mtype == mtype && m.id == old)
+        {
+            m.id = new;
+            count += 1;
+        }
+        count
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum InternedOSMObj {
+    Node(InternedNode),
+    Way(InternedWay),
+    Relation(InternedRelation),
+}
+
+impl InternedOSMObj {
+    /// The tags, like `OSMObjBase::tags` without boxing the iterator
+    pub fn tags_iter(&self) -> InternedTagIter<'_> {
+        match self {
+            InternedOSMObj::Node(x) => x.tags_iter(),
+            InternedOSMObj::Way(x) => x.tags_iter(),
+            InternedOSMObj::Relation(x) => x.tags_iter(),
+        }
+    }
+
+    /// Copy any object into this pool, e.g. `reader.objects().map(|o| InternedOSMObj::from_obj(&o,
+    /// &pool))`
+    pub fn from_obj(obj: &impl OSMObj, pool: &Arc<StringPool>) -> Self {
+        if let Some(n) = obj.as_node() {
+            let mut new = InternedNode::new_in(pool, n.id());
+            copy_node(n, &mut new);
+            InternedOSMObj::Node(new)
+        } else if let Some(w) = obj.as_way() {
+            let mut new = InternedWay::new_in(pool, w.id());
+            copy_way(w, &mut new);
+            InternedOSMObj::Way(new)
+        } else {
+            let r = obj.as_relation().unwrap();
+            let mut new = InternedRelation::new_in(pool, r.id());
+            copy_relation(r, &mut new);
+            InternedOSMObj::Relation(new)
+        }
+    }
+}
+
+impl From<InternedNode> for InternedOSMObj {
+    fn from(n: InternedNode) -> Self {
+        InternedOSMObj::Node(n)
+    }
+}
+impl From<InternedWay> for InternedOSMObj {
+    fn from(w: InternedWay) -> Self {
+        InternedOSMObj::Way(w)
+    }
+}
+impl From<InternedRelation> for InternedOSMObj {
+    fn from(r: InternedRelation) -> Self {
+        InternedOSMObj::Relation(r)
+    }
+}
+
+impl OSMObjBase for InternedOSMObj {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (InternedOSMObj::Node(a), InternedOSMObj::Node(b)) => a.semantic_eq(b),
+            (InternedOSMObj::Way(a), InternedOSMObj::Way(b)) => a.semantic_eq(b),
+            (InternedOSMObj::Relation(a), InternedOSMObj::Relation(b)) => a.semantic_eq(b),
+            _ => false,
+        }
+    }
+
+    fn id(&self) -> ObjId {
+        func_call_inner_get!(self, id)
+    }
+    fn version(&self) -> Option<u32> {
+        func_call_inner_get!(self, version)
+    }
+    fn deleted(&self) -> bool {
+        func_call_inner_get!(self, deleted)
+    }
+    fn changeset_id(&self) -> Option<u64> {
+        func_call_inner_get!(self, changeset_id)
+    }
+    fn timestamp(&self) -> &Option<TimestampFormat> {
+        func_call_inner_get!(self, timestamp)
+    }
+    fn uid(&self) -> Option<u64> {
+        func_call_inner_get!(self, uid)
+    }
+    fn user(&self) -> Option<&str> {
+        func_call_inner_get!(self, user)
+    }
+
+    fn set_id(&mut self, val: impl Into<ObjId>) {
+        func_call_inner_set!(self, set_id, val);
+    }
+    fn set_version(&mut self, val: impl Into<Option<u32>>) {
+        func_call_inner_set!(self, set_version, val);
+    }
+    fn set_deleted(&mut self, val: bool) {
+        func_call_inner_set!(self, set_deleted, val);
+    }
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
+        func_call_inner_set!(self, set_changeset_id, val);
+    }
+    fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
+        func_call_inner_set!(self, set_timestamp, val);
+    }
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
+        func_call_inner_set!(self, set_uid, val);
+    }
+    fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
+        func_call_inner_set!(self, set_user, val);
+    }
+
+    fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
+        func_call_inner_get!(self, tags)
+    }
+
+    fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
+        match self {
+            InternedOSMObj::Node(x) => x.tag(key),
+            InternedOSMObj::Way(x) => x.tag(key),
+            InternedOSMObj::Relation(x) => x.tag(key),
+        }
+    }
+
+    fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
+        match self {
+            InternedOSMObj::Node(x) => x.set_tag(key, value),
+            InternedOSMObj::Way(x) => x.set_tag(key, value),
+            InternedOSMObj::Relation(x) => x.set_tag(key, value),
+        }
+    }
+
+    fn unset_tag(&mut self, key: impl AsRef<str>) {
+        match self {
+            InternedOSMObj::Node(x) => x.unset_tag(key),
+            InternedOSMObj::Way(x) => x.unset_tag(key),
+            InternedOSMObj::Relation(x) => x.unset_tag(key),
+        }
+    }
+}
+
+impl OSMObj for InternedOSMObj {
+    type Node = InternedNode;
+    type Way = InternedWay;
+    type Relation = InternedRelation;
+
+    fn object_type(&self) -> OSMObjectType {
+        match self {
+            InternedOSMObj::Node(_) => OSMObjectType::Node,
+            InternedOSMObj::Way(_) => OSMObjectType::Way,
+            InternedOSMObj::Relation(_) => OSMObjectType::Relation,
+        }
+    }
+
+    fn into_node(self) -> Option<InternedNode> {
+        if let InternedOSMObj::Node(n) = self {
+            Some(n)
+        } else {
+            None
+        }
+    }
+
+    fn into_way(self) -> Option<InternedWay> {
+        if let InternedOSMObj::Way(w) = self {
+            Some(w)
+        } else {
+            None
+        }
+    }
+
+    fn into_relation(self) -> Option<InternedRelation> {
+        if let InternedOSMObj::Relation(r) = self {
+            Some(r)
+        } else {
+            None
+        }
+    }
+
+    fn as_node(&self) -> Option<&InternedNode> {
+        if let InternedOSMObj::Node(n) = self {
+            Some(&n)
+        } else {
+            None
+        }
+    }
+
+    fn as_way(&self) -> Option<&InternedWay> {
+        if let InternedOSMObj::Way(w) = self {
+            Some(&w)
+        } else {
+            None
+        }
+    }
+
+    fn as_relation(&self) -> Option<&InternedRelation> {
+        if let InternedOSMObj::Relation(r) = self {
+            Some(&r)
+        } else {
+            None
+        }
+    }
+
+    fn as_node_mut(&mut self) -> Option<&mut InternedNode> {
+        if let InternedOSMObj::Node(n) = self {
+            Some(n)
+        } else {
+            None
+        }
+    }
+
+    fn as_way_mut(&mut self) -> Option<&mut InternedWay> {
+        if let InternedOSMObj::Way(w) = self {
+            Some(w)
+        } else {
+            None
+        }
+    }
+
+    fn as_relation_mut(&mut self) -> Option<&mut InternedRelation> {
+        if let InternedOSMObj::Relation(r) = self {
+            Some(r)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj_types::StringOSMObj;
+    use opl::OPLReader;
+
+    #[test]
+    fn pool() {
+        let pool = StringPool::new();
+        assert!(pool.is_empty());
+        let a = pool.intern("highway");
+        assert_eq!(pool.intern("highway"), a);
+        assert_ne!(pool.intern("name"), a);
+        assert_eq!(pool.get("name"), Some(Symbol(1)));
+        assert_eq!(pool.get("other"), None);
+        assert_eq!(pool.resolve(a), "highway");
+
+        // past the first few chunks
+        let syms: Vec<_> = (0..1000).map(|i| pool.intern(&i.to_string())).collect();
+        assert_eq!(pool.len(), 1002);
+        assert!(syms
+            .iter()
+            .enumerate()
+            .all(|(i, s)| pool.resolve(*s) == i.to_string()));
+    }
+
+    #[test]
+    fn locations() {
+        assert_eq!(locate(Symbol(0)), (0, 0));
+        assert_eq!(locate(Symbol(63)), (0, 63));
+        assert_eq!(locate(Symbol(64)), (1, 0));
+        assert_eq!(locate(Symbol(191)), (1, 127));
+        assert_eq!(locate(Symbol(192)), (2, 0));
+        assert_eq!(locate(Symbol(u32::MAX)).0, NUM_CHUNKS - 1);
+    }
+
+    #[test]
+    fn objects() {
+        let input = "n1 uAlice Thighway=crossing x1 y2\n\
+                     w2 uAlice Thighway=residential Nn1,n3\n\
+                     r3 uBob Thighway=route Mw2@forward,w4@forward\n";
+        let pool = Arc::new(StringPool::new());
+        let objs: Vec<InternedOSMObj> = OPLReader::new(input.as_bytes())
+            .objects()
+            .map(|o| InternedOSMObj::from_obj(&o, &pool))
+            .collect();
+
+        // highway, crossing, Alice, residential, Bob, route, forward
+        assert_eq!(pool.len(), 7);
+        assert_eq!(objs[0].user(), Some("Alice"));
+        assert_eq!(objs[1].tag("highway"), Some("residential"));
+        assert_eq!(objs[1].tag("name"), None);
+        assert_eq!(
+            objs[2].as_relation().unwrap().members().nth(1),
+            Some(Member::new(OSMObjectType::Way, 4, "forward"))
+        );
+
+        let mut way = objs[1].clone();
+        way.set_tag("highway", "primary");
+        way.unset_tag("unknown");
+        assert_eq!(way.tag("highway"), Some("primary"));
+        assert!(!way.semantic_eq(&objs[1]));
+
+        let string: StringOSMObj = objs[0].clone().into();
+        assert_eq!(string.tag("highway"), Some("crossing"));
+
+        // Same objects in another pool aren't ==, but are semantic_eq
+        let other = InternedOSMObj::from_obj(&string, &Arc::new(StringPool::new()));
+        assert_ne!(other, objs[0]);
+        assert!(other.semantic_eq(&objs[0]));
+    }
+}
@@ -1,84 +1,558 @@
 mod arc_types;
+#[cfg(feature = "compact")]
+mod compact_types;
+mod cow_types;
+mod interned_types;
 mod rc_types;
 mod string_types;
 
-use {Node, OSMObjBase, Relation, Way};
+use std::rc::Rc;
+use std::sync::Arc;
+use {Node, OSMObj, OSMObjBase, ObjId, Relation, TagIter, Way};
 
 pub use self::arc_types::*;
+#[cfg(feature = "compact")]
+pub use self::compact_types::*;
+pub use self::interned_types::*;
 pub use self::rc_types::*;
 pub use self::string_types::*;
 
-impl From<RcNode> for StringNode {
-    fn from(obj: RcNode) -> Self {
-        let mut n = StringNodeBuilder::default()._id(obj.id()).build().unwrap();
+/// Objects which can be created with just an id, so that objects can be converted between the
+/// families
+trait FromId {
+    fn from_id(id: ObjId) -> Self;
+}
 
-        n.set_version(obj.version());
-        n.set_deleted(obj.deleted());
-        n.set_changeset_id(obj.changeset_id());
-        n.set_timestamp(obj.timestamp().clone());
-        n.set_uid(obj.uid());
-        n.set_user(obj.user());
-        n.set_lat_lon(obj.lat_lon());
+// Struct literals rather than the builders, since conversions shouldn't panic on an id of 0
+impl FromId for StringNode {
+    fn from_id(id: ObjId) -> Self {
+        StringNode {
+            _id: id,
+            _version: None,
+            _deleted: false,
+            _changeset_id: None,
+            _timestamp: None,
+            _uid: None,
+            _user: None,
+            _tags: Vec::new(),
+            _lat_lon: None,
+        }
+    }
+}
+impl FromId for StringWay {
+    fn from_id(id: ObjId) -> Self {
+        StringWay {
+            _id: id,
+            _version: None,
+            _deleted: false,
+            _changeset_id: None,
+            _timestamp: None,
+            _uid: None,
+            _user: None,
+            _tags: Vec::new(),
+            _nodes: Vec::new(),
+        }
+    }
+}
+impl FromId for StringRelation {
+    fn from_id(id: ObjId) -> Self {
+        StringRelation {
+            _id: id,
+            _version: None,
+            _deleted: false,
+            _changeset_id: None,
+            _timestamp: None,
+            _uid: None,
+            _user: None,
+            _tags: Vec::new(),
+            _members: Vec::new(),
+        }
+    }
+}
 
-        for (k, v) in obj.tags() {
-            n.set_tag(k, v);
+macro_rules! impl_from_id {
+    ($node:ident, $way:ident, $relation:ident) => {
+        impl FromId for $node {
+            fn from_id(id: ObjId) -> Self {
+                $node {
+                    _id: id,
+                    _version: None,
+                    _deleted: false,
+                    _changeset_id: None,
+                    _timestamp: None,
+                    _uid: None,
+                    _user: None,
+                    _tags: None,
+                    _lat_lon: None,
+                }
+            }
+        }
+        impl FromId for $way {
+            fn from_id(id: ObjId) -> Self {
+                $way {
+                    _id: id,
+                    _version: None,
+                    _deleted: false,
+                    _changeset_id: None,
+                    _timestamp: None,
+                    _uid: None,
+                    _user: None,
+                    _tags: Vec::new(),
+                    _nodes: Vec::new(),
+                }
+            }
+        }
+        impl FromId for $relation {
+            fn from_id(id: ObjId) -> Self {
+                $relation {
+                    _id: id,
+                    _version: None,
+                    _deleted: false,
+                    _changeset_id: None,
+                    _timestamp: None,
+                    _uid: None,
+                    _user: None,
+                    _tags: Vec::new(),
+                    _members: Vec::new(),
+                }
+            }
+        }
+    };
+}
+
+#[cfg(feature = "compact")]
+impl FromId for CompactNode {
+    fn from_id(id: ObjId) -> Self {
+        CompactNode {
+            _id: id,
+            _version: None,
+            _deleted: false,
+            _changeset_id: None,
+            _timestamp: None,
+            _uid: None,
+            _user: None,
+            _tags: Vec::new(),
+            _lat_lon: None,
+        }
+    }
+}
+#[cfg(feature = "compact")]
+impl FromId for CompactWay {
+    fn from_id(id: ObjId) -> Self {
+        CompactWay {
+            _id: id,
+            _version: None,
+            _deleted: false,
+            _changeset_id: None,
+            _timestamp: None,
+            _uid: None,
+            _user: None,
+            _tags: Vec::new(),
+            _nodes: Default::default(),
+        }
+    }
+}
+#[cfg(feature = "compact")]
+impl FromId for CompactRelation {
+    fn from_id(id: ObjId) -> Self {
+        CompactRelation {
+            _id: id,
+            _version: None,
+            _deleted: false,
+            _changeset_id: None,
+            _timestamp: None,
+            _uid: None,
+            _user: None,
+            _tags: Vec::new(),
+            _members: Vec::new(),
         }
+    }
+}
+
+impl_from_id!(RcNode, RcWay, RcRelation);
+impl_from_id!(ArcNode, ArcWay, ArcRelation);
 
-        n
+/// Copy the metadata & tags from one object to an object of (possibly) another family
+fn copy_base(obj: &impl OSMObjBase, new: &mut impl OSMObjBase) {
+    new.set_id(obj.id());
+    new.set_version(obj.version());
+    new.set_deleted(obj.deleted());
+    new.set_changeset_id(obj.changeset_id());
+    new.set_timestamp(obj.timestamp().clone());
+    new.set_uid(obj.uid());
+    new.set_user(obj.user());
+
+    for (k, v) in obj.tags() {
+        new.set_tag(k, v);
     }
 }
 
-impl From<RcWay> for StringWay {
-    fn from(obj: RcWay) -> Self {
-        let mut w = StringWayBuilder::default()._id(obj.id()).build().unwrap();
+fn copy_node(obj: &impl Node, new: &mut impl Node) {
+    copy_base(obj, new);
+    new.set_lat_lon(obj.lat_lon());
+}
 
-        w.set_version(obj.version());
-        w.set_deleted(obj.deleted());
-        w.set_changeset_id(obj.changeset_id());
-        w.set_timestamp(obj.timestamp().clone());
-        w.set_uid(obj.uid());
-        w.set_user(obj.user());
+fn copy_way(obj: &impl Way, new: &mut impl Way) {
+    copy_base(obj, new);
+    new.set_nodes(obj.nodes().iter().cloned());
+}
 
-        for (k, v) in obj.tags() {
-            w.set_tag(k, v);
+fn copy_relation(obj: &impl Relation, new: &mut impl Relation) {
+    copy_base(obj, new);
+    new.set_members(obj.members());
+}
+
+fn convert_node<T: Node + FromId>(obj: &impl Node) -> T {
+    let mut n = T::from_id(obj.id());
+    copy_node(obj, &mut n);
+    n
+}
+
+fn convert_way<T: Way + FromId>(obj: &impl Way) -> T {
+    let mut w = T::from_id(obj.id());
+    copy_way(obj, &mut w);
+    w
+}
+
+fn convert_relation<T: Relation + FromId>(obj: &impl Relation) -> T {
+    let mut r = T::from_id(obj.id());
+    copy_relation(obj, &mut r);
+    r
+}
+
+/// `semantic_eq` for the parts all objects have: the id, deleted status & tags (in any order)
+pub(crate) fn base_semantic_eq(a: &impl OSMObjBase, b: &impl OSMObjBase) -> bool {
+    a.id() == b.id()
+        && a.deleted() == b.deleted()
+        && a.num_tags() == b.num_tags()
+        && a.tags().all(|(k, v)| b.tag(k) == Some(v))
+}
+
+#[doc(hidden)]
+pub fn node_semantic_eq(a: &impl Node, b: &impl Node) -> bool {
+    base_semantic_eq(a, b) && a.lat_lon() == b.lat_lon()
+}
+
+#[doc(hidden)]
+pub fn way_semantic_eq(a: &impl Way, b: &impl Way) -> bool {
+    base_semantic_eq(a, b) && a.nodes() == b.nodes()
+}
+
+#[doc(hidden)]
+pub fn relation_semantic_eq(a: &impl Relation, b: &impl Relation) -> bool {
+    base_semantic_eq(a, b) && a.members().eq(b.members())
+}
+
+// Converting between the object families, with `From`
+macro_rules! impl_conversions {
+    (
+        ($node:ident, $way:ident, $relation:ident, $obj:ident) =>
+        ($to_node:ident, $to_way:ident, $to_relation:ident, $to_obj:ident)
+    ) => {
+        impl From<$node> for $to_node {
+            fn from(obj: $node) -> Self {
+                convert_node(&obj)
+            }
         }
 
-        w.set_nodes(obj.nodes().iter().map(|i| i.clone()));
+        impl From<$way> for $to_way {
+            fn from(obj: $way) -> Self {
+                convert_way(&obj)
+            }
+        }
 
-        w
-    }
+        impl From<$relation> for $to_relation {
+            fn from(obj: $relation) -> Self {
+                convert_relation(&obj)
+            }
+        }
+
+        impl From<$obj> for $to_obj {
+            fn from(obj: $obj) -> Self {
+                match obj {
+                    $obj::Node(n) => $to_obj::Node(n.into()),
+                    $obj::Way(w) => $to_obj::Way(w.into()),
+                    $obj::Relation(r) => $to_obj::Relation(r.into()),
+                }
+            }
+        }
+    };
 }
 
-impl From<RcRelation> for StringRelation {
-    fn from(obj: RcRelation) -> Self {
-        let mut r = StringRelationBuilder::default()
-            ._id(obj.id())
-            .build()
-            .unwrap();
+impl_conversions!(
+    (RcNode, RcWay, RcRelation, RcOSMObj) => (StringNode, StringWay, StringRelation, StringOSMObj)
+);
+impl_conversions!(
+    (ArcNode, ArcWay, ArcRelation, ArcOSMObj) => (StringNode, StringWay, StringRelation, StringOSMObj)
+);
+impl_conversions!(
+    (StringNode, StringWay, StringRelation, StringOSMObj) => (RcNode, RcWay, RcRelation, RcOSMObj)
+);
+impl_conversions!(
+    (StringNode, StringWay, StringRelation, StringOSMObj) => (ArcNode, ArcWay, ArcRelation, ArcOSMObj)
+);
+impl_conversions!(
+    (InternedNode, InternedWay, InternedRelation, InternedOSMObj) => (StringNode, StringWay, StringRelation, StringOSMObj)
+);
+impl_conversions!(
+    (RcNode, RcWay, RcRelation, RcOSMObj) => (ArcNode, ArcWay, ArcRelation, ArcOSMObj)
+);
+impl_conversions!(
+    (ArcNode, ArcWay, ArcRelation, ArcOSMObj) => (RcNode, RcWay, RcRelation, RcOSMObj)
+);
 
-        r.set_version(obj.version());
-        r.set_deleted(obj.deleted());
-        r.set_changeset_id(obj.changeset_id());
-        r.set_timestamp(obj.timestamp().clone());
-        r.set_uid(obj.uid());
-        r.set_user(obj.user());
+/// `tags_iter`, the unboxed `tags()`, for a family which keeps its tags in a slice of `($s, $s)`
+macro_rules! impl_tags_iter {
+    ($s:ty; $($ty:ident => |$x:ident| $tags:expr),*; $obj:ident) => {
+        $(
+            impl $ty {
+                /// The tags, like `OSMObjBase::tags` without boxing the iterator
+                pub fn tags_iter(&self) -> TagIter<'_, $s> {
+                    let $x = self;
+                    TagIter::new($tags)
+                }
+            }
+        )*
 
-        for (k, v) in obj.tags() {
-            r.set_tag(k, v);
+        impl $obj {
+            /// The tags, like `OSMObjBase::tags` without boxing the iterator
+            pub fn tags_iter(&self) -> TagIter<'_, $s> {
+                match self {
+                    $obj::Node(x) => x.tags_iter(),
+                    $obj::Way(x) => x.tags_iter(),
+                    $obj::Relation(x) => x.tags_iter(),
+                }
+            }
         }
+    };
+}
 
-        r.set_members(obj.members());
+impl_tags_iter!(String;
+    StringNode => |n| &n._tags, StringWay => |w| &w._tags, StringRelation => |r| &r._tags;
+    StringOSMObj
+);
+impl_tags_iter!(Rc<str>;
+    RcNode => |n| n._tags.as_ref().map_or(&[], |t| &t[..]),
+    RcWay => |w| &w._tags,
+    RcRelation => |r| &r._tags;
+    RcOSMObj
+);
+impl_tags_iter!(Arc<str>;
+    ArcNode => |n| n._tags.as_ref().map_or(&[], |t| &t[..]),
+    ArcWay => |w| &w._tags,
+    ArcRelation => |r| &r._tags;
+    ArcOSMObj
+);
 
-        r
-    }
+/// `Display` as one line of OPL, see `OSMObj::to_opl_line`
+macro_rules! impl_display {
+    ($node:ident, $way:ident, $relation:ident, $obj:ident) => {
+        impl ::std::fmt::Display for $node {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(&Node::to_opl_line(self))
+            }
+        }
+        impl ::std::fmt::Display for $way {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(&Way::to_opl_line(self))
+            }
+        }
+        impl ::std::fmt::Display for $relation {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(&Relation::to_opl_line(self))
+            }
+        }
+        impl ::std::fmt::Display for $obj {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(&OSMObj::to_opl_line(self))
+            }
+        }
+    };
+}
+
+impl_display!(StringNode, StringWay, StringRelation, StringOSMObj);
+impl_display!(RcNode, RcWay, RcRelation, RcOSMObj);
+impl_display!(ArcNode, ArcWay, ArcRelation, ArcOSMObj);
+impl_display!(InternedNode, InternedWay, InternedRelation, InternedOSMObj);
+
+#[cfg(feature = "compact")]
+mod compact_impls {
+    use super::*;
+
+    impl_display!(CompactNode, CompactWay, CompactRelation, CompactOSMObj);
+    impl_tags_iter!(::smol_str::SmolStr;
+        CompactNode => |n| &n._tags, CompactWay => |w| &w._tags, CompactRelation => |r| &r._tags;
+        CompactOSMObj
+    );
+
+    impl_conversions!(
+        (StringNode, StringWay, StringRelation, StringOSMObj) => (CompactNode, CompactWay, CompactRelation, CompactOSMObj)
+    );
+    impl_conversions!(
+        (CompactNode, CompactWay, CompactRelation, CompactOSMObj) => (StringNode, StringWay, StringRelation, StringOSMObj)
+    );
+    impl_conversions!(
+        (ArcNode, ArcWay, ArcRelation, ArcOSMObj) => (CompactNode, CompactWay, CompactRelation, CompactOSMObj)
+    );
+    impl_conversions!(
+        (RcNode, RcWay, RcRelation, RcOSMObj) => (CompactNode, CompactWay, CompactRelation, CompactOSMObj)
+    );
 }
 
-impl From<RcOSMObj> for StringOSMObj {
-    fn from(obj: RcOSMObj) -> Self {
-        match obj {
-            RcOSMObj::Node(n) => StringOSMObj::Node(n.into()),
-            RcOSMObj::Way(w) => StringOSMObj::Way(w.into()),
-            RcOSMObj::Relation(r) => StringOSMObj::Relation(r.into()),
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Lat, Lon, OSMObjectType, TimestampFormat};
+
+    fn objects() -> Vec<StringOSMObj> {
+        let mut node = StringNode::from_id(1);
+        node.set_version(2);
+        node.set_timestamp(TimestampFormat::EpochNunber(10));
+        node.set_user("foo");
+        node.set_tag("name", "bar");
+        node.set_lat_lon((Lat::from(1.), Lon::from(2.)));
+
+        let mut way = StringWay::from_id(2);
+        way.set_deleted(true);
+        way.set_nodes(vec![1, 2, 3]);
+
+        let mut relation = StringRelation::from_id(3);
+        relation.set_uid(5);
+        relation.set_members(vec![(OSMObjectType::Way, 2, "outer")]);
+
+        vec![node.into(), way.into(), relation.into()]
+    }
+
+    #[test]
+    fn round_trips() {
+        for obj in objects() {
+            let rc: RcOSMObj = obj.clone().into();
+            assert_eq!(rc.id(), obj.id());
+            assert_eq!(rc.object_type(), obj.object_type());
+            let arc: ArcOSMObj = rc.into();
+            let rc: RcOSMObj = arc.clone().into();
+            let string: StringOSMObj = arc.into();
+            assert_eq!(string, obj);
+            let string: StringOSMObj = rc.into();
+            assert_eq!(string, obj);
+        }
+    }
+
+    #[test]
+    fn semantic_eq() {
+        for obj in objects() {
+            let mut other = obj.clone();
+            other.set_version(10);
+            other.set_changeset_id(4);
+            other.set_user("someone else");
+            other.set_timestamp(None);
+            assert_ne!(obj, other);
+            assert!(obj.semantic_eq(&other));
+
+            let rc: RcOSMObj = other.clone().into();
+            let mut rc_other = rc.clone();
+            rc_other.set_tag("new", "tag");
+            assert!(!rc.semantic_eq(&rc_other));
+        }
+
+        let mut a = StringNode::from_id(1);
+        a.set_tag("a", "1");
+        a.set_tag("b", "2");
+        let mut b = StringNode::from_id(1);
+        b.set_tag("b", "2");
+        b.set_tag("a", "1");
+        assert!(a.semantic_eq(&b));
+        b.set_lat_lon((Lat::from(1.), Lon::from(1.)));
+        assert!(!a.semantic_eq(&b));
+
+        let mut w = StringWay::from_id(2);
+        w.set_nodes(vec![1, 2]);
+        let mut w2 = w.clone();
+        w2.set_nodes(vec![2, 1]);
+        assert!(!w.semantic_eq(&w2));
+    }
+
+    #[test]
+    fn arc_types_are_send_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<ArcNode>();
+        assert_send_sync::<ArcWay>();
+        assert_send_sync::<ArcRelation>();
+        assert_send_sync::<ArcOSMObj>();
+
+        let objs: Vec<ArcOSMObj> = objects().into_iter().map(Into::into).collect();
+        let shared = std::sync::Arc::new(objs.clone());
+        let handle = std::thread::spawn(move || shared.iter().map(|o| o.num_tags()).sum::<usize>());
+        assert_eq!(handle.join().unwrap(), 1);
+        // Cloning shares the strings
+        let node = objs[0].clone().into_node().unwrap();
+        assert!(std::ptr::eq(
+            node.tag("name").unwrap(),
+            objs[0].tag("name").unwrap()
+        ));
+    }
+
+    #[test]
+    fn display() {
+        let lines: Vec<String> = objects().iter().map(|o| o.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "n1 v2 dV t1970-01-01T00:00:10Z ufoo Tname=bar x2 y1",
+                "w2 dD T Nn1,n2,n3",
+                "r3 dV i5 T Mw2@outer",
+            ]
+        );
+
+        let objs = objects();
+        assert_eq!(objs[0].as_node().unwrap().to_string(), lines[0]);
+        let arc: ArcOSMObj = objs[2].clone().into();
+        assert_eq!(arc.to_string(), lines[2]);
+        assert_eq!(arc.as_relation().unwrap().to_opl_line(), lines[2]);
+    }
+
+    #[test]
+    fn tags_iter() {
+        let mut objs = objects();
+        objs[0].set_tag("shop", "bakery");
+        let pool = std::sync::Arc::new(StringPool::new());
+        for obj in objs {
+            let tags: Vec<_> = obj.tags().collect();
+            assert_eq!(obj.tags_iter().collect::<Vec<_>>(), tags);
+            assert_eq!(obj.tags_iter().len(), tags.len());
+            assert_eq!(
+                obj.tags_iter().rev().collect::<Vec<_>>(),
+                tags.iter().rev().cloned().collect::<Vec<_>>()
+            );
+            let rc: RcOSMObj = obj.clone().into();
+            assert!(rc.tags_iter().eq(tags.iter().cloned()));
+            let arc: ArcOSMObj = obj.clone().into();
+            assert!(arc.tags_iter().eq(tags.iter().cloned()));
+            let interned = InternedOSMObj::from_obj(&obj, &pool);
+            assert!(interned.tags_iter().eq(tags.iter().cloned()));
         }
     }
+
+    #[test]
+    fn cow() {
+        use std::borrow::Cow;
+        let objs = objects();
+        let mut node = Cow::Borrowed(&objs[0]);
+        node.unset_tag("missing");
+        node.set_tag("name", "bar");
+        assert_eq!(node.as_node().unwrap().lat(), Some(1.));
+        assert!(match node {
+            Cow::Borrowed(_) => true,
+            Cow::Owned(_) => false,
+        });
+
+        node.set_tag("name", "baz");
+        assert_eq!(node.tag("name"), Some("baz"));
+        assert_eq!(objs[0].tag("name"), Some("bar"));
+        assert!(!node.semantic_eq(&Cow::Borrowed(&objs[0])));
+        assert_eq!(
+            node.to_opl_line(),
+            "n1 v2 dV t1970-01-01T00:00:10Z ufoo Tname=baz x2 y1"
+        );
+    }
 }
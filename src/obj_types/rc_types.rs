@@ -1,3 +1,4 @@
+use super::{node_semantic_eq, relation_semantic_eq, way_semantic_eq};
 use std::rc::Rc;
 use *;
 
@@ -21,49 +22,49 @@ macro_rules! func_call_inner_set {
     };
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct RcNode {
     pub(crate) _id: ObjId,
     pub(crate) _version: Option<u32>,
     pub(crate) _deleted: bool,
-    pub(crate) _changeset_id: Option<u32>,
+    pub(crate) _changeset_id: Option<u64>,
     pub(crate) _timestamp: Option<TimestampFormat>,
-    pub(crate) _uid: Option<u32>,
+    pub(crate) _uid: Option<u64>,
     pub(crate) _user: Option<Rc<str>>,
     pub(crate) _tags: Option<Vec<(Rc<str>, Rc<str>)>>,
 
     pub(crate) _lat_lon: Option<(Lat, Lon)>,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct RcWay {
     pub(crate) _id: ObjId,
     pub(crate) _version: Option<u32>,
     pub(crate) _deleted: bool,
-    pub(crate) _changeset_id: Option<u32>,
+    pub(crate) _changeset_id: Option<u64>,
     pub(crate) _timestamp: Option<TimestampFormat>,
-    pub(crate) _uid: Option<u32>,
+    pub(crate) _uid: Option<u64>,
     pub(crate) _user: Option<Rc<str>>,
     pub(crate) _tags: Vec<(Rc<str>, Rc<str>)>,
 
     pub(crate) _nodes: Vec<ObjId>,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct RcRelation {
     pub(crate) _id: ObjId,
     pub(crate) _version: Option<u32>,
     pub(crate) _deleted: bool,
-    pub(crate) _changeset_id: Option<u32>,
+    pub(crate) _changeset_id: Option<u64>,
     pub(crate) _timestamp: Option<TimestampFormat>,
-    pub(crate) _uid: Option<u32>,
+    pub(crate) _uid: Option<u64>,
     pub(crate) _user: Option<Rc<str>>,
     pub(crate) _tags: Vec<(Rc<str>, Rc<str>)>,
 
-    pub(crate) _members: Vec<(OSMObjectType, ObjId, Rc<str>)>,
+    pub(crate) _members: Vec<Member<Rc<str>>>,
 }
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub enum RcOSMObj {
     Node(RcNode),
     Way(RcWay),
@@ -71,6 +72,15 @@ pub enum RcOSMObj {
 }
 
 impl OSMObjBase for RcOSMObj {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (RcOSMObj::Node(a), RcOSMObj::Node(b)) => a.semantic_eq(b),
+            (RcOSMObj::Way(a), RcOSMObj::Way(b)) => a.semantic_eq(b),
+            (RcOSMObj::Relation(a), RcOSMObj::Relation(b)) => a.semantic_eq(b),
+            _ => false,
+        }
+    }
+
     fn id(&self) -> ObjId {
         func_call_inner_get!(self, id)
     }
@@ -80,13 +90,13 @@ impl OSMObjBase for RcOSMObj {
     fn deleted(&self) -> bool {
         func_call_inner_get!(self, deleted)
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         func_call_inner_get!(self, changeset_id)
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         func_call_inner_get!(self, timestamp)
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         func_call_inner_get!(self, uid)
     }
     fn user(&self) -> Option<&str> {
@@ -102,13 +112,13 @@ impl OSMObjBase for RcOSMObj {
     fn set_deleted(&mut self, val: bool) {
         func_call_inner_set!(self, set_deleted, val);
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         func_call_inner_set!(self, set_changeset_id, val);
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         func_call_inner_set!(self, set_timestamp, val);
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         func_call_inner_set!(self, set_uid, val);
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -116,11 +126,7 @@ impl OSMObjBase for RcOSMObj {
     }
 
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        match self {
-            RcOSMObj::Node(x) => x.tags(),
-            RcOSMObj::Way(x) => x.tags(),
-            RcOSMObj::Relation(x) => x.tags(),
-        }
+        Box::new(self.tags_iter())
     }
 
     fn num_tags(&self) -> usize {
@@ -250,6 +256,10 @@ impl OSMObj for RcOSMObj {
 }
 
 impl OSMObjBase for RcNode {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        node_semantic_eq(self, other)
+    }
+
     fn id(&self) -> ObjId {
         self._id
     }
@@ -259,13 +269,13 @@ impl OSMObjBase for RcNode {
     fn deleted(&self) -> bool {
         self._deleted
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         self._changeset_id
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         &self._timestamp
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         self._uid
     }
     fn user(&self) -> Option<&str> {
@@ -284,13 +294,13 @@ impl OSMObjBase for RcNode {
     fn set_deleted(&mut self, val: bool) {
         self._deleted = val.into();
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         self._changeset_id = val.into();
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         self._timestamp = val.into();
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         self._uid = val.into();
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -298,10 +308,7 @@ impl OSMObjBase for RcNode {
     }
 
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        match self._tags {
-            None => Box::new(std::iter::empty()),
-            Some(ref t) => Box::new(t.iter().map(|(k, v)| (k.as_ref(), v.as_ref()))),
-        }
+        Box::new(self.tags_iter())
     }
 
     fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
@@ -369,6 +376,10 @@ impl Node for RcNode {
 }
 
 impl OSMObjBase for RcWay {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        way_semantic_eq(self, other)
+    }
+
     fn id(&self) -> ObjId {
         self._id
     }
@@ -378,13 +389,13 @@ impl OSMObjBase for RcWay {
     fn deleted(&self) -> bool {
         self._deleted
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         self._changeset_id
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         &self._timestamp
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         self._uid
     }
     fn user(&self) -> Option<&str> {
@@ -403,13 +414,13 @@ impl OSMObjBase for RcWay {
     fn set_deleted(&mut self, val: bool) {
         self._deleted = val.into();
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         self._changeset_id = val.into();
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         self._timestamp = val.into();
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         self._uid = val.into();
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -417,7 +428,7 @@ impl OSMObjBase for RcWay {
     }
 
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        Box::new(self._tags.iter().map(|(k, v)| (k.as_ref(), v.as_ref())))
+        Box::new(self.tags_iter())
     }
 
     fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
@@ -479,9 +490,15 @@ impl Way for RcWay {
         self._nodes.truncate(0);
         self._nodes.extend(nodes.into_iter().map(|i| i.into()));
     }
+
+    __osmio_way_edit!(_nodes);
 }
 
 impl OSMObjBase for RcRelation {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        relation_semantic_eq(self, other)
+    }
+
     fn id(&self) -> ObjId {
         self._id
     }
@@ -491,13 +508,13 @@ impl OSMObjBase for RcRelation {
     fn deleted(&self) -> bool {
         self._deleted
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         self._changeset_id
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         &self._timestamp
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         self._uid
     }
     fn user(&self) -> Option<&str> {
@@ -516,13 +533,13 @@ impl OSMObjBase for RcRelation {
     fn set_deleted(&mut self, val: bool) {
         self._deleted = val.into();
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         self._changeset_id = val.into();
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         self._timestamp = val.into();
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         self._uid = val.into();
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -530,7 +547,7 @@ impl OSMObjBase for RcRelation {
     }
 
     fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        Box::new(self._tags.iter().map(|(k, v)| (k.as_ref(), v.as_ref())))
+        Box::new(self.tags_iter())
     }
 
     fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
@@ -577,26 +594,26 @@ impl OSMObjBase for RcRelation {
 }
 
 impl Relation for RcRelation {
-    fn members<'a>(
-        &'a self,
-    ) -> Box<dyn ExactSizeIterator<Item = (OSMObjectType, ObjId, &'a str)> + 'a> {
+    fn members<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = Member<&'a str>> + 'a> {
         Box::new(
             self._members
                 .iter()
-                .map(|(t, o, r)| (t.clone(), o.clone(), r.as_ref())),
+                .map(|m| Member::new(m.mtype, m.id, m.role.as_ref())),
         )
     }
 
-    fn set_members(
-        &mut self,
-        members: impl IntoIterator<Item = (OSMObjectType, ObjId, impl Into<String>)>,
-    ) {
+    fn set_members<M, R>(&mut self, members: impl IntoIterator<Item = M>)
+    where
+        M: Into<Member<R>>,
+        R: Into<String>,
+    {
         self._members.truncate(0);
 
-        self._members.extend(
-            members
-                .into_iter()
-                .map(|(t, i, r)| (t, i, Rc::from(r.into()))),
-        );
+        self._members.extend(members.into_iter().map(|m| {
+            let m = m.into();
+            Member::new(m.mtype, m.id, Rc::from(m.role.into()))
+        }));
     }
+
+    __osmio_relation_edit!(_members);
 }
@@ -20,81 +20,251 @@ macro_rules! func_call_inner_set {
     };
 }
 
-#[builder(setter(strip_option))]
-#[derive(PartialEq, Debug, Builder, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Builder, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[builder(setter(strip_option), build_fn(validate = "Self::validate"))]
 pub struct StringNode {
+    #[cfg_attr(feature = "serde", serde(rename = "id"))]
     pub(crate) _id: ObjId,
 
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "version"))]
     pub(crate) _version: Option<u32>,
 
     #[builder(default = "false")]
+    #[cfg_attr(feature = "serde", serde(rename = "deleted"))]
     pub(crate) _deleted: bool,
     #[builder(default = "None")]
-    pub(crate) _changeset_id: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "changeset_id"))]
+    pub(crate) _changeset_id: Option<u64>,
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "timestamp"))]
     pub(crate) _timestamp: Option<TimestampFormat>,
     #[builder(default = "None")]
-    pub(crate) _uid: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "uid"))]
+    pub(crate) _uid: Option<u64>,
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "user"))]
     pub(crate) _user: Option<String>,
 
-    #[builder(default = "HashMap::new()")]
-    pub(crate) _tags: HashMap<String, String>,
+    #[builder(default = "Vec::new()")]
+    #[cfg_attr(feature = "serde", serde(rename = "tags"))]
+    pub(crate) _tags: Vec<(String, String)>,
 
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "lat_lon"))]
     pub(crate) _lat_lon: Option<(Lat, Lon)>,
 }
 
-#[derive(PartialEq, Debug, Builder, Clone)]
-#[builder(setter(strip_option))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Builder, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[builder(setter(strip_option), build_fn(validate = "Self::validate"))]
 pub struct StringWay {
+    #[cfg_attr(feature = "serde", serde(rename = "id"))]
     pub(crate) _id: ObjId,
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "version"))]
     pub(crate) _version: Option<u32>,
     #[builder(default = "false")]
+    #[cfg_attr(feature = "serde", serde(rename = "deleted"))]
     pub(crate) _deleted: bool,
     #[builder(default = "None")]
-    pub(crate) _changeset_id: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "changeset_id"))]
+    pub(crate) _changeset_id: Option<u64>,
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "timestamp"))]
     pub(crate) _timestamp: Option<TimestampFormat>,
     #[builder(default = "None")]
-    pub(crate) _uid: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "uid"))]
+    pub(crate) _uid: Option<u64>,
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "user"))]
     pub(crate) _user: Option<String>,
 
-    #[builder(default = "HashMap::new()")]
-    pub(crate) _tags: HashMap<String, String>,
+    #[builder(default = "Vec::new()")]
+    #[cfg_attr(feature = "serde", serde(rename = "tags"))]
+    pub(crate) _tags: Vec<(String, String)>,
 
     #[builder(default = "Vec::new()")]
+    #[cfg_attr(feature = "serde", serde(rename = "nodes"))]
     pub(crate) _nodes: Vec<ObjId>,
 }
 
-#[derive(PartialEq, Debug, Builder, Clone)]
-#[builder(setter(strip_option))]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Builder, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+#[builder(setter(strip_option), build_fn(validate = "Self::validate"))]
 pub struct StringRelation {
+    #[cfg_attr(feature = "serde", serde(rename = "id"))]
     pub(crate) _id: ObjId,
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "version"))]
     pub(crate) _version: Option<u32>,
     #[builder(default = "false")]
+    #[cfg_attr(feature = "serde", serde(rename = "deleted"))]
     pub(crate) _deleted: bool,
     #[builder(default = "None")]
-    pub(crate) _changeset_id: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "changeset_id"))]
+    pub(crate) _changeset_id: Option<u64>,
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "timestamp"))]
     pub(crate) _timestamp: Option<TimestampFormat>,
     #[builder(default = "None")]
-    pub(crate) _uid: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(rename = "uid"))]
+    pub(crate) _uid: Option<u64>,
     #[builder(default = "None")]
+    #[cfg_attr(feature = "serde", serde(rename = "user"))]
     pub(crate) _user: Option<String>,
 
-    #[builder(default = "HashMap::new()")]
-    pub(crate) _tags: HashMap<String, String>,
+    #[builder(default = "Vec::new()")]
+    #[cfg_attr(feature = "serde", serde(rename = "tags"))]
+    pub(crate) _tags: Vec<(String, String)>,
 
     #[builder(default = "Vec::new()")]
-    pub(crate) _members: Vec<(OSMObjectType, ObjId, String)>,
+    #[cfg_attr(feature = "serde", serde(rename = "members"))]
+    pub(crate) _members: Vec<Member>,
+}
+
+/// Object ids can be negative (for new objects), but not 0
+fn validate_id(id: Option<ObjId>) -> Result<(), String> {
+    match id {
+        Some(0) => Err("id must not be 0".to_string()),
+        _ => Ok(()),
+    }
+}
+
+impl StringNodeBuilder {
+    /// Set the location from degrees, or an error if it's invalid (see `checked_lat_lon`)
+    pub fn try_lat_lon(
+        &mut self,
+        lat: f64,
+        lon: f64,
+        policy: CoordPolicy,
+    ) -> Result<&mut Self, InvalidLocation> {
+        self._lat_lon = Some(Some(checked_lat_lon(lat, lon, policy)?));
+        Ok(self)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        validate_id(self._id)?;
+        if let Some(Some((lat, lon))) = self._lat_lon {
+            if !lat.is_valid() {
+                return Err(format!("latitude {} out of range", lat));
+            }
+            if !lon.is_valid() {
+                return Err(format!("longitude {} out of range", lon));
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the tags of the object being built, from `(key, value)` pairs
+    pub fn tags<K: Into<String>, V: Into<String>>(
+        &mut self,
+        tags: impl IntoIterator<Item = (K, V)>,
+    ) -> &mut Self {
+        self._tags = Some(
+            tags.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        );
+        self
+    }
+}
+
+impl StringWayBuilder {
+    fn validate(&self) -> Result<(), String> {
+        validate_id(self._id)
+    }
+
+    /// Set the tags of the object being built, from `(key, value)` pairs
+    pub fn tags<K: Into<String>, V: Into<String>>(
+        &mut self,
+        tags: impl IntoIterator<Item = (K, V)>,
+    ) -> &mut Self {
+        self._tags = Some(
+            tags.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        );
+        self
+    }
+}
+
+impl StringRelationBuilder {
+    fn validate(&self) -> Result<(), String> {
+        validate_id(self._id)
+    }
+
+    /// Set the tags of the object being built, from `(key, value)` pairs
+    pub fn tags<K: Into<String>, V: Into<String>>(
+        &mut self,
+        tags: impl IntoIterator<Item = (K, V)>,
+    ) -> &mut Self {
+        self._tags = Some(
+            tags.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        );
+        self
+    }
+
+    /// Add a member (a `Member`, or `(OSMObjectType, ObjId, role)` tuple) to the relation being
+    /// built
+    pub fn member<R: Into<String>>(&mut self, member: impl Into<Member<R>>) -> &mut Self {
+        let m = member.into();
+        self._members
+            .get_or_insert_with(Vec::new)
+            .push(Member::new(m.mtype, m.id, m.role.into()));
+        self
+    }
+}
+
+impl StringNode {
+    /// A node with just an id & location, e.g. for tests.
+    ///
+    /// # Panics
+    /// If the id is 0, or the location is out of range
+    pub fn new(id: ObjId, lat_lon: (impl Into<Lat>, impl Into<Lon>)) -> Self {
+        StringNodeBuilder::default()
+            ._id(id)
+            ._lat_lon((lat_lon.0.into(), lat_lon.1.into()))
+            .build()
+            .unwrap()
+    }
+}
+
+impl StringWay {
+    /// A way with just an id & nodes, e.g. for tests.
+    ///
+    /// # Panics
+    /// If the id is 0
+    pub fn new(id: ObjId, nodes: impl IntoIterator<Item = impl Into<ObjId>>) -> Self {
+        StringWayBuilder::default()
+            ._id(id)
+            ._nodes(nodes.into_iter().map(Into::into).collect())
+            .build()
+            .unwrap()
+    }
 }
 
-#[derive(PartialEq, Debug, Clone)]
+impl StringRelation {
+    /// A relation with just an id & members, e.g. for tests.
+    ///
+    /// # Panics
+    /// If the id is 0
+    pub fn new<M, R>(id: ObjId, members: impl IntoIterator<Item = M>) -> Self
+    where
+        M: Into<Member<R>>,
+        R: Into<String>,
+    {
+        let mut r = StringRelationBuilder::default()._id(id).build().unwrap();
+        r.set_members(members);
+        r
+    }
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
 pub enum StringOSMObj {
     Node(StringNode),
     Way(StringWay),
@@ -118,6 +288,15 @@ impl From<StringRelation> for StringOSMObj {
 }
 
 impl OSMObjBase for StringOSMObj {
+    fn semantic_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (StringOSMObj::Node(a), StringOSMObj::Node(b)) => a.semantic_eq(b),
+            (StringOSMObj::Way(a), StringOSMObj::Way(b)) => a.semantic_eq(b),
+            (StringOSMObj::Relation(a), StringOSMObj::Relation(b)) => a.semantic_eq(b),
+            _ => false,
+        }
+    }
+
     fn id(&self) -> ObjId {
         func_call_inner_get!(self, id)
     }
@@ -127,13 +306,13 @@ impl OSMObjBase for StringOSMObj {
     fn deleted(&self) -> bool {
         func_call_inner_get!(self, deleted)
     }
-    fn changeset_id(&self) -> Option<u32> {
+    fn changeset_id(&self) -> Option<u64> {
         func_call_inner_get!(self, changeset_id)
     }
     fn timestamp(&self) -> &Option<TimestampFormat> {
         func_call_inner_get!(self, timestamp)
     }
-    fn uid(&self) -> Option<u32> {
+    fn uid(&self) -> Option<u64> {
         func_call_inner_get!(self, uid)
     }
     fn user(&self) -> Option<&str> {
@@ -149,13 +328,13 @@ impl OSMObjBase for StringOSMObj {
     fn set_deleted(&mut self, val: bool) {
         func_call_inner_set!(self, set_deleted, val);
     }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
+    fn set_changeset_id(&mut self, val: impl Into<Option<u64>>) {
         func_call_inner_set!(self, set_changeset_id, val);
     }
     fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
         func_call_inner_set!(self, set_timestamp, val);
     }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
+    fn set_uid(&mut self, val: impl Into<Option<u64>>) {
         func_call_inner_set!(self, set_uid, val);
     }
     fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
@@ -277,240 +456,38 @@ impl OSMObj for StringOSMObj {
     }
 }
 
-impl OSMObjBase for StringNode {
-    fn id(&self) -> ObjId {
-        self._id
-    }
-    fn version(&self) -> Option<u32> {
-        self._version
-    }
-    fn deleted(&self) -> bool {
-        self._deleted
-    }
-    fn changeset_id(&self) -> Option<u32> {
-        self._changeset_id
-    }
-    fn timestamp(&self) -> &Option<TimestampFormat> {
-        &self._timestamp
-    }
-    fn uid(&self) -> Option<u32> {
-        self._uid
-    }
-    fn user(&self) -> Option<&str> {
-        match self._user {
-            None => None,
-            Some(ref s) => Some(&s),
-        }
-    }
-
-    fn set_id(&mut self, val: impl Into<ObjId>) {
-        self._id = val.into();
-    }
-    fn set_version(&mut self, val: impl Into<Option<u32>>) {
-        self._version = val.into();
-    }
-    fn set_deleted(&mut self, val: bool) {
-        self._deleted = val.into();
-    }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
-        self._changeset_id = val.into();
-    }
-    fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
-        self._timestamp = val.into();
-    }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
-        self._uid = val.into();
-    }
-    fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
-        self._user = val.into().map(|s| s.to_string());
-    }
-
-    fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        Box::new(self._tags.iter().map(|(k, v)| (k.as_ref(), v.as_ref())))
-    }
-
-    fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
-        self._tags.get(key.as_ref()).map(|s| s.as_ref())
-    }
-
-    fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
-        self._tags.insert(key.as_ref().into(), value.into());
-    }
-
-    fn unset_tag(&mut self, key: impl AsRef<str>) {
-        self._tags.remove(key.as_ref());
-    }
-}
-
-impl Node for StringNode {
-    fn lat_lon(&self) -> Option<(Lat, Lon)> {
-        self._lat_lon
-    }
-
-    fn set_lat_lon(&mut self, loc: impl Into<Option<(Lat, Lon)>>) {
-        self._lat_lon = loc.into();
-    }
-}
-
-impl OSMObjBase for StringWay {
-    fn id(&self) -> ObjId {
-        self._id
-    }
-    fn version(&self) -> Option<u32> {
-        self._version
-    }
-    fn deleted(&self) -> bool {
-        self._deleted
-    }
-    fn changeset_id(&self) -> Option<u32> {
-        self._changeset_id
-    }
-    fn timestamp(&self) -> &Option<TimestampFormat> {
-        &self._timestamp
-    }
-    fn uid(&self) -> Option<u32> {
-        self._uid
-    }
-    fn user(&self) -> Option<&str> {
-        match self._user {
-            None => None,
-            Some(ref s) => Some(&s),
-        }
-    }
-
-    fn set_id(&mut self, val: impl Into<ObjId>) {
-        self._id = val.into();
-    }
-    fn set_version(&mut self, val: impl Into<Option<u32>>) {
-        self._version = val.into();
-    }
-    fn set_deleted(&mut self, val: bool) {
-        self._deleted = val.into();
-    }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
-        self._changeset_id = val.into();
-    }
-    fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
-        self._timestamp = val.into();
-    }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
-        self._uid = val.into();
-    }
-    fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
-        self._user = val.into().map(|s| s.to_string());
-    }
-
-    fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        Box::new(self._tags.iter().map(|(k, v)| (k.as_ref(), v.as_ref())))
-    }
-
-    fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
-        self._tags.get(key.as_ref()).map(|s| s.as_ref())
-    }
-
-    fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
-        self._tags.insert(key.as_ref().into(), value.into());
-    }
-
-    fn unset_tag(&mut self, key: impl AsRef<str>) {
-        self._tags.remove(key.as_ref());
-    }
-}
-
-impl Way for StringWay {
-    fn num_nodes(&self) -> usize {
-        self._nodes.len()
-    }
-    fn nodes(&self) -> &[ObjId] {
-        self._nodes.as_ref()
-    }
-    fn node(&self, idx: usize) -> Option<ObjId> {
-        self._nodes.get(idx).cloned()
-    }
-    fn set_nodes(&mut self, nodes: impl IntoIterator<Item = impl Into<ObjId>>) {
-        self._nodes.truncate(0);
-        self._nodes.extend(nodes.into_iter().map(|i| i.into()));
-    }
-}
-
-impl OSMObjBase for StringRelation {
-    fn id(&self) -> ObjId {
-        self._id
-    }
-    fn version(&self) -> Option<u32> {
-        self._version
-    }
-    fn deleted(&self) -> bool {
-        self._deleted
-    }
-    fn changeset_id(&self) -> Option<u32> {
-        self._changeset_id
-    }
-    fn timestamp(&self) -> &Option<TimestampFormat> {
-        &self._timestamp
-    }
-    fn uid(&self) -> Option<u32> {
-        self._uid
-    }
-    fn user(&self) -> Option<&str> {
-        match self._user {
-            None => None,
-            Some(ref s) => Some(&s),
-        }
-    }
-
-    fn set_id(&mut self, val: impl Into<ObjId>) {
-        self._id = val.into();
-    }
-    fn set_version(&mut self, val: impl Into<Option<u32>>) {
-        self._version = val.into();
-    }
-    fn set_deleted(&mut self, val: bool) {
-        self._deleted = val.into();
-    }
-    fn set_changeset_id(&mut self, val: impl Into<Option<u32>>) {
-        self._changeset_id = val.into();
-    }
-    fn set_timestamp(&mut self, val: impl Into<Option<TimestampFormat>>) {
-        self._timestamp = val.into();
-    }
-    fn set_uid(&mut self, val: impl Into<Option<u32>>) {
-        self._uid = val.into();
-    }
-    fn set_user<'a>(&mut self, val: impl Into<Option<&'a str>>) {
-        self._user = val.into().map(|s| s.to_string());
-    }
-
-    fn tags<'a>(&'a self) -> Box<dyn ExactSizeIterator<Item = (&'a str, &'a str)> + 'a> {
-        Box::new(self._tags.iter().map(|(k, v)| (k.as_ref(), v.as_ref())))
-    }
-
-    fn tag(&self, key: impl AsRef<str>) -> Option<&str> {
-        self._tags.get(key.as_ref()).map(|s| s.as_ref())
-    }
-
-    fn set_tag(&mut self, key: impl AsRef<str>, value: impl Into<String>) {
-        self._tags.insert(key.as_ref().into(), value.into());
-    }
-
-    fn unset_tag(&mut self, key: impl AsRef<str>) {
-        self._tags.remove(key.as_ref());
-    }
-}
-
-impl Relation for StringRelation {
-    fn members<'a>(
-        &'a self,
-    ) -> Box<dyn ExactSizeIterator<Item = (OSMObjectType, ObjId, &'a str)> + 'a> {
-        Box::new(self._members.iter().map(|(t, i, r)| (*t, *i, r.as_str())))
-    }
-
-    fn set_members(
-        &mut self,
-        members: impl IntoIterator<Item = (OSMObjectType, ObjId, impl Into<String>)>,
-    ) {
-        self._members.truncate(0);
-        self._members
-            .extend(members.into_iter().map(|(t, i, r)| (t, i, r.into())))
-    }
-}
+impl_node!(StringNode {
+    id: _id,
+    version: _version,
+    deleted: _deleted,
+    changeset_id: _changeset_id,
+    timestamp: _timestamp,
+    uid: _uid,
+    user: _user,
+    tags: _tags,
+    lat_lon: _lat_lon,
+});
+
+impl_way!(StringWay {
+    id: _id,
+    version: _version,
+    deleted: _deleted,
+    changeset_id: _changeset_id,
+    timestamp: _timestamp,
+    uid: _uid,
+    user: _user,
+    tags: _tags,
+    nodes: _nodes,
+});
+
+impl_relation!(StringRelation {
+    id: _id,
+    version: _version,
+    deleted: _deleted,
+    changeset_id: _changeset_id,
+    timestamp: _timestamp,
+    uid: _uid,
+    user: _user,
+    tags: _tags,
+    members: _members,
+});
@@ -1,266 +1,653 @@
 //! OPL (Object Per Line) file format
 //! See http://osmcode.org/opl-file-format/
-use super::{OSMReader, OSMWriter};
-use super::{OSMWriteError};
-use super::OSMObj;
 use super::TimestampFormat;
-use std::collections::HashMap;
-use super::{ObjId, Lat, Lon, Node, Way, Relation};
+use super::WriterOptions;
+use super::{DynWriter, OSMReader, OSMWriteError, OSMWriter, ReadError, ReaderOptions};
+use super::{Lat, Lon, Member, OSMObjectType, ObjId};
+use super::{Node, OSMObj, OSMObjBase, Relation, Way};
+use obj_types::{StringNode, StringOSMObj, StringRelation, StringWay};
+use std::fmt::Write as FmtWrite;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::iter::Iterator;
-use std::io::{Read, BufReader, Write, BufRead};
-use std::rc::Rc;
 
-pub struct OPLReader<R: Read>  {
+/// Read the OPL file format
+pub struct OPLReader<R: Read> {
     buff_reader: BufReader<R>,
+    line: String,
+    line_no: usize,
+    options: ReaderOptions,
 }
 
 impl<R: Read> OSMReader for OPLReader<R> {
     type R = R;
+    type Obj = StringOSMObj;
 
     fn new(reader: R) -> OPLReader<R> {
-        OPLReader { buff_reader: BufReader::new(reader) }
+        OPLReader::new_with_options(reader, ReaderOptions::default())
+    }
+
+    fn new_with_options(reader: R, options: ReaderOptions) -> OPLReader<R> {
+        OPLReader {
+            buff_reader: BufReader::new(reader),
+            line: String::new(),
+            line_no: 0,
+            options: options,
+        }
+    }
+
+    fn set_sorted_assumption(&mut self, sorted_assumption: bool) {
+        self.options.sorted_assumption = sorted_assumption;
+    }
+    fn get_sorted_assumption(&mut self) -> bool {
+        self.options.sorted_assumption
     }
 
     fn into_inner(self) -> R {
         self.buff_reader.into_inner()
     }
 
-    fn next(&mut self) -> Option<OSMObj> {
-        let mut line = String::new();
-        let res = self.buff_reader.read_line(&mut line);
+    fn inner(&self) -> &R {
+        self.buff_reader.get_ref()
+    }
 
-        if res.is_err() {
-            None
+    fn next(&mut self) -> Option<StringOSMObj> {
+        self.try_next().unwrap()
+    }
+
+    fn try_next(&mut self) -> Result<Option<StringOSMObj>, ReadError> {
+        let mut obj = empty_obj();
+        Ok(if self.try_next_into(&mut obj)? {
+            Some(obj)
         } else {
-            decode_line(line.trim()).ok()
+            None
+        })
+    }
+
+    fn try_next_into(&mut self, obj: &mut StringOSMObj) -> Result<bool, ReadError> {
+        loop {
+            self.line.clear();
+            if self.buff_reader.read_line(&mut self.line)? == 0 {
+                return Ok(false);
+            }
+            self.line_no += 1;
+            let line = self.line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            match decode_line_into(line, obj) {
+                Ok(()) => {
+                    self.options.apply_decode_metadata(obj);
+                    return Ok(true);
+                }
+                Err(DecodeStringError) if self.options.strict => {
+                    return Err(ReadError::OPLInvalidLine(self.line_no, line.to_string()));
+                }
+                // skip it
+                Err(DecodeStringError) => {}
+            }
         }
     }
 }
 
-pub struct OPLWriter<W: Write>  {
-    writer: W,
+/// Write the OPL file format
+pub struct OPLWriter<W: Write> {
+    writer: BufWriter<W>,
     is_open: bool,
-}
-
-impl From<::std::io::Error> for OSMWriteError {
-    fn from(err: ::std::io::Error) -> OSMWriteError { OSMWriteError::OPLWrite(err) }
+    line: String,
 }
 
 impl<W: Write> OSMWriter<W> for OPLWriter<W> {
+    /// Collects 8 KiB of lines before writing them, see `new_with_options`
     fn new(writer: W) -> OPLWriter<W> {
-        OPLWriter { writer: writer, is_open: true }
+        OPLWriter {
+            writer: BufWriter::new(writer),
+            is_open: true,
+            line: String::new(),
+        }
     }
 
     fn is_open(&self) -> bool {
         self.is_open
     }
 
-    fn close(&mut self) {
-        // Do nothing
+    fn close(&mut self) -> Result<(), OSMWriteError> {
+        // OPL has no footer
         self.is_open = false;
+        self.writer.flush().map_err(OSMWriteError::OPLWrite)
     }
 
-    fn write_obj(&mut self, _obj: &OSMObj) -> Result<(), OSMWriteError> {
-        unimplemented!();
-        //match obj {
-        //    OSMObj::Node(n) => {
-        //        write!(self.writer, "n{} v{} d{} c{} t{} i{} u{} T{} x{} y{}\n", n.id, n.version.unwrap(), if n.deleted { 'D' } else { 'V' }, n.changeset_id.unwrap(), n.timestamp.unwrap(), n.uid.unwrap(), n.user.unwrap(), encode_tags(&n.tags), n.lon.map(|x| { format!("{}", x) }).unwrap_or("".to_string()), n.lat.map(|x| { format!("{}", x) }).unwrap_or("".to_string()))?;
-        //    },
-        //   OSMObj::Way(w) => {
-        //        write!(self.writer, "w{} v{} d{} c{} t{} i{} u{} T{} N{}\n", w.id, w.version.unwrap(), if w.deleted { 'D' } else { 'V' }, w.changeset_id.unwrap(), w.timestamp.unwrap(), w.uid.unwrap(), w.user.unwrap(), encode_tags(&w.tags), encode_way_nodes(&w.nodes))?;
-        //    },
-        //    OSMObj::Relation(r) => {
-        //        write!(self.writer, "r{} v{} d{} c{} t{} i{} u{} T{} M{}\n", r.id, r.version.unwrap(), if r.deleted { 'D' } else { 'V' }, r.changeset_id.unwrap(), r.timestamp.unwrap().to_iso_string(), r.uid.unwrap(), r.user.unwrap(), encode_tags(&r.tags), encode_members(&r.members))?;
-        //    },
-        //}
-        //Ok(())
+    fn write_obj(&mut self, obj: &impl OSMObj) -> Result<(), OSMWriteError> {
+        if !self.is_open {
+            return Err(OSMWriteError::AlreadyClosed);
+        }
+        self.line.clear();
+        encode_obj(&mut self.line, obj);
+        self.line.push('\n');
+        self.writer
+            .write_all(self.line.as_bytes())
+            .map_err(OSMWriteError::OPLWrite)
     }
 
+    /// What's still buffered is written, but only `close` returns an error doing that
     fn into_inner(self) -> W {
-        self.writer
+        match self.writer.into_inner() {
+            Ok(writer) => writer,
+            Err(err) => err.into_inner().into_parts().0,
+        }
     }
 }
 
-#[derive(Debug)]
-pub struct DecodeStringError;
+impl<W: Write> OPLWriter<W> {
+    /// A writer which collects `options.buffer_size` bytes of lines before writing them to
+    /// `writer`, rather than making a small write for every object
+    pub fn new_with_options(writer: W, options: &WriterOptions) -> OPLWriter<W> {
+        OPLWriter {
+            writer: BufWriter::with_capacity(options.buffer_size, writer),
+            is_open: true,
+            line: String::new(),
+        }
+    }
+
+    /// The underlying writer
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        self.writer.get_mut()
+    }
+}
+
+impl<W: Write> DynWriter for OPLWriter<W> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        self.write_obj(obj)
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        self.close()
+    }
+}
 
-fn decode_string(s: &str) -> Result<String, DecodeStringError> {
-    let mut buffer: Vec<char> = Vec::new();
-    let mut output: Vec<char> = Vec::new();
-    let mut looking_for_percent = false;
+/// Append `s` to `out`, with the characters which have a meaning in OPL (and non-printable ones)
+/// escaped as `%xx%`
+fn encode_string(out: &mut String, s: &str) {
     for c in s.chars() {
-        if looking_for_percent {
-            if c == '%' {
-                looking_for_percent = false;
-                let hex_string: String = buffer.into_iter().collect();
-                let codepoint: u32 = try!(u32::from_str_radix(hex_string.as_str(), 16).or(Err(DecodeStringError)));
-                let new_char: char = try!(::std::char::from_u32(codepoint).ok_or(DecodeStringError));
-                output.push(new_char);
-                buffer = Vec::new();
-            } else {
-                buffer.push(c);
-            }
-        } else {
-            if c == '%' {
-                looking_for_percent = true;
-                buffer.clear();
-            } else {
-                output.push(c);
-            }
+        match c {
+            ' ' | ',' | '=' | '@' | '%' => write!(out, "%{:x}%", c as u32).unwrap(),
+            c if c.is_control() => write!(out, "%{:x}%", c as u32).unwrap(),
+            c => out.push(c),
         }
     }
-    
-    Ok(output.into_iter().collect())
 }
 
-fn encode_string(s: &str) -> String {
-    let mut result: String = s.to_string();
-    for c in vec![ ' ', '\n', ',', '=', '@' ] {
-        result = result.replace(format!("{}", c).as_str(), format!("%{:X}%", (c as u32)).as_str());
+fn object_type_char(object_type: OSMObjectType) -> char {
+    match object_type {
+        OSMObjectType::Node => 'n',
+        OSMObjectType::Way => 'w',
+        OSMObjectType::Relation => 'r',
     }
-    result
 }
 
+/// Append the OPL line for this object to `out` (without the trailing newline)
+fn encode_obj(out: &mut String, obj: &impl OSMObj) {
+    match obj.object_type() {
+        OSMObjectType::Node => encode_node(out, obj.as_node().unwrap()),
+        OSMObjectType::Way => encode_way(out, obj.as_way().unwrap()),
+        OSMObjectType::Relation => encode_relation(out, obj.as_relation().unwrap()),
+    }
+}
 
-fn decode_tags(line: &str) -> Result<HashMap<Rc<String>, Rc<String>>, DecodeStringError> {
-    if line.len() == 0 {
-        return Ok(HashMap::new());
+/// The type, id, metadata & tags
+fn encode_base(out: &mut String, object_type: OSMObjectType, obj: &impl OSMObjBase) {
+    write!(out, "{}{}", object_type_char(object_type), obj.id()).unwrap();
+    if let Some(v) = obj.version() {
+        write!(out, " v{}", v).unwrap();
+    }
+    out.push_str(if obj.deleted() { " dD" } else { " dV" });
+    if let Some(c) = obj.changeset_id() {
+        write!(out, " c{}", c).unwrap();
+    }
+    if let Some(t) = obj.timestamp() {
+        write!(out, " t{}", t.to_iso_string()).unwrap();
+    }
+    if let Some(uid) = obj.uid() {
+        write!(out, " i{}", uid).unwrap();
+    }
+    if let Some(user) = obj.user() {
+        out.push_str(" u");
+        encode_string(out, user);
     }
 
-    let mut result: HashMap<Rc<String>, Rc<String>> = HashMap::new();
-    for kv in line.split(",") {
-        let kv: Vec<_> = kv.splitn(2, "=").collect();
-        let k = Rc::new(try!(decode_string(kv[0])));
-        let v = Rc::new(try!(decode_string(kv[1])));
-        result.insert(k, v);
+    out.push_str(" T");
+    for (i, (k, v)) in obj.tags().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        encode_string(out, k);
+        out.push('=');
+        encode_string(out, v);
     }
-    Ok(result)
 }
 
-fn encode_tags(tags: &HashMap<Rc<String>, Rc<String>>) -> String {
-    tags.iter().map(|(k, v)| { format!("{}={}", encode_string(k), encode_string(v)) }).collect::<Vec<String>>().join(",")
+/// Append `node` as an OPL line (without the newline) to `out`
+pub(crate) fn encode_node(out: &mut String, node: &impl Node) {
+    encode_base(out, OSMObjectType::Node, node);
+    match node.lat_lon() {
+        Some((lat, lon)) => write!(out, " x{} y{}", lon, lat).unwrap(),
+        None => out.push_str(" x y"),
+    }
+}
+
+/// Append `way` as an OPL line (without the newline) to `out`
+pub(crate) fn encode_way(out: &mut String, way: &impl Way) {
+    encode_base(out, OSMObjectType::Way, way);
+    out.push_str(" N");
+    for (i, nid) in way.nodes().iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write!(out, "n{}", nid).unwrap();
+    }
+}
 
+/// Append `relation` as an OPL line (without the newline) to `out`
+pub(crate) fn encode_relation(out: &mut String, relation: &impl Relation) {
+    encode_base(out, OSMObjectType::Relation, relation);
+    out.push_str(" M");
+    for (i, member) in relation.members().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write!(out, "{}{}@", object_type_char(member.mtype), member.id).unwrap();
+        encode_string(out, member.role);
+    }
 }
 
+#[derive(Debug)]
+pub struct DecodeStringError;
 
-fn decode_way_nodes(line: &str) -> Result<Vec<ObjId>, DecodeStringError> {
-    let mut result: Vec<ObjId> = Vec::new();
-    for x in line.split(",").map(|x| { x.chars().skip(1).collect::<String>() }) {
-        let node_id = try!(x.parse::<ObjId>().or(Err(DecodeStringError)));
-        result.push(node_id);
+/// Decode the `%xx%` escapes in a string, replacing the contents of `output`
+fn decode_string_into(output: &mut String, s: &str) -> Result<(), DecodeStringError> {
+    output.clear();
+    let mut parts = s.split('%');
+    output.push_str(parts.next().unwrap_or(""));
+    loop {
+        let hex_string = match parts.next() {
+            None => break,
+            Some(h) => h,
+        };
+        let codepoint = u32::from_str_radix(hex_string, 16).map_err(|_| DecodeStringError)?;
+        output.push(::std::char::from_u32(codepoint).ok_or(DecodeStringError)?);
+        // the text between this escape and the next one
+        output.push_str(parts.next().ok_or(DecodeStringError)?);
     }
-    Ok(result)
+
+    Ok(())
 }
 
-fn encode_way_nodes(nodes: &Vec<ObjId>) -> String {
-    nodes.iter().map(|&n| { format!("n{}", n) }).collect::<Vec<String>>().join(",")
+/// Decode the tags into `tags`, reusing its strings
+fn decode_tags_into(tags: &mut Vec<(String, String)>, s: &str) -> Result<(), DecodeStringError> {
+    let mut len = 0;
+    for kv in s.split(',').filter(|kv| !kv.is_empty()) {
+        let mut kv = kv.splitn(2, '=');
+        let (k, v) = (
+            kv.next().ok_or(DecodeStringError)?,
+            kv.next().ok_or(DecodeStringError)?,
+        );
+        if len == tags.len() {
+            tags.push((String::new(), String::new()));
+        }
+        decode_string_into(&mut tags[len].0, k)?;
+        decode_string_into(&mut tags[len].1, v)?;
+        len += 1;
+    }
+    tags.truncate(len);
+    Ok(())
 }
 
-fn decode_members(line: &str) -> Result<Vec<(char, ObjId, Rc<String>)>, DecodeStringError> {
-    let mut result = Vec::new();
-    for x in line.split(",") {
-        let (obj_type, rest) = try!(split_key_value(x));
-        let obj_type = try!(obj_type.chars().next().ok_or(DecodeStringError));
-        let rest: Vec<_> = rest.splitn(2, "@").collect();
-        let (id, role) = (rest[0], rest[1]);
-        let id: ObjId = try!(id.parse().or(Err(DecodeStringError)));
-        result.push((obj_type, id, Rc::new(role.to_string())));
-    }
-    Ok(result)
+fn decode_way_nodes_into(nodes: &mut Vec<ObjId>, s: &str) -> Result<(), DecodeStringError> {
+    nodes.clear();
+    for n in s.split(',').filter(|n| !n.is_empty()) {
+        // each node is `n123`, optionally with a location (`n123x1.2y3.4`)
+        let n = n.strip_prefix('n').ok_or(DecodeStringError)?;
+        let end = n.find('x').unwrap_or(n.len());
+        nodes.push(n[..end].parse().map_err(|_| DecodeStringError)?);
+    }
+    Ok(())
 }
 
-fn encode_members(members: &Vec<(char, ObjId, Rc<String>)>) -> String {
-    members.iter().map(|&(t, id, ref role)| { format!("{}{}@{}", t, id, role) }).collect::<Vec<String>>().join(",")
+/// Decode the members into `members`, reusing their role strings
+fn decode_members_into(members: &mut Vec<Member>, s: &str) -> Result<(), DecodeStringError> {
+    let mut len = 0;
+    for m in s.split(',').filter(|m| !m.is_empty()) {
+        let (member_type, rest) = split_key_value(m)?;
+        let member_type = member_type.parse().map_err(|_| DecodeStringError)?;
+        let mut rest = rest.splitn(2, '@');
+        let id = rest
+            .next()
+            .ok_or(DecodeStringError)?
+            .parse()
+            .map_err(|_| DecodeStringError)?;
+        if len == members.len() {
+            members.push(Member::new(member_type, id, String::new()));
+        }
+        let member = &mut members[len];
+        member.mtype = member_type;
+        member.id = id;
+        decode_string_into(&mut member.role, rest.next().ok_or(DecodeStringError)?)?;
+        len += 1;
+    }
+    members.truncate(len);
+    Ok(())
 }
 
 fn split_key_value(s: &str) -> Result<(&str, &str), DecodeStringError> {
-    if s.len() == 1 {
-        // e.g. empty tags
-       Ok((s, ""))
+    // Check that the 2nd (ie index 1) character actually starts at byte 1. This fails when the
+    // first character is a multibyte character (which is invalid input)
+    if !s.is_char_boundary(1) {
+        return Err(DecodeStringError);
+    }
+    Ok(s.split_at(1))
+}
+
+fn parse_optional<T: std::str::FromStr>(s: &str) -> Result<Option<T>, DecodeStringError> {
+    if s.is_empty() {
+        Ok(None)
     } else {
-        // Check if the 2nd (ie index 1) character actually starts at byte 1. This fails when the
-        // first character is a multibyte character (which has happened with the real history file)
-        match s.char_indices().nth(1) {
-            Some((1, _)) => {
-                Ok(s.split_at(1))
+        s.parse().map(Some).map_err(|_| DecodeStringError)
+    }
+}
+
+/// Decode one line of an OPL file into an object
+pub fn decode_line(line: &str) -> Result<StringOSMObj, DecodeStringError> {
+    let mut obj = empty_obj();
+    decode_line_into(line, &mut obj)?;
+    Ok(obj)
+}
+
+/// Decode one line of an OPL file into `obj`, reusing the allocations of its tags, user, and
+/// (if it's the same type) way nodes or relation members. After an error, `obj` is valid but
+/// its contents are unspecified.
+pub fn decode_line_into(line: &str, obj: &mut StringOSMObj) -> Result<(), DecodeStringError> {
+    let mut fields = line.split(' ').filter(|f| !f.is_empty());
+    let (object_type, id) = split_key_value(fields.next().ok_or(DecodeStringError)?)?;
+    let id: ObjId = id.parse().map_err(|_| DecodeStringError)?;
+
+    let (mut tags, mut user, mut nodes, mut members) = take_buffers(obj);
+    let mut has_user = false;
+    let (mut has_tags, mut has_nodes, mut has_members) = (false, false, false);
+    let mut version = None;
+    let mut deleted = false;
+    let mut changeset_id = None;
+    let mut timestamp = None;
+    let mut uid = None;
+    let mut lon: Option<Lon> = None;
+    let mut lat: Option<Lat> = None;
+
+    for field in fields {
+        let (key, value) = split_key_value(field)?;
+        match key {
+            "v" => version = parse_optional(value)?,
+            "d" => deleted = value == "D",
+            "c" => changeset_id = parse_optional(value)?,
+            "t" => {
+                if !value.is_empty() {
+                    timestamp = Some(TimestampFormat::ISOString(value.to_string()));
+                }
             }
-            _ => {
-                // TODO This is invalid input, so it should be logged
-                Err(DecodeStringError)
+            "i" => uid = parse_optional(value)?,
+            "u" => {
+                decode_string_into(&mut user, value)?;
+                has_user = true;
             }
+            "T" => {
+                decode_tags_into(&mut tags, value)?;
+                has_tags = true;
+            }
+            "x" => lon = parse_optional(value)?,
+            "y" => lat = parse_optional(value)?,
+            "N" => {
+                decode_way_nodes_into(&mut nodes, value)?;
+                has_nodes = true;
+            }
+            "M" => {
+                decode_members_into(&mut members, value)?;
+                has_members = true;
+            }
+            _ => return Err(DecodeStringError),
         }
     }
+    // The decode functions reuse the old strings, so only clear what wasn't in the line
+    if !has_tags {
+        tags.clear();
+    }
+    if !has_nodes {
+        nodes.clear();
+    }
+    if !has_members {
+        members.clear();
+    }
+    let user = if has_user { Some(user) } else { None };
+
+    *obj = match object_type {
+        "n" => StringOSMObj::Node(StringNode {
+            _id: id,
+            _version: version,
+            _deleted: deleted,
+            _changeset_id: changeset_id,
+            _timestamp: timestamp,
+            _uid: uid,
+            _user: user,
+            _tags: tags,
+            _lat_lon: match (lat, lon) {
+                (Some(lat), Some(lon)) => Some((lat, lon)),
+                _ => None,
+            },
+        }),
+        "w" => StringOSMObj::Way(StringWay {
+            _id: id,
+            _version: version,
+            _deleted: deleted,
+            _changeset_id: changeset_id,
+            _timestamp: timestamp,
+            _uid: uid,
+            _user: user,
+            _tags: tags,
+            _nodes: nodes,
+        }),
+        "r" => StringOSMObj::Relation(StringRelation {
+            _id: id,
+            _version: version,
+            _deleted: deleted,
+            _changeset_id: changeset_id,
+            _timestamp: timestamp,
+            _uid: uid,
+            _user: user,
+            _tags: tags,
+            _members: members,
+        }),
+        _ => return Err(DecodeStringError),
+    };
+    Ok(())
 }
 
+/// An object for `decode_line_into` to fill in
+fn empty_obj() -> StringOSMObj {
+    StringOSMObj::Way(StringWay {
+        _id: 0,
+        _version: None,
+        _deleted: false,
+        _changeset_id: None,
+        _timestamp: None,
+        _uid: None,
+        _user: None,
+        _tags: Vec::new(),
+        _nodes: Vec::new(),
+    })
+}
 
-pub fn decode_line(line: &str) -> Result<OSMObj, DecodeStringError> {
-    let intermediate_items: Vec<_> = line.split(" ").map(split_key_value).collect();
-    let mut items = Vec::with_capacity(intermediate_items.len());
-    for i in intermediate_items {
-        match i {
-            Err(DecodeStringError) => { return Err(DecodeStringError); },
-            Ok(x) => { items.push(x); }
-        }
+/// Move the tags, user, way nodes & relation members out of `obj`, to be reused
+fn take_buffers(
+    obj: &mut StringOSMObj,
+) -> (Vec<(String, String)>, String, Vec<ObjId>, Vec<Member>) {
+    use std::mem::take;
+    match obj {
+        StringOSMObj::Node(n) => (
+            take(&mut n._tags),
+            n._user.take().unwrap_or_default(),
+            Vec::new(),
+            Vec::new(),
+        ),
+        StringOSMObj::Way(w) => (
+            take(&mut w._tags),
+            w._user.take().unwrap_or_default(),
+            take(&mut w._nodes),
+            Vec::new(),
+        ),
+        StringOSMObj::Relation(r) => (
+            take(&mut r._tags),
+            r._user.take().unwrap_or_default(),
+            Vec::new(),
+            take(&mut r._members),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use OSMObjBase;
+
+    #[test]
+    fn decode_strings() {
+        let decode_string = |s| {
+            let mut output = "previous".to_string();
+            decode_string_into(&mut output, s).map(|()| output)
+        };
+        assert_eq!(decode_string("foo").unwrap(), "foo");
+        assert_eq!(decode_string("foo%20%bar").unwrap(), "foo bar");
+        assert_eq!(decode_string("%2c%%3d%").unwrap(), ",=");
+        assert!(decode_string("foo%20").is_err());
+        assert!(decode_string("foo%zz%").is_err());
     }
-    
-    match items[0].0 {
-        "n" => {
-            let tags = try!(decode_tags(items[7].1));
-            let lon = if items[8].1.len() == 0 { None } else { Some(try!(items[8].1.parse::<Lon>().or(Err(DecodeStringError)))) };
-            let lat = if items[9].1.len() == 0 { None } else { Some(try!(items[9].1.parse::<Lat>().or(Err(DecodeStringError)))) };
-
-            let node = Node {
-                id: try!(items[0].1.parse::<ObjId>().or(Err(DecodeStringError))),
-                version: Some(items[1].1.parse::<u32>().or(Err(DecodeStringError))?),
-                deleted: items[2].1 == "D",
-                changeset_id: Some(items[3].1.parse::<u32>().or(Err(DecodeStringError))?),
-                timestamp: Some(TimestampFormat::ISOString(items[4].1.to_string())),
-                uid: Some(items[5].1.parse::<u32>().or(Err(DecodeStringError))?),
-                user: Some(Rc::new(items[6].1.to_string())),
-                tags: tags,
-                lon: lon,
-                lat: lat,
-            };
-            Ok(OSMObj::Node(node))
-        },
-        "w" => {
-            let tags = try!(decode_tags(items[7].1));
-            let nodes = try!(decode_way_nodes(items[8].1));
-            let way = Way {
-                id: try!(items[0].1.parse::<ObjId>().or(Err(DecodeStringError))),
-                version: Some(items[1].1.parse::<u32>().or(Err(DecodeStringError))?),
-                deleted: items[2].1 == "D",
-                changeset_id: Some(items[3].1.parse::<u32>().or(Err(DecodeStringError))?),
-                timestamp: Some(TimestampFormat::ISOString(items[4].1.to_string())),
-                uid: Some(items[5].1.parse::<u32>().or(Err(DecodeStringError))?),
-                user: Some(Rc::new(items[6].1.to_string())),
-                tags: tags,
-                nodes: nodes,
-            };
-            Ok(OSMObj::Way(way))
-        },
-        "r" => {
-            let tags = try!(decode_tags(items[7].1));
-            let members = try!(decode_members(items[8].1));
-            let relation = Relation {
-                id: try!(items[0].1.parse::<ObjId>().or(Err(DecodeStringError))),
-                version: Some(items[1].1.parse::<u32>().or(Err(DecodeStringError))?),
-                deleted: items[2].1 == "D",
-                changeset_id: Some(items[3].1.parse::<u32>().or(Err(DecodeStringError))?),
-                timestamp: Some(TimestampFormat::ISOString(items[4].1.to_string())),
-                uid: Some(items[5].1.parse::<u32>().or(Err(DecodeStringError))?),
-                user: Some(Rc::new(items[6].1.to_string())),
-                tags: tags,
-                members: members,
-            };
-            Ok(OSMObj::Relation(relation))
+
+    #[test]
+    fn decode_objects() {
+        let node = decode_line(
+            "n1 v2 dV c3 t2020-01-01T00:00:00Z i4 ufoo%20%bar Tname=Caf%e9%,amenity=cafe x1.5 y-2",
+        )
+        .unwrap();
+        let node = node.as_node().unwrap();
+        assert_eq!(node.id(), 1);
+        assert_eq!(node.version(), Some(2));
+        assert!(!node.deleted());
+        assert_eq!(node.user(), Some("foo bar"));
+        assert_eq!(node.tag("name"), Some("Café"));
+        assert_eq!(node.lat_lon_f64(), Some((-2., 1.5)));
+
+        let way = decode_line("w10 v1 dD T Nn1,n2,n3").unwrap();
+        assert!(decode_line("w10 v1 dD T N")
+            .unwrap()
+            .as_way()
+            .unwrap()
+            .nodes()
+            .is_empty());
+        let way = way.as_way().unwrap();
+        assert!(way.deleted());
+        assert!(way.untagged());
+
+        let relation = decode_line("r5 Ttype=route Mn1@stop,w10@").unwrap();
+        let members: Vec<_> = relation.as_relation().unwrap().members().collect();
+        assert_eq!(
+            members,
+            vec![
+                Member::new(OSMObjectType::Node, 1, "stop"),
+                Member::new(OSMObjectType::Way, 10, "")
+            ]
+        );
+
+        assert!(decode_line("x1 v1").is_err());
+        assert!(decode_line("n1 q1").is_err());
+    }
+
+    #[test]
+    fn write_then_read() {
+        let lines = [
+            "n1 v2 dV c3 t2020-01-01T00:00:00Z i4 ufoo%20%bar Tname=a%2c%b x1.5 y-2",
+            "n2 v1 dD T x y",
+            "w10 v1 dV T Nn1,n2",
+            "r5 v1 dV Ttype=route Mn1@stop,w10@",
+        ];
+        let input = lines.join("\n");
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut writer = OPLWriter::new(Vec::new());
+        for obj in reader.objects() {
+            writer.write_obj(&obj).unwrap();
         }
-        _ => Err(DecodeStringError)
+        writer.close().unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(output, lines.join("\n") + "\n");
     }
-}
 
+    #[test]
+    fn read() {
+        let input = "n1 v1 x1 y2\n\nw1 v1 Nn1,n2\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let objs: Vec<_> = reader.objects().collect();
+        assert_eq!(objs.len(), 2);
+        assert!(objs[0].is_node());
+        assert!(objs[1].is_way());
+
+        let mut reader = OPLReader::new("n1 v1\n\nn2 v1 q\n".as_bytes());
+        assert!(reader.try_next().unwrap().is_some());
+        match reader.try_next() {
+            Err(ReadError::OPLInvalidLine(3, ref l)) if l == "n2 v1 q" => {}
+            r => panic!("unexpected result {:?}", r),
+        }
 
-//pub fn read<R: BufRead>(reader: &mut R) -> std::iter::Map<std::io::Lines<&mut R>> {
-//    reader.lines().map(|line| { decode_line(line.unwrap().as_str()).ok() })
-//}
-//
+        let options = ReaderOptions {
+            strict: false,
+            ..Default::default()
+        };
+        let mut reader = OPLReader::new_with_options("n1 v1\nn2 v1 q\nn3\n".as_bytes(), options);
+        let ids: Vec<_> = reader.objects().map(|o| o.id()).collect();
+        assert_eq!(ids, vec![1, 3]);
+    }
+
+    #[test]
+    fn next_into() {
+        let input = "w1 v1 uamy Thighway=road,name=Main%20%Street Nn1,n2,n3
+                     w2 v1 Tbuilding=yes Nn4,n5
+n3 x1 y2
+r4 Mw1@outer
+";
+        let expected: Vec<_> = OPLReader::new(input.as_bytes()).objects().collect();
+
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut obj = decode_line("n1").unwrap();
+        let mut objs = Vec::new();
+        let mut tags_ptr = None;
+        let mut value_ptr = None;
+        while reader.next_into(&mut obj) {
+            if let StringOSMObj::Way(ref way) = obj {
+                // The tags of the 2nd way are stored where the first way's were, and the strings
+                // are reused too
+                assert!(tags_ptr.map_or(true, |p| p == way._tags.as_ptr()));
+                assert!(value_ptr.map_or(true, |p| p == way._tags[0].1.as_ptr()));
+                tags_ptr = Some(way._tags.as_ptr());
+                value_ptr = Some(way._tags[0].1.as_ptr());
+            }
+            objs.push(obj.clone());
+        }
+        assert_eq!(objs, expected);
+        assert_eq!(objs[1].user(), None);
+        assert_eq!(
+            objs[1].tags().collect::<Vec<_>>(),
+            vec![("building", "yes")]
+        );
+
+        // Readers without their own version replace the object
+        let mut obj = expected[0].clone();
+        let mut reader =
+            ::xml::XMLReader::new("<osm><node id=\"7\" lat=\"1\" lon=\"2\"/></osm>".as_bytes());
+        assert!(reader.next_into(&mut obj));
+        assert_eq!(obj.id(), 7);
+        assert!(!reader.next_into(&mut obj));
+    }
+}
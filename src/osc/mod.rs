@@ -1,19 +1,19 @@
 //! OSC File format
 
 use super::version;
+use super::{DynWriter, OSMReader, OSMWriteError, OSMWriter, ReadError, ReaderOptions};
 use super::{Node, OSMObj, Relation, Way};
-use super::{OSMReader, OSMWriteError, OSMWriter};
 use obj_types::StringOSMObj;
 use std::io::{BufReader, Read, Write};
-use std::iter::Iterator;
 
-use xml::xml_elements_to_osm_obj;
+use xml::next_xml_obj;
 
 use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, Event};
-use xml_rs::reader::{EventReader, Events, XmlEvent};
+use xml_rs::reader::{EventReader, Events};
 
 pub struct OSCReader<R: Read> {
     parser: Events<BufReader<R>>,
+    options: ReaderOptions,
 }
 
 #[derive(PartialEq)]
@@ -33,11 +33,23 @@ impl<R: Read> OSMReader for OSCReader<R> {
     type Obj = StringOSMObj;
 
     fn new(reader: R) -> Self {
+        Self::new_with_options(reader, ReaderOptions::default())
+    }
+
+    fn new_with_options(reader: R, options: ReaderOptions) -> Self {
         OSCReader {
             parser: EventReader::new(BufReader::new(reader)).into_iter(),
+            options: options,
         }
     }
 
+    fn set_sorted_assumption(&mut self, sorted_assumption: bool) {
+        self.options.sorted_assumption = sorted_assumption;
+    }
+    fn get_sorted_assumption(&mut self) -> bool {
+        self.options.sorted_assumption
+    }
+
     fn into_inner(self) -> R {
         self.parser.into_inner().into_inner().into_inner()
     }
@@ -47,50 +59,20 @@ impl<R: Read> OSMReader for OSCReader<R> {
     }
 
     fn next(&mut self) -> Option<StringOSMObj> {
-        let mut elements = Vec::new();
-
-        // Pull xml/sax elements from the xml parser into a vector so we know what to work with.
-        let mut should_push = false;
-        loop {
-            let el = match self.parser.next() {
-                None => {
-                    break;
-                }
-                Some(e) => e,
-            };
-
-            let el = el.unwrap();
-
-            let mut should_break = false;
-            match el {
-                XmlEvent::StartElement { ref name, .. } => match name.local_name.as_str() {
-                    "node" | "way" | "relation" => {
-                        should_push = true;
-                    }
-                    _ => {}
-                },
-                XmlEvent::EndElement { ref name, .. } => match name.local_name.as_str() {
-                    "node" | "way" | "relation" => {
-                        should_break = true;
-                    }
-                    _ => {}
-                },
-                _ => {}
-            }
-
-            if should_push {
-                elements.push(el);
-            }
-            if should_break {
-                break;
-            }
-        }
+        self.try_next().unwrap()
+    }
 
-        xml_elements_to_osm_obj(&mut elements)
+    fn try_next(&mut self) -> Result<Option<StringOSMObj>, ReadError> {
+        next_xml_obj(&mut self.parser, &self.options, &mut None)
     }
 }
 
 impl<W: Write> OSCWriter<W> {
+    /// The underlying writer
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        self.writer.inner()
+    }
+
     fn ensure_header(&mut self) -> Result<(), OSMWriteError> {
         if self._state == State::Initial {
             self.writer
@@ -132,6 +114,7 @@ impl<W: Write> OSMWriter<W> for OSCWriter<W> {
             self.writer
                 .write_event(Event::End(BytesEnd::borrowed(b"osmChange")))?;
             self._state = State::Closed;
+            self.writer.inner().flush()?;
         }
 
         Ok(())
@@ -148,7 +131,9 @@ impl<W: Write> OSMWriter<W> for OSCWriter<W> {
         let mut xml_el = BytesStart::borrowed_name(tag_name.as_bytes());
         xml_el.push_attribute(("id", obj.id().to_string().as_ref()));
         xml_el.push_attribute(("visible", if obj.deleted() { "false" } else { "true" }));
-        xml_el.push_attribute(("version", obj.version().unwrap().to_string().as_ref()));
+        if let Some(version) = obj.version() {
+            xml_el.push_attribute(("version", version.to_string().as_ref()));
+        }
         if let Some(user) = obj.user() {
             xml_el.push_attribute((b"user".as_ref(), user.as_ref()));
         }
@@ -182,11 +167,11 @@ impl<W: Write> OSMWriter<W> for OSCWriter<W> {
 
         let mut member_el;
         if let Some(relation) = obj.as_relation() {
-            for (obj_type, id, role) in relation.members() {
+            for member in relation.members() {
                 member_el = BytesStart::borrowed_name(b"member");
-                member_el.push_attribute(("type", format!("{}", obj_type).as_str()));
-                member_el.push_attribute(("ref", id.to_string().as_str()));
-                member_el.push_attribute(("role", role));
+                member_el.push_attribute(("type", format!("{}", member.mtype).as_str()));
+                member_el.push_attribute(("ref", member.id.to_string().as_str()));
+                member_el.push_attribute(("role", member.role));
                 self.writer.write_event(Event::Empty(member_el))?;
             }
         }
@@ -210,6 +195,16 @@ impl<W: Write> OSMWriter<W> for OSCWriter<W> {
     }
 }
 
+impl<W: Write> DynWriter for OSCWriter<W> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        self.write_obj(obj)
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        self.close()
+    }
+}
+
 impl<W: Write> Drop for OSCWriter<W> {
     fn drop(&mut self) {
         self.close().unwrap();
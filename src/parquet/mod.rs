@@ -0,0 +1,323 @@
+//! Parquet file format (write only)
+//!
+//! Objects are buffered into Arrow record batches and written as rows of a single Parquet file,
+//! one row per object. Nodes have `lat`/`lon` columns, ways a `nodes` list, relations a `members`
+//! list, and all objects have their tags as a map column.
+use super::{DynWriter, OSMWriteError, OSMWriter};
+use super::{Node, OSMObj, Relation, Way};
+use obj_types::StringOSMObj;
+use std::io::Write;
+use std::sync::Arc;
+
+use arrow_array::builder::{
+    ArrayBuilder, BooleanBuilder, Float64Builder, Int64Builder, ListBuilder, MapBuilder,
+    StringBuilder, StructBuilder, TimestampSecondBuilder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet_rs::arrow::ArrowWriter;
+use parquet_rs::errors::ParquetError;
+
+/// Default number of objects in a record batch
+pub const DEFAULT_BATCH_SIZE: usize = 65_536;
+
+/// The column builders for one record batch
+struct Columns {
+    osm_type: StringBuilder,
+    id: Int64Builder,
+    version: Int64Builder,
+    deleted: BooleanBuilder,
+    changeset_id: Int64Builder,
+    timestamp: TimestampSecondBuilder,
+    uid: Int64Builder,
+    user: StringBuilder,
+    lat: Float64Builder,
+    lon: Float64Builder,
+    tags: MapBuilder<StringBuilder, StringBuilder>,
+    nodes: ListBuilder<Int64Builder>,
+    members: ListBuilder<StructBuilder>,
+}
+
+fn member_fields() -> Vec<Field> {
+    vec![
+        Field::new("type", DataType::Utf8, false),
+        Field::new("ref", DataType::Int64, false),
+        Field::new("role", DataType::Utf8, false),
+    ]
+}
+
+impl Columns {
+    fn new() -> Self {
+        let member_builder = StructBuilder::new(
+            member_fields(),
+            vec![
+                Box::new(StringBuilder::new()) as Box<dyn ArrayBuilder>,
+                Box::new(Int64Builder::new()),
+                Box::new(StringBuilder::new()),
+            ],
+        );
+        Columns {
+            osm_type: StringBuilder::new(),
+            id: Int64Builder::new(),
+            version: Int64Builder::new(),
+            deleted: BooleanBuilder::new(),
+            changeset_id: Int64Builder::new(),
+            timestamp: TimestampSecondBuilder::new().with_timezone("UTC"),
+            uid: Int64Builder::new(),
+            user: StringBuilder::new(),
+            lat: Float64Builder::new(),
+            lon: Float64Builder::new(),
+            tags: MapBuilder::new(None, StringBuilder::new(), StringBuilder::new()),
+            nodes: ListBuilder::new(Int64Builder::new()),
+            members: ListBuilder::new(member_builder),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.id.len()
+    }
+
+    fn append(&mut self, obj: &impl OSMObj) -> Result<(), OSMWriteError> {
+        self.osm_type.append_value(obj.object_type().to_string());
+        self.id.append_value(obj.id());
+        self.version.append_option(obj.version().map(|v| v as i64));
+        self.deleted.append_value(obj.deleted());
+        self.changeset_id
+            .append_option(obj.changeset_id().map(|c| c as i64));
+        self.timestamp
+            .append_option(obj.timestamp().as_ref().map(|t| t.to_epoch_number()));
+        self.uid.append_option(obj.uid().map(|u| u as i64));
+        self.user.append_option(obj.user());
+
+        match obj.as_node().and_then(|n| n.lat_lon()) {
+            Some((lat, lon)) => {
+                self.lat.append_value(lat.degrees());
+                self.lon.append_value(lon.degrees());
+            }
+            None => {
+                self.lat.append_null();
+                self.lon.append_null();
+            }
+        }
+
+        for (k, v) in obj.tags() {
+            self.tags.keys().append_value(k);
+            self.tags.values().append_value(v);
+        }
+        self.tags
+            .append(true)
+            .map_err(|e| OSMWriteError::ParquetWrite(e.into()))?;
+
+        match obj.as_way() {
+            Some(way) => {
+                self.nodes.values().append_slice(way.nodes());
+                self.nodes.append(true);
+            }
+            None => self.nodes.append(false),
+        }
+
+        match obj.as_relation() {
+            Some(relation) => {
+                for m in relation.members() {
+                    let member = self.members.values();
+                    member
+                        .field_builder::<StringBuilder>(0)
+                        .unwrap()
+                        .append_value(m.mtype.to_string());
+                    member
+                        .field_builder::<Int64Builder>(1)
+                        .unwrap()
+                        .append_value(m.id);
+                    member
+                        .field_builder::<StringBuilder>(2)
+                        .unwrap()
+                        .append_value(m.role);
+                    member.append(true);
+                }
+                self.members.append(true);
+            }
+            None => self.members.append(false),
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Vec<(&'static str, ArrayRef)> {
+        vec![
+            ("type", Arc::new(self.osm_type.finish()) as ArrayRef),
+            ("id", Arc::new(self.id.finish())),
+            ("version", Arc::new(self.version.finish())),
+            ("deleted", Arc::new(self.deleted.finish())),
+            ("changeset", Arc::new(self.changeset_id.finish())),
+            ("timestamp", Arc::new(self.timestamp.finish())),
+            ("uid", Arc::new(self.uid.finish())),
+            ("user", Arc::new(self.user.finish())),
+            ("lat", Arc::new(self.lat.finish())),
+            ("lon", Arc::new(self.lon.finish())),
+            ("tags", Arc::new(self.tags.finish())),
+            ("nodes", Arc::new(self.nodes.finish())),
+            ("members", Arc::new(self.members.finish())),
+        ]
+    }
+}
+
+/// The schema of the written file. Derived from the builders, so they can never disagree.
+fn schema() -> SchemaRef {
+    let fields: Vec<Field> = Columns::new()
+        .finish()
+        .into_iter()
+        .map(|(name, array)| {
+            let nullable = name != "type" && name != "id";
+            Field::new(name, array.data_type().clone(), nullable)
+        })
+        .collect();
+    Arc::new(Schema::new(fields))
+}
+
+/// Write OSM objects to a Parquet file
+pub struct ParquetWriter<W: Write + Send> {
+    writer: Option<ArrowWriter<W>>,
+    inner: Option<W>,
+    schema: SchemaRef,
+    columns: Columns,
+    batch_size: usize,
+}
+
+impl From<ParquetError> for OSMWriteError {
+    fn from(err: ParquetError) -> OSMWriteError {
+        OSMWriteError::ParquetWrite(err)
+    }
+}
+
+impl<W: Write + Send> ParquetWriter<W> {
+    /// Create a writer which writes a record batch (and hence Parquet row group) every
+    /// `batch_size` objects.
+    pub fn with_batch_size(writer: W, batch_size: usize) -> Self {
+        let schema = schema();
+        let writer = ArrowWriter::try_new(writer, schema.clone(), None)
+            .expect("osmio's parquet schema is always valid");
+        ParquetWriter {
+            writer: Some(writer),
+            inner: None,
+            schema: schema,
+            columns: Columns::new(),
+            batch_size: batch_size.max(1),
+        }
+    }
+
+    fn flush_batch(&mut self) -> Result<(), OSMWriteError> {
+        if self.columns.len() == 0 {
+            return Ok(());
+        }
+        let arrays = self
+            .columns
+            .finish()
+            .into_iter()
+            .map(|(_, array)| array)
+            .collect();
+        let batch = RecordBatch::try_new(self.schema.clone(), arrays)
+            .map_err(|e| OSMWriteError::ParquetWrite(e.into()))?;
+        match self.writer {
+            Some(ref mut writer) => {
+                writer.write(&batch)?;
+                writer.flush()?;
+            }
+            None => return Err(OSMWriteError::AlreadyClosed),
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> OSMWriter<W> for ParquetWriter<W> {
+    fn new(writer: W) -> Self {
+        ParquetWriter::with_batch_size(writer, DEFAULT_BATCH_SIZE)
+    }
+
+    fn is_open(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    fn close(&mut self) -> Result<(), OSMWriteError> {
+        if !self.is_open() {
+            return Ok(());
+        }
+        self.flush_batch()?;
+        let writer = self.writer.take().unwrap();
+        self.inner = Some(writer.into_inner()?);
+
+        Ok(())
+    }
+
+    fn write_obj(&mut self, obj: &impl OSMObj) -> Result<(), OSMWriteError> {
+        if !self.is_open() {
+            return Err(OSMWriteError::AlreadyClosed);
+        }
+        self.columns.append(obj)?;
+        if self.columns.len() >= self.batch_size {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn into_inner(mut self) -> W {
+        self.close().unwrap();
+        self.inner.take().unwrap()
+    }
+}
+
+impl<W: Write + Send> DynWriter for ParquetWriter<W> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        self.write_obj(obj)
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        self.close()
+    }
+}
+
+impl<W: Write + Send> Drop for ParquetWriter<W> {
+    fn drop(&mut self) {
+        // Errors can't be returned from here, call `close` to see them
+        if self.is_open() {
+            let _ = self.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj_types::{StringNodeBuilder, StringWayBuilder};
+    use parquet_rs::file::reader::{FileReader, SerializedFileReader};
+    use {Lat, Lon};
+
+    #[test]
+    fn write_nodes_and_ways() {
+        let node: StringOSMObj = StringNodeBuilder::default()
+            ._id(1)
+            ._version(2)
+            ._lat_lon((Lat::from(1.), Lon::from(2.)))
+            .build()
+            .unwrap()
+            .into();
+        let way: StringOSMObj = StringWayBuilder::default()
+            ._id(10)
+            ._nodes(vec![1, 2, 3])
+            .build()
+            .unwrap()
+            .into();
+
+        let path = std::env::temp_dir().join(format!("osmio-test-{}.parquet", std::process::id()));
+        let mut writer = ParquetWriter::with_batch_size(std::fs::File::create(&path).unwrap(), 1);
+        writer.write_obj(&node).unwrap();
+        writer.write_obj(&way).unwrap();
+        writer.close().unwrap();
+        drop(writer);
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        assert_eq!(metadata.file_metadata().num_rows(), 2);
+        assert_eq!(metadata.num_row_groups(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,468 @@
+//! Decode a PBF block into one arena, and read the objects in place.
+//!
+//! `PBFReader::next` allocates a handful of strings & `Vec`s for every object. `Block` instead
+//! decodes a whole block into a few shared buffers: one for the string table, one for all the
+//! tags, one for all the way nodes & relation member ids, etc. The objects (`BlockObj`) borrow
+//! from the block, and dropping the block frees everything with a constant number of
+//! deallocations, however many objects it had.
+//!
+//! ```
+//! # use osmio::pbf::PBFReader;
+//! # use osmio::OSMReader;
+//! # fn count(file: std::fs::File) -> Result<(), osmio::ReadError> {
+//! let mut reader = PBFReader::new(file);
+//! let mut benches = 0;
+//! while let Some(block) = reader.next_block()? {
+//!     benches += block
+//!         .iter()
+//!         .filter(|o| o.tag("amenity") == Some("bench"))
+//!         .count();
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use super::osmformat;
+use super::{delta_decode, DenseArrays};
+use obj_types::{ArcNode, ArcOSMObj, ArcRelation, ArcWay};
+use std::ops::Range;
+use std::sync::Arc;
+use {Lat, Lon, Member, OSMObjectType, ObjId, ReadError, ReaderOptions, TimestampFormat};
+
+/// The fields of one object. The variable length parts are ranges of the `Block`'s buffers.
+#[derive(Debug, Clone)]
+struct Entry {
+    object_type: OSMObjectType,
+    id: ObjId,
+    version: Option<u32>,
+    deleted: bool,
+    changeset_id: Option<u64>,
+    timestamp: Option<i64>,
+    uid: Option<u64>,
+    /// Index into the string table
+    user: Option<u32>,
+    lat_lon: Option<(Lat, Lon)>,
+    /// Range of `Block::tags`
+    tags: Range<u32>,
+    /// Range of `Block::refs` (and `Block::members` for relations)
+    refs: Range<u32>,
+}
+
+/// All the objects of one PBF block, see the module docs
+#[derive(Debug, Clone, Default)]
+pub struct Block {
+    /// Every string of the string table, one after the other
+    strings: String,
+    /// Where each string is in `strings`. `None` if it wasn't valid UTF-8.
+    string_ranges: Vec<Option<Range<u32>>>,
+    entries: Vec<Entry>,
+    /// Key & value string indexes, of every object
+    tags: Vec<(u32, u32)>,
+    /// Way nodes & relation member ids, of every object
+    refs: Vec<ObjId>,
+    /// Member type & role string index, for each relation entry of `refs`. Ways leave a gap.
+    members: Vec<(OSMObjectType, u32)>,
+}
+
+/// Block wide values needed to decode the objects, for `Block` & `PBFReader`
+#[derive(Clone, Copy)]
+pub(super) struct Scale {
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+    /// Milliseconds
+    date_granularity: i64,
+    pub(super) decode_metadata: bool,
+}
+
+impl Scale {
+    pub(super) fn new(block: &osmformat::PrimitiveBlock, decode_metadata: bool) -> Self {
+        Scale {
+            granularity: block.get_granularity() as i64,
+            lat_offset: block.get_lat_offset(),
+            lon_offset: block.get_lon_offset(),
+            date_granularity: block.get_date_granularity() as i64,
+            decode_metadata: decode_metadata,
+        }
+    }
+
+    pub(super) fn lat_lon(&self, lat: i64, lon: i64) -> (Lat, Lon) {
+        // Nanodegrees, so convert to the 1e-7 fixed-point format. Wrapping, like `delta_decode`.
+        let scale = |offset: i64, value: i64| {
+            (offset.wrapping_add(self.granularity.wrapping_mul(value)) / 100) as i32
+        };
+        (
+            Lat::from_inner(scale(self.lat_offset, lat)),
+            Lon::from_inner(scale(self.lon_offset, lon)),
+        )
+    }
+
+    /// Seconds since the epoch
+    pub(super) fn timestamp(&self, timestamp: i64) -> i64 {
+        timestamp.wrapping_mul(self.date_granularity) / 1000
+    }
+}
+
+impl Block {
+    /// Decode a block, checking the string table indexes & dense node locations the same way
+    /// as `PBFReader::try_next`, so the objects can't refer to strings which don't exist.
+    pub(super) fn decode(
+        mut block: osmformat::PrimitiveBlock,
+        options: &ReaderOptions,
+    ) -> Result<Block, ReadError> {
+        let mut result = Block::default();
+        for string in block.take_stringtable().take_s().into_iter() {
+            let range = ::std::str::from_utf8(&string).ok().map(|s| {
+                let start = result.strings.len() as u32;
+                result.strings.push_str(s);
+                start..result.strings.len() as u32
+            });
+            result.string_ranges.push(range);
+        }
+
+        let mut dense = DenseArrays::default();
+        let scale = Scale::new(&block, options.decode_metadata);
+        for group in block.get_primitivegroup() {
+            for node in group.get_nodes() {
+                let tags = result.push_tags(node.get_keys(), node.get_vals())?;
+                let mut entry = Entry::new(OSMObjectType::Node, node.get_id(), tags);
+                if node.has_info() {
+                    let user = result.string_index(node.get_info().get_user_sid() as i64)?;
+                    entry.set_info(node.get_info(), user, &scale);
+                }
+                entry.lat_lon = Some(scale.lat_lon(node.get_lat(), node.get_lon()));
+                result.entries.push(entry);
+            }
+            if group.has_dense() {
+                result.push_dense(group.get_dense(), &mut dense, &scale)?;
+            }
+            for way in group.get_ways() {
+                let tags = result.push_tags(way.get_keys(), way.get_vals())?;
+                let mut entry = Entry::new(OSMObjectType::Way, way.get_id(), tags);
+                // Like `PBFReader::next`, a way without info has the default (0) values
+                let user = result.string_index(way.get_info().get_user_sid() as i64)?;
+                entry.set_info(way.get_info(), user, &scale);
+                entry.refs = result.push_refs(way.get_refs());
+                result.entries.push(entry);
+            }
+            for relation in group.get_relations() {
+                let tags = result.push_tags(relation.get_keys(), relation.get_vals())?;
+                let mut entry = Entry::new(OSMObjectType::Relation, relation.get_id(), tags);
+                let user = result.string_index(relation.get_info().get_user_sid() as i64)?;
+                entry.set_info(relation.get_info(), user, &scale);
+                entry.refs = result.push_refs(relation.get_memids());
+                result
+                    .members
+                    .resize(entry.refs.start as usize, (OSMObjectType::Node, 0));
+                let types = relation.get_types().iter().map(|t| match *t {
+                    osmformat::Relation_MemberType::NODE => OSMObjectType::Node,
+                    osmformat::Relation_MemberType::WAY => OSMObjectType::Way,
+                    osmformat::Relation_MemberType::RELATION => OSMObjectType::Relation,
+                });
+                let roles = relation
+                    .get_roles_sid()
+                    .iter()
+                    .map(|&r| result.string_index(r as i64))
+                    .collect::<Result<Vec<_>, _>>()?;
+                result.members.extend(types.zip(roles));
+                // A corrupt relation could have fewer types or roles than ids
+                let members_end = result.members.len() as u32;
+                entry.refs.end = entry.refs.end.min(members_end);
+                result.entries.push(entry);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Check that this is an index of the string table
+    fn string_index(&self, idx: i64) -> Result<u32, ReadError> {
+        if idx >= 0 && (idx as u64) < self.string_ranges.len() as u64 {
+            Ok(idx as u32)
+        } else {
+            Err(ReadError::PBFInvalidData("string table index out of range"))
+        }
+    }
+
+    fn push_tags(&mut self, keys: &[u32], vals: &[u32]) -> Result<Range<u32>, ReadError> {
+        let start = self.tags.len() as u32;
+        self.tags.reserve(keys.len().min(vals.len()));
+        for (&key, &val) in keys.iter().zip(vals) {
+            let tag = (
+                self.string_index(key as i64)?,
+                self.string_index(val as i64)?,
+            );
+            self.tags.push(tag);
+        }
+        Ok(start..self.tags.len() as u32)
+    }
+
+    fn push_refs(&mut self, deltas: &[i64]) -> Range<u32> {
+        let start = self.refs.len() as u32;
+        self.refs.extend(delta_decode(deltas));
+        start..self.refs.len() as u32
+    }
+
+    fn push_dense(
+        &mut self,
+        dense: &osmformat::DenseNodes,
+        arrays: &mut DenseArrays,
+        scale: &Scale,
+    ) -> Result<(), ReadError> {
+        let info = dense.get_denseinfo();
+        arrays.decode(dense, scale.decode_metadata);
+        let DenseArrays {
+            ref ids,
+            ref lats,
+            ref lons,
+            ref timestamps,
+            ref changesets,
+            ref uids,
+            ref user_sids,
+        } = *arrays;
+        let keys_vals = dense.get_keys_vals();
+        let mut keys_vals = keys_vals.iter().map(|&kv| kv as i64);
+
+        self.entries.reserve(ids.len());
+        for (index, &id) in ids.iter().enumerate() {
+            // Pairs of key & value, ending with a 0
+            let start = self.tags.len() as u32;
+            while let Some(key) = keys_vals.next() {
+                if key == 0 {
+                    break;
+                }
+                let val = keys_vals.next().unwrap_or(0);
+                let tag = (self.string_index(key)?, self.string_index(val)?);
+                self.tags.push(tag);
+            }
+            let mut entry = Entry::new(OSMObjectType::Node, id, start..self.tags.len() as u32);
+            match (lats.get(index), lons.get(index)) {
+                (Some(&lat), Some(&lon)) => entry.lat_lon = Some(scale.lat_lon(lat, lon)),
+                _ => return Err(ReadError::PBFInvalidData("missing dense node lat/lon")),
+            }
+            let uid = match uids.get(index) {
+                Some(&uid) if uid > i32::MAX as i64 => {
+                    return Err(ReadError::PBFInvalidData("dense node uid out of range"))
+                }
+                uid => uid.map(|&uid| uid as u64),
+            };
+            let user = match user_sids.get(index) {
+                Some(&sid) => Some(self.string_index(sid)?),
+                None => None,
+            };
+            entry.version = info.get_version().get(index).map(|&v| v as u32);
+            entry.deleted = !info.get_visible().get(index).unwrap_or(&true);
+            // Empty without `decode_metadata`
+            entry.timestamp = timestamps.get(index).map(|&t| scale.timestamp(t));
+            entry.changeset_id = changesets.get(index).map(|&c| c as u64);
+            entry.uid = uid;
+            entry.user = user;
+            self.entries.push(entry);
+        }
+        Ok(())
+    }
+
+    /// The string at this index of the string table, `None` if it isn't valid UTF-8
+    fn string(&self, idx: u32) -> Option<&str> {
+        match self.string_ranges.get(idx as usize) {
+            Some(&Some(ref range)) => Some(&self.strings[range.start as usize..range.end as usize]),
+            _ => None,
+        }
+    }
+
+    /// How many objects there are
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// True iff there are no objects
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The object at this position
+    pub fn get(&self, idx: usize) -> Option<BlockObj<'_>> {
+        self.entries.get(idx).map(|entry| BlockObj {
+            block: self,
+            entry: entry,
+        })
+    }
+
+    /// All the objects, in file order
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = BlockObj<'_>> + '_ {
+        self.entries.iter().map(move |entry| BlockObj {
+            block: self,
+            entry: entry,
+        })
+    }
+}
+
+impl Entry {
+    fn new(object_type: OSMObjectType, id: i64, tags: Range<u32>) -> Self {
+        Entry {
+            object_type: object_type,
+            id: id as ObjId,
+            version: None,
+            deleted: false,
+            changeset_id: None,
+            timestamp: None,
+            uid: None,
+            user: None,
+            lat_lon: None,
+            tags: tags,
+            refs: 0..0,
+        }
+    }
+
+    /// `user` is the (checked) string index of the user
+    fn set_info(&mut self, info: &osmformat::Info, user: u32, scale: &Scale) {
+        self.version = Some(info.get_version() as u32);
+        self.deleted = !info.get_visible();
+        if scale.decode_metadata {
+            self.timestamp = Some(scale.timestamp(info.get_timestamp()));
+            self.changeset_id = Some(info.get_changeset() as u64);
+            self.uid = Some(info.get_uid() as u64);
+            self.user = Some(user);
+        }
+    }
+}
+
+/// One object of a `Block`. The strings & slices all borrow from the block.
+#[derive(Debug, Clone, Copy)]
+pub struct BlockObj<'a> {
+    block: &'a Block,
+    entry: &'a Entry,
+}
+
+impl<'a> BlockObj<'a> {
+    pub fn object_type(&self) -> OSMObjectType {
+        self.entry.object_type
+    }
+
+    pub fn id(&self) -> ObjId {
+        self.entry.id
+    }
+
+    pub fn version(&self) -> Option<u32> {
+        self.entry.version
+    }
+
+    pub fn deleted(&self) -> bool {
+        self.entry.deleted
+    }
+
+    pub fn changeset_id(&self) -> Option<u64> {
+        self.entry.changeset_id
+    }
+
+    /// Seconds since the epoch
+    pub fn timestamp(&self) -> Option<i64> {
+        self.entry.timestamp
+    }
+
+    pub fn uid(&self) -> Option<u64> {
+        self.entry.uid
+    }
+
+    pub fn user(&self) -> Option<&'a str> {
+        self.entry.user.and_then(|sid| self.block.string(sid))
+    }
+
+    /// The tags, skipping any which aren't valid UTF-8
+    pub fn tags(&self) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        let block = self.block;
+        let range = self.entry.tags.start as usize..self.entry.tags.end as usize;
+        block.tags[range].iter().filter_map(move |&(k, v)| {
+            match (block.string(k), block.string(v)) {
+                (Some(k), Some(v)) => Some((k, v)),
+                _ => None,
+            }
+        })
+    }
+
+    pub fn tag(&self, key: impl AsRef<str>) -> Option<&'a str> {
+        let key = key.as_ref();
+        self.tags().find(|&(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    /// The location of a node. `None` for ways & relations.
+    pub fn lat_lon(&self) -> Option<(Lat, Lon)> {
+        self.entry.lat_lon
+    }
+
+    /// The nodes of a way. Empty for nodes & relations.
+    pub fn nodes(&self) -> &'a [ObjId] {
+        if self.entry.object_type == OSMObjectType::Way {
+            &self.block.refs[self.entry.refs.start as usize..self.entry.refs.end as usize]
+        } else {
+            &[]
+        }
+    }
+
+    /// The members of a relation, skipping any whose role isn't valid UTF-8. Empty for nodes
+    /// & ways.
+    pub fn members(&self) -> impl Iterator<Item = Member<&'a str>> + 'a {
+        let block = self.block;
+        let range = if self.entry.object_type == OSMObjectType::Relation {
+            self.entry.refs.start as usize..self.entry.refs.end as usize
+        } else {
+            0..0
+        };
+        block.refs[range.clone()]
+            .iter()
+            .zip(block.members[range].iter())
+            .filter_map(move |(&id, &(mtype, role))| {
+                block.string(role).map(|role| Member::new(mtype, id, role))
+            })
+    }
+
+    /// Copy this object out of the block, as the `ArcOSMObj` that `PBFReader::next` returns
+    pub fn to_obj(&self) -> ArcOSMObj {
+        let entry = self.entry;
+        let tags = self.tags().map(|(k, v)| (Arc::from(k), Arc::from(v)));
+        let user = self.user().map(Arc::from);
+        let timestamp = entry.timestamp.map(TimestampFormat::EpochNunber);
+        match entry.object_type {
+            OSMObjectType::Node => ArcOSMObj::Node(ArcNode {
+                _id: entry.id,
+                _version: entry.version,
+                _deleted: entry.deleted,
+                _changeset_id: entry.changeset_id,
+                _timestamp: timestamp,
+                _uid: entry.uid,
+                _user: user,
+                // Untagged nodes have no tags `Vec`, as in `PBFReader::next`
+                _tags: if entry.tags.start == entry.tags.end {
+                    None
+                } else {
+                    Some(tags.collect())
+                },
+                _lat_lon: entry.lat_lon,
+            }),
+            OSMObjectType::Way => ArcOSMObj::Way(ArcWay {
+                _id: entry.id,
+                _version: entry.version,
+                _deleted: entry.deleted,
+                _changeset_id: entry.changeset_id,
+                _timestamp: timestamp,
+                _uid: entry.uid,
+                _user: user,
+                _tags: tags.collect(),
+                _nodes: self.nodes().to_vec(),
+            }),
+            OSMObjectType::Relation => ArcOSMObj::Relation(ArcRelation {
+                _id: entry.id,
+                _version: entry.version,
+                _deleted: entry.deleted,
+                _changeset_id: entry.changeset_id,
+                _timestamp: timestamp,
+                _uid: entry.uid,
+                _user: user,
+                _tags: tags.collect(),
+                _members: self
+                    .members()
+                    .map(|m| Member::new(m.mtype, m.id, Arc::from(m.role)))
+                    .collect(),
+            }),
+        }
+    }
+}
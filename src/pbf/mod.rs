@@ -14,34 +14,47 @@ use flate2::read::ZlibDecoder;
 
 use obj_types::{ArcNode, ArcOSMObj, ArcRelation, ArcWay};
 
+use self::block::Scale;
 use protobuf;
+pub mod block;
 mod fileformat;
 mod osmformat;
 
 struct FileReader<R: Read> {
     reader: R,
+    header: Option<Header>,
+    /// The first data blob, if it was read while looking for the header
+    pending: Option<fileformat::Blob>,
+    started: bool,
 }
 
-fn blob_raw_data<'a>(blob: &mut fileformat::Blob) -> Option<Vec<u8>> {
+fn blob_raw_data<'a>(blob: &mut fileformat::Blob) -> Result<Vec<u8>, ReadError> {
     // TODO Shame this can't return a Option<&[u8]>, then I don't need blob to be mut. However I
     // get lifetime errors with bytes not living long enough.
     if blob.has_raw() {
-        Some(blob.take_raw())
+        Ok(blob.take_raw())
     } else if blob.has_zlib_data() {
         let zlib_data = blob.get_zlib_data();
         let cursor = Cursor::new(zlib_data);
         let mut bytes = Vec::with_capacity(blob.get_raw_size() as usize);
-        ZlibDecoder::new(cursor).read_to_end(&mut bytes).ok()?;
+        ZlibDecoder::new(cursor).read_to_end(&mut bytes)?;
 
-        Some(bytes)
+        Ok(bytes)
+    } else if blob.has_lzma_data() {
+        Err(ReadError::PBFUnsupportedCompression("lzma"))
     } else {
-        None
+        Err(ReadError::PBFUnsupportedCompression("unknown"))
     }
 }
 
 impl<R: Read> FileReader<R> {
     pub fn new(reader: R) -> Self {
-        FileReader { reader: reader }
+        FileReader {
+            reader: reader,
+            header: None,
+            pending: None,
+            started: false,
+        }
     }
 
     pub fn inner(&self) -> &R {
@@ -52,205 +65,333 @@ impl<R: Read> FileReader<R> {
         self.reader
     }
 
-    fn get_next_osmdata_blob(&mut self) -> Option<fileformat::Blob> {
-        loop {
-            // FIXME is there a way we can ask self.reader if it's at EOF? Rather than waiting for
-            // the failure and catching that?
-            let size = self.reader.read_u32::<byteorder::BigEndian>().ok()?;
-            let mut header_bytes_vec = vec![0; size as usize];
+    /// The type & bytes of the next blob
+    fn next_blob(&mut self) -> Result<Option<(String, Vec<u8>)>, ReadError> {
+        // FIXME is there a way we can ask self.reader if it's at EOF? Rather than waiting for
+        // the failure and catching that?
+        let size = match self.reader.read_u32::<byteorder::BigEndian>() {
+            Ok(size) => size,
+            Err(ref e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut header_bytes_vec = vec![0; size as usize];
 
-            self.reader
-                .read_exact(header_bytes_vec.as_mut_slice())
-                .unwrap();
+        self.reader.read_exact(header_bytes_vec.as_mut_slice())?;
 
-            let blob_header: fileformat::BlobHeader =
-                protobuf::parse_from_bytes(&header_bytes_vec).unwrap();
+        let mut blob_header: fileformat::BlobHeader =
+            protobuf::parse_from_bytes(&header_bytes_vec)?;
 
-            let mut blob_bytes = vec![0; blob_header.get_datasize() as usize];
-            self.reader.read_exact(blob_bytes.as_mut_slice()).unwrap();
+        let mut blob_bytes = vec![0; blob_header.get_datasize() as usize];
+        self.reader.read_exact(blob_bytes.as_mut_slice())?;
+
+        Ok(Some((blob_header.take_field_type(), blob_bytes)))
+    }
 
-            if blob_header.get_field_type() != "OSMData" {
+    fn get_next_osmdata_blob(&mut self) -> Result<Option<fileformat::Blob>, ReadError> {
+        if let Some(blob) = self.pending.take() {
+            return Ok(Some(blob));
+        }
+        self.started = true;
+        loop {
+            let (blob_type, blob_bytes) = match self.next_blob()? {
+                None => return Ok(None),
+                Some(blob) => blob,
+            };
+
+            match blob_type.as_str() {
+                "OSMData" => return Ok(Some(protobuf::parse_from_bytes(&blob_bytes)?)),
+                "OSMHeader" if self.header.is_none() => {
+                    self.header = Some(decode_header(protobuf::parse_from_bytes(&blob_bytes)?)?);
+                }
                 // keep going to the next blob
-                continue;
+                _ => {}
             }
+        }
+    }
 
-            let blob: fileformat::Blob = protobuf::parse_from_bytes(&blob_bytes).unwrap();
-
-            return Some(blob);
+    /// The file header. The header is the first blob, so this only reads from the file if
+    /// nothing has been read yet.
+    fn header(&mut self) -> Result<Option<&Header>, ReadError> {
+        if !self.started {
+            self.pending = self.get_next_osmdata_blob()?;
         }
+        Ok(self.header.as_ref())
+    }
+}
+
+/// Decode an OSMHeader blob
+fn decode_header(mut blob: fileformat::Blob) -> Result<Header, ReadError> {
+    let blob_data = blob_raw_data(&mut blob)?;
+    let mut block: osmformat::HeaderBlock = protobuf::parse_from_bytes(&blob_data)?;
+
+    let mut header = Header::default();
+    if block.has_bbox() {
+        // in nanodegrees
+        let bbox = block.get_bbox();
+        header.bbox = Some(BBox {
+            min_lat: Lat::from_inner((bbox.get_bottom() / 100) as i32),
+            min_lon: Lon::from_inner((bbox.get_left() / 100) as i32),
+            max_lat: Lat::from_inner((bbox.get_top() / 100) as i32),
+            max_lon: Lon::from_inner((bbox.get_right() / 100) as i32),
+        });
+    }
+    if block.has_writingprogram() {
+        header.generator = Some(block.take_writingprogram());
+    }
+    if block.has_osmosis_replication_timestamp() {
+        header.timestamp = Some(block.get_osmosis_replication_timestamp().into());
+    }
+    if block.has_osmosis_replication_sequence_number() {
+        header.replication_sequence_number =
+            Some(block.get_osmosis_replication_sequence_number() as u64);
+    }
+    if block.has_osmosis_replication_base_url() {
+        header.replication_base_url = Some(block.take_osmosis_replication_base_url());
     }
+    header.required_features = block.take_required_features().into_vec();
+    header.optional_features = block.take_optional_features().into_vec();
+    if block.has_source() {
+        header
+            .other
+            .push(("source".to_string(), block.take_source()));
+    }
+
+    Ok(header)
 }
 
+/// Plain (not dense) nodes. Few files use them, but they're valid.
 fn decode_nodes(
-    _primitive_group: &osmformat::PrimitiveGroup,
-    _granularity: i64,
-    _lat_offset: i64,
-    _lon_offset: i64,
-    _date_granularity: i32,
-    _stringtable: &Vec<Option<Arc<str>>>,
-    _results: &mut Vec<ArcOSMObj>,
-) {
-    unimplemented!("Dense node");
+    primitive_group: &osmformat::PrimitiveGroup,
+    scale: &Scale,
+    stringtable: &Vec<Option<Arc<str>>>,
+    results: &mut Vec<ArcOSMObj>,
+) -> Result<(), ReadError> {
+    let nodes = primitive_group.get_nodes();
+    results.reserve(nodes.len());
+    for node in nodes {
+        let tags = decode_tags(stringtable, node.get_keys(), node.get_vals())?;
+        let mut obj = ArcNode {
+            _id: node.get_id() as ObjId,
+            // Untagged nodes have no tags `Vec`, like dense nodes
+            _tags: if tags.is_empty() { None } else { Some(tags) },
+            _lat_lon: Some(scale.lat_lon(node.get_lat(), node.get_lon())),
+            _deleted: false,
+            _changeset_id: None,
+            _uid: None,
+            _user: None,
+            _version: None,
+            _timestamp: None,
+        };
+        // The metadata is optional
+        if node.has_info() {
+            let info = node.get_info();
+            obj._deleted = !info.get_visible();
+            obj._changeset_id = Some(info.get_changeset() as u64);
+            obj._uid = Some(info.get_uid() as u64);
+            obj._user = get_string(stringtable, info.get_user_sid() as usize)?;
+            obj._version = Some(info.get_version() as u32);
+            obj._timestamp = Some(TimestampFormat::EpochNunber(
+                scale.timestamp(info.get_timestamp()),
+            ));
+        }
+        results.push(ArcOSMObj::Node(obj));
+    }
+    Ok(())
+}
+
+/// Undo the delta coding of a whole array, i.e. the running sum of `deltas`, replacing what
+/// was in `out`. Wrapping arithmetic, as a corrupt file shouldn't be able to panic the reader.
+fn delta_decode_into<T: Copy + Into<i64>>(deltas: &[T], out: &mut Vec<i64>) {
+    out.clear();
+    let mut last = 0i64;
+    out.extend(deltas.iter().map(|&delta| {
+        last = last.wrapping_add(delta.into());
+        last
+    }));
+}
+
+/// `delta_decode_into` a new `Vec`
+fn delta_decode<T: Copy + Into<i64>>(deltas: &[T]) -> Vec<i64> {
+    let mut out = Vec::with_capacity(deltas.len());
+    delta_decode_into(deltas, &mut out);
+    out
+}
+
+/// The delta decoded arrays of a `DenseNodes`. They're reused for every group, rather than
+/// allocating them each time.
+#[derive(Debug, Default)]
+struct DenseArrays {
+    ids: Vec<i64>,
+    lats: Vec<i64>,
+    lons: Vec<i64>,
+    timestamps: Vec<i64>,
+    changesets: Vec<i64>,
+    uids: Vec<i64>,
+    user_sids: Vec<i64>,
+}
+
+impl DenseArrays {
+    /// Decode these nodes' arrays. Without `decode_metadata`, the timestamps, changesets, uids
+    /// & users are left empty, as they'd be removed anyway.
+    fn decode(&mut self, dense: &osmformat::DenseNodes, decode_metadata: bool) {
+        let info = dense.get_denseinfo();
+        delta_decode_into(dense.get_id(), &mut self.ids);
+        delta_decode_into(dense.get_lat(), &mut self.lats);
+        delta_decode_into(dense.get_lon(), &mut self.lons);
+        let (timestamps, changesets, uids, user_sids) = if decode_metadata {
+            (
+                info.get_timestamp(),
+                info.get_changeset(),
+                info.get_uid(),
+                info.get_user_sid(),
+            )
+        } else {
+            (&[][..], &[][..], &[][..], &[][..])
+        };
+        delta_decode_into(timestamps, &mut self.timestamps);
+        delta_decode_into(changesets, &mut self.changesets);
+        delta_decode_into(uids, &mut self.uids);
+        delta_decode_into(user_sids, &mut self.user_sids);
+    }
 }
 
 fn decode_dense_nodes(
     primitive_group: &osmformat::PrimitiveGroup,
-    granularity: i64,
-    lat_offset: i64,
-    lon_offset: i64,
-    date_granularity: i32,
+    scale: &Scale,
     stringtable: &Vec<Option<Arc<str>>>,
+    arrays: &mut DenseArrays,
     results: &mut Vec<ArcOSMObj>,
-) {
+) -> Result<(), ReadError> {
     let dense = primitive_group.get_dense();
-    let ids = dense.get_id();
-    let lats = dense.get_lat();
-    let lons = dense.get_lon();
     let denseinfo = dense.get_denseinfo();
 
-    let uids = denseinfo.get_uid();
-    let changesets = denseinfo.get_changeset();
-    let user_sids = denseinfo.get_user_sid();
-    let timestamps = denseinfo.get_timestamp();
+    arrays.decode(dense, scale.decode_metadata);
+    let DenseArrays {
+        ref ids,
+        ref lats,
+        ref lons,
+        ref timestamps,
+        ref changesets,
+        ref uids,
+        ref user_sids,
+    } = *arrays;
+    let versions = denseinfo.get_version();
+    let visibles = denseinfo.get_visible();
 
     let num_nodes = ids.len();
     results.reserve(num_nodes);
-    // TODO assert that the id, denseinfo, lat, lon and optionally keys_vals has the same
-    // length
 
     let keys_vals = dense.get_keys_vals();
-    let has_tags = !keys_vals.is_empty();
-
     let mut keys_vals_index = 0;
 
-    // NB it's important that these start at zero, makes the code easier later
-    let mut last_id = 0;
-    let mut last_lat = 0;
-    let mut last_lon = 0;
-    let mut last_timestamp = 0;
-    let mut last_changset = 0;
-    let mut last_uid = 0;
-    let mut last_user_sid = 0;
-
-    for index in 0..num_nodes {
-        // last_* start off 0
-        let id = ids[index] + last_id;
-        last_id = id;
-        let lat = lats[index] + last_lat;
-        last_lat = lat;
-        let lon = lons[index] + last_lon;
-        last_lon = lon;
-
-        // FIXME lat/lon here seem to be missing the last digit compared to the website
-        let lat = lat_offset + (granularity * lat);
-        let lat = 0.000000001 * (lat as f32);
-        let lon = lon_offset + (granularity * lon);
-        let lon = 0.000000001 * (lon as f32);
-
-        let tags = if !has_tags {
-            None
-        } else {
-            let mut tags = Vec::new();
-            loop {
-                //assert!(keys_vals_index <= keys_vals.len());
-                let next = keys_vals[keys_vals_index];
+    for (index, &id) in ids.iter().enumerate() {
+        let lat_lon = match (lats.get(index), lons.get(index)) {
+            (Some(&lat), Some(&lon)) => scale.lat_lon(lat, lon),
+            _ => return Err(ReadError::PBFInvalidData("missing dense node lat/lon")),
+        };
+
+        let tags = match keys_vals.get(keys_vals_index) {
+            // No tags in this block
+            None => None,
+            // Most nodes are untagged, so don't build any tags for them
+            Some(&0) => {
                 keys_vals_index += 1;
-                if next == 0 {
-                    break;
-                } else {
-                    let key = next;
-                    let val = keys_vals[keys_vals_index];
+                None
+            }
+            Some(_) => {
+                let mut tags = Vec::new();
+                // Pairs of key & value, ending with a 0
+                while let Some(&key) = keys_vals.get(keys_vals_index) {
+                    keys_vals_index += 1;
+                    if key == 0 {
+                        break;
+                    }
+                    let val = keys_vals.get(keys_vals_index).cloned().unwrap_or(0);
                     keys_vals_index += 1;
-                    tags.push((key, val));
+                    if let (Some(k), Some(v)) = (
+                        get_string(stringtable, key as usize)?,
+                        get_string(stringtable, val as usize)?,
+                    ) {
+                        tags.push((k, v));
+                    }
                 }
-                // FIXME infinite loop detection maybe?
+                Some(tags)
             }
-
-            Some(
-                tags.iter()
-                    .map(|&(kidx, vidx)| {
-                        (
-                            stringtable[kidx as usize].clone(),
-                            stringtable[vidx as usize].clone(),
-                        )
-                    })
-                    .filter_map(|(k, v)| match (k, v) {
-                        (Some(k), Some(v)) => Some((k, v)),
-                        _ => None,
-                    })
-                    .collect(),
-            )
         };
 
-        let changeset_id = changesets[index] + last_changset;
-        last_changset = changeset_id;
-        let uid_id = uids[index] + last_uid;
-        last_uid = uid_id;
-        let user_sid = user_sids[index] + last_user_sid;
-        last_user_sid = user_sid;
-        let timestamp = timestamps[index] as i32 + last_timestamp;
-        let timestamp = timestamp * date_granularity;
-        last_timestamp = timestamp;
-        let timestamp = TimestampFormat::EpochNunber(timestamp as i64);
-        assert!(uid_id < std::i32::MAX);
+        // The metadata is optional, and the timestamps are in units of date_granularity
+        let timestamp = timestamps
+            .get(index)
+            .map(|&t| TimestampFormat::EpochNunber(scale.timestamp(t)));
+        let uid = match uids.get(index) {
+            Some(&uid) if uid > std::i32::MAX as i64 => {
+                return Err(ReadError::PBFInvalidData("dense node uid out of range"))
+            }
+            uid => uid.map(|&uid| uid as u64),
+        };
+        let user = match user_sids.get(index) {
+            Some(&sid) => get_string(stringtable, sid as usize)?,
+            None => None,
+        };
 
         results.push(ArcOSMObj::Node(ArcNode {
             _id: id as ObjId,
             _tags: tags,
-            _lat_lon: Some((lat, lon)),
-            _deleted: !denseinfo.get_visible().get(index).unwrap_or(&true),
-            _changeset_id: Some(changeset_id as u32),
-            _uid: Some(uid_id as u32),
-            _user: Some(stringtable[user_sid as usize].clone().unwrap()),
-            _version: Some(denseinfo.get_version()[index] as u32),
-            _timestamp: Some(timestamp),
+            _lat_lon: Some(lat_lon),
+            _deleted: !visibles.get(index).unwrap_or(&true),
+            _changeset_id: changesets.get(index).map(|&c| c as u64),
+            _uid: uid,
+            _user: user,
+            _version: versions.get(index).map(|&v| v as u32),
+            _timestamp: timestamp,
         }));
     }
+    Ok(())
+}
 
-    // convert the keys_vals to
+/// The string at this index of the string table, `None` if it isn't valid UTF-8
+fn get_string(stringtable: &[Option<Arc<str>>], idx: usize) -> Result<Option<Arc<str>>, ReadError> {
+    stringtable
+        .get(idx)
+        .cloned()
+        .ok_or(ReadError::PBFInvalidData("string table index out of range"))
+}
+
+type ArcTags = Vec<(Arc<str>, Arc<str>)>;
+
+/// The tags of a way or relation, skipping any which aren't valid UTF-8
+fn decode_tags(
+    stringtable: &[Option<Arc<str>>],
+    keys: &[u32],
+    vals: &[u32],
+) -> Result<ArcTags, ReadError> {
+    let mut tags = Vec::with_capacity(keys.len());
+    for (&key, &val) in keys.iter().zip(vals) {
+        // TODO check for +itive keys/vals
+        if let (Some(k), Some(v)) = (
+            get_string(stringtable, key as usize)?,
+            get_string(stringtable, val as usize)?,
+        ) {
+            tags.push((k, v));
+        }
+    }
+    Ok(tags)
 }
 
 fn decode_ways(
     primitive_group: &osmformat::PrimitiveGroup,
-    _granularity: i64,
-    _lat_offset: i64,
-    _lon_offset: i64,
-    _date_granularity: i32,
+    scale: &Scale,
     stringtable: &Vec<Option<Arc<str>>>,
     results: &mut Vec<ArcOSMObj>,
-) {
+) -> Result<(), ReadError> {
     let ways = primitive_group.get_ways();
     results.reserve(ways.len());
     for way in ways {
         let id = way.get_id() as ObjId;
-        // TODO check for +itive keys/vals
-        let keys = way
-            .get_keys()
-            .into_iter()
-            .map(|&idx| stringtable[idx as usize].clone());
-        let vals = way
-            .get_vals()
-            .into_iter()
-            .map(|&idx| stringtable[idx as usize].clone());
-        let tags = keys.zip(vals);
-        let tags: Vec<_> = tags
-            .filter_map(|(k, v)| match (k, v) {
-                (Some(k), Some(v)) => Some((k, v)),
-                _ => None,
-            })
-            .collect();
+        let tags = decode_tags(stringtable, way.get_keys(), way.get_vals())?;
 
-        let refs = way.get_refs();
-        let mut nodes = Vec::with_capacity(refs.len());
         // TODO assert node.len() > 0
-        if !refs.is_empty() {
-            let mut last_id = refs[0];
-            nodes.push(last_id as ObjId);
-            for nid in &refs[1..] {
-                last_id = nid + last_id;
-                nodes.push(last_id as ObjId);
-            }
-        }
+        let nodes = delta_decode(way.get_refs());
 
         // TODO assert all node ids are positive
 
@@ -261,74 +402,42 @@ fn decode_ways(
         //let timestamp = timestamp * date_granularity;
         //last_timestamp = timestamp;
         //let timestamp = epoch_to_iso(timestamp);
-        let timestamp = TimestampFormat::EpochNunber(way.get_info().get_timestamp());
+        let timestamp =
+            TimestampFormat::EpochNunber(scale.timestamp(way.get_info().get_timestamp()));
 
         results.push(ArcOSMObj::Way(ArcWay {
             _id: id,
             _tags: tags,
             _nodes: nodes,
             _deleted: !way.get_info().get_visible(),
-            _changeset_id: Some(way.get_info().get_changeset() as u32),
-            _uid: Some(way.get_info().get_uid() as u32),
-            _user: Some(
-                stringtable[way.get_info().get_user_sid() as usize]
-                    .clone()
-                    .unwrap()
-                    .clone(),
-            ),
+            _changeset_id: Some(way.get_info().get_changeset() as u64),
+            _uid: Some(way.get_info().get_uid() as u64),
+            _user: get_string(stringtable, way.get_info().get_user_sid() as usize)?,
             _version: Some(way.get_info().get_version() as u32),
             _timestamp: Some(timestamp),
         }));
     }
+    Ok(())
 }
 
 fn decode_relations(
     primitive_group: &osmformat::PrimitiveGroup,
-    _granularity: i64,
-    _lat_offset: i64,
-    _lon_offset: i64,
-    _date_granularity: i32,
+    scale: &Scale,
     stringtable: &Vec<Option<Arc<str>>>,
     results: &mut Vec<ArcOSMObj>,
-) {
-    let _last_timestamp = 0;
+) -> Result<(), ReadError> {
     for relation in primitive_group.get_relations() {
         let id = relation.get_id() as ObjId;
-        // TODO check for +itive keys/vals
-        let keys = relation
-            .get_keys()
-            .into_iter()
-            .map(|&idx| stringtable[idx as usize].clone());
-        let vals = relation
-            .get_vals()
-            .into_iter()
-            .map(|&idx| stringtable[idx as usize].clone());
-        let tags = keys.zip(vals);
-        let tags: Vec<_> = tags
-            .filter_map(|(k, v)| match (k, v) {
-                (Some(k), Some(v)) => Some((k, v)),
-                _ => None,
-            })
-            .collect();
+        let tags = decode_tags(stringtable, relation.get_keys(), relation.get_vals())?;
 
         let roles = relation
             .get_roles_sid()
-            .into_iter()
-            .map(|&idx| stringtable[idx as usize].clone());
+            .iter()
+            .map(|&idx| get_string(stringtable, idx as usize))
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let refs = relation.get_memids();
-        let mut member_ids = Vec::with_capacity(refs.len());
         // TODO assert node.len() > 0
-        if !refs.is_empty() {
-            let mut last_id = refs[0];
-            member_ids.push(last_id as ObjId);
-            for nid in &refs[1..] {
-                last_id = nid + last_id;
-                member_ids.push(last_id as ObjId);
-            }
-        }
-        let _num_members = member_ids.len();
-        let member_ids = member_ids.iter();
+        let member_ids = delta_decode(relation.get_memids());
 
         let member_types = relation.get_types().iter().map(|t| match *t {
             osmformat::Relation_MemberType::NODE => OSMObjectType::Node,
@@ -339,10 +448,7 @@ fn decode_relations(
         let members: Vec<_> = member_types
             .zip(member_ids)
             .zip(roles)
-            .filter_map(|((t, &id), r_opt)| match r_opt {
-                Some(r) => Some((t, id, r)),
-                None => None,
-            })
+            .filter_map(|((t, id), r_opt)| r_opt.map(|r| Member::new(t, id, r)))
             .collect();
 
         // TODO could there be *no* info? What should be done there
@@ -350,82 +456,50 @@ fn decode_relations(
         //let timestamp = timestamp * date_granularity;
         //last_timestamp = timestamp;
         //let timestamp = epoch_to_iso(timestamp);
-        let timestamp = TimestampFormat::EpochNunber(relation.get_info().get_timestamp());
+        let timestamp =
+            TimestampFormat::EpochNunber(scale.timestamp(relation.get_info().get_timestamp()));
 
         results.push(ArcOSMObj::Relation(ArcRelation {
             _id: id,
             _tags: tags,
             _members: members,
             _deleted: !relation.get_info().get_visible(),
-            _changeset_id: Some(relation.get_info().get_changeset() as u32),
-            _uid: Some(relation.get_info().get_uid() as u32),
-            _user: Some(
-                stringtable[relation.get_info().get_user_sid() as usize]
-                    .clone()
-                    .unwrap(),
-            ),
+            _changeset_id: Some(relation.get_info().get_changeset() as u64),
+            _uid: Some(relation.get_info().get_uid() as u64),
+            _user: get_string(stringtable, relation.get_info().get_user_sid() as usize)?,
             _version: Some(relation.get_info().get_version() as u32),
             _timestamp: Some(timestamp),
         }));
     }
+    Ok(())
 }
 
 fn decode_primitive_group_to_objs(
     primitive_group: &osmformat::PrimitiveGroup,
-    granularity: i64,
-    lat_offset: i64,
-    lon_offset: i64,
-    date_granularity: i32,
+    scale: &Scale,
     stringtable: &Vec<Option<Arc<str>>>,
+    dense: &mut DenseArrays,
     mut results: &mut Vec<ArcOSMObj>,
-) {
-    let date_granularity = date_granularity / 1000;
+) -> Result<(), ReadError> {
     if !primitive_group.get_nodes().is_empty() {
-        decode_nodes(
-            primitive_group,
-            granularity,
-            lat_offset,
-            lon_offset,
-            date_granularity,
-            &stringtable,
-            &mut results,
-        );
+        decode_nodes(primitive_group, scale, &stringtable, &mut results)
     } else if primitive_group.has_dense() {
-        decode_dense_nodes(
-            primitive_group,
-            granularity,
-            lat_offset,
-            lon_offset,
-            date_granularity,
-            &stringtable,
-            &mut results,
-        );
+        decode_dense_nodes(primitive_group, scale, &stringtable, dense, &mut results)
     } else if !primitive_group.get_ways().is_empty() {
-        decode_ways(
-            primitive_group,
-            granularity,
-            lat_offset,
-            lon_offset,
-            date_granularity,
-            &stringtable,
-            &mut results,
-        );
+        decode_ways(primitive_group, scale, &stringtable, &mut results)
     } else if !primitive_group.get_relations().is_empty() {
-        decode_relations(
-            primitive_group,
-            granularity,
-            lat_offset,
-            lon_offset,
-            date_granularity,
-            &stringtable,
-            &mut results,
-        );
+        decode_relations(primitive_group, scale, &stringtable, &mut results)
     } else {
-        unreachable!();
+        // Only changesets, or nothing at all, neither of which are objects
+        Ok(())
     }
 }
 
-fn decode_block_to_objs(mut block: osmformat::PrimitiveBlock) -> Vec<ArcOSMObj> {
+fn decode_block_to_objs(
+    mut block: osmformat::PrimitiveBlock,
+    dense: &mut DenseArrays,
+    decode_metadata: bool,
+) -> Result<Vec<ArcOSMObj>, ReadError> {
     let stringtable: Vec<Option<Arc<str>>> = block
         .take_stringtable()
         .take_s()
@@ -433,40 +507,51 @@ fn decode_block_to_objs(mut block: osmformat::PrimitiveBlock) -> Vec<ArcOSMObj>
         .map(|chars| std::str::from_utf8(&chars).ok().map(|s| Arc::from(s)))
         .collect();
 
-    let granularity = block.get_granularity() as i64;
-    let lat_offset = block.get_lat_offset();
-    let lon_offset = block.get_lon_offset();
-    let date_granularity = block.get_date_granularity();
+    let scale = Scale::new(&block, decode_metadata);
 
     let mut results: Vec<ArcOSMObj> = Vec::new();
 
     for primitive_group in block.get_primitivegroup() {
-        decode_primitive_group_to_objs(
-            primitive_group,
-            granularity,
-            lat_offset,
-            lon_offset,
-            date_granularity,
-            &stringtable,
-            &mut results,
-        );
+        decode_primitive_group_to_objs(primitive_group, &scale, &stringtable, dense, &mut results)?;
     }
 
-    results
-}
-
-impl<R: Read> Iterator for FileReader<R> {
-    type Item = fileformat::Blob;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.get_next_osmdata_blob()
-    }
+    Ok(results)
 }
 
 pub struct PBFReader<R: Read> {
     filereader: FileReader<R>,
     _buffer: Vec<ArcOSMObj>,
-    _sorted_assumption: bool,
+    options: ReaderOptions,
+    /// For decoding dense nodes, one per thread
+    dense: Vec<DenseArrays>,
+}
+
+/// Decompress & decode one blob
+fn decode_blob(
+    mut blob: fileformat::Blob,
+    dense: &mut DenseArrays,
+    decode_metadata: bool,
+) -> Result<Vec<ArcOSMObj>, ReadError> {
+    let blob_data = blob_raw_data(&mut blob)?;
+    let block: osmformat::PrimitiveBlock = protobuf::parse_from_bytes(&blob_data)?;
+
+    // Turn a block into OSM objects
+    decode_block_to_objs(block, dense, decode_metadata)
+}
+
+impl<R: Read> PBFReader<R> {
+    /// Decode the next block of the file, see `block`. This reads from the file directly, so
+    /// don't mix it with `next`, which might have already buffered the following objects.
+    pub fn next_block(&mut self) -> Result<Option<block::Block>, ReadError> {
+        match self.filereader.get_next_osmdata_blob()? {
+            None => Ok(None),
+            Some(mut blob) => {
+                let blob_data = blob_raw_data(&mut blob)?;
+                let primitive_block = protobuf::parse_from_bytes(&blob_data)?;
+                Ok(Some(block::Block::decode(primitive_block, &self.options)?))
+            }
+        }
+    }
 }
 
 impl<R: Read> OSMReader for PBFReader<R> {
@@ -474,18 +559,23 @@ impl<R: Read> OSMReader for PBFReader<R> {
     type Obj = ArcOSMObj;
 
     fn new(reader: R) -> PBFReader<R> {
+        PBFReader::new_with_options(reader, ReaderOptions::default())
+    }
+
+    fn new_with_options(reader: R, options: ReaderOptions) -> PBFReader<R> {
         PBFReader {
             filereader: FileReader::new(reader),
             _buffer: Vec::new(),
-            _sorted_assumption: false,
+            options: options,
+            dense: Vec::new(),
         }
     }
 
     fn set_sorted_assumption(&mut self, sorted_assumption: bool) {
-        self._sorted_assumption = sorted_assumption;
+        self.options.sorted_assumption = sorted_assumption;
     }
     fn get_sorted_assumption(&mut self) -> bool {
-        self._sorted_assumption
+        self.options.sorted_assumption
     }
 
     fn inner(&self) -> &R {
@@ -496,19 +586,61 @@ impl<R: Read> OSMReader for PBFReader<R> {
         self.filereader.into_inner()
     }
 
-    fn next(&mut self) -> Option<ArcOSMObj> {
-        while self._buffer.is_empty() {
-            // get the next file block and fill up our buffer
-            // FIXME make this parallel
+    fn try_header(&mut self) -> Result<Option<Header>, ReadError> {
+        Ok(self.filereader.header()?.cloned())
+    }
 
-            // get the next block
-            let mut blob = self.filereader.next()?;
+    fn next(&mut self) -> Option<ArcOSMObj> {
+        self.try_next().unwrap()
+    }
 
-            let blob_data = blob_raw_data(&mut blob).unwrap();
-            let block: osmformat::PrimitiveBlock = protobuf::parse_from_bytes(&blob_data).unwrap();
+    fn try_next(&mut self) -> Result<Option<ArcOSMObj>, ReadError> {
+        while self._buffer.is_empty() {
+            // get the next file blocks (one per thread) and fill up our buffer
+            let mut blobs = Vec::with_capacity(self.options.threads);
+            while blobs.len() < self.options.threads.max(1) {
+                match self.filereader.get_next_osmdata_blob()? {
+                    None => break,
+                    Some(blob) => blobs.push(blob),
+                }
+            }
+            if blobs.is_empty() {
+                return Ok(None);
+            }
 
-            // Turn a block into OSM objects
-            let mut objs = decode_block_to_objs(block);
+            if self.dense.len() < blobs.len() {
+                self.dense.resize_with(blobs.len(), Default::default);
+            }
+            let decode_metadata = self.options.decode_metadata;
+            let mut objs = if blobs.len() == 1 {
+                decode_blob(blobs.pop().unwrap(), &mut self.dense[0], decode_metadata)?
+            } else {
+                let decoded: Vec<_> = std::thread::scope(|scope| {
+                    let handles: Vec<_> = blobs
+                        .into_iter()
+                        .zip(self.dense.iter_mut())
+                        .map(|(blob, dense)| {
+                            scope.spawn(move || decode_blob(blob, dense, decode_metadata))
+                        })
+                        .collect();
+                    handles
+                        .into_iter()
+                        .map(|h| {
+                            h.join().unwrap_or(Err(ReadError::PBFInvalidData(
+                                "decoding a block panicked",
+                            )))
+                        })
+                        .collect()
+                });
+                let mut objs = Vec::new();
+                for block_objs in decoded {
+                    objs.extend(block_objs?);
+                }
+                objs
+            };
+            for obj in objs.iter_mut() {
+                self.options.apply_decode_metadata(obj);
+            }
 
             // we reverse the Vec so that we can .pop from the buffer, rather than .remove(0)
             // IME pop'ing is faster, since it means less memory moving
@@ -517,6 +649,474 @@ impl<R: Read> OSMReader for PBFReader<R> {
             self._buffer = objs;
         }
 
-        self._buffer.pop()
+        Ok(self._buffer.pop())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use byteorder::WriteBytesExt;
+    use protobuf::Message;
+
+    /// A block with one way, with this id
+    pub(crate) fn way_block(id: ObjId) -> osmformat::PrimitiveBlock {
+        let mut block = osmformat::PrimitiveBlock::new();
+        block.mut_stringtable().mut_s().push(b"".to_vec());
+        block.mut_stringtable().mut_s().push(b"highway".to_vec());
+        block.mut_stringtable().mut_s().push(b"road".to_vec());
+
+        let mut way = osmformat::Way::new();
+        way.set_id(id);
+        way.set_keys(vec![1]);
+        way.set_vals(vec![2]);
+        way.set_refs(vec![1, 1]);
+        way.mut_info().set_version(1);
+        way.mut_info().set_uid(5);
+        way.mut_info().set_visible(true);
+
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.mut_ways().push(way);
+        block.mut_primitivegroup().push(group);
+        block
+    }
+
+    /// A block of 3 dense nodes: 10 & 16 are benches, and all are by alice
+    fn dense_block() -> osmformat::PrimitiveBlock {
+        let mut block = osmformat::PrimitiveBlock::new();
+        block.mut_stringtable().mut_s().push(b"".to_vec());
+        block.mut_stringtable().mut_s().push(b"amenity".to_vec());
+        block.mut_stringtable().mut_s().push(b"bench".to_vec());
+        block.mut_stringtable().mut_s().push(b"alice".to_vec());
+        let mut dense = osmformat::DenseNodes::new();
+        dense.set_id(vec![10, 1, 5]);
+        dense.set_lat(vec![100, -50, 0]);
+        dense.set_lon(vec![200, 0, 30]);
+        dense.set_keys_vals(vec![1, 2, 0, 0, 1, 2, 0]);
+        dense.mut_denseinfo().set_version(vec![1, 2, 3]);
+        dense.mut_denseinfo().set_timestamp(vec![1000, 5, 5]);
+        dense.mut_denseinfo().set_changeset(vec![7, 0, 1]);
+        dense.mut_denseinfo().set_uid(vec![3, 0, 0]);
+        dense.mut_denseinfo().set_user_sid(vec![3, 0, 0]);
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.set_dense(dense);
+        block.mut_primitivegroup().push(group);
+        block
+    }
+
+    /// Append a blob of this type, with this message, to `file`
+    fn push_blob(file: &mut Vec<u8>, blob_type: &str, message: &impl Message) {
+        push_raw_blob(file, blob_type, message.write_to_bytes().unwrap());
+    }
+
+    /// Append a blob of this type, with these (uncompressed) bytes, to `file`
+    fn push_raw_blob(file: &mut Vec<u8>, blob_type: &str, block_bytes: Vec<u8>) {
+        let mut blob = fileformat::Blob::new();
+        blob.set_raw_size(block_bytes.len() as i32);
+        blob.set_raw(block_bytes);
+        let blob_bytes = blob.write_to_bytes().unwrap();
+
+        let mut header = fileformat::BlobHeader::new();
+        header.set_field_type(blob_type.to_string());
+        header.set_datasize(blob_bytes.len() as i32);
+        let header_bytes = header.write_to_bytes().unwrap();
+
+        file.write_u32::<byteorder::BigEndian>(header_bytes.len() as u32)
+            .unwrap();
+        file.extend(header_bytes);
+        file.extend(blob_bytes);
+    }
+
+    /// A PBF file of these blocks (without a OSMHeader block)
+    pub(crate) fn pbf_file(blocks: Vec<osmformat::PrimitiveBlock>) -> Vec<u8> {
+        let mut file = Vec::new();
+        for block in blocks {
+            push_blob(&mut file, "OSMData", &block);
+        }
+        file
+    }
+
+    #[test]
+    fn read_ways() {
+        let file = pbf_file(vec![way_block(1), way_block(2)]);
+        let mut reader = PBFReader::new(file.as_slice());
+        let ways: Vec<_> = reader.objects().collect();
+        assert_eq!(ways.len(), 2);
+        let way = ways[0].as_way().unwrap();
+        assert_eq!(way.id(), 1);
+        assert_eq!(way.nodes(), &[1, 2]);
+        assert_eq!(way.tag("highway"), Some("road"));
+        assert_eq!(way.uid(), Some(5));
+    }
+
+    #[test]
+    fn read_with_options() {
+        let file = pbf_file((1..=5).map(way_block).collect());
+        let options = ReaderOptions {
+            threads: 3,
+            decode_metadata: false,
+            ..Default::default()
+        };
+        let mut reader = PBFReader::new_with_options(file.as_slice(), options.clone());
+        let objs: Vec<_> = reader.objects().collect();
+        assert_eq!(
+            objs.iter().map(|o| o.id()).collect::<Vec<_>>(),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert!(objs.iter().all(|o| o.uid().is_none()));
+
+        // Dense node metadata isn't decoded (or checked) at all
+        let mut block = dense_block();
+        let info = block.mut_primitivegroup()[0].mut_dense().mut_denseinfo();
+        info.set_uid(vec![3, i32::MAX, 0]);
+        let file = pbf_file(vec![block]);
+        let mut reader = PBFReader::new_with_options(file.as_slice(), options.clone());
+        let nodes: Vec<_> = reader.try_objects().collect::<Result<_, _>>().unwrap();
+        assert_eq!(nodes.len(), 3);
+        assert!(nodes
+            .iter()
+            .all(|n| n.timestamp().is_none() && n.user().is_none()));
+        let block = PBFReader::new_with_options(file.as_slice(), options)
+            .next_block()
+            .unwrap()
+            .unwrap();
+        assert_eq!(block.get(0).unwrap().version(), Some(1));
+        assert_eq!(block.get(0).unwrap().uid(), None);
+    }
+
+    #[test]
+    fn dense_nodes() {
+        assert_eq!(delta_decode(&[5i32, -2, 10]), vec![5, 3, 13]);
+        assert_eq!(delta_decode::<i64>(&[]), Vec::<i64>::new());
+
+        // The arrays are reused, and the metadata is skipped when it isn't wanted
+        let block = dense_block();
+        let dense = block.get_primitivegroup()[0].get_dense();
+        let mut arrays = DenseArrays::default();
+        arrays.decode(dense, true);
+        assert_eq!(arrays.user_sids, vec![3, 3, 3]);
+        let ids_ptr = arrays.ids.as_ptr();
+        arrays.decode(dense, false);
+        assert_eq!(arrays.ids.as_ptr(), ids_ptr);
+        assert_eq!(arrays.ids, vec![10, 11, 16]);
+        assert!(arrays.timestamps.is_empty());
+        assert!(arrays.user_sids.is_empty());
+
+        let block = dense_block();
+
+        let file = pbf_file(vec![block]);
+        let nodes: Vec<_> = PBFReader::new(file.as_slice()).objects().collect();
+        let nodes: Vec<_> = nodes.iter().map(|n| n.as_node().unwrap()).collect();
+        assert_eq!(
+            nodes.iter().map(|n| n.id()).collect::<Vec<_>>(),
+            vec![10, 11, 16]
+        );
+        assert_eq!(
+            nodes[1].lat_lon_f64(),
+            Some((50. * 100. / 1e9, 200. * 100. / 1e9))
+        );
+        assert_eq!(nodes[0].tag("amenity"), Some("bench"));
+        assert_eq!(nodes[1].num_tags(), 0);
+        // Untagged nodes take the fast path, without a tags Vec
+        assert!(nodes[1]._tags.is_none());
+        assert_eq!(nodes[2].tag("amenity"), Some("bench"));
+        assert_eq!(
+            nodes
+                .iter()
+                .map(|n| n.timestamp().clone())
+                .collect::<Vec<_>>(),
+            vec![
+                Some(TimestampFormat::EpochNunber(1000)),
+                Some(TimestampFormat::EpochNunber(1005)),
+                Some(TimestampFormat::EpochNunber(1010)),
+            ]
+        );
+        assert_eq!(nodes[2].changeset_id(), Some(8));
+        assert_eq!(nodes[2].uid(), Some(3));
+        assert_eq!(nodes[2].user(), Some("alice"));
+        assert_eq!(nodes[2].version(), Some(3));
+    }
+
+    #[test]
+    fn plain_nodes() {
+        let mut block = dense_block();
+        let mut node = osmformat::Node::new();
+        node.set_id(20);
+        node.set_lat(100);
+        node.set_lon(-200);
+        node.set_keys(vec![1]);
+        node.set_vals(vec![2]);
+        node.mut_info().set_version(2);
+        node.mut_info().set_timestamp(1000);
+        node.mut_info().set_user_sid(3);
+        node.mut_info().set_visible(true);
+        let mut untagged = osmformat::Node::new();
+        untagged.set_id(21);
+        untagged.set_lat(0);
+        untagged.set_lon(0);
+        block.mut_primitivegroup()[0].mut_nodes().push(node);
+        block.mut_primitivegroup()[0].mut_nodes().push(untagged);
+        block.mut_primitivegroup()[0].clear_dense();
+
+        let file = pbf_file(vec![block]);
+        let nodes: Vec<_> = PBFReader::new(file.as_slice()).objects().collect();
+        assert_eq!(nodes.len(), 2);
+        let node = nodes[0].as_node().unwrap();
+        assert_eq!(node.id(), 20);
+        assert_eq!(
+            node.lat_lon_f64(),
+            Some((100. * 100. / 1e9, -200. * 100. / 1e9))
+        );
+        assert_eq!(node.tag("amenity"), Some("bench"));
+        assert_eq!(node.user(), Some("alice"));
+        assert_eq!(node.version(), Some(2));
+        assert_eq!(node.timestamp(), &Some(TimestampFormat::EpochNunber(1000)));
+        let untagged = nodes[1].as_node().unwrap();
+        assert!(untagged._tags.is_none());
+        assert_eq!(untagged.version(), None);
+
+        // The same as the block's objects
+        let block = PBFReader::new(file.as_slice()).next_block().unwrap();
+        let from_block: Vec<_> = block.unwrap().iter().map(|o| o.to_obj()).collect();
+        assert_eq!(from_block, nodes);
+    }
+
+    #[test]
+    fn read_blocks() {
+        let mut relations = way_block(1);
+        let mut relation = osmformat::Relation::new();
+        relation.set_id(3);
+        relation.set_keys(vec![1]);
+        relation.set_vals(vec![2]);
+        relation.set_memids(vec![1, 4]);
+        relation.set_types(vec![
+            osmformat::Relation_MemberType::WAY,
+            osmformat::Relation_MemberType::NODE,
+        ]);
+        relation.set_roles_sid(vec![0, 1]);
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.mut_relations().push(relation);
+        relations.mut_primitivegroup().push(group);
+        let file = pbf_file(vec![dense_block(), relations]);
+
+        let mut reader = PBFReader::new(file.as_slice());
+        let nodes = reader.next_block().unwrap().unwrap();
+        assert_eq!(nodes.len(), 3);
+        let node = nodes.get(2).unwrap();
+        assert_eq!(node.object_type(), OSMObjectType::Node);
+        assert_eq!(
+            (node.id(), node.version(), node.uid()),
+            (16, Some(3), Some(3))
+        );
+        assert_eq!((node.user(), node.timestamp()), (Some("alice"), Some(1010)));
+        assert_eq!(node.tags().collect::<Vec<_>>(), vec![("amenity", "bench")]);
+        assert_eq!(nodes.get(1).unwrap().tag("amenity"), None);
+        assert!(nodes.get(3).is_none());
+
+        let block = reader.next_block().unwrap().unwrap();
+        assert!(reader.next_block().unwrap().is_none());
+        let objs: Vec<_> = block.iter().collect();
+        assert_eq!(objs[0].nodes(), &[1, 2]);
+        assert_eq!(objs[0].members().count(), 0);
+        assert_eq!(objs[1].nodes(), &[] as &[ObjId]);
+        assert_eq!(
+            objs[1].members().collect::<Vec<_>>(),
+            vec![
+                Member::new(OSMObjectType::Way, 1, ""),
+                Member::new(OSMObjectType::Node, 5, "highway"),
+            ]
+        );
+
+        // The same as the owned objects
+        let owned: Vec<_> = PBFReader::new(&file[..]).objects().collect();
+        let from_blocks: Vec<_> = PBFReader::new(&file[..])
+            .next_block()
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|o| o.to_obj())
+            .collect();
+        assert_eq!(from_blocks, owned[..3]);
+        assert_eq!(objs[0].to_obj(), owned[3]);
+        assert_eq!(objs[1].to_obj(), owned[4]);
+        assert_eq!(
+            objs[1].to_obj().as_relation().unwrap().tag("highway"),
+            Some("road")
+        );
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut file = pbf_file(vec![way_block(1), way_block(2)]);
+        let len = file.len();
+        file.truncate(len - 3);
+        let mut reader = PBFReader::new(file.as_slice());
+        assert!(reader.try_next().unwrap().is_some());
+        match reader.try_next() {
+            Err(ReadError::IOError(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    /// Both `try_next` & `next_block` return `PBFInvalidData` for this block
+    fn assert_invalid(block: osmformat::PrimitiveBlock) {
+        let file = pbf_file(vec![block]);
+        match PBFReader::new(file.as_slice()).try_next() {
+            Err(ReadError::PBFInvalidData(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        match PBFReader::new(file.as_slice()).next_block() {
+            Err(ReadError::PBFInvalidData(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn read_corrupt_blocks() {
+        // A tag key past the end of the string table
+        let mut block = way_block(1);
+        block.mut_primitivegroup()[0].mut_ways()[0].set_keys(vec![99]);
+        assert_invalid(block);
+
+        // A user past the end of the string table, and member ids which overflow
+        let mut block = way_block(1);
+        let mut relation = osmformat::Relation::new();
+        relation.set_id(3);
+        relation.set_memids(vec![i64::MAX, 1]);
+        relation.set_types(vec![
+            osmformat::Relation_MemberType::WAY,
+            osmformat::Relation_MemberType::WAY,
+        ]);
+        relation.set_roles_sid(vec![0, 0]);
+        relation.mut_info().set_user_sid(99);
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.mut_relations().push(relation.clone());
+        block.mut_primitivegroup().push(group);
+        assert_invalid(block);
+
+        // A member role past the end of the string table
+        let mut block = way_block(1);
+        let mut group = osmformat::PrimitiveGroup::new();
+        let mut bad_role = relation.clone();
+        bad_role.mut_info().set_user_sid(0);
+        bad_role.set_roles_sid(vec![0, 99]);
+        group.mut_relations().push(bad_role);
+        block.mut_primitivegroup().push(group);
+        assert_invalid(block);
+
+        // The same, with several threads
+        let mut blocks: Vec<_> = (1..=3).map(way_block).collect();
+        blocks[1].mut_primitivegroup()[0].mut_ways()[0].set_vals(vec![99]);
+        let file = pbf_file(blocks);
+        let options = ReaderOptions {
+            threads: 3,
+            ..Default::default()
+        };
+        let mut reader = PBFReader::new_with_options(file.as_slice(), options);
+        assert!(reader.try_next().is_err());
+
+        // Overflowing ids wrap, rather than panic
+        relation.mut_info().set_user_sid(0);
+        let mut group = osmformat::PrimitiveGroup::new();
+        group.mut_relations().push(relation);
+        let mut block = osmformat::PrimitiveBlock::new();
+        block.mut_stringtable().mut_s().push(b"".to_vec());
+        block.mut_primitivegroup().push(group);
+        let file = pbf_file(vec![block]);
+        let relation = PBFReader::new(file.as_slice()).try_next().unwrap().unwrap();
+        let ids: Vec<_> = relation
+            .as_relation()
+            .unwrap()
+            .members()
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(ids, vec![i64::MAX, i64::MIN]);
+
+        // Dense nodes with fewer lats than ids, or a uid which is too large
+        let mut block = dense_block();
+        block.mut_primitivegroup()[0]
+            .mut_dense()
+            .set_lat(vec![100, -50]);
+        assert_invalid(block);
+        let mut block = dense_block();
+        let info = block.mut_primitivegroup()[0].mut_dense().mut_denseinfo();
+        info.set_uid(vec![3, i32::MAX, 0]);
+        assert_invalid(block);
+
+        // Dense node tags & users past the end of the string table
+        let mut block = dense_block();
+        block.mut_primitivegroup()[0]
+            .mut_dense()
+            .set_keys_vals(vec![1, 99, 0, 0, 0]);
+        assert_invalid(block);
+        let mut block = dense_block();
+        let info = block.mut_primitivegroup()[0].mut_dense().mut_denseinfo();
+        info.set_user_sid(vec![3, 96, 0]);
+        assert_invalid(block);
+
+        // A huge granularity & date_granularity wrap, rather than panic
+        let mut block = dense_block();
+        block.set_granularity(i32::MAX);
+        block.set_date_granularity(i32::MAX);
+        block.mut_primitivegroup()[0]
+            .mut_dense()
+            .set_lat(vec![i64::MAX, 0, 0]);
+        let file = pbf_file(vec![block]);
+        assert_eq!(PBFReader::new(file.as_slice()).objects().count(), 3);
+        let block = PBFReader::new(file.as_slice()).next_block().unwrap();
+        assert_eq!(block.unwrap().len(), 3);
+
+        // A block which ends in the middle of a number can't be decoded
+        let mut block_bytes = way_block(1).write_to_bytes().unwrap();
+        *block_bytes.last_mut().unwrap() = 0x80;
+        let mut file = Vec::new();
+        push_raw_blob(&mut file, "OSMData", block_bytes);
+        match PBFReader::new(file.as_slice()).try_next() {
+            Err(ReadError::PBFDecodeError(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn header() {
+        let mut block = osmformat::HeaderBlock::new();
+        block.mut_bbox().set_left(-1_500_000_000);
+        block.mut_bbox().set_right(2_000_000_000);
+        block.mut_bbox().set_top(3_000_000_000);
+        block.mut_bbox().set_bottom(1_000_000_000);
+        block.mut_required_features().push("DenseNodes".to_string());
+        block.set_writingprogram("test".to_string());
+        block.set_osmosis_replication_timestamp(700);
+        block.set_osmosis_replication_sequence_number(12);
+        let mut file = Vec::new();
+        push_blob(&mut file, "OSMHeader", &block);
+        file.extend(pbf_file(vec![way_block(1)]));
+
+        let mut reader = PBFReader::new(file.as_slice());
+        let header = reader.header().unwrap();
+        assert_eq!(header.generator.as_ref().unwrap(), "test");
+        assert_eq!(header.timestamp, Some(700.into()));
+        assert_eq!(header.replication_sequence_number, Some(12));
+        assert_eq!(header.required_features, vec!["DenseNodes"]);
+        assert_eq!(
+            reader.bounds(),
+            Some(BBox {
+                min_lat: Lat::from(1.),
+                min_lon: Lon::from(-1.5),
+                max_lat: Lat::from(3.),
+                max_lon: Lon::from(2.),
+            })
+        );
+        // reading the header doesn't lose any objects
+        assert_eq!(reader.objects().count(), 1);
+
+        // the header is also read when reading objects
+        let mut reader = PBFReader::new(file.as_slice());
+        assert_eq!(reader.objects().count(), 1);
+        assert!(reader.header().is_some());
+
+        let mut reader = PBFReader::new(&file[file.len() - pbf_file(vec![way_block(1)]).len()..]);
+        assert_eq!(reader.header(), None);
+        assert_eq!(reader.objects().count(), 1);
     }
 }
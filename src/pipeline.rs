@@ -0,0 +1,707 @@
+//! Connect a reader, through some transformations (any `ObjTransform`), to a writer, without
+//! writing the loop around `objects()` each time.
+//!
+//! ```
+//! use osmio::opl::{OPLReader, OPLWriter};
+//! use osmio::pipeline::Pipeline;
+//! use osmio::{OSMObjBase, OSMReader, OSMWriter};
+//!
+//! let input = "n1 Tamenity=bench x1 y2\nn2 x1 y2\nw3 Tamenity=bench Nn1\n";
+//! let mut writer = OPLWriter::new(Vec::new());
+//! let written = Pipeline::from(OPLReader::new(input.as_bytes()))
+//!     .filter(|o| o.tag("amenity").is_some())
+//!     .map(|mut o| {
+//!         o.set_tag("amenity", "seat");
+//!         o
+//!     })
+//!     .write_to(&mut writer)
+//!     .unwrap();
+//! assert_eq!(written, 2);
+//! assert_eq!(
+//!     String::from_utf8(writer.into_inner()).unwrap(),
+//!     "n1 dV Tamenity=seat x1 y2\nw3 dV Tamenity=seat Nn1\n"
+//! );
+//! ```
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use formats::{read_from_path, OpenError};
+use obj_types::StringOSMObj;
+use transform::{from_fn, ObjTransform, TransformResult};
+use {DynReader, DynWriter, Header, OSMWriteError, ReadError};
+
+/// How many objects are passed on at once, to the writer, or between threads
+const BATCH_SIZE: usize = 1024;
+
+/// How objects are sent between threads, see `copy_threaded` & `Pipeline::write_to_threaded`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChannelOptions {
+    /// How many objects are sent at once. Default: 1024
+    pub batch_size: usize,
+    /// How many batches can be waiting for the receiving thread, before the sending thread waits
+    /// for it. Default: 4
+    pub capacity: usize,
+}
+
+impl Default for ChannelOptions {
+    fn default() -> Self {
+        ChannelOptions {
+            batch_size: BATCH_SIZE,
+            capacity: 4,
+        }
+    }
+}
+
+/// An error when running a pipeline
+#[derive(Debug)]
+pub enum PipelineError {
+    Read(ReadError),
+    Write(OSMWriteError),
+}
+impl std::fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for PipelineError {}
+
+impl From<ReadError> for PipelineError {
+    fn from(err: ReadError) -> PipelineError {
+        PipelineError::Read(err)
+    }
+}
+impl From<OSMWriteError> for PipelineError {
+    fn from(err: OSMWriteError) -> PipelineError {
+        PipelineError::Write(err)
+    }
+}
+
+/// Objects from a reader, with the transformations to do to them, see the module docs. Nothing
+/// is read until it's run with `write_to` or `for_each`.
+pub struct Pipeline<'a> {
+    reader: Box<dyn DynReader + 'a>,
+    /// Every stage, chained together
+    transform: Option<Box<dyn ObjTransform<StringOSMObj> + 'a>>,
+    /// Objects from the last `TransformResult::Many`
+    pending: ::std::vec::IntoIter<StringOSMObj>,
+}
+
+impl<'a, R: DynReader + 'a> From<R> for Pipeline<'a> {
+    fn from(reader: R) -> Self {
+        Pipeline::boxed(Box::new(reader))
+    }
+}
+
+impl Pipeline<'static> {
+    /// Read this file, see `read_from_path`
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, OpenError> {
+        Ok(Pipeline::boxed(read_from_path(path)?))
+    }
+
+    /// Read this file, on another thread, see `threaded`
+    pub fn open_threaded(path: impl AsRef<Path>, batches: usize) -> Result<Self, OpenError> {
+        Ok(Pipeline::from(ThreadedReader::new(
+            read_from_path(path)?,
+            batches,
+        )))
+    }
+
+    /// Read with this reader on another thread, so reading & decoding happen at the same time as
+    /// the transformations & writing. Up to `batches` batches of objects are buffered between
+    /// the threads.
+    pub fn threaded<R: DynReader + Send + 'static>(reader: R, batches: usize) -> Self {
+        Pipeline::from(ThreadedReader::new(Box::new(reader), batches))
+    }
+}
+
+impl<'a> Pipeline<'a> {
+    /// Objects from this reader, e.g. from `read_from_path`
+    pub fn boxed(reader: Box<dyn DynReader + 'a>) -> Self {
+        Pipeline {
+            reader: reader,
+            transform: None,
+            pending: Vec::new().into_iter(),
+        }
+    }
+
+    /// Add this stage, after the others
+    pub fn transform(mut self, transform: impl ObjTransform<StringOSMObj> + 'a) -> Self {
+        self.transform = Some(match self.transform.take() {
+            None => Box::new(transform),
+            Some(previous) => Box::new(previous.then(transform)),
+        });
+        self
+    }
+
+    /// Only keep the objects for which this is true
+    pub fn filter(self, mut f: impl FnMut(&StringOSMObj) -> bool + 'a) -> Self {
+        self.transform(from_fn(move |obj| {
+            if f(&obj) {
+                TransformResult::Keep(obj)
+            } else {
+                TransformResult::Drop
+            }
+        }))
+    }
+
+    /// Change every object with this
+    pub fn map(self, mut f: impl FnMut(StringOSMObj) -> StringOSMObj + 'a) -> Self {
+        self.transform(from_fn(move |obj| TransformResult::Keep(f(obj))))
+    }
+
+    /// Run this on every object, on a pool of `threads` threads, keeping the objects in order.
+    /// The threads are started once, and batches of objects are sent to them, so this is worth
+    /// it for slow, CPU heavy work (e.g. geometry, or matching), rather than a simple `filter`
+    /// or `map`.
+    pub fn par_transform(
+        self,
+        threads: usize,
+        f: impl Fn(StringOSMObj) -> TransformResult<StringOSMObj> + Send + Sync + 'static,
+    ) -> Self {
+        Pipeline::boxed(Box::new(ParallelStage::new(self, threads.max(1), f)))
+    }
+
+    /// Change every object with this, on `threads` threads, see `par_transform`
+    pub fn par_map(
+        self,
+        threads: usize,
+        f: impl Fn(StringOSMObj) -> StringOSMObj + Send + Sync + 'static,
+    ) -> Self {
+        self.par_transform(threads, move |obj| TransformResult::Keep(f(obj)))
+    }
+
+    /// The file header of the reader
+    pub fn header(&mut self) -> Result<Option<Header>, ReadError> {
+        self.reader.file_header()
+    }
+
+    /// The next object, after every stage
+    fn try_next(&mut self) -> Result<Option<StringOSMObj>, ReadError> {
+        loop {
+            if let Some(obj) = self.pending.next() {
+                return Ok(Some(obj));
+            }
+            let obj = match self.reader.try_next_obj()? {
+                None => return Ok(None),
+                Some(obj) => obj,
+            };
+            let transform = match self.transform {
+                None => return Ok(Some(obj)),
+                Some(ref mut transform) => transform,
+            };
+            match transform.apply(obj) {
+                TransformResult::Keep(obj) => return Ok(Some(obj)),
+                TransformResult::Drop => {}
+                TransformResult::Many(objs) => self.pending = objs.into_iter(),
+            }
+        }
+    }
+
+    /// Call this for every object. Returns how many objects there were.
+    pub fn for_each(mut self, mut f: impl FnMut(StringOSMObj)) -> Result<u64, ReadError> {
+        let mut num = 0;
+        while let Some(obj) = self.try_next()? {
+            f(obj);
+            num += 1;
+        }
+        Ok(num)
+    }
+
+    /// Write every object to this writer, in batches, then finish it. Returns how many objects
+    /// were written.
+    pub fn write_to<W: DynWriter + ?Sized>(mut self, writer: &mut W) -> Result<u64, PipelineError> {
+        let mut written = 0;
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while let Some(obj) = self.try_next()? {
+            batch.push(obj);
+            if batch.len() == BATCH_SIZE {
+                writer.write_string_objs(&batch)?;
+                written += batch.len() as u64;
+                batch.clear();
+            }
+        }
+        writer.write_string_objs(&batch)?;
+        written += batch.len() as u64;
+        writer.finish()?;
+        Ok(written)
+    }
+
+    /// `write_to`, with the writer on another thread, so the reading & transformations happen
+    /// at the same time as the encoding. Batches of objects are sent to the writer as set by
+    /// `options`.
+    pub fn write_to_threaded<W: DynWriter + Send + ?Sized>(
+        mut self,
+        writer: &mut W,
+        options: &ChannelOptions,
+    ) -> Result<u64, PipelineError> {
+        let (sender, receiver) = sync_channel(options.capacity);
+        thread::scope(|scope| {
+            let writing = scope.spawn(move || write_batches(&receiver, writer));
+            send_batches(&sender, || self.try_next(), options.batch_size);
+            // Ends the writing thread, once it's written everything
+            drop(sender);
+            writing
+                .join()
+                .unwrap_or_else(|panic| ::std::panic::resume_unwind(panic))
+        })
+    }
+}
+
+/// A batch of objects for a `ParallelStage` worker, and its position in the stream
+type Job = (u64, Vec<StringOSMObj>);
+/// The objects from a job, or the panic of the function
+type JobResult = (u64, thread::Result<Vec<StringOSMObj>>);
+
+/// The objects of a pipeline, after running a function on them on a pool of threads, see
+/// `Pipeline::par_transform`
+struct ParallelStage<'a> {
+    upstream: Pipeline<'a>,
+    /// `None` once dropped, which stops the workers
+    jobs: Option<Sender<Job>>,
+    results: Receiver<JobResult>,
+    workers: Vec<thread::JoinHandle<()>>,
+    /// Position of the next batch to send to the workers
+    next_sent: u64,
+    /// Position of the next batch to return
+    next_returned: u64,
+    /// Results which came back before an earlier batch
+    finished: BTreeMap<u64, Vec<StringOSMObj>>,
+    /// The upstream pipeline has no more objects
+    done: bool,
+    /// From the upstream pipeline, returned after the objects before it
+    error: Option<ReadError>,
+    batch: ::std::vec::IntoIter<StringOSMObj>,
+}
+
+impl<'a> ParallelStage<'a> {
+    fn new<F>(upstream: Pipeline<'a>, threads: usize, f: F) -> Self
+    where
+        F: Fn(StringOSMObj) -> TransformResult<StringOSMObj> + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        let (jobs, job_receiver) = channel::<Job>();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let (result_sender, results) = channel();
+        let workers = (0..threads)
+            .map(|_| {
+                let f = f.clone();
+                let job_receiver = job_receiver.clone();
+                let result_sender = result_sender.clone();
+                thread::spawn(move || loop {
+                    let job = job_receiver.lock().unwrap().recv();
+                    let (seq, objs) = match job {
+                        Ok(job) => job,
+                        // The stage has been dropped
+                        Err(_) => return,
+                    };
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        let mut results = Vec::with_capacity(objs.len());
+                        for obj in objs {
+                            match f(obj) {
+                                TransformResult::Keep(obj) => results.push(obj),
+                                TransformResult::Drop => {}
+                                TransformResult::Many(objs) => results.extend(objs),
+                            }
+                        }
+                        results
+                    }));
+                    if result_sender.send((seq, result)).is_err() {
+                        return;
+                    }
+                })
+            })
+            .collect();
+        ParallelStage {
+            upstream: upstream,
+            jobs: Some(jobs),
+            results: results,
+            workers: workers,
+            next_sent: 0,
+            next_returned: 0,
+            finished: BTreeMap::new(),
+            done: false,
+            error: None,
+            batch: Vec::new().into_iter(),
+        }
+    }
+
+    /// Send batches from upstream until every worker has a couple waiting
+    fn send_jobs(&mut self) {
+        let max_in_flight = 2 * self.workers.len() as u64;
+        while !self.done && self.next_sent - self.next_returned < max_in_flight {
+            let mut objs = Vec::with_capacity(BATCH_SIZE);
+            while objs.len() < BATCH_SIZE {
+                match self.upstream.try_next() {
+                    Ok(Some(obj)) => objs.push(obj),
+                    Ok(None) => self.done = true,
+                    Err(e) => {
+                        self.error = Some(e);
+                        self.done = true;
+                    }
+                }
+                if self.done {
+                    break;
+                }
+            }
+            if !objs.is_empty() {
+                let jobs = self.jobs.as_ref().unwrap();
+                jobs.send((self.next_sent, objs))
+                    .expect("ParallelStage workers stopped");
+                self.next_sent += 1;
+            }
+        }
+    }
+}
+
+impl<'a> DynReader for ParallelStage<'a> {
+    fn next_obj(&mut self) -> Option<StringOSMObj> {
+        self.try_next_obj().unwrap()
+    }
+
+    fn try_next_obj(&mut self) -> Result<Option<StringOSMObj>, ReadError> {
+        loop {
+            if let Some(obj) = self.batch.next() {
+                return Ok(Some(obj));
+            }
+            self.send_jobs();
+            if self.next_returned == self.next_sent {
+                return match self.error.take() {
+                    Some(e) => Err(e),
+                    None => Ok(None),
+                };
+            }
+            let objs = loop {
+                if let Some(objs) = self.finished.remove(&self.next_returned) {
+                    break objs;
+                }
+                let (seq, result) = self.results.recv().expect("ParallelStage workers stopped");
+                match result {
+                    Ok(objs) => self.finished.insert(seq, objs),
+                    Err(panic) => panic::resume_unwind(panic),
+                };
+            };
+            self.next_returned += 1;
+            self.batch = objs.into_iter();
+        }
+    }
+
+    fn file_header(&mut self) -> Result<Option<Header>, ReadError> {
+        self.upstream.header()
+    }
+}
+
+impl<'a> Drop for ParallelStage<'a> {
+    fn drop(&mut self) {
+        // Stops the workers, after their current batch
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Read every object from `reader` on another thread, and write them to `writer` on this
+/// thread, then finish it, so decoding & encoding each get their own core. Returns how many
+/// objects were written.
+///
+/// ```
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::pipeline::{copy_threaded, ChannelOptions};
+/// use osmio::{OSMReader, OSMWriter};
+///
+/// let mut reader = OPLReader::new("n1 x1 y2\nn2 x1 y2\n".as_bytes());
+/// let mut writer = OPLWriter::new(Vec::new());
+/// let written = copy_threaded(&mut reader, &mut writer, &ChannelOptions::default()).unwrap();
+/// assert_eq!(written, 2);
+/// ```
+pub fn copy_threaded<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    options: &ChannelOptions,
+) -> Result<u64, PipelineError>
+where
+    R: DynReader + Send + ?Sized,
+    W: DynWriter + ?Sized,
+{
+    let (sender, receiver) = sync_channel(options.capacity);
+    let batch_size = options.batch_size;
+    thread::scope(|scope| {
+        let reading =
+            scope.spawn(move || send_batches(&sender, || reader.try_next_obj(), batch_size));
+        let result = write_batches(&receiver, writer);
+        // Stops the reading thread, at its next send
+        drop(receiver);
+        if let Err(panic) = reading.join() {
+            ::std::panic::resume_unwind(panic);
+        }
+        result
+    })
+}
+
+/// Send batches of the objects from `next` until the end, an error, or the receiver is gone
+fn send_batches(
+    sender: &SyncSender<Result<Vec<StringOSMObj>, ReadError>>,
+    mut next: impl FnMut() -> Result<Option<StringOSMObj>, ReadError>,
+    batch_size: usize,
+) {
+    let batch_size = batch_size.max(1);
+    loop {
+        let mut batch = Vec::with_capacity(batch_size);
+        let result = loop {
+            match next() {
+                Ok(Some(obj)) => {
+                    batch.push(obj);
+                    if batch.len() == batch_size {
+                        break Ok(false);
+                    }
+                }
+                Ok(None) => break Ok(true),
+                Err(e) => break Err(e),
+            }
+        };
+        let (message, done) = match result {
+            Ok(done) => (Ok(batch), done),
+            Err(e) => (Err(e), true),
+        };
+        if sender.send(message).is_err() || done {
+            return;
+        }
+    }
+}
+
+/// Write the batches from `receiver`, then finish the writer. A read error is passed on, without
+/// finishing.
+fn write_batches<W: DynWriter + ?Sized>(
+    receiver: &Receiver<Result<Vec<StringOSMObj>, ReadError>>,
+    writer: &mut W,
+) -> Result<u64, PipelineError> {
+    let mut written = 0;
+    for batch in receiver.iter() {
+        let batch = batch?;
+        writer.write_string_objs(&batch)?;
+        written += batch.len() as u64;
+    }
+    writer.finish()?;
+    Ok(written)
+}
+
+/// A reader running on another thread, sending batches of objects back
+struct ThreadedReader {
+    header: Result<Option<Header>, ReadError>,
+    receiver: Option<Receiver<Result<Vec<StringOSMObj>, ReadError>>>,
+    thread: Option<thread::JoinHandle<()>>,
+    batch: ::std::vec::IntoIter<StringOSMObj>,
+}
+
+impl ThreadedReader {
+    fn new(mut reader: Box<dyn DynReader + Send>, batches: usize) -> Self {
+        let header = reader.file_header();
+        let (sender, receiver) = sync_channel(batches);
+        let thread =
+            thread::spawn(move || send_batches(&sender, || reader.try_next_obj(), BATCH_SIZE));
+        ThreadedReader {
+            header: header,
+            receiver: Some(receiver),
+            thread: Some(thread),
+            batch: Vec::new().into_iter(),
+        }
+    }
+
+    /// Wait for the reading thread to end, passing on any panic
+    fn join(&mut self) {
+        self.receiver = None;
+        if let Some(thread) = self.thread.take() {
+            if let Err(panic) = thread.join() {
+                ::std::panic::resume_unwind(panic);
+            }
+        }
+    }
+}
+
+impl DynReader for ThreadedReader {
+    fn next_obj(&mut self) -> Option<StringOSMObj> {
+        self.try_next_obj().unwrap()
+    }
+
+    fn try_next_obj(&mut self) -> Result<Option<StringOSMObj>, ReadError> {
+        loop {
+            if let Some(obj) = self.batch.next() {
+                return Ok(Some(obj));
+            }
+            let received = match self.receiver {
+                Some(ref receiver) => receiver.recv(),
+                None => return Ok(None),
+            };
+            match received {
+                Ok(batch) => self.batch = batch?.into_iter(),
+                Err(_) => {
+                    self.join();
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn file_header(&mut self) -> Result<Option<Header>, ReadError> {
+        match self.header {
+            Ok(ref header) => Ok(header.clone()),
+            Err(_) => Err(::std::mem::replace(&mut self.header, Ok(None)).unwrap_err()),
+        }
+    }
+}
+
+impl Drop for ThreadedReader {
+    fn drop(&mut self) {
+        // Stops the thread, at its next send
+        self.receiver = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::{OPLReader, OPLWriter};
+    use {OSMObjBase, OSMReader, OSMWriter};
+
+    #[test]
+    fn threaded() {
+        let input: String = (1..=3000).map(|i| format!("n{} x1 y2\n", i)).collect();
+        let mut ids = Vec::new();
+        let num = Pipeline::threaded(OPLReader::new(::std::io::Cursor::new(input)), 2)
+            .filter(|o| o.id() % 2 == 0)
+            .for_each(|o| ids.push(o.id()))
+            .unwrap();
+        assert_eq!(num, 1500);
+        assert_eq!(ids, (1..=1500).map(|i| i * 2).collect::<Vec<_>>());
+
+        // dropped before the end
+        let input: String = (1..=10_000).map(|i| format!("n{} x1 y2\n", i)).collect();
+        let pipeline = Pipeline::threaded(OPLReader::new(::std::io::Cursor::new(input)), 1);
+        drop(pipeline);
+    }
+
+    #[test]
+    fn errors() {
+        let mut writer = OPLWriter::new(Vec::new());
+        let result =
+            Pipeline::from(OPLReader::new("n1 x1 y2\nq2\n".as_bytes())).write_to(&mut writer);
+        assert!(matches!(result, Err(PipelineError::Read(_))));
+
+        let result =
+            Pipeline::threaded(OPLReader::new(::std::io::Cursor::new("n1 x1 y2\nq2\n")), 1)
+                .for_each(|_| {});
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn transforms() {
+        let input = "n1 x1 y2\nn2 x1 y2\nn3 x1 y2\n";
+        let mut ids = Vec::new();
+        Pipeline::from(OPLReader::new(input.as_bytes()))
+            .filter(|o| o.id() != 2)
+            .transform(from_fn(|o: StringOSMObj| {
+                TransformResult::Many(vec![o.clone(), o])
+            }))
+            .transform(::transform::Renumber::starting_at(10, 1, 1))
+            .for_each(|o| ids.push(o.id()))
+            .unwrap();
+        assert_eq!(ids, vec![10, 10, 11, 11]);
+    }
+
+    #[test]
+    fn parallel() {
+        let input: String = (1..=5000).map(|i| format!("n{} x1 y2\n", i)).collect();
+        let mut ids = Vec::new();
+        let num = Pipeline::from(OPLReader::new(input.as_bytes()))
+            .filter(|o| o.id() != 3)
+            .par_transform(4, |o| match o.id() % 3 {
+                0 => TransformResult::Drop,
+                1 => TransformResult::Keep(o),
+                _ => TransformResult::Many(vec![o.clone(), o]),
+            })
+            .par_map(3, |mut o| {
+                let id = o.id();
+                o.set_tag("id", id.to_string());
+                o
+            })
+            .for_each(|o| {
+                assert_eq!(o.tag("id"), Some(o.id().to_string().as_str()));
+                ids.push(o.id())
+            })
+            .unwrap();
+        let expected: Vec<_> = (1..=5000)
+            .filter(|i| i % 3 != 0)
+            .flat_map(|i| if i % 3 == 1 { vec![i] } else { vec![i, i] })
+            .collect();
+        assert_eq!(ids, expected);
+        assert_eq!(num, expected.len() as u64);
+
+        // The same threads are used for every batch
+        let thread_ids = Arc::new(Mutex::new(::std::collections::HashSet::new()));
+        let seen = thread_ids.clone();
+        Pipeline::from(OPLReader::new(input.as_bytes()))
+            .par_map(2, move |o| {
+                seen.lock().unwrap().insert(thread::current().id());
+                o
+            })
+            .for_each(|_| {})
+            .unwrap();
+        let num_threads = thread_ids.lock().unwrap().len();
+        assert!(num_threads == 1 || num_threads == 2);
+
+        let result = Pipeline::from(OPLReader::new("n1 x1 y2\nq2\n".as_bytes()))
+            .par_map(2, |o| o)
+            .for_each(|_| {});
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn channels() {
+        let input: String = (1..=100).map(|i| format!("n{} dV T x1 y2\n", i)).collect();
+        for &(batch_size, capacity) in &[(1, 0), (7, 2), (1024, 4)] {
+            let options = ChannelOptions {
+                batch_size: batch_size,
+                capacity: capacity,
+            };
+            let mut writer = OPLWriter::new(Vec::new());
+            let mut reader = OPLReader::new(input.as_bytes());
+            assert_eq!(
+                copy_threaded(&mut reader, &mut writer, &options).unwrap(),
+                100
+            );
+            assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), input);
+
+            let mut writer = OPLWriter::new(Vec::new());
+            let written = Pipeline::from(OPLReader::new(input.as_bytes()))
+                .filter(|o| o.id() <= 10)
+                .write_to_threaded(&mut writer, &options)
+                .unwrap();
+            assert_eq!(written, 10);
+            assert_eq!(
+                writer.into_inner().len(),
+                input.lines().take(10).map(|l| l.len() + 1).sum::<usize>()
+            );
+        }
+
+        // errors from either side
+        let options = ChannelOptions::default();
+        let mut writer = OPLWriter::new(Vec::new());
+        let mut reader = OPLReader::new("n1 x1 y2\nq2\n".as_bytes());
+        let result = copy_threaded(&mut reader, &mut writer, &options);
+        assert!(matches!(result, Err(PipelineError::Read(_))));
+        assert!(writer.is_open());
+
+        let mut writer = OPLWriter::new(Vec::new());
+        writer.close().unwrap();
+        let result = Pipeline::from(OPLReader::new(input.as_bytes()))
+            .write_to_threaded(&mut writer, &options);
+        assert!(matches!(result, Err(PipelineError::Write(_))));
+    }
+}
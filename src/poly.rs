@@ -0,0 +1,378 @@
+//! Osmosis `.poly` polygon file format
+//! See https://wiki.openstreetmap.org/wiki/Osmosis/Polygon_Filter_File_Format
+//!
+//! A file has a name, then one or more rings. Each ring is a name line, a list of `lon lat`
+//! coordinate lines, and `END`. Rings whose name starts with `!` are holes. The file ends with a
+//! final `END`.
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// One ring of a polygon file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ring {
+    pub name: String,
+    /// True iff this ring is subtracted from the area (name starts with `!` in the file)
+    pub hole: bool,
+    /// The points as `(lat, lon)` (NB: the file stores them as `lon lat`)
+    pub points: Vec<(f64, f64)>,
+}
+
+/// A (multi)polygon read from a `.poly` file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polygon {
+    pub name: String,
+    pub rings: Vec<Ring>,
+}
+
+/// An error when trying to read a `.poly` file
+#[derive(Debug)]
+pub enum PolyError {
+    IOError(::std::io::Error),
+    /// The file ended before the final `END`
+    UnexpectedEOF,
+    /// A line (1-based line number given) could not be parsed as a coordinate pair
+    InvalidCoordinate(usize, String),
+    /// The GeoJSON isn't valid, or has no (Multi)Polygon geometry. Requires the `geojson`
+    /// feature
+    InvalidGeoJSON(String),
+}
+impl std::fmt::Display for PolyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for PolyError {}
+
+impl From<std::io::Error> for PolyError {
+    fn from(err: std::io::Error) -> PolyError {
+        PolyError::IOError(err)
+    }
+}
+
+impl Ring {
+    /// True iff this point is inside this ring (even-odd rule). The ring doesn't need to be
+    /// explicitly closed.
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        let mut inside = false;
+        let points = &self.points;
+        if points.len() < 3 {
+            return false;
+        }
+        let mut j = points.len() - 1;
+        for i in 0..points.len() {
+            let (lat_i, lon_i) = points[i];
+            let (lat_j, lon_j) = points[j];
+            if (lat_i > lat) != (lat_j > lat)
+                && lon < (lon_j - lon_i) * (lat - lat_i) / (lat_j - lat_i) + lon_i
+            {
+                inside = !inside;
+            }
+            j = i;
+        }
+        inside
+    }
+}
+
+impl Polygon {
+    /// True iff this point is inside an odd number of rings (even-odd rule), so a point in a hole
+    /// is outside, but a point in an island inside that hole is inside.
+    pub fn contains(&self, lat: f64, lon: f64) -> bool {
+        self.rings
+            .iter()
+            .filter(|ring| ring.contains(lat, lon))
+            .count()
+            % 2
+            == 1
+    }
+
+    /// The bounding box of all outer rings, as `(min_lat, min_lon, max_lat, max_lon)`. `None` if
+    /// there are no points.
+    pub fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        self.rings
+            .iter()
+            .filter(|r| !r.hole)
+            .flat_map(|r| r.points.iter())
+            .fold(None, |acc, &(lat, lon)| match acc {
+                None => Some((lat, lon, lat, lon)),
+                Some((min_lat, min_lon, max_lat, max_lon)) => Some((
+                    min_lat.min(lat),
+                    min_lon.min(lon),
+                    max_lat.max(lat),
+                    max_lon.max(lon),
+                )),
+            })
+    }
+}
+
+impl std::str::FromStr for Polygon {
+    type Err = PolyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        read_poly(s.as_bytes())
+    }
+}
+
+/// Read a polygon from a `.poly` file
+pub fn read_poly(reader: impl Read) -> Result<Polygon, PolyError> {
+    let mut lines = BufReader::new(reader)
+        .lines()
+        .enumerate()
+        .map(|(i, l)| l.map(|l| (i + 1, l.trim().to_string())))
+        .filter(|l| match l {
+            Ok((_, l)) => !l.is_empty(),
+            Err(_) => true,
+        });
+
+    let name = match lines.next() {
+        Some(l) => l?.1,
+        None => return Err(PolyError::UnexpectedEOF),
+    };
+
+    let mut rings = Vec::new();
+    loop {
+        let ring_name = match lines.next() {
+            Some(l) => l?.1,
+            None => return Err(PolyError::UnexpectedEOF),
+        };
+        if ring_name == "END" {
+            break;
+        }
+        let (hole, ring_name) = match ring_name.strip_prefix('!') {
+            Some(name) => (true, name.to_string()),
+            None => (false, ring_name),
+        };
+
+        let mut points = Vec::new();
+        loop {
+            let (line_no, line) = match lines.next() {
+                Some(l) => l?,
+                None => return Err(PolyError::UnexpectedEOF),
+            };
+            if line == "END" {
+                break;
+            }
+            let mut parts = line.split_whitespace().map(|p| p.parse::<f64>());
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(Ok(lon)), Some(Ok(lat)), None) => points.push((lat, lon)),
+                _ => return Err(PolyError::InvalidCoordinate(line_no, line)),
+            }
+        }
+
+        rings.push(Ring {
+            name: ring_name,
+            hole: hole,
+            points: points,
+        });
+    }
+
+    Ok(Polygon {
+        name: name,
+        rings: rings,
+    })
+}
+
+/// The rings of a GeoJSON Polygon's coordinates. The first is the outer ring, the others holes.
+#[cfg(feature = "geojson")]
+fn geojson_rings(coordinates: &serde_json::Value, rings: &mut Vec<Ring>) -> Result<(), PolyError> {
+    let invalid = || PolyError::InvalidGeoJSON("invalid Polygon coordinates".to_string());
+    for (i, ring) in coordinates
+        .as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .enumerate()
+    {
+        let points = ring
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|p| match p.as_array().map(|p| &p[..]) {
+                Some([lon, lat, ..]) => Ok((
+                    lat.as_f64().ok_or_else(invalid)?,
+                    lon.as_f64().ok_or_else(invalid)?,
+                )),
+                _ => Err(invalid()),
+            })
+            .collect::<Result<_, _>>()?;
+        rings.push(Ring {
+            name: (rings.len() + 1).to_string(),
+            hole: i > 0,
+            points: points,
+        });
+    }
+    Ok(())
+}
+
+/// Add the rings of a GeoJSON object (a Polygon or MultiPolygon geometry, a Feature, or a
+/// FeatureCollection)
+#[cfg(feature = "geojson")]
+fn geojson_object(object: &serde_json::Value, rings: &mut Vec<Ring>) -> Result<(), PolyError> {
+    let invalid = |msg: &str| PolyError::InvalidGeoJSON(msg.to_string());
+    match object["type"].as_str() {
+        Some("Polygon") => geojson_rings(&object["coordinates"], rings),
+        Some("MultiPolygon") => {
+            let polygons = object["coordinates"]
+                .as_array()
+                .ok_or_else(|| invalid("invalid MultiPolygon coordinates"))?;
+            for polygon in polygons {
+                geojson_rings(polygon, rings)?;
+            }
+            Ok(())
+        }
+        Some("GeometryCollection") => {
+            let geometries = object["geometries"]
+                .as_array()
+                .ok_or_else(|| invalid("invalid GeometryCollection"))?;
+            for geometry in geometries {
+                geojson_object(geometry, rings)?;
+            }
+            Ok(())
+        }
+        Some("Feature") => geojson_object(&object["geometry"], rings),
+        Some("FeatureCollection") => {
+            let features = object["features"]
+                .as_array()
+                .ok_or_else(|| invalid("invalid FeatureCollection"))?;
+            for feature in features {
+                geojson_object(feature, rings)?;
+            }
+            Ok(())
+        }
+        Some(other) => Err(invalid(&format!("unsupported type {}", other))),
+        None => Err(invalid("missing type")),
+    }
+}
+
+/// Read a polygon from GeoJSON: a Polygon or MultiPolygon geometry, or a Feature or
+/// FeatureCollection of them. The inner rings of the polygons are holes. Requires the `geojson`
+/// feature.
+#[cfg(feature = "geojson")]
+pub fn read_geojson(reader: impl Read) -> Result<Polygon, PolyError> {
+    let object: serde_json::Value =
+        serde_json::from_reader(reader).map_err(|e| PolyError::InvalidGeoJSON(e.to_string()))?;
+    let mut rings = Vec::new();
+    geojson_object(&object, &mut rings)?;
+    if rings.is_empty() {
+        return Err(PolyError::InvalidGeoJSON("no polygons".to_string()));
+    }
+    Ok(Polygon {
+        name: object["properties"]["name"]
+            .as_str()
+            .unwrap_or("")
+            .to_string(),
+        rings: rings,
+    })
+}
+
+/// Read a polygon from a file, as GeoJSON if the filename ends in `.geojson` or `.json`,
+/// otherwise as a `.poly` file
+pub fn read_polygon_file(path: impl AsRef<std::path::Path>) -> Result<Polygon, PolyError> {
+    let path = path.as_ref();
+    let file = std::fs::File::open(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        #[cfg(feature = "geojson")]
+        Some("geojson") | Some("json") => read_geojson(BufReader::new(file)),
+        #[cfg(not(feature = "geojson"))]
+        Some("geojson") | Some("json") => Err(PolyError::InvalidGeoJSON(
+            "the geojson feature isn't enabled".to_string(),
+        )),
+        _ => read_poly(file),
+    }
+}
+
+/// Write a polygon in the `.poly` file format
+pub fn write_poly(writer: &mut impl Write, polygon: &Polygon) -> std::io::Result<()> {
+    writeln!(writer, "{}", polygon.name)?;
+    for ring in polygon.rings.iter() {
+        writeln!(writer, "{}{}", if ring.hole { "!" } else { "" }, ring.name)?;
+        for (lat, lon) in ring.points.iter() {
+            writeln!(writer, "\t{}\t{}", lon, lat)?;
+        }
+        writeln!(writer, "END")?;
+    }
+    writeln!(writer, "END")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE_WITH_HOLE: &str = "square\n1\n\t0.0E+00\t0.0E+00\n\t10.0\t0.0\n\t10.0\t10.0\n\t0.0\t10.0\nEND\n!2\n\t4.0\t4.0\n\t6.0\t4.0\n\t6.0\t6.0\n\t4.0\t6.0\nEND\nEND\n";
+    const SQUARE_WITH_ISLAND_IN_HOLE: &str = "square\n1\n\t0.0\t0.0\n\t10.0\t0.0\n\t10.0\t10.0\n\t0.0\t10.0\nEND\n!2\n\t4.0\t4.0\n\t6.0\t4.0\n\t6.0\t6.0\n\t4.0\t6.0\nEND\n3\n\t4.5\t4.5\n\t5.5\t4.5\n\t5.5\t5.5\n\t4.5\t5.5\nEND\nEND\n";
+
+    #[test]
+    fn read() {
+        let poly: Polygon = SQUARE_WITH_HOLE.parse().unwrap();
+        assert_eq!(poly.name, "square");
+        assert_eq!(poly.rings.len(), 2);
+        assert!(!poly.rings[0].hole);
+        assert_eq!(poly.rings[0].name, "1");
+        assert_eq!(poly.rings[0].points[1], (0., 10.));
+        assert!(poly.rings[1].hole);
+        assert_eq!(poly.rings[1].name, "2");
+        assert_eq!(poly.bounds(), Some((0., 0., 10., 10.)));
+    }
+
+    #[test]
+    fn contains() {
+        let poly: Polygon = SQUARE_WITH_HOLE.parse().unwrap();
+        assert!(poly.contains(1., 1.));
+        assert!(poly.contains(9., 1.));
+        assert!(!poly.contains(5., 5.));
+        assert!(!poly.contains(11., 1.));
+        assert!(!poly.contains(-1., -1.));
+
+        // an island inside the hole
+        let poly: Polygon = SQUARE_WITH_ISLAND_IN_HOLE.parse().unwrap();
+        assert_eq!(poly.rings.len(), 3);
+        assert!(!poly.rings[2].hole);
+        assert!(poly.contains(1., 1.));
+        assert!(!poly.contains(4.2, 4.2));
+        assert!(poly.contains(5., 5.));
+        assert!(!poly.contains(11., 1.));
+    }
+
+    #[test]
+    fn read_errors() {
+        assert!(matches!(
+            "name\n1\n\t0 0\n".parse::<Polygon>(),
+            Err(PolyError::UnexpectedEOF)
+        ));
+        assert!(matches!(
+            "name\n1\n\t0 zero\nEND\nEND\n".parse::<Polygon>(),
+            Err(PolyError::InvalidCoordinate(3, _))
+        ));
+    }
+
+    #[cfg(feature = "geojson")]
+    #[test]
+    fn geojson() {
+        let json = r#"{"type": "Feature", "properties": {"name": "square"},
+            "geometry": {"type": "MultiPolygon", "coordinates": [
+                [[[0, 0], [10, 0], [10, 10], [0, 10], [0, 0]], [[4, 4], [6, 4], [6, 6], [4, 4]]],
+                [[[20, 20], [21, 20], [21, 21], [20, 20]]]
+            ]}}"#;
+        let poly = read_geojson(json.as_bytes()).unwrap();
+        assert_eq!(poly.name, "square");
+        assert_eq!(poly.rings.len(), 3);
+        assert!(poly.rings[1].hole);
+        assert!(!poly.rings[2].hole);
+        assert_eq!(poly.rings[0].points[1], (0., 10.));
+        assert!(poly.contains(1., 1.));
+        assert!(poly.contains(20.2, 20.5));
+        assert!(!poly.contains(15., 15.));
+
+        assert!(matches!(
+            read_geojson(r#"{"type": "Point", "coordinates": [1, 2]}"#.as_bytes()),
+            Err(PolyError::InvalidGeoJSON(_))
+        ));
+    }
+
+    #[test]
+    fn write_then_read() {
+        let poly: Polygon = SQUARE_WITH_HOLE.parse().unwrap();
+        let mut output = Vec::new();
+        write_poly(&mut output, &poly).unwrap();
+        let poly2 = read_poly(output.as_slice()).unwrap();
+        assert_eq!(poly, poly2);
+    }
+}
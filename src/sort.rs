@@ -0,0 +1,839 @@
+//! Sorting more objects than fit in memory. `ExternalSorter` sorts batches in memory, writes
+//! each one to a temporary file (a "run"), and then merges the runs.
+//!
+//! ```
+//! use osmio::opl::OPLReader;
+//! use osmio::sort::ExternalSorter;
+//! use osmio::{OSMObjBase, OSMReader};
+//!
+//! let input = "w3 Nn1,n2\nn2 x1 y1\nn1 x2 y2\nr4 M\n";
+//! let mut reader = OPLReader::new(input.as_bytes());
+//! // At most 2 objects in memory
+//! let sorted = ExternalSorter::new(2).sort(reader.objects()).unwrap();
+//! let ids: Vec<_> = sorted.map(|o| o.id()).collect();
+//! assert_eq!(ids, vec![1, 2, 3, 4]);
+//! ```
+//!
+//! To sort a file, use `sort` (which is also `osmio::sort`).
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+
+use obj_types::{ArcOSMObj, RcOSMObj, StringOSMObj};
+use opl;
+use {
+    DynReader, DynWriter, Header, OSMObj, OSMObjBase, OSMObjectType, OSMReader, OSMWriteError,
+    ObjId, ReadError,
+};
+
+/// Something which can be written to, and read back from, a run file
+pub trait Spill: Sized {
+    /// Write this to the run
+    fn spill(&self, out: &mut impl Write) -> io::Result<()>;
+    /// Read the next one from the run, `None` at the end
+    fn unspill(input: &mut impl BufRead) -> io::Result<Option<Self>>;
+}
+
+/// Objects are spilled as lines of OPL
+impl Spill for StringOSMObj {
+    fn spill(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut line = self.to_opl_line();
+        line.push('\n');
+        out.write_all(line.as_bytes())
+    }
+
+    fn unspill(input: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        opl::decode_line(line.trim_end_matches('\n'))
+            .map(Some)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid line in run"))
+    }
+}
+
+macro_rules! impl_spill_via_string {
+    ($ty:ty) => {
+        impl Spill for $ty {
+            fn spill(&self, out: &mut impl Write) -> io::Result<()> {
+                let mut line = self.to_opl_line();
+                line.push('\n');
+                out.write_all(line.as_bytes())
+            }
+
+            fn unspill(input: &mut impl BufRead) -> io::Result<Option<Self>> {
+                Ok(StringOSMObj::unspill(input)?.map(Into::into))
+            }
+        }
+    };
+}
+
+impl_spill_via_string!(ArcOSMObj);
+impl_spill_via_string!(RcOSMObj);
+#[cfg(feature = "compact")]
+impl_spill_via_string!(::obj_types::CompactOSMObj);
+
+/// For the run file names, so sorters in one process don't share files
+static SORTER_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorts items (by `Ord`, stably) with at most `max_in_memory` of them in memory at a time.
+/// The run files are removed when the sorter, or the merged iterator, is dropped.
+pub struct ExternalSorter<T> {
+    max_in_memory: usize,
+    dir: PathBuf,
+    prefix: String,
+    buffer: Vec<T>,
+    runs: Vec<PathBuf>,
+}
+
+impl<T: Ord + Spill> ExternalSorter<T> {
+    /// A sorter which keeps at most this many items in memory, with runs in the system's
+    /// temporary directory
+    pub fn new(max_in_memory: usize) -> Self {
+        ExternalSorter {
+            max_in_memory: max_in_memory.max(1),
+            dir: std::env::temp_dir(),
+            prefix: format!(
+                "osmio-sort-{}-{}",
+                std::process::id(),
+                SORTER_COUNT.fetch_add(1, AtomicOrdering::Relaxed)
+            ),
+            buffer: Vec::new(),
+            runs: Vec::new(),
+        }
+    }
+
+    /// Write the runs in this directory
+    pub fn temp_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Number of runs written so far
+    pub fn num_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Add an item, writing a run if the memory is full
+    pub fn push(&mut self, item: T) -> io::Result<()> {
+        self.buffer.push(item);
+        if self.buffer.len() >= self.max_in_memory {
+            self.write_run()?;
+        }
+        Ok(())
+    }
+
+    /// Add all these items, then merge everything
+    pub fn sort(mut self, items: impl IntoIterator<Item = T>) -> io::Result<Sorted<T>> {
+        for item in items {
+            self.push(item)?;
+        }
+        self.finish()
+    }
+
+    /// Merge everything added, in order. Items still in memory aren't written to a run.
+    pub fn finish(mut self) -> io::Result<Sorted<T>> {
+        self.buffer.sort();
+        let runs = std::mem::take(&mut self.runs);
+        let mut readers = Vec::with_capacity(runs.len());
+        for path in runs.iter() {
+            readers.push(BufReader::new(fs::File::open(path)?));
+        }
+        let mut sorted = Sorted {
+            heap: BinaryHeap::with_capacity(readers.len() + 1),
+            readers: readers,
+            memory: std::mem::take(&mut self.buffer).into_iter(),
+            runs: runs,
+        };
+        for source in 0..=sorted.readers.len() {
+            sorted.refill(source)?;
+        }
+        Ok(sorted)
+    }
+
+    fn write_run(&mut self) -> io::Result<()> {
+        self.buffer.sort();
+        let path = self
+            .dir
+            .join(format!("{}-{}", self.prefix, self.runs.len()));
+        // Remembered first, so it's removed even if writing fails
+        self.runs.push(path.clone());
+        let mut fp = BufWriter::new(fs::File::create(&path)?);
+        for item in self.buffer.drain(..) {
+            item.spill(&mut fp)?;
+        }
+        fp.flush()
+    }
+}
+
+impl<T> Drop for ExternalSorter<T> {
+    fn drop(&mut self) {
+        for path in self.runs.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// The next item of a run (or of the items in memory, the last source)
+struct Head<T> {
+    item: T,
+    source: usize,
+}
+
+// BinaryHeap is a max heap, so this is reversed. Equal items come out in source order, which
+// is the order they were added in.
+impl<T: Ord> Ord for Head<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .item
+            .cmp(&self.item)
+            .then(other.source.cmp(&self.source))
+    }
+}
+
+impl<T: Ord> PartialOrd for Head<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> PartialEq for Head<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Ord> Eq for Head<T> {}
+
+/// Iterator over the sorted items, merged from the runs. `next` panics if a run can't be read,
+/// use `try_next` to handle those errors.
+pub struct Sorted<T> {
+    heap: BinaryHeap<Head<T>>,
+    readers: Vec<BufReader<fs::File>>,
+    memory: std::vec::IntoIter<T>,
+    runs: Vec<PathBuf>,
+}
+
+impl<T: Ord + Spill> Sorted<T> {
+    /// The next item, `Ok(None)` at the end, or an error reading a run
+    pub fn try_next(&mut self) -> io::Result<Option<T>> {
+        let head = match self.heap.pop() {
+            None => return Ok(None),
+            Some(head) => head,
+        };
+        self.refill(head.source)?;
+        Ok(Some(head.item))
+    }
+
+    /// Put the next item of this source in the heap
+    fn refill(&mut self, source: usize) -> io::Result<()> {
+        let next = if source == self.readers.len() {
+            self.memory.next()
+        } else {
+            T::unspill(&mut self.readers[source])?
+        };
+        if let Some(item) = next {
+            self.heap.push(Head {
+                item: item,
+                source: source,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<T: Ord + Spill> Iterator for Sorted<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.try_next().unwrap()
+    }
+}
+
+impl<T> Drop for Sorted<T> {
+    fn drop(&mut self) {
+        for path in self.runs.iter() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// An error when sorting
+#[derive(Debug)]
+pub enum SortError {
+    Read(ReadError),
+    /// Writing or reading the runs failed
+    IOError(io::Error),
+    Write(OSMWriteError),
+}
+impl std::fmt::Display for SortError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for SortError {}
+
+impl From<ReadError> for SortError {
+    fn from(err: ReadError) -> SortError {
+        SortError::Read(err)
+    }
+}
+impl From<io::Error> for SortError {
+    fn from(err: io::Error) -> SortError {
+        SortError::IOError(err)
+    }
+}
+impl From<OSMWriteError> for SortError {
+    fn from(err: OSMWriteError) -> SortError {
+        SortError::Write(err)
+    }
+}
+
+/// Options for `sort_with_options`
+#[derive(Debug, Clone)]
+pub struct SortOptions {
+    /// Sort in memory if there are at most this many objects, otherwise keep at most this many
+    /// in memory, and write the rest to runs. Default: 1,000,000
+    pub max_in_memory: usize,
+    /// Where to write the runs. Default: the system's temporary directory
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl Default for SortOptions {
+    fn default() -> Self {
+        SortOptions {
+            max_in_memory: 1_000_000,
+            temp_dir: None,
+        }
+    }
+}
+
+/// Read all the objects from `reader` and write them to `writer`, sorted by type, then id,
+/// then version, as most of osmio expects. Small inputs are sorted in memory, large ones with
+/// an `ExternalSorter`. Returns the number of objects written. `writer` isn't closed.
+///
+/// ```
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::{OSMReader, OSMWriter};
+///
+/// let mut reader = OPLReader::new("w1 Nn2\nn2 x1 y2\nn1 v2 x1 y2\nn1 v1 x1 y2\n".as_bytes());
+/// let mut writer = OPLWriter::new(Vec::new());
+/// osmio::sort(&mut reader, &mut writer).unwrap();
+/// writer.close().unwrap();
+/// assert_eq!(
+///     String::from_utf8(writer.into_inner()).unwrap(),
+///     "n1 v1 dV T x1 y2\nn1 v2 dV T x1 y2\nn2 dV T x1 y2\nw1 dV T Nn2\n"
+/// );
+/// ```
+pub fn sort<R, W>(reader: &mut R, writer: &mut W) -> Result<u64, SortError>
+where
+    R: DynReader + ?Sized,
+    W: DynWriter + ?Sized,
+{
+    sort_with_options(reader, writer, &SortOptions::default())
+}
+
+/// Like `sort`, but with options
+pub fn sort_with_options<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    options: &SortOptions,
+) -> Result<u64, SortError>
+where
+    R: DynReader + ?Sized,
+    W: DynWriter + ?Sized,
+{
+    let mut sorter = ExternalSorter::new(options.max_in_memory);
+    if let Some(dir) = &options.temp_dir {
+        sorter = sorter.temp_dir(dir);
+    }
+    while let Some(obj) = reader.try_next_obj()? {
+        sorter.push(obj)?;
+    }
+    let mut sorted: Sorted<StringOSMObj> = sorter.finish()?;
+    let mut written = 0;
+    while let Some(obj) = sorted.try_next()? {
+        writer.write_string_obj(&obj)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// What a `SortCheckedReader` does when objects are out of order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnUnsorted {
+    /// Return `ReadError::NotSorted` from `try_next` (so `next` panics)
+    Error,
+    /// Keep going, and remember the first objects out of order, see `first_unsorted`
+    Record,
+}
+
+/// The sort key of an object
+type SortKey = (OSMObjectType, ObjId, Option<u32>);
+
+/// A reader which checks the objects of another reader are sorted by type, then id, then
+/// version (with no duplicates), as they're read. For pipelines which need sorted input, to
+/// fail fast rather than give wrong results.
+///
+/// ```
+/// use osmio::opl::OPLReader;
+/// use osmio::sort::{OnUnsorted, SortCheckedReader};
+/// use osmio::{OSMReader, ReadError};
+///
+/// let input = "n1 x1 y2\nn3 x1 y2\nn2 x1 y2\n";
+/// let mut reader = SortCheckedReader::new(OPLReader::new(input.as_bytes()), OnUnsorted::Error);
+/// assert!(reader.try_next().unwrap().is_some());
+/// assert!(reader.try_next().unwrap().is_some());
+/// assert!(matches!(reader.try_next(), Err(ReadError::NotSorted(_, _))));
+/// ```
+pub struct SortCheckedReader<R> {
+    inner: R,
+    on_unsorted: OnUnsorted,
+    last: Option<SortKey>,
+    first_unsorted: Option<(SortKey, SortKey)>,
+}
+
+impl<R: OSMReader> SortCheckedReader<R> {
+    /// Check the objects of this reader
+    pub fn new(inner: R, on_unsorted: OnUnsorted) -> Self {
+        SortCheckedReader {
+            inner: inner,
+            on_unsorted: on_unsorted,
+            last: None,
+            first_unsorted: None,
+        }
+    }
+
+    /// The first two objects (type, id, version) which were out of order, if any
+    pub fn first_unsorted(&self) -> Option<(SortKey, SortKey)> {
+        self.first_unsorted
+    }
+
+    /// True iff all the objects so far were in order
+    pub fn is_sorted(&self) -> bool {
+        self.first_unsorted.is_none()
+    }
+
+    /// Convert back to the wrapped reader
+    pub fn into_reader(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: OSMReader> OSMReader for SortCheckedReader<R> {
+    type R = R::R;
+    type Obj = R::Obj;
+
+    /// With `OnUnsorted::Error`
+    fn new(reader: R::R) -> Self {
+        SortCheckedReader::new(R::new(reader), OnUnsorted::Error)
+    }
+
+    fn set_sorted_assumption(&mut self, sorted_assumption: bool) {
+        self.inner.set_sorted_assumption(sorted_assumption)
+    }
+    fn get_sorted_assumption(&mut self) -> bool {
+        self.inner.get_sorted_assumption()
+    }
+
+    fn into_inner(self) -> R::R {
+        self.inner.into_inner()
+    }
+
+    fn inner(&self) -> &R::R {
+        self.inner.inner()
+    }
+
+    fn try_header(&mut self) -> Result<Option<Header>, ReadError> {
+        self.inner.try_header()
+    }
+
+    fn next(&mut self) -> Option<R::Obj> {
+        self.try_next().unwrap()
+    }
+
+    fn try_next(&mut self) -> Result<Option<R::Obj>, ReadError> {
+        let obj = match self.inner.try_next()? {
+            None => return Ok(None),
+            Some(obj) => obj,
+        };
+        let key = (obj.object_type(), obj.id(), obj.version());
+        if let Some(last) = self.last {
+            if key <= last && self.first_unsorted.is_none() {
+                self.first_unsorted = Some((last, key));
+                if self.on_unsorted == OnUnsorted::Error {
+                    return Err(ReadError::NotSorted(last, key));
+                }
+            }
+        }
+        self.last = Some(key);
+        Ok(Some(obj))
+    }
+}
+
+/// The next object of one of the readers of a `MergedReader`
+struct MergeHead {
+    key: SortKey,
+    source: usize,
+    obj: StringOSMObj,
+}
+
+// Reversed, like `Head`
+impl Ord for MergeHead {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.key, other.source).cmp(&(self.key, self.source))
+    }
+}
+
+impl PartialOrd for MergeHead {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeHead {}
+
+/// Merges several sorted readers (e.g. adjacent extracts) into one sorted stream, with each
+/// object only once. When several readers have an object, the newest version is kept (or, for
+/// the same version, the one from the reader added first). Iterating panics on read errors,
+/// use `try_next` to handle them.
+///
+/// ```
+/// use osmio::opl::OPLReader;
+/// use osmio::sort::MergedReader;
+/// use osmio::{OSMObjBase, OSMReader};
+///
+/// let mut merged = MergedReader::new();
+/// merged.push(OPLReader::new("n1 v1 x1 y2\nn2 v1 x1 y2\n".as_bytes()));
+/// merged.push(OPLReader::new("n2 v3 x1 y2\nn3 v1 x1 y2\n".as_bytes()));
+/// let objs: Vec<_> = merged.by_ref().map(|o| (o.id(), o.version())).collect();
+/// assert_eq!(objs, vec![(1, Some(1)), (2, Some(3)), (3, Some(1))]);
+/// assert_eq!(merged.duplicates(), 1);
+/// ```
+pub struct MergedReader<'a> {
+    readers: Vec<Box<dyn DynReader + 'a>>,
+    heap: BinaryHeap<MergeHead>,
+    /// Number of readers which have been read from, the others were added later
+    started: usize,
+    duplicates: u64,
+}
+
+impl<'a> MergedReader<'a> {
+    /// No readers yet
+    pub fn new() -> Self {
+        MergedReader {
+            readers: Vec::new(),
+            heap: BinaryHeap::new(),
+            started: 0,
+            duplicates: 0,
+        }
+    }
+
+    /// Add a reader. Readers should be added before reading starts.
+    pub fn push(&mut self, reader: impl DynReader + 'a) {
+        self.readers.push(Box::new(reader));
+    }
+
+    /// Add a boxed reader, e.g. from `read_from_path`
+    pub fn push_boxed(&mut self, reader: Box<dyn DynReader + 'a>) {
+        self.readers.push(reader);
+    }
+
+    /// Number of readers
+    pub fn len(&self) -> usize {
+        self.readers.len()
+    }
+
+    /// True iff there are no readers
+    pub fn is_empty(&self) -> bool {
+        self.readers.is_empty()
+    }
+
+    /// Number of objects dropped so far, because another reader had them too
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+
+    fn refill(&mut self, source: usize) -> Result<(), ReadError> {
+        if let Some(obj) = self.readers[source].try_next_obj()? {
+            self.heap.push(MergeHead {
+                key: (obj.object_type(), obj.id(), obj.version()),
+                source: source,
+                obj: obj,
+            });
+        }
+        Ok(())
+    }
+
+    /// The next object, `Ok(None)` at the end, or an error from one of the readers
+    pub fn try_next(&mut self) -> Result<Option<StringOSMObj>, ReadError> {
+        while self.started < self.readers.len() {
+            self.started += 1;
+            self.refill(self.started - 1)?;
+        }
+        let mut head = match self.heap.pop() {
+            None => return Ok(None),
+            Some(head) => head,
+        };
+        self.refill(head.source)?;
+        // Versions come in increasing order, so the last is the newest
+        while let Some(next) = self.heap.peek() {
+            if (next.key.0, next.key.1) != (head.key.0, head.key.1) {
+                break;
+            }
+            let next = self.heap.pop().unwrap();
+            self.refill(next.source)?;
+            self.duplicates += 1;
+            if next.key.2 > head.key.2 {
+                head = next;
+            }
+        }
+        Ok(Some(head.obj))
+    }
+}
+
+impl<'a> Default for MergedReader<'a> {
+    fn default() -> Self {
+        MergedReader::new()
+    }
+}
+
+impl<'a> Iterator for MergedReader<'a> {
+    type Item = StringOSMObj;
+
+    fn next(&mut self) -> Option<StringOSMObj> {
+        self.try_next().unwrap()
+    }
+}
+
+/// Iterator adapter which collapses consecutive versions of the same object (as after
+/// concatenating files, or naively applying diffs) into the one with the highest version. The
+/// input should be sorted, or at least have all versions of an object next to each other.
+///
+/// ```
+/// use osmio::opl::OPLReader;
+/// use osmio::sort::Deduplicated;
+/// use osmio::{OSMObjBase, OSMReader};
+///
+/// let input = "n1 v1 x1 y2\nn1 v2 x1 y2\nn2 v1 x1 y2\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let mut dedup = Deduplicated::new(reader.objects());
+/// let objs: Vec<_> = dedup.by_ref().map(|o| (o.id(), o.version())).collect();
+/// assert_eq!(objs, vec![(1, Some(2)), (2, Some(1))]);
+/// assert_eq!(dedup.duplicates(), 1);
+/// ```
+pub struct Deduplicated<I: Iterator> {
+    inner: I,
+    /// The first object of the next group
+    pending: Option<I::Item>,
+    duplicates: u64,
+}
+
+impl<I> Deduplicated<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    pub fn new(inner: I) -> Self {
+        Deduplicated {
+            inner: inner,
+            pending: None,
+            duplicates: 0,
+        }
+    }
+
+    /// Number of objects dropped so far
+    pub fn duplicates(&self) -> u64 {
+        self.duplicates
+    }
+}
+
+impl<I> Iterator for Deduplicated<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let mut best = match self.pending.take() {
+            Some(obj) => obj,
+            None => self.inner.next()?,
+        };
+        for obj in self.inner.by_ref() {
+            if (obj.object_type(), obj.id()) != (best.object_type(), best.id()) {
+                self.pending = Some(obj);
+                break;
+            }
+            self.duplicates += 1;
+            // For the same version, the first is kept
+            if obj.version() > best.version() {
+                best = obj;
+            }
+        }
+        Some(best)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = self.pending.is_some() as usize;
+        let (min, max) = self.inner.size_hint();
+        (
+            (min + pending).min(1),
+            max.and_then(|m| m.checked_add(pending)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::{OPLReader, OPLWriter};
+    use {Node, OSMObjBase, OSMReader, OSMWriter, Way};
+
+    #[test]
+    fn external_sort() {
+        let input: String = (0..50)
+            .map(|i| {
+                format!(
+                    "n{} v{} x1 y2 Tname=a%20%b\nw{} Nn1\n",
+                    (i * 7) % 50,
+                    i % 3 + 1,
+                    50 - i
+                )
+            })
+            .collect();
+        let objs: Vec<StringOSMObj> = OPLReader::new(input.as_bytes()).objects().collect();
+        let mut expected = objs.clone();
+        expected.sort();
+
+        let mut sorter = ExternalSorter::new(8);
+        for obj in objs.iter().cloned() {
+            sorter.push(obj).unwrap();
+        }
+        assert_eq!(sorter.num_runs(), 12);
+        let prefix = sorter.prefix.clone();
+        let sorted: Vec<StringOSMObj> = sorter.finish().unwrap().collect();
+        assert_eq!(sorted, expected);
+        let left = fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(&prefix)
+            })
+            .count();
+        assert_eq!(left, 0);
+
+        let arcs: Vec<ArcOSMObj> = objs.iter().cloned().map(Into::into).collect();
+        let sorted: Vec<ArcOSMObj> = ExternalSorter::new(1000).sort(arcs).unwrap().collect();
+        assert_eq!(sorted.len(), 100);
+        assert_eq!(sorted[0].id(), 0);
+
+        let empty: Vec<StringOSMObj> = ExternalSorter::new(3).sort(vec![]).unwrap().collect();
+        assert!(empty.is_empty());
+
+        let input: String = (0..20).rev().map(|i| format!("n{} x1 y2\n", i)).collect();
+        let mut writer = OPLWriter::new(Vec::new());
+        let options = SortOptions {
+            max_in_memory: 3,
+            ..Default::default()
+        };
+        let written =
+            sort_with_options(&mut OPLReader::new(input.as_bytes()), &mut writer, &options)
+                .unwrap();
+        assert_eq!(written, 20);
+        writer.close().unwrap();
+        let output = String::from_utf8(writer.into_inner()).unwrap();
+        let ids: Vec<_> = output
+            .lines()
+            .map(|l| l.split(' ').next().unwrap())
+            .collect();
+        assert_eq!(ids[..3], ["n0", "n1", "n2"]);
+        assert_eq!(ids[19], "n19");
+    }
+
+    #[test]
+    fn sort_checked() {
+        let input = "n1 x1 y2\nn1 v2 x1 y2\nw1 Nn1\nn5 x1 y2\nw1 Nn1\nw2 Nn1\n";
+        let mut reader =
+            SortCheckedReader::new(OPLReader::new(input.as_bytes()), OnUnsorted::Record);
+        assert_eq!(reader.objects().count(), 6);
+        assert!(!reader.is_sorted());
+        assert_eq!(
+            reader.first_unsorted(),
+            Some((
+                (OSMObjectType::Way, 1, None),
+                (OSMObjectType::Node, 5, None)
+            ))
+        );
+
+        let input = "n1 x1 y2\nn2 x1 y2\nn2 x1 y2\n";
+        let mut reader: SortCheckedReader<OPLReader<_>> = OSMReader::new(input.as_bytes());
+        assert_eq!(reader.try_objects().filter(|o| o.is_ok()).count(), 2);
+        assert_eq!(
+            reader.first_unsorted(),
+            Some((
+                (OSMObjectType::Node, 2, None),
+                (OSMObjectType::Node, 2, None)
+            ))
+        );
+    }
+
+    #[test]
+    fn merged_reader() {
+        let a = "n1 v1 x1 y2\nn3 v2 x1 y2\nw1 v1 Nn1\n";
+        let b = "n2 v1 x1 y2\nn3 v2 x5 y5\nr1 v1 M\n";
+        let c = "n3 v1 x1 y2\nw1 v2 Nn1,n2\n";
+        let mut merged = MergedReader::new();
+        merged.push(OPLReader::new(a.as_bytes()));
+        merged.push(OPLReader::new(b.as_bytes()));
+        let boxed: Box<dyn DynReader> = Box::new(OPLReader::new(c.as_bytes()));
+        merged.push_boxed(boxed);
+        assert_eq!(merged.len(), 3);
+        let objs: Vec<_> = merged.by_ref().collect();
+        let keys: Vec<_> = objs
+            .iter()
+            .map(|o| (o.object_type(), o.id(), o.version()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (OSMObjectType::Node, 1, Some(1)),
+                (OSMObjectType::Node, 2, Some(1)),
+                (OSMObjectType::Node, 3, Some(2)),
+                (OSMObjectType::Way, 1, Some(2)),
+                (OSMObjectType::Relation, 1, Some(1)),
+            ]
+        );
+        // The same version is taken from the first reader
+        assert_eq!(objs[2].as_node().unwrap().lat_lon_f64(), Some((2., 1.)));
+        assert_eq!(merged.duplicates(), 3);
+    }
+
+    #[test]
+    fn deduplicated() {
+        let input = "n1 v2 x1 y2\nn1 v1 x1 y2\nn1 v3 x1 y2\nw1 v1 Nn1\nw1 v1 Nn2\nr1 M\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut dedup = Deduplicated::new(reader.objects());
+        let objs: Vec<_> = dedup.by_ref().collect();
+        assert_eq!(objs.len(), 3);
+        assert_eq!(objs[0].version(), Some(3));
+        assert_eq!(objs[1].as_way().unwrap().nodes(), [1]);
+        assert_eq!(objs[2].id(), 1);
+        assert_eq!(dedup.duplicates(), 3);
+        assert_eq!(dedup.next(), None);
+    }
+}
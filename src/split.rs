@@ -0,0 +1,607 @@
+//! Write objects to several outputs at once, splitting them up: by type (`SplitByType`), or by
+//! tile (`TileSplit`)
+use std::collections::{BTreeMap, HashMap};
+use std::f64::consts::PI;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use formats::{write_to_path, write_to_path_with_options, FileFormat, OpenError};
+use obj_types::StringOSMObj;
+use utils::type_idx;
+use {
+    BBox, DynReader, DynWriter, HeaderField, Lat, Lon, Node, OSMObj, OSMObjBase, OSMObjectType,
+    OSMWriteError, OSMWriter, ObjId, ReadError, Relation, Way, WriterOptions,
+};
+
+/// A writer which sends nodes, ways, and relations to 3 different writers, in one pass. e.g. to
+/// load each into its own database table.
+///
+/// ```
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::split::SplitByType;
+/// use osmio::{OSMReader, OSMWriter};
+///
+/// let input = "n1 x1 y2\nn2 x1 y2\nw3 Nn1,n2\nr4 Mw3@\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let mut writer = SplitByType::new(
+///     OPLWriter::new(Vec::new()),
+///     OPLWriter::new(Vec::new()),
+///     OPLWriter::new(Vec::new()),
+/// );
+/// writer.write_objs(reader.objects()).unwrap();
+/// writer.close().unwrap();
+/// let (nodes, ways, relations) = writer.into_writers();
+/// assert_eq!(
+///     String::from_utf8(nodes.into_inner()).unwrap(),
+///     "n1 dV T x1 y2\nn2 dV T x1 y2\n"
+/// );
+/// assert_eq!(String::from_utf8(ways.into_inner()).unwrap(), "w3 dV T Nn1,n2\n");
+/// assert_eq!(String::from_utf8(relations.into_inner()).unwrap(), "r4 dV T Mw3@\n");
+/// ```
+pub struct SplitByType<O> {
+    /// Nodes, ways, relations
+    writers: [O; 3],
+    counts: [u64; 3],
+}
+
+impl<O> SplitByType<O> {
+    pub fn new(nodes: O, ways: O, relations: O) -> Self {
+        SplitByType {
+            writers: [nodes, ways, relations],
+            counts: [0; 3],
+        }
+    }
+
+    /// The writer for this type
+    pub fn writer(&self, object_type: OSMObjectType) -> &O {
+        &self.writers[type_idx(object_type)]
+    }
+
+    /// The writer for this type
+    pub fn writer_mut(&mut self, object_type: OSMObjectType) -> &mut O {
+        &mut self.writers[type_idx(object_type)]
+    }
+
+    /// How many objects of this type have been written
+    pub fn count(&self, object_type: OSMObjectType) -> u64 {
+        self.counts[type_idx(object_type)]
+    }
+
+    /// Convert back to the node, way & relation writers
+    pub fn into_writers(self) -> (O, O, O) {
+        let [nodes, ways, relations] = self.writers;
+        (nodes, ways, relations)
+    }
+
+    /// Write this object, to the writer for its type
+    pub fn write_obj<W>(&mut self, obj: &impl OSMObj) -> Result<(), OSMWriteError>
+    where
+        W: Write,
+        O: OSMWriter<W>,
+    {
+        let idx = type_idx(obj.object_type());
+        self.writers[idx].write_obj(obj)?;
+        self.counts[idx] += 1;
+        Ok(())
+    }
+
+    /// Write all these objects
+    pub fn write_objs<W, T>(
+        &mut self,
+        objs: impl IntoIterator<Item = T>,
+    ) -> Result<(), OSMWriteError>
+    where
+        W: Write,
+        O: OSMWriter<W>,
+        T: OSMObj,
+    {
+        for obj in objs {
+            self.write_obj(&obj)?;
+        }
+        Ok(())
+    }
+
+    /// Set this header field on every writer
+    pub fn set_header<W>(&mut self, field: HeaderField) -> Result<(), OSMWriteError>
+    where
+        W: Write,
+        O: OSMWriter<W>,
+    {
+        for writer in self.writers.iter_mut() {
+            writer.set_header(field.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Close every writer
+    pub fn close<W>(&mut self) -> Result<(), OSMWriteError>
+    where
+        W: Write,
+        O: OSMWriter<W>,
+    {
+        for writer in self.writers.iter_mut() {
+            writer.close()?;
+        }
+        Ok(())
+    }
+}
+
+impl SplitByType<Box<dyn DynWriter>> {
+    /// Write to these 3 files, with formats from their file names, see `write_to_path`
+    pub fn create(
+        nodes: impl AsRef<Path>,
+        ways: impl AsRef<Path>,
+        relations: impl AsRef<Path>,
+    ) -> Result<Self, OpenError> {
+        Ok(SplitByType::new(
+            write_to_path(nodes)?,
+            write_to_path(ways)?,
+            write_to_path(relations)?,
+        ))
+    }
+}
+
+impl<O: DynWriter> DynWriter for SplitByType<O> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        let idx = type_idx(obj.object_type());
+        self.writers[idx].write_string_obj(obj)?;
+        self.counts[idx] += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        for writer in self.writers.iter_mut() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// An error when splitting objects into tiles
+#[derive(Debug)]
+pub enum SplitError {
+    Open(OpenError),
+    Read(ReadError),
+    Write(OSMWriteError),
+}
+impl std::fmt::Display for SplitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for SplitError {}
+
+impl From<OpenError> for SplitError {
+    fn from(err: OpenError) -> SplitError {
+        SplitError::Open(err)
+    }
+}
+impl From<ReadError> for SplitError {
+    fn from(err: ReadError) -> SplitError {
+        SplitError::Read(err)
+    }
+}
+impl From<OSMWriteError> for SplitError {
+    fn from(err: OSMWriteError) -> SplitError {
+        SplitError::Write(err)
+    }
+}
+impl From<::std::io::Error> for SplitError {
+    fn from(err: ::std::io::Error) -> SplitError {
+        SplitError::Open(OpenError::IOError(err))
+    }
+}
+
+/// A web mercator ("slippy map") tile, `z/x/y`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tile {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Tile {
+    pub fn new(z: u8, x: u32, y: u32) -> Self {
+        Tile { z: z, x: x, y: y }
+    }
+
+    /// The tile at this zoom with this location. Latitudes beyond web mercator's ±85.0511° are
+    /// in the top or bottom row.
+    pub fn at(z: u8, lat: Lat, lon: Lon) -> Self {
+        let n = f64::from(1u32 << z);
+        let max = (1u32 << z) - 1;
+        let x = (lon.degrees() + 180.) / 360. * n;
+        let lat = lat.degrees().to_radians();
+        let y = (1. - (lat.tan() + 1. / lat.cos()).ln() / PI) / 2. * n;
+        let clamp = |v: f64| {
+            if v.is_nan() || v < 0. {
+                0
+            } else {
+                (v as u32).min(max)
+            }
+        };
+        Tile::new(z, clamp(x), clamp(y))
+    }
+
+    /// The area this tile covers
+    pub fn bbox(&self) -> BBox {
+        let n = f64::from(1u32 << self.z);
+        let lon = |x: u32| f64::from(x) / n * 360. - 180.;
+        let lat = |y: u32| {
+            (PI * (1. - 2. * f64::from(y) / n))
+                .sinh()
+                .atan()
+                .to_degrees()
+        };
+        BBox::new(lat(self.y + 1), lon(self.x), lat(self.y), lon(self.x + 1))
+    }
+}
+
+impl fmt::Display for Tile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.z, self.x, self.y)
+    }
+}
+
+/// Splits objects into tiles of one zoom level, writing each to a writer per (non-empty) tile.
+/// Nodes go to the tile with their location; ways to every tile with one of their nodes; and
+/// relations to every tile with one of their members, so objects on tile boundaries are
+/// duplicated. The input must be sorted, nodes then ways then relations, and objects with no
+/// known tile (e.g. ways whose nodes are all missing) are skipped.
+///
+/// The tiles of every node & way are kept in memory. Every writer stays open until `finish`,
+/// unless there's a limit (`set_max_open`), when the least recently written is finished to make
+/// room, and reopened (to append to) if it's needed again.
+///
+/// ```
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::split::{Tile, TileSplit};
+/// use osmio::{OSMReader, OSMWriter};
+///
+/// let input = "n1 x-10 y10\nn2 x10 y10\nn3 x10 y-10\nw4 Nn1,n2\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let mut split = TileSplit::new(1, |_tile, _append| Ok(OPLWriter::new(Vec::new())));
+/// split.run(&mut reader).unwrap();
+/// split.finish().unwrap();
+/// let tiles: Vec<Tile> = split.tiles().collect();
+/// assert_eq!(tiles, vec![Tile::new(1, 0, 0), Tile::new(1, 1, 0), Tile::new(1, 1, 1)]);
+/// ```
+pub struct TileSplit<'a, O> {
+    zoom: u8,
+    open: Box<dyn FnMut(Tile, bool) -> Result<O, OpenError> + 'a>,
+    /// The open writers
+    writers: BTreeMap<Tile, O>,
+    max_open: usize,
+    /// Every tile written to, and when it was last written to (a count of writes)
+    written: BTreeMap<Tile, u64>,
+    writes: u64,
+    node_tiles: HashMap<ObjId, Tile>,
+    /// Sorted & deduplicated
+    way_tiles: HashMap<ObjId, Vec<Tile>>,
+    relation_tiles: HashMap<ObjId, Vec<Tile>>,
+    skipped: u64,
+}
+
+impl<'a> TileSplit<'a, Box<dyn DynWriter>> {
+    /// Write each tile to `dir/z/x/y.extension`, with the format from the extension (e.g.
+    /// `osm`, `opl.gz`), see `write_to_path`. Each tile has a smaller buffer than
+    /// `write_to_path`'s, and OPL tiles are limited to 256 open files. XML & osmChange files
+    /// can't be reopened to append to, so every one of those stays open.
+    pub fn create(dir: impl AsRef<Path>, zoom: u8, extension: &str) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        let extension = extension.to_string();
+        let opl = matches!(
+            FileFormat::from_path(format!("tile.{}", extension)),
+            Some((FileFormat::OPL, _))
+        );
+        let mut split = TileSplit::new(zoom, move |tile, append| {
+            let tile_dir = dir.join(tile.z.to_string()).join(tile.x.to_string());
+            fs::create_dir_all(&tile_dir)?;
+            let options = WriterOptions {
+                buffer_size: 64 * 1024,
+                append: append,
+            };
+            write_to_path_with_options(tile_dir.join(format!("{}.{}", tile.y, extension)), &options)
+        });
+        if opl {
+            split.set_max_open(256);
+        }
+        split
+    }
+}
+
+impl<'a, O: DynWriter> TileSplit<'a, O> {
+    /// Split into tiles at this zoom, with `open` creating the writer for each tile, when
+    /// the first object for it is written. `open`'s `bool` is true when reopening a tile which
+    /// was finished to stay under `set_max_open`, so it should be appended to.
+    pub fn new(zoom: u8, open: impl FnMut(Tile, bool) -> Result<O, OpenError> + 'a) -> Self {
+        assert!(zoom < 32, "zoom {} is too large", zoom);
+        TileSplit {
+            zoom: zoom,
+            open: Box::new(open),
+            writers: BTreeMap::new(),
+            max_open: usize::MAX,
+            written: BTreeMap::new(),
+            writes: 0,
+            node_tiles: HashMap::new(),
+            way_tiles: HashMap::new(),
+            relation_tiles: HashMap::new(),
+            skipped: 0,
+        }
+    }
+
+    /// The zoom of the tiles
+    pub fn zoom(&self) -> u8 {
+        self.zoom
+    }
+
+    /// Keep at most this many writers open. Default: no limit
+    pub fn set_max_open(&mut self, max_open: usize) {
+        assert!(max_open > 0, "at least one writer must be open");
+        self.max_open = max_open;
+    }
+
+    /// The tiles which something has been written to, in order
+    pub fn tiles<'b>(&'b self) -> impl Iterator<Item = Tile> + 'b {
+        self.written.keys().cloned()
+    }
+
+    /// The writer for this tile, if it's open
+    pub fn writer(&self, tile: Tile) -> Option<&O> {
+        self.writers.get(&tile)
+    }
+
+    /// How many objects were skipped, because they have no known tile
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// The tiles this object belongs in, sorted. Nodes & ways are remembered for later ways &
+    /// relations.
+    fn tiles_of(&mut self, obj: &StringOSMObj) -> Vec<Tile> {
+        let id = obj.id();
+        if let Some(node) = obj.as_node() {
+            return match node.lat_lon() {
+                Some((lat, lon)) => {
+                    let tile = Tile::at(self.zoom, lat, lon);
+                    self.node_tiles.insert(id, tile);
+                    vec![tile]
+                }
+                None => vec![],
+            };
+        }
+
+        let mut tiles = Vec::new();
+        if let Some(way) = obj.as_way() {
+            tiles.extend(way.nodes().iter().filter_map(|n| self.node_tiles.get(n)));
+        } else if let Some(relation) = obj.as_relation() {
+            for m in relation.members() {
+                match m.mtype {
+                    OSMObjectType::Node => tiles.extend(self.node_tiles.get(&m.id)),
+                    OSMObjectType::Way => {
+                        tiles.extend(self.way_tiles.get(&m.id).into_iter().flatten())
+                    }
+                    OSMObjectType::Relation => {
+                        tiles.extend(self.relation_tiles.get(&m.id).into_iter().flatten())
+                    }
+                }
+            }
+        }
+        tiles.sort();
+        tiles.dedup();
+        match obj.object_type() {
+            OSMObjectType::Way => {
+                self.way_tiles.insert(id, tiles.clone());
+            }
+            OSMObjectType::Relation => {
+                self.relation_tiles.insert(id, tiles.clone());
+            }
+            OSMObjectType::Node => {}
+        }
+        tiles
+    }
+
+    /// Write this object to every tile it's in
+    pub fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), SplitError> {
+        let tiles = self.tiles_of(obj);
+        if tiles.is_empty() {
+            self.skipped += 1;
+        }
+        for tile in tiles {
+            if !self.writers.contains_key(&tile) {
+                if self.writers.len() >= self.max_open {
+                    self.close_least_recent()?;
+                }
+                let append = self.written.contains_key(&tile);
+                let writer = (self.open)(tile, append)?;
+                self.writers.insert(tile, writer);
+            }
+            self.writes += 1;
+            self.written.insert(tile, self.writes);
+            self.writers.get_mut(&tile).unwrap().write_string_obj(obj)?;
+        }
+        Ok(())
+    }
+
+    /// Finish the open writer which was written to least recently
+    fn close_least_recent(&mut self) -> Result<(), SplitError> {
+        let written = &self.written;
+        let oldest = self
+            .writers
+            .keys()
+            .cloned()
+            .min_by_key(|tile| written[tile]);
+        if let Some(tile) = oldest {
+            let mut writer = self.writers.remove(&tile).unwrap();
+            writer.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Write every object of this reader. Returns how many objects were read.
+    pub fn run<R: DynReader + ?Sized>(&mut self, reader: &mut R) -> Result<u64, SplitError> {
+        let mut read = 0;
+        while let Some(obj) = reader.try_next_obj()? {
+            self.write_string_obj(&obj)?;
+            read += 1;
+        }
+        Ok(read)
+    }
+
+    /// Close every open writer
+    pub fn finish(&mut self) -> Result<(), SplitError> {
+        for writer in self.writers.values_mut() {
+            writer.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Convert into the writer of each tile which is still open
+    pub fn into_writers(self) -> BTreeMap<Tile, O> {
+        self.writers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::OPLReader;
+    use std::fs;
+    use OSMReader;
+
+    #[test]
+    fn split_to_paths() {
+        let dir = ::std::env::temp_dir().join(format!("osmio-split-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = "n1 x1 y2\nw2 Nn1\nn3 x1 y2\nr4 Mn3@\n";
+        {
+            let mut reader = OPLReader::new(input.as_bytes());
+            let mut writer = SplitByType::create(
+                dir.join("nodes.opl"),
+                dir.join("ways.opl"),
+                dir.join("relations.opl"),
+            )
+            .unwrap();
+            for obj in reader.objects() {
+                writer.write_string_obj(&obj).unwrap();
+            }
+            writer.finish().unwrap();
+            assert_eq!(writer.count(OSMObjectType::Node), 2);
+            assert_eq!(writer.count(OSMObjectType::Way), 1);
+            assert_eq!(writer.count(OSMObjectType::Relation), 1);
+        }
+        assert_eq!(
+            fs::read_to_string(dir.join("nodes.opl")).unwrap(),
+            "n1 dV T x1 y2\nn3 dV T x1 y2\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("ways.opl")).unwrap(),
+            "w2 dV T Nn1\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("relations.opl")).unwrap(),
+            "r4 dV T Mn3@\n"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tile() {
+        assert_eq!(
+            Tile::at(0, Lat::from_degrees(51.5), Lon::from_degrees(-0.1)),
+            Tile::new(0, 0, 0)
+        );
+        // London, & the extremes
+        let z = 10;
+        assert_eq!(
+            Tile::at(z, Lat::from_degrees(51.5), Lon::from_degrees(-0.1)),
+            Tile::new(10, 511, 340)
+        );
+        assert_eq!(
+            Tile::at(z, Lat::from_degrees(90.), Lon::from_degrees(180.)),
+            Tile::new(10, 1023, 0)
+        );
+        assert_eq!(
+            Tile::at(z, Lat::from_degrees(-90.), Lon::from_degrees(-180.)),
+            Tile::new(10, 0, 1023)
+        );
+
+        let tile = Tile::new(10, 511, 340);
+        let bbox = tile.bbox();
+        assert!(bbox.contains(Lat::from_degrees(51.5), Lon::from_degrees(-0.1)));
+        assert!((bbox.max_lon.degrees() - 0.).abs() < 1e-6);
+        assert_eq!(tile.to_string(), "10/511/340");
+    }
+
+    #[test]
+    fn tile_split() {
+        // n1 & n2 in tile 1/0/0, n3 in 1/1/0, n4 in 1/1/1
+        let input = "n1 x-10 y10\nn2 x-20 y20\nn3 x10 y10\nn4 x10 y-10\nn5 dD\n\
+                     w6 Nn1,n2\nw7 Nn2,n3\nw8 Nn5\nr9 Mw7@,n4@\nr10 Mr9@\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut split = TileSplit::new(1, |_, _| Ok(::opl::OPLWriter::new(Vec::new())));
+        assert_eq!(split.run(&mut reader).unwrap(), 10);
+        split.finish().unwrap();
+        assert_eq!(split.skipped(), 2);
+
+        assert!(split.writer(Tile::new(1, 0, 1)).is_none());
+        let mut writers = split.into_writers();
+        let mut ids = |tile: Tile| -> Vec<String> {
+            String::from_utf8(writers.remove(&tile).unwrap().into_inner())
+                .unwrap()
+                .lines()
+                .map(|l| l.split(' ').next().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(
+            ids(Tile::new(1, 0, 0)),
+            vec!["n1", "n2", "w6", "w7", "r9", "r10"]
+        );
+        assert_eq!(ids(Tile::new(1, 1, 0)), vec!["n3", "w7", "r9", "r10"]);
+        assert_eq!(ids(Tile::new(1, 1, 1)), vec!["n4", "r9", "r10"]);
+    }
+
+    #[test]
+    fn tile_split_to_dir() {
+        let dir = ::std::env::temp_dir().join(format!("osmio-tiles-{}", ::std::process::id()));
+        let input = "n1 x-10 y10\nn2 x10 y-10\nw3 Nn1,n2\n";
+        {
+            let mut reader = OPLReader::new(input.as_bytes());
+            let mut split = TileSplit::create(&dir, 1, "opl");
+            split.run(&mut reader).unwrap();
+            split.finish().unwrap();
+        }
+        assert_eq!(
+            fs::read_to_string(dir.join("1/0/0.opl")).unwrap(),
+            "n1 dV T x-10 y10\nw3 dV T Nn1,n2\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("1/1/1.opl")).unwrap(),
+            "n2 dV T x10 y-10\nw3 dV T Nn1,n2\n"
+        );
+
+        // With one open file, each tile is reopened & appended to, rather than overwritten
+        let input = "n1 x-10 y10\nn2 x10 y-10\nn3 x-10 y10\nw4 Nn1,n2\n";
+        {
+            let mut reader = OPLReader::new(input.as_bytes());
+            let mut split = TileSplit::create(&dir, 1, "opl.gz");
+            split.set_max_open(1);
+            split.run(&mut reader).unwrap();
+            assert_eq!(split.tiles().count(), 2);
+            assert!(split.writer(Tile::new(1, 0, 0)).is_none());
+            assert!(split.writer(Tile::new(1, 1, 1)).is_some());
+            split.finish().unwrap();
+        }
+        let ids = |path: &str| -> Vec<ObjId> {
+            ::read_from_path(dir.join(path))
+                .unwrap()
+                .map(|o| o.id())
+                .collect()
+        };
+        assert_eq!(ids("1/0/0.opl.gz"), vec![1, 3, 4]);
+        assert_eq!(ids("1/1/1.opl.gz"), vec![2, 4]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,708 @@
+//! Statistics of OSM data. `stats` gives a summary of a whole file (like osmium `fileinfo`), and
+//! `TagStats` taginfo-style statistics of tag usage: how often each key, and each key/value
+//! combination, is used, by object type. `UserStats` summarises the edits of each user.
+//!
+//! Counting is exact by default, which needs memory for every distinct key & tag. With
+//! `TagStats::top_k`, memory is bounded, and only the most common are kept. Then counts are lower
+//! bounds, which can be off by up to `TagStats::max_error`.
+//!
+//! ```
+//! use osmio::opl::OPLReader;
+//! use osmio::stats::TagStats;
+//! use osmio::OSMReader;
+//!
+//! let input = "n1 Tamenity=bench x1 y2\nn2 Tamenity=cafe,name=A x1 y2\nw3 Tamenity=cafe Nn1\n";
+//! let mut reader = OPLReader::new(input.as_bytes());
+//! let mut stats = TagStats::new();
+//! stats.add_reader(&mut reader).unwrap();
+//! assert_eq!(stats.keys()[0].0, "amenity");
+//! assert_eq!(stats.keys()[0].1.count, 3);
+//! assert_eq!(stats.tag("amenity", "cafe").unwrap().ways, 1);
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use utils::type_idx;
+use {BBox, DynReader, Node, OSMObj, OSMObjBase, OSMObjectType, ObjId, ReadError, TimestampFormat};
+
+/// How often something is used, in total, and by object type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Usage {
+    pub count: u64,
+    pub nodes: u64,
+    pub ways: u64,
+    pub relations: u64,
+}
+
+impl Usage {
+    /// The count for this object type
+    pub fn of(&self, object_type: OSMObjectType) -> u64 {
+        match object_type {
+            OSMObjectType::Node => self.nodes,
+            OSMObjectType::Way => self.ways,
+            OSMObjectType::Relation => self.relations,
+        }
+    }
+
+    fn add(&mut self, object_type: OSMObjectType) {
+        self.count += 1;
+        match object_type {
+            OSMObjectType::Node => self.nodes += 1,
+            OSMObjectType::Way => self.ways += 1,
+            OSMObjectType::Relation => self.relations += 1,
+        }
+    }
+}
+
+/// Counts of keys, or tags. When bounded, it's pruned to the most common `2 * k` once it has
+/// `4 * k` entries.
+#[derive(Debug, Clone)]
+struct Counter<K: Hash + Eq> {
+    counts: HashMap<K, Usage>,
+    top_k: Option<usize>,
+    /// The largest count pruned so far
+    max_pruned: u64,
+}
+
+impl<K: Hash + Eq + Ord + Clone> Counter<K> {
+    fn new(top_k: Option<usize>) -> Self {
+        Counter {
+            counts: HashMap::new(),
+            top_k: top_k,
+            max_pruned: 0,
+        }
+    }
+
+    fn add(&mut self, key: K, object_type: OSMObjectType) {
+        self.counts.entry(key).or_default().add(object_type);
+        if let Some(k) = self.top_k {
+            if self.counts.len() >= 4 * k.max(1) {
+                self.prune(2 * k);
+            }
+        }
+    }
+
+    fn prune(&mut self, keep: usize) {
+        let mut entries = self.all_sorted();
+        for (_, usage) in entries.drain(keep.min(entries.len())..) {
+            self.max_pruned = self.max_pruned.max(usage.count);
+        }
+        self.counts = entries.into_iter().collect();
+    }
+
+    /// Most common first, then by key
+    fn all_sorted(&self) -> Vec<(K, Usage)> {
+        let mut entries: Vec<(K, Usage)> =
+            self.counts.iter().map(|(k, u)| (k.clone(), *u)).collect();
+        entries.sort_by(|a, b| b.1.count.cmp(&a.1.count).then_with(|| a.0.cmp(&b.0)));
+        entries
+    }
+
+    /// The top `k`, when bounded
+    fn sorted(&self) -> Vec<(K, Usage)> {
+        let mut entries = self.all_sorted();
+        if let Some(k) = self.top_k {
+            entries.truncate(k);
+        }
+        entries
+    }
+}
+
+/// Collects tag statistics from a stream of objects, see the module docs.
+#[derive(Debug, Clone)]
+pub struct TagStats {
+    objects: Usage,
+    tagged: Usage,
+    keys: Counter<String>,
+    tags: Counter<(String, String)>,
+}
+
+impl Default for TagStats {
+    fn default() -> Self {
+        TagStats::new()
+    }
+}
+
+impl TagStats {
+    /// Exact statistics, which keeps every key & tag in memory
+    pub fn new() -> Self {
+        TagStats::with_top_k(None)
+    }
+
+    /// Bounded statistics, which only report the `k` most common keys, and the `k` most common
+    /// tags, using memory for a few times `k` of each.
+    pub fn top_k(k: usize) -> Self {
+        TagStats::with_top_k(Some(k))
+    }
+
+    fn with_top_k(top_k: Option<usize>) -> Self {
+        TagStats {
+            objects: Usage::default(),
+            tagged: Usage::default(),
+            keys: Counter::new(top_k),
+            tags: Counter::new(top_k),
+        }
+    }
+
+    /// Count this object
+    pub fn add(&mut self, obj: &impl OSMObj) {
+        let object_type = obj.object_type();
+        self.objects.add(object_type);
+        if obj.num_tags() > 0 {
+            self.tagged.add(object_type);
+        }
+        for (k, v) in obj.tags() {
+            self.keys.add(k.to_string(), object_type);
+            self.tags.add((k.to_string(), v.to_string()), object_type);
+        }
+    }
+
+    /// Count all these objects
+    pub fn add_all<O: OSMObj>(&mut self, objs: impl IntoIterator<Item = O>) {
+        for obj in objs {
+            self.add(&obj);
+        }
+    }
+
+    /// Count all the objects of this reader
+    pub fn add_reader<R: DynReader + ?Sized>(&mut self, reader: &mut R) -> Result<(), ReadError> {
+        while let Some(obj) = reader.try_next_obj()? {
+            self.add(&obj);
+        }
+        Ok(())
+    }
+
+    /// How many objects have been counted
+    pub fn objects(&self) -> &Usage {
+        &self.objects
+    }
+
+    /// How many objects with tags have been counted
+    pub fn tagged_objects(&self) -> &Usage {
+        &self.tagged
+    }
+
+    /// The keys, most common first
+    pub fn keys(&self) -> Vec<(String, Usage)> {
+        self.keys.sorted()
+    }
+
+    /// The key/value combinations, most common first
+    pub fn tags(&self) -> Vec<((String, String), Usage)> {
+        self.tags.sorted()
+    }
+
+    /// The usage of this key, if it's been seen (and kept)
+    pub fn key(&self, key: &str) -> Option<&Usage> {
+        self.keys.counts.get(key)
+    }
+
+    /// The usage of this tag, if it's been seen (and kept)
+    pub fn tag(&self, key: &str, value: &str) -> Option<&Usage> {
+        self.tags.counts.get(&(key.to_string(), value.to_string()))
+    }
+
+    /// True iff every count is exact. Bounded statistics are exact until they prune something.
+    pub fn is_exact(&self) -> bool {
+        self.max_error() == 0
+    }
+
+    /// The most any count could be short by, because of pruning
+    pub fn max_error(&self) -> u64 {
+        self.keys.max_pruned.max(self.tags.max_pruned)
+    }
+}
+
+/// A summary of some objects, see `stats`
+#[derive(Debug, Clone, Default)]
+pub struct FileStats {
+    objects: Usage,
+    min_ids: [Option<ObjId>; 3],
+    max_ids: [Option<ObjId>; 3],
+    /// Unix epochs
+    min_timestamp: Option<i64>,
+    max_timestamp: Option<i64>,
+    bbox: Option<BBox>,
+    uids: HashSet<u64>,
+    /// Of objects without a uid
+    users: HashSet<String>,
+    changesets: HashSet<u64>,
+    tagged_nodes: u64,
+}
+
+impl FileStats {
+    pub fn new() -> Self {
+        FileStats::default()
+    }
+
+    /// Count this object
+    pub fn add(&mut self, obj: &impl OSMObj) {
+        let object_type = obj.object_type();
+        let idx = type_idx(object_type);
+        self.objects.add(object_type);
+        let id = obj.id();
+        self.min_ids[idx] = Some(self.min_ids[idx].map_or(id, |m| m.min(id)));
+        self.max_ids[idx] = Some(self.max_ids[idx].map_or(id, |m| m.max(id)));
+
+        if let Some(t) = obj.timestamp().as_ref().and_then(|t| t.to_datetime()) {
+            let t = t.timestamp();
+            self.min_timestamp = Some(self.min_timestamp.map_or(t, |m| m.min(t)));
+            self.max_timestamp = Some(self.max_timestamp.map_or(t, |m| m.max(t)));
+        }
+        match (obj.uid(), obj.user()) {
+            (Some(uid), _) => {
+                self.uids.insert(uid);
+            }
+            (None, Some(user)) => {
+                if !self.users.contains(user) {
+                    self.users.insert(user.to_string());
+                }
+            }
+            (None, None) => {}
+        }
+        if let Some(c) = obj.changeset_id() {
+            self.changesets.insert(c);
+        }
+
+        if let Some(node) = obj.as_node() {
+            if node.num_tags() > 0 {
+                self.tagged_nodes += 1;
+            }
+            if let Some((lat, lon)) = node.lat_lon() {
+                self.bbox = Some(match self.bbox {
+                    None => BBox::point(lat, lon),
+                    Some(b) => b.extended(lat, lon),
+                });
+            }
+        }
+    }
+
+    /// How many objects there are, of each type
+    pub fn objects(&self) -> &Usage {
+        &self.objects
+    }
+
+    /// The smallest id of this type
+    pub fn min_id(&self, object_type: OSMObjectType) -> Option<ObjId> {
+        self.min_ids[type_idx(object_type)]
+    }
+
+    /// The largest id of this type
+    pub fn max_id(&self, object_type: OSMObjectType) -> Option<ObjId> {
+        self.max_ids[type_idx(object_type)]
+    }
+
+    /// The earliest (valid) timestamp
+    pub fn min_timestamp(&self) -> Option<TimestampFormat> {
+        self.min_timestamp.map(TimestampFormat::EpochNunber)
+    }
+
+    /// The latest (valid) timestamp
+    pub fn max_timestamp(&self) -> Option<TimestampFormat> {
+        self.max_timestamp.map(TimestampFormat::EpochNunber)
+    }
+
+    /// The smallest box around all the nodes with locations
+    pub fn bbox(&self) -> Option<BBox> {
+        self.bbox
+    }
+
+    /// How many different users edited these objects. By uid, or by name for objects without one
+    pub fn num_users(&self) -> usize {
+        self.uids.len() + self.users.len()
+    }
+
+    /// How many different changesets these objects were edited in
+    pub fn num_changesets(&self) -> usize {
+        self.changesets.len()
+    }
+
+    /// How many nodes have tags
+    pub fn tagged_nodes(&self) -> u64 {
+        self.tagged_nodes
+    }
+
+    /// How many nodes have no tags
+    pub fn untagged_nodes(&self) -> u64 {
+        self.objects.nodes - self.tagged_nodes
+    }
+}
+
+/// A summary of all the objects of this reader: counts, id & timestamp ranges, the bbox of the
+/// nodes, & more. See `FileStats`.
+///
+/// ```
+/// use osmio::opl::OPLReader;
+/// use osmio::{OSMObjectType, OSMReader};
+///
+/// let input = "n1 c1 i1 ua Tamenity=bench x1 y2\nn5 c2 i1 ua x3 y-4\nw2 c2 i2 ub Nn1,n5\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let stats = osmio::stats(&mut reader).unwrap();
+/// assert_eq!(stats.objects().nodes, 2);
+/// assert_eq!(stats.max_id(OSMObjectType::Node), Some(5));
+/// assert_eq!(stats.num_users(), 2);
+/// assert_eq!(stats.bbox().unwrap().min_lat.degrees(), -4.);
+/// ```
+pub fn stats<R: DynReader + ?Sized>(reader: &mut R) -> Result<FileStats, ReadError> {
+    let mut stats = FileStats::new();
+    while let Some(obj) = reader.try_next_obj()? {
+        stats.add(&obj);
+    }
+    Ok(stats)
+}
+
+/// The bbox of all the nodes of this reader, calculated as they're read, and not from the file
+/// header (which can be missing, or wrong). `None` if no node has a location.
+///
+/// ```
+/// use osmio::opl::OPLReader;
+/// use osmio::{BBox, OSMReader};
+///
+/// let input = "n1 x1 y2\nn2 x3 y-4\nn3\nw4 Nn1,n2\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// assert_eq!(osmio::file_bbox(&mut reader).unwrap(), Some(BBox::new(-4., 1., 2., 3.)));
+/// ```
+pub fn file_bbox<R: DynReader + ?Sized>(reader: &mut R) -> Result<Option<BBox>, ReadError> {
+    let mut bbox: Option<BBox> = None;
+    while let Some(obj) = reader.try_next_obj()? {
+        if let Some((lat, lon)) = obj.as_node().and_then(|n| n.lat_lon()) {
+            bbox = Some(match bbox {
+                None => BBox::point(lat, lon),
+                Some(b) => b.extended(lat, lon),
+            });
+        }
+    }
+    Ok(bbox)
+}
+
+/// The edits of one user, see `UserStats`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserActivity {
+    pub uid: Option<u64>,
+    /// The user name, from the last edit with one
+    pub user: Option<String>,
+    /// How many objects (versions) they edited, by type
+    pub edits: Usage,
+    /// Unix epochs
+    min_timestamp: Option<i64>,
+    max_timestamp: Option<i64>,
+    bbox: Option<BBox>,
+    changesets: HashSet<u64>,
+}
+
+impl UserActivity {
+    fn new(uid: Option<u64>) -> Self {
+        UserActivity {
+            uid: uid,
+            user: None,
+            edits: Usage::default(),
+            min_timestamp: None,
+            max_timestamp: None,
+            bbox: None,
+            changesets: HashSet::new(),
+        }
+    }
+
+    fn add(&mut self, obj: &impl OSMObj) {
+        self.edits.add(obj.object_type());
+        if let Some(user) = obj.user() {
+            if self.user.as_deref() != Some(user) {
+                self.user = Some(user.to_string());
+            }
+        }
+        if let Some(t) = obj.timestamp().as_ref().and_then(|t| t.to_datetime()) {
+            let t = t.timestamp();
+            self.min_timestamp = Some(self.min_timestamp.map_or(t, |m| m.min(t)));
+            self.max_timestamp = Some(self.max_timestamp.map_or(t, |m| m.max(t)));
+        }
+        if let Some((lat, lon)) = obj.as_node().and_then(|n| n.lat_lon()) {
+            self.bbox = Some(match self.bbox {
+                None => BBox::point(lat, lon),
+                Some(b) => b.extended(lat, lon),
+            });
+        }
+        if let Some(c) = obj.changeset_id() {
+            self.changesets.insert(c);
+        }
+    }
+
+    /// The earliest (valid) timestamp of their edits
+    pub fn first_edit(&self) -> Option<TimestampFormat> {
+        self.min_timestamp.map(TimestampFormat::EpochNunber)
+    }
+
+    /// The latest (valid) timestamp of their edits
+    pub fn last_edit(&self) -> Option<TimestampFormat> {
+        self.max_timestamp.map(TimestampFormat::EpochNunber)
+    }
+
+    /// The bbox of the nodes (with a location) they edited
+    pub fn bbox(&self) -> Option<BBox> {
+        self.bbox
+    }
+
+    /// How many changesets their edits are in
+    pub fn num_changesets(&self) -> usize {
+        self.changesets.len()
+    }
+}
+
+/// Users are told apart by uid, or by name if there's no uid
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum UserKey {
+    Uid(u64),
+    Name(String),
+}
+
+/// Summarises the edits of each user in a stream of objects: how many of each type, when, where,
+/// & in how many changesets. Each object (version) is one edit, so it's most useful with
+/// full-history data. Objects without a uid or user name aren't counted for anyone.
+///
+/// ```
+/// use osmio::opl::OPLReader;
+/// use osmio::stats::UserStats;
+/// use osmio::OSMReader;
+///
+/// let input = "n1 c1 i1 ua t2020-01-01T00:00:00Z x1 y2\nn2 c2 i1 ua x3 y4\nw3 c3 i2 ub Nn1\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let mut stats = UserStats::new();
+/// stats.add_reader(&mut reader).unwrap();
+/// let a = stats.by_uid(1).unwrap();
+/// assert_eq!(a.user.as_deref(), Some("a"));
+/// assert_eq!(a.edits.nodes, 2);
+/// assert_eq!(a.num_changesets(), 2);
+/// assert_eq!(stats.users()[1].edits.ways, 1);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UserStats {
+    users: HashMap<UserKey, UserActivity>,
+    anonymous: u64,
+}
+
+impl UserStats {
+    pub fn new() -> Self {
+        UserStats::default()
+    }
+
+    /// Count this object
+    pub fn add(&mut self, obj: &impl OSMObj) {
+        let key = match (obj.uid(), obj.user()) {
+            (Some(uid), _) => UserKey::Uid(uid),
+            (None, Some(user)) => UserKey::Name(user.to_string()),
+            (None, None) => {
+                self.anonymous += 1;
+                return;
+            }
+        };
+        let uid = obj.uid();
+        self.users
+            .entry(key)
+            .or_insert_with(|| UserActivity::new(uid))
+            .add(obj);
+    }
+
+    /// Count all these objects
+    pub fn add_all<O: OSMObj>(&mut self, objs: impl IntoIterator<Item = O>) {
+        for obj in objs {
+            self.add(&obj);
+        }
+    }
+
+    /// Count all the objects of this reader
+    pub fn add_reader<R: DynReader + ?Sized>(&mut self, reader: &mut R) -> Result<(), ReadError> {
+        while let Some(obj) = reader.try_next_obj()? {
+            self.add(&obj);
+        }
+        Ok(())
+    }
+
+    /// The edits of the user with this uid
+    pub fn by_uid(&self, uid: u64) -> Option<&UserActivity> {
+        self.users.get(&UserKey::Uid(uid))
+    }
+
+    /// The edits of the user with this name. If several users had this name, any of them.
+    pub fn by_name(&self, user: &str) -> Option<&UserActivity> {
+        self.users
+            .get(&UserKey::Name(user.to_string()))
+            .or_else(|| {
+                self.users
+                    .values()
+                    .find(|a| a.user.as_deref() == Some(user))
+            })
+    }
+
+    /// Every user, most edits first, then by uid, then by name
+    pub fn users(&self) -> Vec<&UserActivity> {
+        let mut users: Vec<&UserActivity> = self.users.values().collect();
+        users.sort_by(|a, b| {
+            b.edits
+                .count
+                .cmp(&a.edits.count)
+                .then_with(|| a.uid.cmp(&b.uid))
+                .then_with(|| a.user.cmp(&b.user))
+        });
+        users
+    }
+
+    /// How many users there are
+    pub fn len(&self) -> usize {
+        self.users.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty()
+    }
+
+    /// How many objects had no uid or user name
+    pub fn anonymous(&self) -> u64 {
+        self.anonymous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::OPLReader;
+    use OSMReader;
+
+    #[test]
+    fn exact() {
+        let input = "n1 Tamenity=bench x1 y2\nn2 Tamenity=cafe,name=A x1 y2\nn3 x1 y2\n\
+                     w4 Tamenity=cafe,highway=path Nn1\nr5 Tname=A M\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut stats = TagStats::new();
+        stats.add_reader(&mut reader).unwrap();
+
+        assert_eq!(stats.objects().count, 5);
+        assert_eq!(stats.objects().nodes, 3);
+        assert_eq!(stats.tagged_objects().nodes, 2);
+        assert_eq!(
+            stats
+                .keys()
+                .iter()
+                .map(|(k, u)| (k.as_str(), u.count))
+                .collect::<Vec<_>>(),
+            vec![("amenity", 3), ("name", 2), ("highway", 1)]
+        );
+        assert_eq!(
+            stats.key("name"),
+            Some(&Usage {
+                count: 2,
+                nodes: 1,
+                ways: 0,
+                relations: 1
+            })
+        );
+        assert_eq!(
+            stats.tags()[0].0,
+            ("amenity".to_string(), "cafe".to_string())
+        );
+        assert_eq!(
+            stats.tag("amenity", "cafe").unwrap().of(OSMObjectType::Way),
+            1
+        );
+        assert_eq!(stats.tag("amenity", "pub"), None);
+        assert!(stats.is_exact());
+    }
+
+    #[test]
+    fn bounded() {
+        // the key `common` is on every object, then each has a different, rare, key
+        let input: String = (1..=100)
+            .map(|i| format!("n{} Tcommon=yes,rare{}=yes x1 y2\n", i, i))
+            .collect();
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut stats = TagStats::top_k(2);
+        stats.add_reader(&mut reader).unwrap();
+
+        let keys = stats.keys();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[0].0, "common");
+        assert_eq!(keys[0].1.count, 100);
+        assert!(stats.keys.counts.len() < 8);
+        assert!(!stats.is_exact());
+        assert_eq!(stats.max_error(), 1);
+    }
+
+    #[test]
+    fn file_stats() {
+        let input = "n3 c1 i1 ua t2010-01-01T00:00:00Z Tamenity=bench x1 y2\n\
+                     n1 c2 uanon t2012-01-01T00:00:00Z x-3 y4\nn7 x5 y-6\nn8 dD\n\
+                     w2 c2 i2 ub t2011-01-01T00:00:00Z Nn1,n3\nr9 c3 i1 ua M\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let stats = stats(&mut reader).unwrap();
+
+        assert_eq!(
+            *stats.objects(),
+            Usage {
+                count: 6,
+                nodes: 4,
+                ways: 1,
+                relations: 1
+            }
+        );
+        assert_eq!(stats.min_id(OSMObjectType::Node), Some(1));
+        assert_eq!(stats.max_id(OSMObjectType::Node), Some(8));
+        assert_eq!(stats.min_id(OSMObjectType::Relation), Some(9));
+        assert_eq!(
+            stats.min_timestamp().unwrap().to_iso_string(),
+            "2010-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            stats.max_timestamp().unwrap().to_iso_string(),
+            "2012-01-01T00:00:00Z"
+        );
+        assert_eq!(stats.bbox(), Some(BBox::new(-6., -3., 4., 5.)));
+        assert_eq!(stats.num_users(), 3);
+        assert_eq!(stats.num_changesets(), 3);
+        assert_eq!(stats.tagged_nodes(), 1);
+        assert_eq!(stats.untagged_nodes(), 3);
+
+        let empty = FileStats::new();
+        assert_eq!(empty.min_id(OSMObjectType::Way), None);
+        assert_eq!(empty.bbox(), None);
+        assert_eq!(empty.min_timestamp(), None);
+    }
+
+    #[test]
+    fn user_stats() {
+        let input = "n1 c1 i1 uold t2020-01-01T00:00:00Z x1 y2\nn1 c2 i1 unew t2021-01-01T00:00:00Z x3 y-4\n\
+                     w1 c2 i1 unew Nn1\nn2 c3 unouid x5 y6\nr1 c4 i2 ub M\nn3 x0 y0\n";
+        let mut stats = UserStats::new();
+        stats
+            .add_reader(&mut OPLReader::new(input.as_bytes()))
+            .unwrap();
+
+        assert_eq!(stats.len(), 3);
+        assert_eq!(stats.anonymous(), 1);
+        let users: Vec<_> = stats
+            .users()
+            .iter()
+            .map(|a| (a.uid, a.edits.count))
+            .collect();
+        assert_eq!(users, vec![(Some(1), 3), (None, 1), (Some(2), 1)]);
+
+        let new = stats.by_name("new").unwrap();
+        assert_eq!(new.uid, Some(1));
+        assert_eq!(
+            new.edits,
+            Usage {
+                count: 3,
+                nodes: 2,
+                ways: 1,
+                relations: 0
+            }
+        );
+        assert_eq!(new.first_edit().unwrap().to_epoch_number(), 1577836800);
+        assert_eq!(new.last_edit().unwrap().to_epoch_number(), 1609459200);
+        assert_eq!(new.bbox(), Some(BBox::new(-4., 1., 2., 3.)));
+        assert_eq!(new.num_changesets(), 2);
+        assert!(stats.by_name("old").is_none());
+
+        let no_uid = stats.by_name("nouid").unwrap();
+        assert_eq!(no_uid.uid, None);
+        assert_eq!(no_uid.first_edit(), None);
+        assert_eq!(stats.by_uid(2).unwrap().edits.relations, 1);
+    }
+}
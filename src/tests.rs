@@ -85,4 +85,846 @@ mod timestamp_format {
         TimestampFormat::EpochNunber(1577836800),
         Equal
     );
+
+    assert_cmp!(
+        isos_offset,
+        "2020-01-01T00:00:00Z".parse().unwrap(),
+        TimestampFormat::ISOString("2020-01-01T01:00:00+01:00".to_string()),
+        Equal
+    );
+    assert_cmp!(
+        isos_offset2,
+        TimestampFormat::ISOString("2020-01-01T00:30:00+01:00".to_string()),
+        TimestampFormat::ISOString("2020-01-01T00:00:00Z".to_string()),
+        Less
+    );
+    assert_cmp!(
+        invalid_iso,
+        TimestampFormat::ISOString("garbage".to_string()),
+        0.into(),
+        Greater
+    );
+
+    #[test]
+    fn total_order() {
+        let mut timestamps = vec![
+            TimestampFormat::ISOString("2020-01-01T00:00:00+00:00".to_string()),
+            TimestampFormat::ISOString("garbage".to_string()),
+            TimestampFormat::EpochNunber(1577836800),
+            TimestampFormat::ISOString("2020-01-01T00:00:00Z".to_string()),
+            TimestampFormat::ISOString("2019-12-31T23:00:00-02:00".to_string()),
+            TimestampFormat::EpochNunber(0),
+            TimestampFormat::ISOString("also garbage".to_string()),
+        ];
+        // Every pair agrees with the other way round, and with ==
+        for a in &timestamps {
+            for b in &timestamps {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                assert_eq!(a == b, a.cmp(b) == Equal);
+                assert_eq!(a.partial_cmp(b), Some(a.cmp(b)));
+            }
+        }
+        timestamps.sort();
+        assert_eq!(
+            timestamps
+                .iter()
+                .map(|t| t.to_iso_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "1970-01-01T00:00:00Z",
+                "also garbage",
+                "garbage",
+                "2020-01-01T00:00:00+00:00",
+                "2020-01-01T00:00:00Z",
+                "2020-01-01T00:00:00Z",
+                "2019-12-31T23:00:00-02:00",
+            ]
+        );
+    }
+
+    #[test]
+    fn outside_i32() {
+        let after_2038 = TimestampFormat::EpochNunber(4102444800);
+        assert_eq!(after_2038.to_iso_string(), "2100-01-01T00:00:00Z");
+        let before_1970 = TimestampFormat::EpochNunber(-315619200);
+        assert_eq!(before_1970.to_iso_string(), "1960-01-01T00:00:00Z");
+        assert_eq!(
+            TimestampFormat::ISOString("1960-01-01T00:00:00Z".to_string()).to_epoch_number(),
+            -315619200
+        );
+    }
+
+    #[test]
+    fn chrono() {
+        use chrono::{TimeZone, Utc};
+        let dt = Utc.with_ymd_and_hms(2005, 4, 9, 12, 30, 0).unwrap();
+        let ts = TimestampFormat::from_datetime(&dt);
+        assert_eq!(ts.to_iso_string(), "2005-04-09T12:30:00Z");
+        assert_eq!(ts.to_datetime(), Some(dt));
+        assert_eq!(
+            TimestampFormat::ISOString("2005-04-09T14:30:00+02:00".to_string()).to_datetime(),
+            Some(dt)
+        );
+        assert_eq!(
+            TimestampFormat::ISOString("garbage".to_string()).to_datetime(),
+            None
+        );
+    }
+}
+
+mod typed_iterators {
+    use super::*;
+    use opl::OPLReader;
+
+    const INPUT: &str = "n1\nn2\nw1\nn3\nw2\nr1\nw3\nr2\n";
+
+    #[test]
+    fn unsorted() {
+        let nodes: Vec<_> = OPLReader::new(INPUT.as_bytes())
+            .nodes()
+            .map(|n| n.id())
+            .collect();
+        assert_eq!(nodes, vec![1, 2, 3]);
+        let ways: Vec<_> = OPLReader::new(INPUT.as_bytes())
+            .ways()
+            .map(|w| w.id())
+            .collect();
+        assert_eq!(ways, vec![1, 2, 3]);
+        let relations: Vec<_> = OPLReader::new(INPUT.as_bytes())
+            .relations()
+            .map(|r| r.id())
+            .collect();
+        assert_eq!(relations, vec![1, 2]);
+    }
+
+    #[test]
+    fn sorted_assumption() {
+        let mut reader = OPLReader::new(INPUT.as_bytes());
+        reader.assume_sorted();
+        let ids: Vec<_> = reader.nodes().map(|n| n.id()).collect();
+        assert_eq!(ids, vec![1, 2]);
+        // w1 was consumed by nodes()
+        let ids: Vec<_> = reader.ways().map(|w| w.id()).collect();
+        assert_eq!(ids, vec![2]);
+        let ids: Vec<_> = reader.relations().map(|r| r.id()).collect();
+        assert_eq!(ids, vec![2]);
+    }
+}
+
+#[cfg(feature = "rayon")]
+mod par_objects {
+    use super::*;
+    use opl::OPLReader;
+    use rayon::iter::ParallelIterator;
+
+    #[test]
+    fn par_objects() {
+        let input: String = (1..=5000).map(|i| format!("n{} x1 y2\n", i)).collect();
+        let mut reader = OPLReader::new(input.as_bytes());
+        let sum: ObjId = reader.par_objects().map(|o| o.id()).sum();
+        assert_eq!(sum, 5000 * 5001 / 2);
+        assert!(reader.next().is_none());
+    }
+}
+
+mod members {
+    use super::*;
+    use obj_types::StringRelationBuilder;
+
+    #[test]
+    fn builder_and_tuples() {
+        let mut r = StringRelationBuilder::default()
+            ._id(1)
+            .member(Member::new(OSMObjectType::Node, 2, "stop"))
+            .member((OSMObjectType::Way, 3, "".to_string()))
+            .build()
+            .unwrap();
+        let members: Vec<_> = r.members().collect();
+        assert_eq!(members[0], Member::new(OSMObjectType::Node, 2, "stop"));
+        assert_eq!(members[1].mtype, OSMObjectType::Way);
+        assert_eq!(
+            members[1].to_owned(),
+            Member::new(OSMObjectType::Way, 3, String::new())
+        );
+
+        r.set_members(vec![(OSMObjectType::Relation, 4, "sub")]);
+        #[allow(deprecated)]
+        let tuples: Vec<_> = r.member_tuples().collect();
+        assert_eq!(tuples, vec![(OSMObjectType::Relation, 4, "sub")]);
+    }
+}
+
+mod tag_order {
+    use super::*;
+    use obj_types::StringNodeBuilder;
+    use opl::OPLReader;
+
+    #[test]
+    fn insertion_order() {
+        let mut n = StringNodeBuilder::default()._id(1).build().unwrap();
+        n.set_tag("name", "foo");
+        n.set_tag("amenity", "cafe");
+        n.set_tag("name", "bar");
+        assert_eq!(
+            n.tags().collect::<Vec<_>>(),
+            vec![("name", "bar"), ("amenity", "cafe")]
+        );
+        assert_eq!(n.tags_sorted(), vec![("amenity", "cafe"), ("name", "bar")]);
+
+        n.unset_tag("name");
+        assert_eq!(n.tags().collect::<Vec<_>>(), vec![("amenity", "cafe")]);
+    }
+
+    #[test]
+    fn read_order() {
+        let mut reader = OPLReader::new("n1 Tz=1,b=2,m=3 x1 y2\n".as_bytes());
+        let obj = reader.next().unwrap();
+        assert_eq!(
+            obj.tags().collect::<Vec<_>>(),
+            vec![("z", "1"), ("b", "2"), ("m", "3")]
+        );
+        assert_eq!(obj.tags_sorted(), vec![("b", "2"), ("m", "3"), ("z", "1")]);
+    }
+}
+
+mod coordinates {
+    use super::*;
+    use opl::OPLReader;
+
+    #[test]
+    fn fixed_point() {
+        let lat = Lat::from_degrees(51.5074456);
+        assert_eq!(lat.inner(), 515074456);
+        assert_eq!(lat.to_string(), "51.5074456");
+        assert_eq!(Lon::from(-0.25).to_string(), "-0.25");
+        assert_eq!(Lon::from_inner(-1).to_string(), "-0.0000001");
+        assert_eq!(Lat::from(0.).to_string(), "0");
+        assert_eq!("-12.3456789".parse::<Lon>().unwrap().inner(), -123456789);
+        assert_eq!(f64::from(Lat::from_inner(900000000)), 90.);
+    }
+
+    #[test]
+    fn full_precision() {
+        let mut reader = OPLReader::new("n1 x-179.9999999 y89.1234567\n".as_bytes());
+        let node = reader.next().unwrap().into_node().unwrap();
+        assert_eq!(node.lat(), Some(89.1234567));
+        assert_eq!(node.lon(), Some(-179.9999999));
+        assert_eq!(node.lat_lon().unwrap().1.to_string(), "-179.9999999");
+    }
+
+    #[test]
+    fn validation() {
+        let mut node = obj_types::StringNode::new(1, (0., 0.));
+        node.try_set_lat_lon(90., -180.).unwrap();
+        assert_eq!(node.lat_lon_f64(), Some((90., -180.)));
+        for &(lat, lon) in &[
+            (90.1, 0.),
+            (0., 180.5),
+            (std::f64::NAN, 0.),
+            (0., std::f64::INFINITY),
+        ] {
+            assert!(node.try_set_lat_lon(lat, lon).is_err(), "{} {}", lat, lon);
+        }
+        assert_eq!(node.lat_lon_f64(), Some((90., -180.)));
+
+        node.try_set_lat_lon_with(95., -200., CoordPolicy::Clamp)
+            .unwrap();
+        assert_eq!(node.lat_lon_f64(), Some((90., -180.)));
+        assert!(node
+            .try_set_lat_lon_with(std::f64::NAN, 0., CoordPolicy::Clamp)
+            .is_err());
+        assert!(!Lat::from_degrees(91.).is_valid());
+
+        let mut builder = obj_types::StringNodeBuilder::default();
+        builder._id(1);
+        assert!(builder.try_lat_lon(0., 181., CoordPolicy::Reject).is_err());
+        let node = builder
+            .try_lat_lon(1., 2., CoordPolicy::Reject)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(node.lat_lon_f64(), Some((1., 2.)));
+    }
+}
+
+#[test]
+fn large_changeset_and_uid() {
+    let mut reader = opl::OPLReader::new("n1 v1 c5000000000 i4294967296 x1 y2\n".as_bytes());
+    let node = reader.next().unwrap();
+    assert_eq!(node.changeset_id(), Some(5_000_000_000));
+    assert_eq!(node.uid(), Some(4_294_967_296));
+}
+
+#[cfg(feature = "serde")]
+mod serialization {
+    use super::*;
+    use obj_types::{StringNodeBuilder, StringOSMObj};
+
+    #[test]
+    fn round_trip() {
+        let node = StringNodeBuilder::default()
+            ._id(1)
+            ._version(2)
+            ._timestamp(TimestampFormat::EpochNunber(10))
+            ._tags(vec![("name".to_string(), "foo".to_string())])
+            ._lat_lon((Lat::from(1.5), Lon::from(-2.)))
+            .build()
+            .unwrap();
+        let json = serde_json::to_string(&node).unwrap();
+        assert!(json.contains("\"id\":1"));
+        assert!(json.contains("\"lat_lon\":[15000000,-20000000]"));
+
+        let obj: StringOSMObj = node.into();
+        let json = serde_json::to_string(&obj).unwrap();
+        assert_eq!(serde_json::from_str::<StringOSMObj>(&json).unwrap(), obj);
+
+        let json = serde_json::to_string(&OSMObjectType::Way).unwrap();
+        assert_eq!(
+            serde_json::from_str::<OSMObjectType>(&json).unwrap(),
+            OSMObjectType::Way
+        );
+    }
+}
+
+mod ordering {
+    use super::*;
+    use obj_types::{ArcOSMObj, StringOSMObj};
+    use opl::OPLReader;
+    use std::collections::{BTreeSet, HashSet};
+
+    fn objects() -> Vec<StringOSMObj> {
+        let input = "r1 v1 M\nw2 v1 Nn1,n2\nn5 v2 x1 y1\nn5 v1 x1 y1\nn1 v1 x2 y2\n";
+        OPLReader::new(input.as_bytes()).objects().collect()
+    }
+
+    #[test]
+    fn sort() {
+        let mut objs = objects();
+        objs.sort_unstable();
+        let keys: Vec<_> = objs
+            .iter()
+            .map(|o| (o.object_type(), o.id(), o.version()))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (OSMObjectType::Node, 1, Some(1)),
+                (OSMObjectType::Node, 5, Some(1)),
+                (OSMObjectType::Node, 5, Some(2)),
+                (OSMObjectType::Way, 2, Some(1)),
+                (OSMObjectType::Relation, 1, Some(1)),
+            ]
+        );
+
+        let arcs: BTreeSet<ArcOSMObj> = objs.iter().cloned().map(Into::into).collect();
+        assert_eq!(arcs.iter().next().unwrap().id(), 1);
+    }
+
+    #[test]
+    fn hash() {
+        let mut set: HashSet<StringOSMObj> = objects().into_iter().collect();
+        assert_eq!(set.len(), 5);
+        set.extend(objects());
+        assert_eq!(set.len(), 5);
+    }
+}
+
+mod impl_macros {
+    use super::*;
+
+    #[derive(PartialEq, Debug, Clone)]
+    struct MyWay {
+        id: ObjId,
+        version: Option<u32>,
+        user: Option<String>,
+        tags: Vec<(String, String)>,
+        nodes: Vec<ObjId>,
+    }
+
+    impl_way!(MyWay {
+        id: id,
+        version: version,
+        user: user,
+        tags: tags,
+        nodes: nodes,
+    });
+
+    #[test]
+    fn way() {
+        let mut w = MyWay {
+            id: 1,
+            version: None,
+            user: None,
+            tags: vec![],
+            nodes: vec![],
+        };
+        w.set_version(3);
+        w.set_user("foo");
+        w.set_uid(10);
+        w.set_nodes(vec![1, 2, 3]);
+        w.set_tag("highway", "road");
+        assert_eq!(w.version(), Some(3));
+        assert_eq!(w.user(), Some("foo"));
+        assert_eq!(w.uid(), None);
+        assert_eq!(w.timestamp(), &None);
+        assert_eq!(w.node(1), Some(2));
+        assert!(w.semantic_eq(&w.clone()));
+
+        let mut other = w.clone();
+        other.set_nodes(vec![3, 2, 1]);
+        assert!(!w.semantic_eq(&other));
+    }
+}
+
+mod builders {
+    use super::*;
+    use obj_types::{
+        StringNode, StringNodeBuilder, StringRelation, StringRelationBuilder, StringWay,
+        StringWayBuilder,
+    };
+
+    #[test]
+    fn validation() {
+        assert!(StringNodeBuilder::default()._id(0).build().is_err());
+        assert!(StringWayBuilder::default()._id(0).build().is_err());
+        assert!(StringRelationBuilder::default()._id(0).build().is_err());
+        assert!(StringWayBuilder::default()._id(-1).build().is_ok());
+
+        let node = |lat: f64, lon: f64| {
+            StringNodeBuilder::default()
+                ._id(1)
+                ._lat_lon((Lat::from(lat), Lon::from(lon)))
+                .build()
+        };
+        assert!(node(90., -180.).is_ok());
+        assert!(node(90.1, 0.).is_err());
+        assert!(node(0., 180.0000001).is_err());
+    }
+
+    #[test]
+    fn constructors() {
+        let n = StringNode::new(1, (51.5, -0.1));
+        assert_eq!(n.lat_lon_f64(), Some((51.5, -0.1)));
+        let w = StringWay::new(2, vec![1, 2]);
+        assert_eq!(w.nodes(), &[1, 2]);
+        let r = StringRelation::new(3, vec![(OSMObjectType::Way, 2, "outer")]);
+        assert_eq!(
+            r.members().next(),
+            Some(Member::new(OSMObjectType::Way, 2, "outer"))
+        );
+    }
+}
+
+#[test]
+fn set_tags() {
+    use obj_types::{RcOSMObj, StringWayBuilder};
+
+    let w = StringWayBuilder::default()
+        ._id(1)
+        .tags([("highway", "residential"), ("name", "Main Street")])
+        .build()
+        .unwrap();
+    assert_eq!(w.tag("highway"), Some("residential"));
+    assert_eq!(w.num_tags(), 2);
+
+    let mut rc: RcOSMObj = obj_types::StringOSMObj::Way(w).into();
+    rc.set_tags(vec![("oneway", "yes".to_string())]);
+    assert_eq!(rc.tags().collect::<Vec<_>>(), vec![("oneway", "yes")]);
+}
+
+#[test]
+fn visit() {
+    let input = "n1 x1 y2\nw2 Nn1,n3,n4\nr3 Mn1@,w2@\n";
+    let objs: Vec<_> = opl::OPLReader::new(input.as_bytes()).objects().collect();
+    let sizes: Vec<usize> = objs
+        .iter()
+        .map(|o| o.visit_ref(|_| 1, |w| w.num_nodes(), |r| r.members().len()))
+        .collect();
+    assert_eq!(sizes, vec![1, 3, 2]);
+
+    let ids: Vec<ObjId> = objs
+        .into_iter()
+        .map(|o| o.visit(|n| n.id(), |w| w.id() * 10, |r| r.id() * 100))
+        .collect();
+    assert_eq!(ids, vec![1, 20, 300]);
+}
+
+mod batch_writing {
+    use super::*;
+    use opl::{OPLReader, OPLWriter};
+    use std::io::{self, Write};
+
+    const INPUT: &str = "n1 v1 dV c0 i0 u T x1 y2\nw2 v1 dV c0 i0 u T Nn1\n";
+
+    /// Accepts `n` bytes, then fails
+    struct Full(usize);
+
+    impl Write for Full {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.0 {
+                return Err(io::Error::new(io::ErrorKind::WriteZero, "full"));
+            }
+            self.0 -= buf.len();
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn try_from_iter() {
+        let mut reader = OPLReader::new(INPUT.as_bytes());
+        let writer = OPLWriter::try_from_iter(Vec::new(), reader.objects()).unwrap();
+        assert_eq!(String::from_utf8(writer.into_inner()).unwrap(), INPUT);
+
+        let mut reader = OPLReader::new(INPUT.as_bytes());
+        match OPLWriter::try_from_iter(Full(40), reader.objects()) {
+            Err(OSMWriteError::OPLWrite(e)) => assert_eq!(e.kind(), io::ErrorKind::WriteZero),
+            Err(e) => panic!("wrong error {:?}", e),
+            Ok(_) => panic!("write should have failed"),
+        }
+    }
+
+    /// Counts the writes to it
+    #[derive(Default)]
+    struct CountWrites {
+        writes: usize,
+        bytes: Vec<u8>,
+    }
+
+    impl Write for CountWrites {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+            self.bytes.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn buffered() {
+        let input = INPUT.repeat(500);
+        let objs: Vec<_> = OPLReader::new(input.as_bytes()).objects().collect();
+        let options = WriterOptions {
+            buffer_size: 1 << 20,
+            ..Default::default()
+        };
+
+        // One write for everything, when it's closed
+        let mut writer = OPLWriter::new_with_options(CountWrites::default(), &options);
+        writer.write_objs(objs.iter().cloned()).unwrap();
+        writer.close().unwrap();
+        let output = writer.into_inner();
+        assert_eq!(output.writes, 1);
+        assert_eq!(output.bytes, input.as_bytes());
+
+        let mut writer = xml::XMLWriter::new_with_options(CountWrites::default(), &options);
+        writer.write_objs(objs.iter().cloned()).unwrap();
+        assert_eq!(writer.get_mut().writes, 0);
+        writer.close().unwrap();
+        assert_eq!(writer.get_mut().writes, 1);
+
+        // `new` collects 8 KiB at a time, not a write per object
+        let mut writer = OPLWriter::new(CountWrites::default());
+        writer.write_objs(objs.iter().cloned()).unwrap();
+        writer.close().unwrap();
+        let output = writer.into_inner();
+        assert!(output.writes <= input.len() / 8000 + 1);
+        assert_eq!(output.bytes, input.as_bytes());
+    }
+
+    #[test]
+    fn write_objs() {
+        let objs: Vec<_> = OPLReader::new(INPUT.as_bytes()).objects().collect();
+        let mut writer = OPLWriter::new(Vec::new());
+        writer.write_objs(objs.iter().cloned()).unwrap();
+        writer.close().unwrap();
+        assert!(writer.write_objs(objs.iter().cloned()).is_err());
+
+        let mut writer: Box<dyn DynWriter> = Box::new(OPLWriter::new(Vec::new()));
+        writer.write_string_objs(&objs).unwrap();
+        writer.finish().unwrap();
+        assert!(writer.write_string_objs(&objs).is_err());
+    }
+}
+
+/// Every reader & writer works boxed, through the object safe traits
+#[test]
+fn dyn_readers_and_writers() {
+    use obj_types::StringOSMObj;
+
+    const INPUT: &str = "n1 v1 dV c0 t2020-01-01T00:00:00Z i0 u T x1 y2\n\
+                         w2 v1 dV c0 t2020-01-01T00:00:00Z i0 u T Nn1\n";
+    let objs: Vec<StringOSMObj> = opl::OPLReader::new(INPUT.as_bytes()).objects().collect();
+
+    fn read_all(mut reader: Box<dyn DynReader + '_>) -> (Option<Header>, Vec<StringOSMObj>) {
+        let header = reader.file_header().unwrap();
+        let mut objs = Vec::new();
+        while let Some(obj) = reader.try_next_obj().unwrap() {
+            objs.push(obj);
+        }
+        (header, objs)
+    }
+
+    for format in &["opl", "xml", "osc"] {
+        let mut output = Vec::new();
+        {
+            let mut writer: Box<dyn DynWriter> = match *format {
+                "opl" => Box::new(opl::OPLWriter::new(&mut output)),
+                "xml" => Box::new(xml::XMLWriter::new(&mut output)),
+                _ => Box::new(osc::OSCWriter::new(&mut output)),
+            };
+            for obj in &objs {
+                writer.write_string_obj(obj).unwrap();
+            }
+            writer.finish().unwrap();
+            assert!(writer.write_string_obj(&objs[0]).is_err());
+        }
+        let reader: Box<dyn DynReader> = match *format {
+            "opl" => Box::new(opl::OPLReader::new(output.as_slice())),
+            "xml" => Box::new(xml::XMLReader::new(output.as_slice())),
+            _ => Box::new(osc::OSCReader::new(output.as_slice())),
+        };
+        let (header, read) = read_all(reader);
+        assert_eq!(read, objs, "{}", format);
+        let expected = match *format {
+            "opl" => opl::OPLReader::new(output.as_slice()).try_header(),
+            "xml" => xml::XMLReader::new(output.as_slice()).try_header(),
+            _ => osc::OSCReader::new(output.as_slice()).try_header(),
+        };
+        assert_eq!(header, expected.unwrap(), "{}", format);
+    }
+
+    let file = pbf::tests::pbf_file(vec![pbf::tests::way_block(1)]);
+    let (header, read) = read_all(Box::new(pbf::PBFReader::new(file.as_slice())));
+    assert_eq!(header, None);
+    let expected: Vec<StringOSMObj> = pbf::PBFReader::new(file.as_slice())
+        .objects()
+        .map(|o| o.into())
+        .collect();
+    assert_eq!(read, expected);
+}
+
+#[test]
+fn set_headers() {
+    let header = Header {
+        generator: Some("test".to_string()),
+        timestamp: Some(700.into()),
+        replication_sequence_number: Some(12),
+        required_features: vec!["DenseNodes".to_string()],
+        other: vec![("upload".to_string(), "false".to_string())],
+        ..Default::default()
+    };
+    let mut copy = Header::default();
+    for field in header.fields() {
+        copy.set(field);
+    }
+    assert_eq!(copy.required_features, Vec::<String>::new());
+    copy.required_features = header.required_features.clone();
+    assert_eq!(copy, header);
+
+    // XML has nowhere for the sequence number
+    let mut output = Vec::new();
+    let mut writer = xml::XMLWriter::new(&mut output);
+    writer.set_headers(&header).unwrap();
+    writer.close().unwrap();
+    drop(writer);
+    let output = String::from_utf8(output).unwrap();
+    assert!(output.contains(
+        "<osm version=\"0.6\" generator=\"test\" timestamp=\"1970-01-01T00:11:40Z\" upload=\"false\">"
+    ));
+
+    let mut writer = opl::OPLWriter::new(Vec::new());
+    writer.set_headers(&header).unwrap();
+    assert!(writer
+        .set_header(HeaderField::Generator("test".to_string()))
+        .is_err());
+}
+
+#[test]
+fn readers_are_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<pbf::PBFReader<std::fs::File>>();
+    assert_send::<xml::XMLReader<std::fs::File>>();
+    assert_send::<osc::OSCReader<std::fs::File>>();
+    assert_send::<opl::OPLReader<std::fs::File>>();
+
+    // decode on one thread, process on another
+    let (sender, receiver) = std::sync::mpsc::sync_channel(10);
+    let mut reader = xml::XMLReader::new(
+        "<osm><node id=\"1\" lat=\"1\" lon=\"2\"/><node id=\"2\" lat=\"1\" lon=\"2\"/></osm>"
+            .as_bytes(),
+    );
+    let decoder = std::thread::spawn(move || {
+        for obj in reader.objects() {
+            sender.send(obj).unwrap();
+        }
+    });
+    let ids: Vec<ObjId> = receiver.iter().map(|o| o.id()).collect();
+    decoder.join().unwrap();
+    assert_eq!(ids, vec![1, 2]);
+}
+
+#[test]
+fn way_editing() {
+    fn edit<W: Way>(mut way: W) {
+        way.push_node(4);
+        way.insert_node(0, 1);
+        assert_eq!(way.nodes(), [1, 2, 3, 4]);
+        assert_eq!(way.remove_node(1), Some(2));
+        assert_eq!(way.remove_node(3), None);
+        way.push_node(1);
+        assert_eq!(way.replace_node(1, 5), 2);
+        assert_eq!(way.replace_node(1, 5), 0);
+        way.reverse();
+        assert_eq!(way.nodes(), [5, 4, 3, 5]);
+    }
+    let way = obj_types::StringWay::new(1, vec![2, 3]);
+    edit(way.clone());
+    edit(obj_types::ArcWay::from(way.clone()));
+    edit(std::borrow::Cow::Borrowed(&way));
+}
+
+#[test]
+fn tag_items() {
+    let mut way = obj_types::StringWay::new(1, vec![1, 2]);
+    way.set_tags(vec![("oneway", "yes"), ("lanes", "2"), ("bridge", "no")]);
+    let yes: Vec<&str> = way
+        .tag_items()
+        .filter(|t| t.is_yes())
+        .map(|t| t.key)
+        .collect();
+    assert_eq!(yes, vec!["oneway"]);
+    let lanes = way.tag_items().find(|t| t.key == "lanes").unwrap();
+    assert_eq!(lanes.parse::<u32>(), Ok(2));
+    assert!(lanes.parse::<bool>().is_err());
+    assert!(way.tag_items().any(|t| t.is_no()));
+    assert_eq!(lanes.to_string(), "lanes=2");
+    assert!(way.tags_iter().map(Tag::from).eq(way.tag_items()));
+    let tuple: (&str, &str) = lanes.into();
+    assert_eq!(tuple, ("lanes", "2"));
+}
+
+#[test]
+fn bulk_tag_editing() {
+    fn edit<O: OSMObjBase>(mut obj: O) {
+        obj.retain_tags(|k, _| k != "created_by");
+        assert!(!obj.has_tag("created_by"));
+        assert!(obj.rename_tag_key("phone", "contact:phone"));
+        assert!(!obj.rename_tag_key("phone", "contact:phone"));
+        assert_eq!(obj.tag("contact:phone"), Some("+1 234"));
+        obj.merge_tags(
+            vec![("name", "B"), ("shop", "bakery")],
+            OverwritePolicy::Keep,
+        );
+        assert_eq!(obj.tag("name"), Some("A"));
+        assert_eq!(obj.tag("shop"), Some("bakery"));
+        obj.merge_tags(vec![("name", "B")], OverwritePolicy::Overwrite);
+        assert_eq!(
+            obj.tags_sorted(),
+            vec![
+                ("contact:phone", "+1 234"),
+                ("name", "B"),
+                ("shop", "bakery")
+            ]
+        );
+    }
+    let mut node = obj_types::StringNode::new(1, (0., 0.));
+    node.set_tags(vec![
+        ("name", "A"),
+        ("created_by", "JOSM"),
+        ("phone", "+1 234"),
+    ]);
+    edit(node.clone());
+    edit(obj_types::ArcNode::from(node.clone()));
+    edit(std::borrow::Cow::Borrowed(&node));
+
+    let mut cow = std::borrow::Cow::Borrowed(&node);
+    cow.retain_tags(|_, _| true);
+    cow.merge_tags(vec![("name", "B")], OverwritePolicy::Keep);
+    assert!(!cow.rename_tag_key("shop", "craft"));
+    assert!(match cow {
+        std::borrow::Cow::Borrowed(_) => true,
+        std::borrow::Cow::Owned(_) => false,
+    });
+}
+
+#[test]
+fn relation_editing() {
+    fn edit<R: Relation>(mut rel: R) {
+        rel.push_member((OSMObjectType::Way, 3, "inner"));
+        rel.push_member(Member::new(OSMObjectType::Node, 1, "label"));
+        assert_eq!(rel.members().len(), 3);
+        assert_eq!(
+            rel.remove_member(0),
+            Some(Member::new(OSMObjectType::Way, 2, "outer".to_string()))
+        );
+        assert_eq!(rel.remove_member(2), None);
+        rel.push_member((OSMObjectType::Way, 3, "outer"));
+        assert_eq!(rel.replace_member_id(OSMObjectType::Way, 3, 4), 2);
+        assert_eq!(rel.replace_member_id(OSMObjectType::Relation, 1, 4), 0);
+        rel.retain_members(|m| m.role != "label");
+        assert_eq!(
+            rel.members().collect::<Vec<_>>(),
+            vec![
+                Member::new(OSMObjectType::Way, 4, "inner"),
+                Member::new(OSMObjectType::Way, 4, "outer"),
+            ]
+        );
+    }
+    let rel = obj_types::StringRelation::new(1, vec![(OSMObjectType::Way, 2, "outer")]);
+    edit(rel.clone());
+    edit(obj_types::ArcRelation::from(rel.clone()));
+    edit(obj_types::RcRelation::from(rel.clone()));
+    let mut interned = obj_types::InternedRelation::new_in(&Default::default(), 1);
+    interned.set_members(rel.members());
+    edit(interned);
+    edit(std::borrow::Cow::Borrowed(&rel));
+}
+
+#[test]
+fn bboxes() {
+    use nodestore::{LocationStore, WayStore};
+    use std::collections::HashMap;
+
+    let mut locations = LocationStore::new();
+    locations.set(1, (Lat::from(1.), Lon::from(2.))).unwrap();
+    locations.set(2, (Lat::from(-3.), Lon::from(4.))).unwrap();
+    locations.set(3, (Lat::from(5.), Lon::from(-6.))).unwrap();
+
+    let way = obj_types::StringWay::new(1, vec![1, 2, 99]);
+    assert_eq!(way.bbox(&locations), Some(BBox::new(-3., 2., 1., 4.)));
+    assert_eq!(
+        obj_types::StringWay::new(2, vec![99]).bbox(&locations),
+        None
+    );
+    let map: HashMap<ObjId, (Lat, Lon)> = locations.iter().collect();
+    assert_eq!(way.bbox(&map), way.bbox(&locations));
+
+    let rel = obj_types::StringRelation::new(
+        1,
+        vec![
+            (OSMObjectType::Node, 3, ""),
+            (OSMObjectType::Way, 1, ""),
+            (OSMObjectType::Relation, 2, ""),
+        ],
+    );
+    assert_eq!(
+        rel.bbox(&locations),
+        Some(BBox::point(Lat::from(5.), Lon::from(-6.)))
+    );
+    let mut ways = WayStore::new();
+    ways.add_way(&way);
+    assert_eq!(
+        rel.bbox_with_ways(&locations, &ways),
+        Some(BBox::new(-3., -6., 5., 4.))
+    );
+    assert_eq!(
+        BBox::new(0., 0., 1., 1.).union(&BBox::new(-1., 0.5, 0.5, 2.)),
+        BBox::new(-1., 0., 1., 2.)
+    );
+    assert_eq!(BBox::around(vec![]), None);
 }
@@ -0,0 +1,1216 @@
+//! Change objects on their way from a reader, or to a writer.
+//!
+//! Every transformation (& filter) here is also an `ObjTransform`, so they can be combined with
+//! `then`, & your own stages (`from_fn`), then used on an iterator or writer.
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+
+use filter::TagFilter;
+use formats::{read_from_path, OpenError};
+use idmap::IdMap;
+use obj_types::StringOSMObj;
+use utils::type_idx;
+use {
+    DynWriter, HeaderField, Node, OSMObj, OSMObjBase, OSMObjectType, OSMWriteError, OSMWriter,
+    ObjId, ReadError, Relation, TimestampFormat, Way, COORD_PRECISION,
+};
+
+/// Seconds in a day
+const DAY: i64 = 24 * 60 * 60;
+
+/// Removes personal data from objects, to publish derived datasets. Use it on objects
+/// directly (`anonymize`), on an iterator of objects (`iter`), or as a writer wrapper
+/// (`writer`).
+///
+/// ```
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::transform::Anonymizer;
+/// use osmio::{OSMReader, OSMWriter};
+///
+/// let input = "n1 v3 dV c4 t2020-01-01T12:34:56Z i5 uuser T x1 y2\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let anonymizer = Anonymizer::default();
+/// let objs: Vec<_> = anonymizer.iter(reader.objects()).collect();
+///
+/// let mut writer = anonymizer.writer(OPLWriter::new(Vec::new()));
+/// writer.write_objs(objs).unwrap();
+/// writer.close().unwrap();
+/// assert_eq!(
+///     String::from_utf8(writer.into_inner()).unwrap(),
+///     "n1 v3 dV t2020-01-01T00:00:00Z T x1 y2\n"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anonymizer {
+    /// Remove the uid, user & changeset id. Default: true
+    pub strip_metadata: bool,
+    /// Round timestamps down to the start of the (UTC) day. Invalid timestamps are removed.
+    /// Default: true
+    pub coarsen_timestamps: bool,
+    /// Remove the version. Default: false
+    pub strip_version: bool,
+}
+
+impl Default for Anonymizer {
+    fn default() -> Self {
+        Anonymizer {
+            strip_metadata: true,
+            coarsen_timestamps: true,
+            strip_version: false,
+        }
+    }
+}
+
+impl Anonymizer {
+    /// Anonymize this object
+    pub fn anonymize(&self, obj: &mut impl OSMObjBase) {
+        if self.strip_metadata {
+            obj.strip_metadata();
+        }
+        if self.coarsen_timestamps && obj.timestamp().is_some() {
+            let day = obj
+                .timestamp()
+                .as_ref()
+                .and_then(|t| t.to_datetime())
+                .map(|t| {
+                    TimestampFormat::EpochNunber(t.timestamp() - t.timestamp().rem_euclid(DAY))
+                });
+            obj.set_timestamp(day);
+        }
+        if self.strip_version {
+            obj.set_version(None);
+        }
+    }
+
+    /// Anonymize all the objects of this iterator
+    pub fn iter<I>(&self, iter: I) -> Anonymized<I>
+    where
+        I: Iterator,
+        I::Item: OSMObjBase,
+    {
+        Anonymized {
+            inner: iter,
+            anonymizer: self.clone(),
+        }
+    }
+
+    /// Wrap this writer, so every object written to it is anonymized
+    pub fn writer<O>(&self, writer: O) -> Anonymize<O> {
+        Anonymize {
+            inner: writer,
+            anonymizer: self.clone(),
+        }
+    }
+
+    fn apply<T: OSMObjBase>(&self, obj: &T) -> T {
+        let mut obj = obj.clone();
+        self.anonymize(&mut obj);
+        obj
+    }
+}
+
+/// Iterator of anonymized objects, see `Anonymizer::iter`
+pub struct Anonymized<I> {
+    inner: I,
+    anonymizer: Anonymizer,
+}
+
+impl<I> Iterator for Anonymized<I>
+where
+    I: Iterator,
+    I::Item: OSMObjBase,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let mut obj = self.inner.next()?;
+        self.anonymizer.anonymize(&mut obj);
+        Some(obj)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// Implement `OSMWriter` & `DynWriter` for a wrapper of another writer (`inner`), which writes
+/// `self.anonymizer.apply(obj)` instead of each object
+macro_rules! impl_wrapped_writer {
+    ($ty:ident) => {
+        impl<W: Write, O: OSMWriter<W>> OSMWriter<W> for $ty<O> {
+            fn new(writer: W) -> Self {
+                $ty::from_writer(O::new(writer))
+            }
+
+            fn close(&mut self) -> Result<(), OSMWriteError> {
+                self.inner.close()
+            }
+
+            fn is_open(&self) -> bool {
+                self.inner.is_open()
+            }
+
+            fn write_obj(&mut self, obj: &impl OSMObj) -> Result<(), OSMWriteError> {
+                let obj = self.anonymizer.apply(obj);
+                self.inner.write_obj(&obj)
+            }
+
+            fn into_inner(self) -> W {
+                self.inner.into_inner()
+            }
+
+            fn set_header(&mut self, field: HeaderField) -> Result<(), OSMWriteError> {
+                self.inner.set_header(field)
+            }
+        }
+
+        impl<O: DynWriter> DynWriter for $ty<O> {
+            fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+                let obj = self.anonymizer.apply(obj);
+                self.inner.write_string_obj(&obj)
+            }
+
+            fn finish(&mut self) -> Result<(), OSMWriteError> {
+                self.inner.finish()
+            }
+        }
+
+        impl<O> $ty<O> {
+            /// The wrapped writer
+            pub fn writer(&self) -> &O {
+                &self.inner
+            }
+
+            /// Convert back to the wrapped writer
+            pub fn into_writer(self) -> O {
+                self.inner
+            }
+        }
+    };
+}
+
+/// A writer which anonymizes every object before writing it, see `Anonymizer::writer`. Created
+/// with `OSMWriter::new` or `from_writer`, it uses the default `Anonymizer`.
+pub struct Anonymize<O> {
+    inner: O,
+    anonymizer: Anonymizer,
+}
+
+impl<O> Anonymize<O> {
+    /// Wrap this writer, with the default `Anonymizer`
+    pub fn from_writer(inner: O) -> Self {
+        Anonymizer::default().writer(inner)
+    }
+}
+
+impl_wrapped_writer!(Anonymize);
+
+/// A writer which removes the metadata from every object before writing it, see
+/// `OSMObjBase::strip_metadata`. Optionally the version is removed too.
+///
+/// ```
+/// use osmio::opl::OPLWriter;
+/// use osmio::transform::StripMetadata;
+/// use osmio::OSMWriter;
+///
+/// let writer: StripMetadata<OPLWriter<Vec<u8>>> = StripMetadata::new(Vec::new());
+/// let writer = StripMetadata::from_writer(OPLWriter::new(Vec::new())).strip_version(true);
+/// ```
+pub struct StripMetadata<O> {
+    inner: O,
+    anonymizer: Anonymizer,
+}
+
+impl<O> StripMetadata<O> {
+    /// Wrap this writer
+    pub fn from_writer(inner: O) -> Self {
+        StripMetadata {
+            inner: inner,
+            anonymizer: Anonymizer {
+                strip_metadata: true,
+                coarsen_timestamps: false,
+                strip_version: false,
+            },
+        }
+    }
+
+    /// Also remove the version of every object. Default: false
+    pub fn strip_version(mut self, strip_version: bool) -> Self {
+        self.anonymizer.strip_version = strip_version;
+        self
+    }
+}
+
+impl_wrapped_writer!(StripMetadata);
+
+/// Renumbers the objects, so the ids of each type are a dense sequence starting at 1 (or
+/// another start), in the order they're first seen. Way nodes & relation members are changed
+/// to match, so ids can be renumbered in one pass, even when a relation is a member before
+/// it's seen. The old to new ids are kept in an `IdMap`.
+///
+/// ```
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::transform::Renumber;
+/// use osmio::{OSMReader, OSMWriter};
+///
+/// let input = "n100 x1 y2\nn50 x1 y2\nw7 Nn50,n100\nr9 Mr8@,w7@\nr8 M\n";
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let mut writer = Renumber::new().writer(OPLWriter::new(Vec::new()));
+/// writer.write_objs(reader.objects()).unwrap();
+/// writer.close().unwrap();
+/// assert_eq!(
+///     String::from_utf8(writer.into_inner()).unwrap(),
+///     "n1 dV T x1 y2\nn2 dV T x1 y2\nw1 dV T Nn2,n1\nr1 dV T Mr2@,w1@\nr2 dV T M\n"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Renumber {
+    map: IdMap,
+    /// The next new id of each type
+    next: [ObjId; 3],
+}
+
+impl Default for Renumber {
+    fn default() -> Self {
+        Renumber::new()
+    }
+}
+
+impl Renumber {
+    /// New ids start at 1
+    pub fn new() -> Self {
+        Renumber::starting_at(1, 1, 1)
+    }
+
+    /// The first new node, way & relation ids
+    pub fn starting_at(node: ObjId, way: ObjId, relation: ObjId) -> Self {
+        Renumber {
+            map: IdMap::new(),
+            next: [node, way, relation],
+        }
+    }
+
+    /// The new id of this object, given a new one if it doesn't have one yet
+    pub fn new_id(&mut self, otype: OSMObjectType, old: ObjId) -> ObjId {
+        if let Some(new) = self.map.get(otype, old) {
+            return new;
+        }
+        let new = self.next[type_idx(otype)];
+        self.next[type_idx(otype)] += 1;
+        self.map.insert(otype, old, new);
+        new
+    }
+
+    /// Renumber this object, and the nodes & members it refers to
+    pub fn renumber(&mut self, obj: &mut impl OSMObj) {
+        self.new_id(obj.object_type(), obj.id());
+        if let Some(way) = obj.as_way() {
+            for &n in way.nodes() {
+                self.new_id(OSMObjectType::Node, n);
+            }
+        }
+        if let Some(rel) = obj.as_relation() {
+            for m in rel.members() {
+                self.new_id(m.mtype, m.id);
+            }
+        }
+        self.map.apply(obj);
+    }
+
+    /// The old to new ids so far
+    pub fn map(&self) -> &IdMap {
+        &self.map
+    }
+
+    /// Convert to the old to new ids
+    pub fn into_map(self) -> IdMap {
+        self.map
+    }
+
+    /// Renumber all the objects of this iterator
+    pub fn iter<I>(self, iter: I) -> Renumbered<I>
+    where
+        I: Iterator,
+        I::Item: OSMObj,
+    {
+        Renumbered {
+            inner: iter,
+            renumber: self,
+        }
+    }
+
+    /// Wrap this writer, so every object written to it is renumbered
+    pub fn writer<O>(self, writer: O) -> RenumberWriter<O> {
+        RenumberWriter {
+            inner: writer,
+            renumber: self,
+        }
+    }
+}
+
+/// Iterator of renumbered objects, see `Renumber::iter`
+pub struct Renumbered<I> {
+    inner: I,
+    renumber: Renumber,
+}
+
+impl<I> Renumbered<I> {
+    /// The ids renumbered so far
+    pub fn renumber(&self) -> &Renumber {
+        &self.renumber
+    }
+}
+
+impl<I> Iterator for Renumbered<I>
+where
+    I: Iterator,
+    I::Item: OSMObj,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        let mut obj = self.inner.next()?;
+        self.renumber.renumber(&mut obj);
+        Some(obj)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+/// A writer which renumbers every object before writing it, see `Renumber::writer`. Created
+/// with `OSMWriter::new`, ids start at 1.
+pub struct RenumberWriter<O> {
+    inner: O,
+    renumber: Renumber,
+}
+
+impl<O> RenumberWriter<O> {
+    /// The ids renumbered so far
+    pub fn renumber(&self) -> &Renumber {
+        &self.renumber
+    }
+
+    /// The wrapped writer
+    pub fn writer(&self) -> &O {
+        &self.inner
+    }
+
+    /// Convert back to the wrapped writer, and the renumbered ids
+    pub fn into_parts(self) -> (O, Renumber) {
+        (self.inner, self.renumber)
+    }
+}
+
+impl<W: Write, O: OSMWriter<W>> OSMWriter<W> for RenumberWriter<O> {
+    fn new(writer: W) -> Self {
+        Renumber::new().writer(O::new(writer))
+    }
+
+    fn close(&mut self) -> Result<(), OSMWriteError> {
+        self.inner.close()
+    }
+
+    fn is_open(&self) -> bool {
+        self.inner.is_open()
+    }
+
+    fn write_obj(&mut self, obj: &impl OSMObj) -> Result<(), OSMWriteError> {
+        let mut obj = obj.clone();
+        self.renumber.renumber(&mut obj);
+        self.inner.write_obj(&obj)
+    }
+
+    fn into_inner(self) -> W {
+        self.inner.into_inner()
+    }
+
+    fn set_header(&mut self, field: HeaderField) -> Result<(), OSMWriteError> {
+        self.inner.set_header(field)
+    }
+}
+
+impl<O: DynWriter> DynWriter for RenumberWriter<O> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        let mut obj = obj.clone();
+        self.renumber.renumber(&mut obj);
+        self.inner.write_string_obj(&obj)
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        self.inner.finish()
+    }
+}
+
+/// What an `ObjTransform` does with an object
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransformResult<T> {
+    /// Keep this object, which might be changed
+    Keep(T),
+    /// Drop the object
+    Drop,
+    /// Replace the object with these (none, one, or many)
+    Many(Vec<T>),
+}
+
+impl<T> TransformResult<T> {
+    /// The objects to keep
+    pub fn into_vec(self) -> Vec<T> {
+        match self {
+            TransformResult::Keep(obj) => vec![obj],
+            TransformResult::Drop => vec![],
+            TransformResult::Many(objs) => objs,
+        }
+    }
+}
+
+impl<T> From<Option<T>> for TransformResult<T> {
+    fn from(obj: Option<T>) -> Self {
+        match obj {
+            Some(obj) => TransformResult::Keep(obj),
+            None => TransformResult::Drop,
+        }
+    }
+}
+
+/// One stage of processing objects, which can keep, change, drop, or replace each object.
+/// `Anonymizer`, `Renumber`, and the filters of `filter` are all transforms, & `from_fn` makes
+/// one from a closure.
+///
+/// ```
+/// use osmio::filter::TagFilter;
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::transform::{from_fn, ObjTransform, Renumber, TransformResult, Transformed};
+/// use osmio::{OSMObjBase, OSMReader, OSMWriter};
+///
+/// let input = "n5 Tamenity=bench x1 y2\nn6 x1 y2\nn7 Tamenity=cafe x1 y2\n";
+/// let filter: TagFilter = "amenity".parse().unwrap();
+/// let rename = from_fn(|mut o: osmio::obj_types::StringOSMObj| {
+///     o.set_tag("leisure", o.tag("amenity").unwrap().to_string());
+///     o.unset_tag("amenity");
+///     TransformResult::Keep(o)
+/// });
+/// let mut reader = OPLReader::new(input.as_bytes());
+/// let objs = Transformed::new(reader.objects(), filter.then(rename).then(Renumber::new()));
+/// let mut writer = OPLWriter::new(Vec::new());
+/// writer.write_objs(objs).unwrap();
+/// writer.close().unwrap();
+/// assert_eq!(
+///     String::from_utf8(writer.into_inner()).unwrap(),
+///     "n1 dV Tleisure=bench x1 y2\nn2 dV Tleisure=cafe x1 y2\n"
+/// );
+/// ```
+pub trait ObjTransform<T> {
+    /// Transform this object
+    fn apply(&mut self, obj: T) -> TransformResult<T>;
+
+    /// Do this, then `next` to every object that's left
+    fn then<B: ObjTransform<T>>(self, next: B) -> Chain<Self, B>
+    where
+        Self: Sized,
+    {
+        Chain {
+            first: self,
+            second: next,
+        }
+    }
+}
+
+impl<T, X: ObjTransform<T> + ?Sized> ObjTransform<T> for Box<X> {
+    fn apply(&mut self, obj: T) -> TransformResult<T> {
+        (**self).apply(obj)
+    }
+}
+
+impl<T: OSMObjBase> ObjTransform<T> for Anonymizer {
+    fn apply(&mut self, mut obj: T) -> TransformResult<T> {
+        self.anonymize(&mut obj);
+        TransformResult::Keep(obj)
+    }
+}
+
+impl<T: OSMObj> ObjTransform<T> for Renumber {
+    fn apply(&mut self, mut obj: T) -> TransformResult<T> {
+        self.renumber(&mut obj);
+        TransformResult::Keep(obj)
+    }
+}
+
+/// A transform from a closure, see `from_fn`
+pub struct FromFn<F>(F);
+
+/// A transform which calls this closure for every object
+pub fn from_fn<T, F: FnMut(T) -> TransformResult<T>>(f: F) -> FromFn<F> {
+    FromFn(f)
+}
+
+impl<T, F: FnMut(T) -> TransformResult<T>> ObjTransform<T> for FromFn<F> {
+    fn apply(&mut self, obj: T) -> TransformResult<T> {
+        (self.0)(obj)
+    }
+}
+
+/// 2 transforms, one after the other, see `ObjTransform::then`
+pub struct Chain<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<T, A: ObjTransform<T>, B: ObjTransform<T>> ObjTransform<T> for Chain<A, B> {
+    fn apply(&mut self, obj: T) -> TransformResult<T> {
+        match self.first.apply(obj) {
+            TransformResult::Keep(obj) => self.second.apply(obj),
+            TransformResult::Drop => TransformResult::Drop,
+            TransformResult::Many(objs) => TransformResult::Many(
+                objs.into_iter()
+                    .flat_map(|o| self.second.apply(o).into_vec())
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Iterator of transformed objects
+pub struct Transformed<I, X, T> {
+    inner: I,
+    transform: X,
+    /// Objects from the last `Many`
+    pending: ::std::vec::IntoIter<T>,
+}
+
+impl<I, X> Transformed<I, X, I::Item>
+where
+    I: Iterator,
+    X: ObjTransform<I::Item>,
+{
+    /// Transform the objects of this iterator
+    pub fn new(inner: I, transform: X) -> Self {
+        Transformed {
+            inner: inner,
+            transform: transform,
+            pending: Vec::new().into_iter(),
+        }
+    }
+
+    /// The transform
+    pub fn transform(&self) -> &X {
+        &self.transform
+    }
+}
+
+impl<I, X> Iterator for Transformed<I, X, I::Item>
+where
+    I: Iterator,
+    X: ObjTransform<I::Item>,
+{
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        loop {
+            if let Some(obj) = self.pending.next() {
+                return Some(obj);
+            }
+            match self.transform.apply(self.inner.next()?) {
+                TransformResult::Keep(obj) => return Some(obj),
+                TransformResult::Drop => {}
+                TransformResult::Many(objs) => self.pending = objs.into_iter(),
+            }
+        }
+    }
+}
+
+/// A writer which transforms every object before writing it
+pub struct TransformWriter<O, X> {
+    inner: O,
+    transform: X,
+}
+
+impl<O, X> TransformWriter<O, X> {
+    /// Wrap this writer, so every object written to it is transformed
+    pub fn new(writer: O, transform: X) -> Self {
+        TransformWriter {
+            inner: writer,
+            transform: transform,
+        }
+    }
+
+    /// The transform
+    pub fn transform(&self) -> &X {
+        &self.transform
+    }
+
+    /// The wrapped writer
+    pub fn writer(&self) -> &O {
+        &self.inner
+    }
+
+    /// Convert back to the wrapped writer, and the transform
+    pub fn into_parts(self) -> (O, X) {
+        (self.inner, self.transform)
+    }
+}
+
+impl<O: DynWriter, X: ObjTransform<StringOSMObj>> DynWriter for TransformWriter<O, X> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        for obj in self.transform.apply(obj.clone()).into_vec() {
+            self.inner.write_string_obj(&obj)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        self.inner.finish()
+    }
+}
+
+/// An error when transforming a file
+#[derive(Debug)]
+pub enum TransformError {
+    Open(OpenError),
+    Read(ReadError),
+    Write(OSMWriteError),
+}
+impl std::fmt::Display for TransformError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for TransformError {}
+
+impl From<OpenError> for TransformError {
+    fn from(err: OpenError) -> TransformError {
+        TransformError::Open(err)
+    }
+}
+impl From<ReadError> for TransformError {
+    fn from(err: ReadError) -> TransformError {
+        TransformError::Read(err)
+    }
+}
+impl From<OSMWriteError> for TransformError {
+    fn from(err: OSMWriteError) -> TransformError {
+        TransformError::Write(err)
+    }
+}
+
+/// The most segments one way can be split into, see `WaySplitter`
+pub const MAX_SEGMENTS: ObjId = 10_000;
+
+/// Splits ways at junctions, the nodes used more than once by the chosen ways (by default,
+/// `highway`s), so every way only joins others at its ends, like the edges of a routing graph.
+///
+/// It needs 2 passes: count the node uses of every way (`count_way`), then split them (`split`,
+/// or as an `ObjTransform`). `run` does both for a file. Segment `i` (from 0) of way `w` gets the
+/// id `w * MAX_SEGMENTS + i`, so the ids are the same each time, even for ways which aren't split.
+/// Other objects are unchanged.
+///
+/// ```
+/// use osmio::obj_types::StringWayBuilder;
+/// use osmio::transform::WaySplitter;
+/// use osmio::{OSMObjBase, Way};
+///
+/// let mut splitter = WaySplitter::new();
+/// let way = |id, nodes| {
+///     StringWayBuilder::default()
+///         ._id(id)
+///         ._nodes(nodes)
+///         .tags([("highway", "residential")])
+///         .build()
+///         .unwrap()
+/// };
+/// let main = way(1, vec![1, 2, 3]);
+/// let side = way(2, vec![2, 4]);
+/// splitter.count_way(&main);
+/// splitter.count_way(&side);
+///
+/// let segments = splitter.split(&main);
+/// assert_eq!(segments.len(), 2);
+/// assert_eq!(segments[0].id(), 10_000);
+/// assert_eq!(segments[0].nodes(), &[1, 2]);
+/// assert_eq!(segments[1].id(), 10_001);
+/// assert_eq!(segments[1].nodes(), &[2, 3]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct WaySplitter {
+    filter: TagFilter,
+    /// How many times each node is used
+    uses: HashMap<ObjId, u32>,
+}
+
+impl Default for WaySplitter {
+    fn default() -> Self {
+        WaySplitter::new()
+    }
+}
+
+impl WaySplitter {
+    /// Split `highway`s
+    pub fn new() -> Self {
+        WaySplitter::with_filter("w/highway".parse().unwrap())
+    }
+
+    /// Split the ways which match this filter
+    pub fn with_filter(filter: TagFilter) -> Self {
+        WaySplitter {
+            filter: filter,
+            uses: HashMap::new(),
+        }
+    }
+
+    /// True iff this way is split
+    pub fn is_chosen(&self, way: &impl Way) -> bool {
+        self.filter.matches_way(way)
+    }
+
+    /// Count the node uses of this way, if it's chosen. First pass
+    pub fn count_way(&mut self, way: &impl Way) {
+        if self.is_chosen(way) {
+            let nodes = way.nodes();
+            // A closed way's first & last node is one use
+            let end = if nodes.len() > 1 && nodes[0] == nodes[nodes.len() - 1] {
+                nodes.len() - 1
+            } else {
+                nodes.len()
+            };
+            for &n in &nodes[..end] {
+                *self.uses.entry(n).or_default() += 1;
+            }
+        }
+    }
+
+    /// True iff this node is used more than once by the ways counted
+    pub fn is_junction(&self, node: ObjId) -> bool {
+        self.uses.get(&node).map_or(false, |&c| c > 1)
+    }
+
+    /// The id of the way this segment id is from
+    pub fn original_id(segment_id: ObjId) -> ObjId {
+        segment_id.div_euclid(MAX_SEGMENTS)
+    }
+
+    /// The segments of this way, split at every junction. Second pass. A way which isn't chosen
+    /// is returned as it is.
+    pub fn split<W: Way>(&self, way: &W) -> Vec<W> {
+        if !self.is_chosen(way) {
+            return vec![way.clone()];
+        }
+        let nodes = way.nodes();
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for i in 1..nodes.len() {
+            if i == nodes.len() - 1 || self.is_junction(nodes[i]) {
+                let mut segment = way.clone();
+                segment.set_id(way.id() * MAX_SEGMENTS + segments.len() as ObjId);
+                segment.set_nodes(nodes[start..=i].iter().cloned());
+                segments.push(segment);
+                start = i;
+            }
+        }
+        if segments.is_empty() {
+            // Fewer than 2 nodes
+            let mut segment = way.clone();
+            segment.set_id(way.id() * MAX_SEGMENTS);
+            segments.push(segment);
+        }
+        segments
+    }
+
+    /// Split all the chosen ways of this file, writing everything to `output`. The file is read
+    /// twice. Returns how many objects were written.
+    pub fn run(
+        mut self,
+        input: impl AsRef<Path>,
+        output: &mut impl DynWriter,
+    ) -> Result<u64, TransformError> {
+        let input = input.as_ref();
+        let mut reader = read_from_path(input)?;
+        while let Some(obj) = reader.try_next_obj()? {
+            if let Some(way) = obj.as_way() {
+                self.count_way(way);
+            }
+        }
+
+        let mut reader = read_from_path(input)?;
+        let mut written = 0;
+        while let Some(obj) = reader.try_next_obj()? {
+            for obj in self.apply(obj).into_vec() {
+                output.write_string_obj(&obj)?;
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl<T: OSMObj> ObjTransform<T> for WaySplitter {
+    fn apply(&mut self, obj: T) -> TransformResult<T> {
+        let segments = match obj.as_way() {
+            Some(way) if self.is_chosen(way) => self.split(way),
+            _ => return TransformResult::Keep(obj),
+        };
+        TransformResult::Many(
+            segments
+                .into_iter()
+                .map(|segment| {
+                    let mut obj = obj.clone();
+                    let way = obj.as_way_mut().unwrap();
+                    way.set_id(segment.id());
+                    way.set_nodes(segment.nodes().iter().cloned());
+                    obj
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Merges duplicate nodes: nodes with the same tags, at the same location (or within
+/// `epsilon`), a common problem of imports. Each duplicate is merged into the first node seen
+/// like it, and way nodes & relation members are changed to match.
+///
+/// It needs 2 passes: find the duplicates (`add_node`, for every node), then remove them &
+/// change the references (`merge`, or as an `ObjTransform`). `run` does both for a file. The
+/// duplicates are kept in an `IdMap`, from each duplicate to the node it's merged into.
+///
+/// ```
+/// use osmio::opl::{OPLReader, OPLWriter};
+/// use osmio::transform::NodeMerger;
+/// use osmio::OSMWriter;
+///
+/// let path = std::env::temp_dir().join(format!("osmio-merge-doc-{}.opl", std::process::id()));
+/// std::fs::write(&path, "n1 x1 y2\nn2 x1 y2\nn3 Tname=A x1 y2\nw1 Nn1,n2,n3\n").unwrap();
+/// let mut merger = NodeMerger::new();
+/// let mut writer = OPLWriter::new(Vec::new());
+/// merger.run(&path, &mut writer).unwrap();
+/// std::fs::remove_file(&path).unwrap();
+///
+/// assert_eq!(merger.merged(), 1);
+/// assert_eq!(
+///     String::from_utf8(writer.into_inner()).unwrap(),
+///     "n1 dV T x1 y2\nn3 dV Tname=A x1 y2\nw1 dV T Nn1,n3\n"
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct NodeMerger {
+    /// In 1e-7 degrees
+    epsilon: i32,
+    /// The nodes kept, by grid cell (of size `epsilon`)
+    kept: HashMap<(i32, i32), Vec<KeptNode>>,
+    map: IdMap,
+}
+
+#[derive(Debug, Clone)]
+struct KeptNode {
+    id: ObjId,
+    lat: i32,
+    lon: i32,
+    /// Sorted
+    tags: Vec<(String, String)>,
+}
+
+impl NodeMerger {
+    /// Merge nodes at exactly the same location
+    pub fn new() -> Self {
+        NodeMerger::default()
+    }
+
+    /// Also merge nodes up to this many degrees apart, in latitude & in longitude
+    pub fn epsilon(mut self, degrees: f64) -> Self {
+        self.epsilon = (degrees.abs() * COORD_PRECISION as f64).round() as i32;
+        self
+    }
+
+    fn cell(&self, lat: i32, lon: i32) -> (i32, i32) {
+        let size = self.epsilon.max(1);
+        (lat.div_euclid(size), lon.div_euclid(size))
+    }
+
+    /// Check if this node is a duplicate of a node added before. Nodes without a location are
+    /// never duplicates. First pass
+    pub fn add_node(&mut self, node: &impl Node) {
+        let (lat, lon) = match node.lat_lon() {
+            None => return,
+            Some((lat, lon)) => (lat.inner(), lon.inner()),
+        };
+        let mut tags: Vec<_> = node
+            .tags()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        tags.sort();
+
+        let (y, x) = self.cell(lat, lon);
+        let epsilon = self.epsilon;
+        let duplicate_of = (y - 1..=y + 1)
+            .flat_map(|y| (x - 1..=x + 1).map(move |x| (y, x)))
+            .filter_map(|cell| self.kept.get(&cell))
+            .flatten()
+            .filter(|k| (k.lat - lat).abs() <= epsilon && (k.lon - lon).abs() <= epsilon)
+            .filter(|k| k.tags == tags)
+            .map(|k| k.id)
+            // A node added again isn't its own duplicate
+            .min_by_key(|&id| id != node.id());
+        match duplicate_of {
+            Some(id) if id == node.id() => {}
+            Some(id) => {
+                self.map.insert(OSMObjectType::Node, node.id(), id);
+            }
+            None => self.kept.entry((y, x)).or_default().push(KeptNode {
+                id: node.id(),
+                lat: lat,
+                lon: lon,
+                tags: tags,
+            }),
+        }
+    }
+
+    /// True iff this node is a duplicate, and is removed
+    pub fn is_duplicate(&self, node: ObjId) -> bool {
+        self.map.get(OSMObjectType::Node, node).is_some()
+    }
+
+    /// How many duplicates have been found
+    pub fn merged(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Each duplicate, to the node it's merged into
+    pub fn map(&self) -> &IdMap {
+        &self.map
+    }
+
+    /// Change the duplicate nodes this way or relation refers to, to the nodes they're merged
+    /// into. The nodes of a way which are then repeated (`a,a`) are removed. Second pass
+    pub fn merge(&self, obj: &mut impl OSMObj) {
+        self.map.apply(obj);
+        if let Some(way) = obj.as_way_mut() {
+            let mut nodes = way.nodes().to_vec();
+            let len = nodes.len();
+            nodes.dedup();
+            if nodes.len() != len {
+                way.set_nodes(nodes);
+            }
+        }
+    }
+
+    /// Merge the duplicate nodes of this file, writing everything else to `output`. The file is
+    /// read twice. Returns how many objects were written.
+    pub fn run(
+        &mut self,
+        input: impl AsRef<Path>,
+        output: &mut impl DynWriter,
+    ) -> Result<u64, TransformError> {
+        let input = input.as_ref();
+        let mut reader = read_from_path(input)?;
+        while let Some(obj) = reader.try_next_obj()? {
+            if let Some(node) = obj.as_node() {
+                self.add_node(node);
+            }
+        }
+
+        let mut reader = read_from_path(input)?;
+        let mut written = 0;
+        while let Some(obj) = reader.try_next_obj()? {
+            if let TransformResult::Keep(obj) = self.apply(obj) {
+                output.write_string_obj(&obj)?;
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl<T: OSMObj> ObjTransform<T> for NodeMerger {
+    fn apply(&mut self, mut obj: T) -> TransformResult<T> {
+        if obj.is_node() && self.is_duplicate(obj.id()) {
+            return TransformResult::Drop;
+        }
+        self.merge(&mut obj);
+        TransformResult::Keep(obj)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opl::{OPLReader, OPLWriter};
+    use OSMReader;
+
+    #[test]
+    fn strip_metadata() {
+        let input = "n1 v3 dV c4 t2020-01-01T00:00:00Z i5 uuser T x1 y2\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let writer: StripMetadata<OPLWriter<Vec<u8>>> =
+            StripMetadata::try_from_iter(Vec::new(), reader.objects()).unwrap();
+        assert_eq!(
+            String::from_utf8(writer.into_inner()).unwrap(),
+            "n1 v3 dV t2020-01-01T00:00:00Z T x1 y2\n"
+        );
+
+        let mut output = Vec::new();
+        {
+            let mut reader = OPLReader::new(input.as_bytes());
+            let mut writer: Box<dyn DynWriter> = Box::new(
+                StripMetadata::from_writer(OPLWriter::new(&mut output)).strip_version(true),
+            );
+            for obj in reader.objects() {
+                writer.write_string_obj(&obj).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "n1 dV t2020-01-01T00:00:00Z T x1 y2\n"
+        );
+    }
+
+    #[test]
+    fn anonymizer() {
+        let input = "n1 v3 dV c4 t1969-12-31T23:00:00Z i5 uuser T x1 y2\nn2 v1 dV tnonsense\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let anonymizer = Anonymizer {
+            strip_version: true,
+            ..Default::default()
+        };
+        let objs: Vec<_> = anonymizer.iter(reader.objects()).collect();
+        assert_eq!(objs[0].timestamp(), &Some((-86400).into()));
+        assert_eq!(objs[0].uid(), None);
+        assert_eq!(objs[0].user(), None);
+        assert_eq!(objs[0].changeset_id(), None);
+        assert_eq!(objs[0].version(), None);
+        assert_eq!(objs[1].timestamp(), &None);
+    }
+
+    #[test]
+    fn renumber() {
+        let input = "n-5 x1 y2\nn-3 x1 y2\nw-1 Nn-3,n-5,n10\nr-1 Mw-1@,r-9@,n10@\n";
+        let mut reader = OPLReader::new(input.as_bytes());
+        let mut renumbered = Renumber::starting_at(1000, 1, 1).iter(reader.objects());
+        let objs: Vec<_> = renumbered.by_ref().collect();
+        assert_eq!(objs[0].id(), 1000);
+        assert_eq!(objs[1].id(), 1001);
+        assert_eq!(objs[2].as_way().unwrap().nodes(), [1001, 1000, 1002]);
+        assert_eq!(objs[3].id(), 1);
+        let members: Vec<_> = objs[3]
+            .as_relation()
+            .unwrap()
+            .members()
+            .map(|m| m.id)
+            .collect();
+        assert_eq!(members, vec![1, 2, 1002]);
+
+        let map = renumbered.renumber().map();
+        assert_eq!(map.len(), 6);
+        assert_eq!(map.get(OSMObjectType::Relation, -9), Some(2));
+    }
+
+    #[test]
+    fn transforms() {
+        let input = "n1 Tamenity=bench x1 y2\nn2 x1 y2\nw3 Nn1,n2\n";
+        // every node twice, with a new id
+        let split = from_fn(|o: StringOSMObj| {
+            if o.object_type() == OSMObjectType::Node {
+                let mut copy = o.clone();
+                copy.set_id(o.id() + 100);
+                TransformResult::Many(vec![o, copy])
+            } else {
+                TransformResult::Keep(o)
+            }
+        });
+        let drop_ways = from_fn(|o: StringOSMObj| match o.object_type() {
+            OSMObjectType::Way => TransformResult::Drop,
+            _ => TransformResult::Keep(o),
+        });
+        let mut reader = OPLReader::new(input.as_bytes());
+        let ids: Vec<_> = Transformed::new(
+            reader.objects(),
+            split.then(drop_ways).then(Renumber::starting_at(10, 1, 1)),
+        )
+        .map(|o| o.id())
+        .collect();
+        assert_eq!(ids, vec![10, 11, 12, 13]);
+
+        let mut output = Vec::new();
+        {
+            let mut reader = OPLReader::new(input.as_bytes());
+            let ways = from_fn(|o: StringOSMObj| Some(o).filter(|o| o.is_way()).into());
+            let mut writer = TransformWriter::new(
+                OPLWriter::new(&mut output),
+                Anonymizer::default().then(ways),
+            );
+            for obj in reader.objects() {
+                writer.write_string_obj(&obj).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        assert_eq!(String::from_utf8(output).unwrap(), "w3 dV T Nn1,n2\n");
+    }
+
+    #[test]
+    fn way_splitter() {
+        let path =
+            ::std::env::temp_dir().join(format!("osmio-split-ways-{}.opl", ::std::process::id()));
+        ::std::fs::write(
+            &path,
+            "n1 x1 y2\nw1 Thighway=primary Nn1,n2,n3,n4\nw2 Thighway=service Nn3,n5\n\
+             w3 Tbuilding=yes Nn2,n6,n7,n2\nw4 Thighway=footway Nn4,n8,n9,n4\n",
+        )
+        .unwrap();
+        let mut output = Vec::new();
+        {
+            let mut writer = OPLWriter::new(&mut output);
+            let written = WaySplitter::new().run(&path, &mut writer).unwrap();
+            writer.finish().unwrap();
+            assert_eq!(written, 6);
+        }
+        ::std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "n1 dV T x1 y2\n\
+             w10000 dV Thighway=primary Nn1,n2,n3\nw10001 dV Thighway=primary Nn3,n4\n\
+             w20000 dV Thighway=service Nn3,n5\n\
+             w3 dV Tbuilding=yes Nn2,n6,n7,n2\n\
+             w40000 dV Thighway=footway Nn4,n8,n9,n4\n"
+        );
+        assert_eq!(WaySplitter::original_id(10001), 1);
+        assert_eq!(WaySplitter::original_id(-20001), -3);
+    }
+
+    #[test]
+    fn node_merger() {
+        let input = "n1 x1 y2\nn2 x1 y2\nn3 Tname=A x1 y2\nn4 x1.00001 y2\nn5 Tname=A x1 y2\n\
+                     n6 x1.0001 y2\nn7\nn8\nw1 Nn1,n2,n4,n6,n2\nr1 Mn5@,w1@\n";
+        let run = |merger: &mut NodeMerger| {
+            let mut reader = OPLReader::new(input.as_bytes());
+            let mut objs = Vec::new();
+            for obj in reader.objects() {
+                if let Some(node) = obj.as_node() {
+                    merger.add_node(node);
+                }
+                objs.push(obj);
+            }
+            let mut writer = TransformWriter::new(OPLWriter::new(Vec::new()), merger.clone());
+            writer.write_string_objs(&objs).unwrap();
+            writer.finish().unwrap();
+            String::from_utf8(writer.into_parts().0.into_inner()).unwrap()
+        };
+
+        let mut merger = NodeMerger::new();
+        assert_eq!(
+            run(&mut merger),
+            "n1 dV T x1 y2\nn3 dV Tname=A x1 y2\nn4 dV T x1.00001 y2\nn6 dV T x1.0001 y2\n\
+             n7 dV T x y\nn8 dV T x y\nw1 dV T Nn1,n4,n6,n1\nr1 dV T Mn3@,w1@\n"
+        );
+        assert_eq!(merger.merged(), 2);
+        assert!(merger.is_duplicate(5));
+        assert_eq!(merger.map().get(OSMObjectType::Node, 2), Some(1));
+
+        let mut merger = NodeMerger::new().epsilon(0.00005);
+        assert_eq!(
+            run(&mut merger),
+            "n1 dV T x1 y2\nn3 dV Tname=A x1 y2\nn6 dV T x1.0001 y2\n\
+             n7 dV T x y\nn8 dV T x y\nw1 dV T Nn1,n6,n1\nr1 dV T Mn3@,w1@\n"
+        );
+        assert_eq!(merger.merged(), 3);
+    }
+}
@@ -1,13 +1,60 @@
-use chrono::{offset::Utc, DateTime, NaiveDateTime};
+use chrono::{offset::Utc, DateTime};
+use std::io;
+use std::io::{Read, Write};
 
-pub fn epoch_to_iso(epoch: i32) -> String {
-    // Chrono is a little silly and can't easily convert from epoch to utc timezone
-    let d: DateTime<Utc> = DateTime::from_utc(NaiveDateTime::from_timestamp(epoch as i64, 0), Utc);
-    d.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+use byteorder::{ReadBytesExt, WriteBytesExt};
+
+use OSMObjectType;
+
+/// Format a unix epoch (which can be negative, or after 2038) as an ISO 8601 string. `None` if
+/// the date is out of range.
+pub fn epoch_to_iso(epoch: i64) -> Option<String> {
+    DateTime::<Utc>::from_timestamp(epoch, 0)
+        .map(|d| d.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
 }
 
-pub fn iso_to_epoch(iso: &str) -> u32 {
+pub fn iso_to_epoch(iso: &str) -> i64 {
     DateTime::parse_from_rfc3339(iso)
-        .map(|x| x.timestamp() as u32)
+        .map(|x| x.timestamp())
         .unwrap_or(0)
 }
+
+/// The index of this type in arrays of something for nodes, ways & relations
+pub(crate) fn type_idx(otype: OSMObjectType) -> usize {
+    match otype {
+        OSMObjectType::Node => 0,
+        OSMObjectType::Way => 1,
+        OSMObjectType::Relation => 2,
+    }
+}
+
+/// Write a signed integer as a zigzag varint, like protobuf's `sint64`
+pub(crate) fn write_varint(fp: &mut impl Write, val: i64) -> io::Result<()> {
+    let mut val = ((val << 1) ^ (val >> 63)) as u64;
+    while val >= 0x80 {
+        fp.write_u8((val as u8) | 0x80)?;
+        val >>= 7;
+    }
+    fp.write_u8(val as u8)
+}
+
+/// Read a zigzag varint written by `write_varint`
+pub(crate) fn read_varint(fp: &mut impl Read) -> io::Result<i64> {
+    let mut val = 0u64;
+    let mut shift = 0;
+    loop {
+        let byte = fp.read_u8()?;
+        if shift > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "varint too long",
+            ));
+        }
+        val |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    Ok(((val >> 1) as i64) ^ -((val & 1) as i64))
+}
@@ -0,0 +1,454 @@
+//! Checks of the geometry of ways, and of the rings of multipolygon & boundary relations, for QA.
+//! Each check returns the `Issue`s it found, empty if the object is valid. `Validator` checks a
+//! whole file.
+//!
+//! ```
+//! use osmio::validate::{check_way, Issue};
+//! use osmio::obj_types::StringWay;
+//! use osmio::{Lat, Lon};
+//!
+//! let loc = |id: i64| match id {
+//!     1 => Some((Lat::from(0.), Lon::from(0.))),
+//!     2 => Some((Lat::from(1.), Lon::from(1.))),
+//!     3 => Some((Lat::from(0.), Lon::from(1.))),
+//!     4 => Some((Lat::from(1.), Lon::from(0.))),
+//!     _ => None,
+//! };
+//! // A bow tie
+//! let way = StringWay::new(1, vec![1, 2, 2, 3, 4]);
+//! assert_eq!(
+//!     check_way(&way, loc),
+//!     vec![
+//!         Issue::DuplicateNode { index: 2, node: 2 },
+//!         Issue::SelfIntersection((1, 2), (3, 4)),
+//!     ]
+//! );
+//! ```
+use std::collections::HashSet;
+use std::path::Path;
+
+use areas::{is_area_relation, join_rings};
+use formats::{read_from_path, OpenError};
+use nodestore::{LocationStore, WayStore};
+use {Lat, Lon, OSMObj, OSMObjBase, OSMObjectType, ObjId, ReadError, Relation, Way};
+
+/// A problem with the geometry of an object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The same node twice in a row. `index` is the position of the second one
+    DuplicateNode { index: usize, node: ObjId },
+    /// The way has fewer than 2 different nodes
+    TooFewNodes,
+    /// Every node of the way is at the same location
+    ZeroLength,
+    /// This node's location isn't known
+    MissingNode(ObjId),
+    /// These 2 segments of the way (by their nodes) cross or overlap
+    SelfIntersection((ObjId, ObjId), (ObjId, ObjId)),
+    /// This member way isn't in the input
+    MissingWay(ObjId),
+    /// The member ways don't join into a closed ring. The ends (node ids) of the unclosed ring
+    UnclosedRing(ObjId, ObjId),
+    /// These 2 segments of the rings (by their nodes) cross or overlap
+    RingCrossing((ObjId, ObjId), (ObjId, ObjId)),
+}
+
+/// The issues of one object
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjIssues {
+    pub object_type: OSMObjectType,
+    pub id: ObjId,
+    pub issues: Vec<Issue>,
+}
+
+/// An error when validating a file
+#[derive(Debug)]
+pub enum ValidationError {
+    Open(OpenError),
+    Read(ReadError),
+}
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+impl std::error::Error for ValidationError {}
+
+impl From<OpenError> for ValidationError {
+    fn from(err: OpenError) -> ValidationError {
+        ValidationError::Open(err)
+    }
+}
+impl From<ReadError> for ValidationError {
+    fn from(err: ReadError) -> ValidationError {
+        ValidationError::Read(err)
+    }
+}
+impl From<::std::io::Error> for ValidationError {
+    fn from(err: ::std::io::Error) -> ValidationError {
+        ValidationError::Read(ReadError::IOError(err))
+    }
+}
+
+/// The location of this node, as fixed-point (x, y)
+type Point = (i64, i64);
+
+/// Side of `p` from the line `a`-`b`: positive if left, negative if right, 0 if on it
+fn orientation(a: Point, b: Point, p: Point) -> i128 {
+    (b.0 - a.0) as i128 * (p.1 - a.1) as i128 - (b.1 - a.1) as i128 * (p.0 - a.0) as i128
+}
+
+/// True iff `p`, which is on the line `a`-`b`, is between them
+fn on_segment(a: Point, b: Point, p: Point) -> bool {
+    a.0.min(b.0) <= p.0 && p.0 <= a.0.max(b.0) && a.1.min(b.1) <= p.1 && p.1 <= a.1.max(b.1)
+}
+
+/// True iff these segments have a point in common
+fn intersects((a, b): (Point, Point), (c, d): (Point, Point)) -> bool {
+    let (o1, o2) = (orientation(a, b, c), orientation(a, b, d));
+    let (o3, o4) = (orientation(c, d, a), orientation(c, d, b));
+    if o1.signum() * o2.signum() < 0 && o3.signum() * o4.signum() < 0 {
+        return true;
+    }
+    (o1 == 0 && on_segment(a, b, c))
+        || (o2 == 0 && on_segment(a, b, d))
+        || (o3 == 0 && on_segment(c, d, a))
+        || (o4 == 0 && on_segment(c, d, b))
+}
+
+/// The pairs of these segments (by node id) which intersect, other than at a node they share.
+/// Every node must have a location.
+fn crossings(
+    segments: &[(ObjId, ObjId)],
+    location: &impl Fn(ObjId) -> Option<(Lat, Lon)>,
+) -> Vec<((ObjId, ObjId), (ObjId, ObjId))> {
+    let point = |id| {
+        let (lat, lon) = location(id).unwrap();
+        (lon.inner() as i64, lat.inner() as i64)
+    };
+    let mut located: Vec<((ObjId, ObjId), (Point, Point))> = segments
+        .iter()
+        .map(|&(a, b)| ((a, b), (point(a), point(b))))
+        .collect();
+    // Sweep from west to east, only comparing segments which overlap in x
+    located.sort_by_key(|&(_, (a, b))| a.0.min(b.0));
+    let mut found = Vec::new();
+    for (i, &(s1, p1)) in located.iter().enumerate() {
+        let max_x = (p1.0).0.max((p1.1).0);
+        for &(s2, p2) in located[i + 1..].iter() {
+            if (p2.0).0.min((p2.1).0) > max_x {
+                break;
+            }
+            let shares_node = s1.0 == s2.0 || s1.0 == s2.1 || s1.1 == s2.0 || s1.1 == s2.1;
+            if !shares_node && intersects(p1, p2) {
+                found.push(if s1 <= s2 { (s1, s2) } else { (s2, s1) });
+            }
+        }
+    }
+    found.sort();
+    found
+}
+
+/// The nodes, in order, which have no location
+fn missing_nodes<'a>(
+    nodes: impl IntoIterator<Item = &'a ObjId>,
+    location: &impl Fn(ObjId) -> Option<(Lat, Lon)>,
+) -> Vec<Issue> {
+    let mut seen = HashSet::new();
+    nodes
+        .into_iter()
+        .filter(|&&id| seen.insert(id) && location(id).is_none())
+        .map(|&id| Issue::MissingNode(id))
+        .collect()
+}
+
+/// Check this way, with `location` giving the location of every node: for repeated nodes, too
+/// few nodes, no length, and segments which cross or overlap (other than at a shared node).
+pub fn check_way(way: &impl Way, location: impl Fn(ObjId) -> Option<(Lat, Lon)>) -> Vec<Issue> {
+    let nodes = way.nodes();
+    let mut issues: Vec<Issue> = (1..nodes.len())
+        .filter(|&i| nodes[i] == nodes[i - 1])
+        .map(|i| Issue::DuplicateNode {
+            index: i,
+            node: nodes[i],
+        })
+        .collect();
+    let mut nodes = nodes.to_vec();
+    nodes.dedup();
+    if nodes.iter().collect::<HashSet<_>>().len() < 2 {
+        issues.push(Issue::TooFewNodes);
+        return issues;
+    }
+
+    let missing = missing_nodes(&nodes, &location);
+    if !missing.is_empty() {
+        issues.extend(missing);
+        return issues;
+    }
+    let first = location(nodes[0]);
+    if nodes.iter().all(|&id| location(id) == first) {
+        issues.push(Issue::ZeroLength);
+        return issues;
+    }
+
+    let segments: Vec<_> = nodes.windows(2).map(|pair| (pair[0], pair[1])).collect();
+    issues.extend(
+        crossings(&segments, &location)
+            .into_iter()
+            .map(|(a, b)| Issue::SelfIntersection(a, b)),
+    );
+    issues
+}
+
+/// Check the rings made by these node id chains (e.g. the member ways of a multipolygon), with
+/// `location` giving the location of every node: for chains which don't join into closed rings,
+/// and segments of the rings which cross or overlap (other than at a shared node). See
+/// `areas::assemble` for how rings are joined.
+pub fn check_rings(
+    chains: &[Vec<ObjId>],
+    location: impl Fn(ObjId) -> Option<(Lat, Lon)>,
+) -> Vec<Issue> {
+    let (rings, unclosed) = join_rings(chains);
+    let mut issues: Vec<Issue> = unclosed
+        .into_iter()
+        .map(|(start, end)| Issue::UnclosedRing(start, end))
+        .collect();
+
+    let missing = missing_nodes(rings.iter().flatten(), &location);
+    if !missing.is_empty() {
+        issues.extend(missing);
+        return issues;
+    }
+    let segments: Vec<_> = rings
+        .iter()
+        .flat_map(|ring| ring.windows(2).map(|pair| (pair[0], pair[1])))
+        .collect();
+    issues.extend(
+        crossings(&segments, &location)
+            .into_iter()
+            .map(|(a, b)| Issue::RingCrossing(a, b)),
+    );
+    issues
+}
+
+/// Checks all the ways (`check_way`), and the rings of the multipolygon & boundary relations
+/// (`check_rings`), of a file. The file is read twice (once if relations aren't checked), & must
+/// be sorted. Every node location is kept in memory, and the nodes of the member ways of
+/// relations.
+#[derive(Debug, Clone)]
+pub struct Validator {
+    ways: bool,
+    relations: bool,
+}
+
+impl Default for Validator {
+    fn default() -> Self {
+        Validator {
+            ways: true,
+            relations: true,
+        }
+    }
+}
+
+impl Validator {
+    pub fn new() -> Self {
+        Validator::default()
+    }
+
+    /// Check the ways. Default: true
+    pub fn ways(mut self, ways: bool) -> Self {
+        self.ways = ways;
+        self
+    }
+
+    /// Check the multipolygon & boundary relations. Default: true
+    pub fn relations(mut self, relations: bool) -> Self {
+        self.relations = relations;
+        self
+    }
+
+    /// Check this file, calling `f` with the issues of every object which has any. Returns how
+    /// many objects had issues.
+    pub fn run(
+        &self,
+        input: impl AsRef<Path>,
+        mut f: impl FnMut(ObjIssues),
+    ) -> Result<u64, ValidationError> {
+        let input = input.as_ref();
+        // The member ways of the area relations
+        let mut member_ways = HashSet::new();
+        if self.relations {
+            let mut reader = read_from_path(input)?;
+            while let Some(obj) = reader.try_next_obj()? {
+                if let Some(relation) = obj.as_relation() {
+                    if is_area_relation(relation) {
+                        member_ways.extend(
+                            relation
+                                .members()
+                                .filter(|m| m.mtype == OSMObjectType::Way)
+                                .map(|m| m.id),
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut locations = LocationStore::new();
+        let mut ways = WayStore::new();
+        let mut invalid = 0;
+        let mut emit = |object_type, id, issues: Vec<Issue>| {
+            if !issues.is_empty() {
+                invalid += 1;
+                f(ObjIssues {
+                    object_type: object_type,
+                    id: id,
+                    issues: issues,
+                });
+            }
+        };
+        let mut reader = read_from_path(input)?;
+        while let Some(obj) = reader.try_next_obj()? {
+            if let Some(node) = obj.as_node() {
+                locations.add_node(node)?;
+            } else if let Some(way) = obj.as_way() {
+                if member_ways.contains(&way.id()) {
+                    ways.add_way(way);
+                }
+                if self.ways {
+                    let issues = check_way(way, |id| locations.get(id));
+                    emit(OSMObjectType::Way, way.id(), issues);
+                }
+            } else if let Some(relation) = obj.as_relation() {
+                if self.relations && is_area_relation(relation) {
+                    let mut issues = Vec::new();
+                    let mut chains = Vec::new();
+                    for m in relation.members().filter(|m| m.mtype == OSMObjectType::Way) {
+                        match ways.get(m.id) {
+                            Some(nodes) => chains.push(nodes),
+                            None => issues.push(Issue::MissingWay(m.id)),
+                        }
+                    }
+                    issues.extend(check_rings(&chains, |id| locations.get(id)));
+                    emit(OSMObjectType::Relation, relation.id(), issues);
+                }
+            }
+        }
+        Ok(invalid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use obj_types::StringWay;
+    use std::fs;
+
+    /// Nodes 1-16 on a 4x4 grid, (0, 0) to (3, 3), row by row
+    fn grid(id: ObjId) -> Option<(Lat, Lon)> {
+        if (1..=16).contains(&id) {
+            let i = id - 1;
+            Some((Lat::from((i / 4) as f64), Lon::from((i % 4) as f64)))
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn ways() {
+        let check = |nodes: Vec<ObjId>| check_way(&StringWay::new(1, nodes), grid);
+        assert_eq!(check(vec![1, 2, 3, 7, 6, 5, 1]), vec![]);
+        assert_eq!(
+            check(vec![1, 1]),
+            vec![
+                Issue::DuplicateNode { index: 1, node: 1 },
+                Issue::TooFewNodes,
+            ]
+        );
+        assert_eq!(check(vec![1, 2, 99]), vec![Issue::MissingNode(99)]);
+        // overlapping, along the same line
+        assert_eq!(
+            check(vec![1, 3, 2, 4]),
+            vec![Issue::SelfIntersection((1, 3), (2, 4))]
+        );
+        // touching itself at a node is fine, but not in the middle of a segment
+        assert_eq!(check(vec![1, 2, 6, 5, 2, 3]), vec![]);
+        assert_eq!(
+            check(vec![1, 3, 7, 6, 2]),
+            vec![Issue::SelfIntersection((1, 3), (6, 2))]
+        );
+
+        let mut same_place = LocationStore::new();
+        same_place.set(1, grid(1).unwrap()).unwrap();
+        same_place.set(2, grid(1).unwrap()).unwrap();
+        assert_eq!(
+            check_way(&StringWay::new(1, vec![1, 2]), |id| same_place.get(id)),
+            vec![Issue::ZeroLength]
+        );
+    }
+
+    #[test]
+    fn rings() {
+        // a square with a square hole
+        assert_eq!(
+            check_rings(
+                &[vec![1, 4, 16], vec![1, 13, 16], vec![6, 7, 11, 10, 6]],
+                grid
+            ),
+            vec![]
+        );
+        assert_eq!(
+            check_rings(&[vec![1, 4, 16], vec![1, 13], vec![6, 7]], grid),
+            vec![Issue::UnclosedRing(13, 16), Issue::UnclosedRing(6, 7)]
+        );
+        // 2 squares, overlapping
+        assert_eq!(
+            check_rings(&[vec![1, 3, 11, 9, 1], vec![6, 8, 16, 14, 6]], grid),
+            vec![
+                Issue::RingCrossing((3, 11), (6, 8)),
+                Issue::RingCrossing((11, 9), (14, 6)),
+            ]
+        );
+    }
+
+    #[test]
+    fn validator() {
+        let path = std::env::temp_dir().join(format!("osmio-validate-{}.opl", std::process::id()));
+        fs::write(
+            &path,
+            "n1 x0 y0\nn2 x1 y0\nn3 x1 y1\nn4 x0 y1\n\
+             w1 Nn1,n2,n3\nw2 Nn3,n4,n1\nw3 Nn1,n3,n2,n4\nw4 Nn1,n1\n\
+             r1 Ttype=multipolygon Mw1@,w2@\nr2 Ttype=multipolygon Mw1@,w9@\nr3 Ttype=route Mw4@\n",
+        )
+        .unwrap();
+        let mut found = Vec::new();
+        let invalid = Validator::new().run(&path, |i| found.push(i)).unwrap();
+        assert_eq!(invalid, 3);
+        assert_eq!(
+            found,
+            vec![
+                ObjIssues {
+                    object_type: OSMObjectType::Way,
+                    id: 3,
+                    issues: vec![Issue::SelfIntersection((1, 3), (2, 4))],
+                },
+                ObjIssues {
+                    object_type: OSMObjectType::Way,
+                    id: 4,
+                    issues: vec![
+                        Issue::DuplicateNode { index: 1, node: 1 },
+                        Issue::TooFewNodes
+                    ],
+                },
+                ObjIssues {
+                    object_type: OSMObjectType::Relation,
+                    id: 2,
+                    issues: vec![Issue::MissingWay(9), Issue::UnclosedRing(1, 3)],
+                },
+            ]
+        );
+
+        let invalid = Validator::new().ways(false).run(&path, |_| {}).unwrap();
+        assert_eq!(invalid, 1);
+        fs::remove_file(&path).unwrap();
+    }
+}
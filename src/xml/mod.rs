@@ -3,18 +3,28 @@
 use super::version;
 use super::ObjId;
 use super::TimestampFormat;
-use super::{Node, OSMObj, OSMObjectType, Relation, Way};
-use super::{OSMReader, OSMWriteError, OSMWriter};
+use super::WriterOptions;
+use super::{BBox, Header, HeaderField};
+use super::{DynWriter, OSMReader, OSMWriteError, OSMWriter, ReadError, ReaderOptions};
+use super::{Member, Node, OSMObj, OSMObjectType, Relation, Way};
+use changeset::{Changeset, Element};
 use obj_types::{StringNode, StringOSMObj, StringRelation, StringWay};
-use std::collections::HashMap;
-use std::io::{BufReader, Read, Write};
+use std::io::{BufReader, BufWriter, Read, Write};
 use std::iter::Iterator;
 
 use xml_rs::attribute::OwnedAttribute;
 use xml_rs::reader::{EventReader, Events, XmlEvent};
 
+pub mod parallel;
+pub use self::parallel::ParallelXMLReader;
+
 pub struct XMLReader<R: Read> {
     parser: Events<BufReader<R>>,
+    options: ReaderOptions,
+    header: Option<Header>,
+    /// The first element, if it was read while looking for the header
+    pending: Option<Element>,
+    started: bool,
 }
 
 fn write_xml_escaped(writer: &mut impl Write, s: &str) -> std::io::Result<()> {
@@ -35,12 +45,27 @@ impl<R: Read> OSMReader for XMLReader<R> {
     type R = R;
     type Obj = StringOSMObj;
 
-    fn new(reader: R) -> XMLReader<R> {
+    fn new(reader: R) -> Self {
+        Self::new_with_options(reader, ReaderOptions::default())
+    }
+
+    fn new_with_options(reader: R, options: ReaderOptions) -> Self {
         XMLReader {
             parser: EventReader::new(BufReader::new(reader)).into_iter(),
+            options: options,
+            header: None,
+            pending: None,
+            started: false,
         }
     }
 
+    fn set_sorted_assumption(&mut self, sorted_assumption: bool) {
+        self.options.sorted_assumption = sorted_assumption;
+    }
+    fn get_sorted_assumption(&mut self) -> bool {
+        self.options.sorted_assumption
+    }
+
     fn into_inner(self) -> R {
         self.parser.into_inner().into_inner().into_inner()
     }
@@ -50,30 +75,149 @@ impl<R: Read> OSMReader for XMLReader<R> {
     }
 
     fn next(&mut self) -> Option<StringOSMObj> {
+        self.try_next().unwrap()
+    }
+
+    /// The next object. Changesets are skipped, see `try_next_element`
+    fn try_next(&mut self) -> Result<Option<StringOSMObj>, ReadError> {
+        loop {
+            match self.try_next_element()? {
+                None => return Ok(None),
+                Some(Element::Object(obj)) => return Ok(Some(obj)),
+                Some(Element::Changeset(_)) => {}
+            }
+        }
+    }
+
+    /// The `<osm>` attributes & `<bounds>`, which come before the first object.
+    fn try_header(&mut self) -> Result<Option<Header>, ReadError> {
+        if !self.started {
+            self.pending = self.try_next_element()?;
+        }
+        Ok(self.header.clone())
+    }
+}
+
+impl<R: Read> XMLReader<R> {
+    /// The next object or changeset, `Ok(None)` at the end of the file, or an error if the file
+    /// can't be decoded.
+    pub fn try_next_element(&mut self) -> Result<Option<Element>, ReadError> {
+        if let Some(el) = self.pending.take() {
+            return Ok(Some(el));
+        }
+        self.started = true;
+        next_xml_element(&mut self.parser, &self.options, &mut self.header)
+    }
+
+    /// The next object or changeset. Panics if the file can't be decoded
+    pub fn next_element(&mut self) -> Option<Element> {
+        self.try_next_element().unwrap()
+    }
+
+    /// Iterator over the objects & changesets of this reader
+    pub fn elements<'a>(&'a mut self) -> impl Iterator<Item = Element> + 'a {
+        std::iter::from_fn(move || self.next_element())
+    }
+
+    /// Iterator over just the changesets, e.g. of a changeset dump
+    pub fn changesets<'a>(&'a mut self) -> impl Iterator<Item = Changeset> + 'a {
+        self.elements().filter_map(|el| el.into_changeset())
+    }
+}
+
+/// The header from the attributes of the `<osm>` element
+fn osm_element_header(attrs: &[OwnedAttribute]) -> Header {
+    let mut header = Header::default();
+    for attr in attrs {
+        let value = attr.value.clone();
+        match attr.name.local_name.as_str() {
+            "version" => {}
+            "generator" => header.generator = Some(value),
+            "timestamp" => header.timestamp = Some(TimestampFormat::ISOString(value)),
+            key => header.other.push((key.to_string(), value)),
+        }
+    }
+    header
+}
+
+/// The `<bounds>` element, `None` if any attribute is missing or invalid
+fn bounds_element_bbox(attrs: &[OwnedAttribute]) -> Option<BBox> {
+    bbox_attrs(attrs, ["minlat", "minlon", "maxlat", "maxlon"])
+}
+
+/// The bbox from these attributes (min lat, min lon, max lat & max lon)
+fn bbox_attrs(attrs: &[OwnedAttribute], names: [&str; 4]) -> Option<BBox> {
+    let attr = |name: &str| {
+        attrs
+            .iter()
+            .find(|a| a.name.local_name == name)
+            .map(|a| a.value.as_str())
+    };
+    Some(BBox {
+        min_lat: attr(names[0])?.parse().ok()?,
+        min_lon: attr(names[1])?.parse().ok()?,
+        max_lat: attr(names[2])?.parse().ok()?,
+        max_lon: attr(names[3])?.parse().ok()?,
+    })
+}
+
+/// The next object from this parser, skipping any changesets. Shared with the OSC reader.
+pub(crate) fn next_xml_obj<R: Read>(
+    parser: &mut Events<BufReader<R>>,
+    options: &ReaderOptions,
+    header: &mut Option<Header>,
+) -> Result<Option<StringOSMObj>, ReadError> {
+    loop {
+        match next_xml_element(parser, options, header)? {
+            None => return Ok(None),
+            Some(Element::Object(obj)) => return Ok(Some(obj)),
+            Some(Element::Changeset(_)) => {}
+        }
+    }
+}
+
+/// Pull the xml events for the next object (or changeset) from this parser, and convert them.
+/// Header elements before the object are stored in `header`.
+fn next_xml_element<R: Read>(
+    parser: &mut Events<BufReader<R>>,
+    options: &ReaderOptions,
+    header: &mut Option<Header>,
+) -> Result<Option<Element>, ReadError> {
+    loop {
         let mut elements = Vec::new();
 
         // Pull xml/sax elements from the xml parser into a vector so we know what to work with.
         let mut should_push = false;
         loop {
-            let el = match self.parser.next() {
+            let el = match parser.next() {
                 None => {
                     break;
                 }
                 Some(e) => e,
             };
 
-            let el = el.unwrap();
+            let el = el?;
 
             let mut should_break = false;
             match el {
-                XmlEvent::StartElement { ref name, .. } => match name.local_name.as_str() {
-                    "node" | "way" | "relation" => {
+                XmlEvent::StartElement {
+                    ref name,
+                    ref attributes,
+                    ..
+                } => match name.local_name.as_str() {
+                    "node" | "way" | "relation" | "changeset" => {
                         should_push = true;
                     }
+                    "osm" if !should_push => *header = Some(osm_element_header(attributes)),
+                    "bounds" if !should_push => {
+                        if let Some(bbox) = bounds_element_bbox(attributes) {
+                            header.get_or_insert_with(Header::default).bbox = Some(bbox);
+                        }
+                    }
                     _ => {}
                 },
                 XmlEvent::EndElement { ref name, .. } => match name.local_name.as_str() {
-                    "node" | "way" | "relation" => {
+                    "node" | "way" | "relation" | "changeset" => {
                         should_break = true;
                     }
                     _ => {}
@@ -89,7 +233,33 @@ impl<R: Read> OSMReader for XMLReader<R> {
             }
         }
 
-        xml_elements_to_osm_obj(&mut elements)
+        let object_name = match elements.first() {
+            None => return Ok(None),
+            Some(&XmlEvent::StartElement { ref name, .. }) => name.local_name.clone(),
+            Some(_) => unreachable!(),
+        };
+        if object_name == "changeset" {
+            match xml_elements_to_changeset(&mut elements) {
+                Some(mut changeset) => {
+                    if !options.decode_metadata {
+                        changeset.uid = None;
+                        changeset.user = None;
+                    }
+                    return Ok(Some(Element::Changeset(changeset)));
+                }
+                None if options.strict => return Err(ReadError::XMLInvalidObject(object_name)),
+                None => continue,
+            }
+        }
+        match xml_elements_to_osm_obj(&mut elements) {
+            Some(mut obj) => {
+                options.apply_decode_metadata(&mut obj);
+                return Ok(Some(Element::Object(obj)));
+            }
+            None if options.strict => return Err(ReadError::XMLInvalidObject(object_name)),
+            // skip it
+            None => {}
+        }
     }
 }
 
@@ -103,8 +273,8 @@ enum State {
 
 /// Write as OSM XML file format
 pub struct XMLWriter<W: Write> {
-    writer: W,
-    headers: HashMap<String, String>,
+    writer: BufWriter<W>,
+    header: Header,
     _state: State,
 }
 
@@ -131,8 +301,8 @@ fn get_xml_attribute<'a>(attrs: &mut Vec<OwnedAttribute>, key: &str) -> Option<S
         })
 }
 
-fn get_tags(els: &mut Vec<XmlEvent>) -> HashMap<String, String> {
-    let mut result = HashMap::new();
+fn get_tags(els: &mut Vec<XmlEvent>) -> Vec<(String, String)> {
+    let mut result = Vec::new();
     for el in els.iter_mut() {
         if let &mut XmlEvent::StartElement {
             ref name,
@@ -144,7 +314,7 @@ fn get_tags(els: &mut Vec<XmlEvent>) -> HashMap<String, String> {
                 let ko = get_xml_attribute(attributes, "k");
                 let vo = get_xml_attribute(attributes, "v");
                 if let (Some(k), Some(v)) = (ko, vo) {
-                    result.insert(k, v);
+                    result.push((k, v));
                 }
             }
         }
@@ -176,7 +346,7 @@ fn get_nodes(els: &mut Vec<XmlEvent>) -> Vec<ObjId> {
     result
 }
 
-fn get_members(els: &mut Vec<XmlEvent>) -> Vec<(OSMObjectType, ObjId, String)> {
+fn get_members(els: &mut Vec<XmlEvent>) -> Vec<Member> {
     let mut result = Vec::new();
 
     for el in els.iter_mut() {
@@ -193,7 +363,7 @@ fn get_members(els: &mut Vec<XmlEvent>) -> Vec<(OSMObjectType, ObjId, String)> {
                     get_xml_attribute(attributes, "type").and_then(|t| t.parse().ok());
                 let role = get_xml_attribute(attributes, "role").unwrap_or_else(|| String::new());
                 if let (Some(ref_id), Some(member_type)) = (ref_id_o, member_type_o) {
-                    result.push((member_type, ref_id, role));
+                    result.push(Member::new(member_type, ref_id, role));
                 }
             }
         }
@@ -202,7 +372,7 @@ fn get_members(els: &mut Vec<XmlEvent>) -> Vec<(OSMObjectType, ObjId, String)> {
     result
 }
 
-pub(crate) fn xml_elements_to_osm_obj(els: &mut Vec<XmlEvent>) -> Option<StringOSMObj> {
+fn xml_elements_to_osm_obj(els: &mut Vec<XmlEvent>) -> Option<StringOSMObj> {
     match els.first() {
         Some(&XmlEvent::StartElement { ref name, .. }) => match name.local_name.as_str() {
             "node" => node_xml_elements_to_osm_obj(els),
@@ -223,8 +393,8 @@ fn node_xml_elements_to_osm_obj(els: &mut Vec<XmlEvent>) -> Option<StringOSMObj>
         .map(|x| TimestampFormat::ISOString(x.to_owned()));
     let uid = get_xml_attribute(&mut attrs, "uid").and_then(|x| x.parse().ok());
     let user = get_xml_attribute(&mut attrs, "user");
-    let lat = get_xml_attribute(&mut attrs, "lat")?.parse().ok();
-    let lon = get_xml_attribute(&mut attrs, "lon")?.parse().ok();
+    let lat = get_xml_attribute(&mut attrs, "lat").and_then(|x| x.parse().ok());
+    let lon = get_xml_attribute(&mut attrs, "lon").and_then(|x| x.parse().ok());
 
     let lat_lon = match (lat, lon) {
         (Some(lat), Some(lon)) => Some((lat, lon)),
@@ -317,6 +487,35 @@ fn relation_xml_elements_to_osm_obj(els: &mut Vec<XmlEvent>) -> Option<StringOSM
     }))
 }
 
+fn xml_elements_to_changeset(els: &mut Vec<XmlEvent>) -> Option<Changeset> {
+    let mut attrs = extract_attrs(els.first_mut()?)?;
+    let bbox = bbox_attrs(attrs, ["min_lat", "min_lon", "max_lat", "max_lon"]);
+    let id = get_xml_attribute(&mut attrs, "id").and_then(|x| x.parse().ok())?;
+    let created_at =
+        get_xml_attribute(&mut attrs, "created_at").map(|x| TimestampFormat::ISOString(x));
+    let closed_at =
+        get_xml_attribute(&mut attrs, "closed_at").map(|x| TimestampFormat::ISOString(x));
+    let open = get_xml_attribute(&mut attrs, "open").map_or(false, |x| x == "true");
+    let uid = get_xml_attribute(&mut attrs, "uid").and_then(|x| x.parse().ok());
+    let user = get_xml_attribute(&mut attrs, "user");
+    let num_changes = get_xml_attribute(&mut attrs, "num_changes").and_then(|x| x.parse().ok());
+    let comments_count =
+        get_xml_attribute(&mut attrs, "comments_count").and_then(|x| x.parse().ok());
+
+    Some(Changeset {
+        id: id,
+        created_at: created_at,
+        closed_at: closed_at,
+        open: open,
+        bbox: bbox,
+        uid: uid,
+        user: user,
+        num_changes: num_changes,
+        comments_count: comments_count,
+        tags: get_tags(els),
+    })
+}
+
 impl From<quick_xml::Error> for OSMWriteError {
     fn from(err: quick_xml::Error) -> OSMWriteError {
         OSMWriteError::XMLWriteXMLError(err)
@@ -330,47 +529,146 @@ impl From<std::io::Error> for OSMWriteError {
 }
 
 impl<W: Write> XMLWriter<W> {
+    /// A writer which collects `options.buffer_size` bytes before writing them to `writer`,
+    /// rather than making several small writes for every object
+    pub fn new_with_options(writer: W, options: &WriterOptions) -> Self {
+        XMLWriter {
+            writer: BufWriter::with_capacity(options.buffer_size, writer),
+            header: Header::default(),
+            _state: State::Initial,
+        }
+    }
+
+    /// The underlying writer
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        self.writer.get_mut()
+    }
+
     fn ensure_header(&mut self) -> Result<(), OSMWriteError> {
         if self._state == State::Initial {
             write!(self.writer, "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n")?;
-            write!(
-                self.writer,
-                "<osm version=\"0.6\" generator=\"osmio/{}\"",
-                version()
-            )?;
+            write!(self.writer, "<osm version=\"0.6\" generator=\"")?;
+            match self.header.generator {
+                Some(ref generator) => write_xml_escaped(&mut self.writer, generator)?,
+                None => write!(self.writer, "osmio/{}", version())?,
+            }
+            write!(self.writer, "\"")?;
 
-            for (k, v) in self.headers.iter() {
+            if let Some(ref timestamp) = self.header.timestamp {
+                write!(self.writer, " timestamp=\"{}\"", timestamp.to_iso_string())?;
+            }
+            for (k, v) in self.header.other.iter() {
                 write!(self.writer, " {}=\"", k)?;
                 write_xml_escaped(&mut self.writer, v)?;
                 write!(self.writer, "\"")?;
             }
             write!(self.writer, ">")?;
 
+            if let Some(bbox) = self.header.bbox {
+                write!(
+                    self.writer,
+                    "\n\t<bounds minlat=\"{}\" minlon=\"{}\" maxlat=\"{}\" maxlon=\"{}\"/>",
+                    bbox.min_lat, bbox.min_lon, bbox.max_lat, bbox.max_lon
+                )?;
+            }
+
             self._state = State::WritingObjects;
         }
         Ok(())
     }
 }
 
+impl<W: Write> XMLWriter<W> {
+    /// Write a changeset, like in a changeset dump
+    pub fn write_changeset(&mut self, changeset: &Changeset) -> Result<(), OSMWriteError> {
+        match self._state {
+            State::Initial => self.ensure_header()?,
+            State::WritingObjects => {}
+            State::Closed => return Err(OSMWriteError::AlreadyClosed),
+        }
+
+        write!(self.writer, "\n\t<changeset id=\"{}\"", changeset.id)?;
+        if let Some(ref created_at) = changeset.created_at {
+            write!(self.writer, " created_at=\"{}\"", created_at)?;
+        }
+        if let Some(ref closed_at) = changeset.closed_at {
+            write!(self.writer, " closed_at=\"{}\"", closed_at)?;
+        }
+        write!(self.writer, " open=\"{}\"", changeset.open)?;
+        if let Some(ref user) = changeset.user {
+            write!(self.writer, " user=\"")?;
+            write_xml_escaped(&mut self.writer, user)?;
+            write!(self.writer, "\"")?;
+        }
+        if let Some(uid) = changeset.uid {
+            write!(self.writer, " uid=\"{}\"", uid)?;
+        }
+        if let Some(bbox) = changeset.bbox {
+            write!(
+                self.writer,
+                " min_lat=\"{}\" min_lon=\"{}\" max_lat=\"{}\" max_lon=\"{}\"",
+                bbox.min_lat, bbox.min_lon, bbox.max_lat, bbox.max_lon
+            )?;
+        }
+        if let Some(num_changes) = changeset.num_changes {
+            write!(self.writer, " num_changes=\"{}\"", num_changes)?;
+        }
+        if let Some(comments_count) = changeset.comments_count {
+            write!(self.writer, " comments_count=\"{}\"", comments_count)?;
+        }
+
+        if changeset.tags.is_empty() {
+            write!(self.writer, " />")?;
+            return Ok(());
+        }
+        write!(self.writer, ">")?;
+        for (k, v) in changeset.tags.iter() {
+            write!(self.writer, "\n\t\t<tag k=\"")?;
+            write_xml_escaped(&mut self.writer, k)?;
+            write!(self.writer, "\" v=\"")?;
+            write_xml_escaped(&mut self.writer, v)?;
+            write!(self.writer, "\" />")?;
+        }
+        write!(self.writer, "\n\t</changeset>")?;
+
+        Ok(())
+    }
+}
+
 impl<W: Write> OSMWriter<W> for XMLWriter<W> {
+    /// Collects 8 KiB before writing it, see `new_with_options`
     fn new(writer: W) -> Self {
         // TODO have a config that does indentation and stuff
         XMLWriter {
-            writer: writer,
-            headers: HashMap::new(),
+            writer: BufWriter::new(writer),
+            header: Header::default(),
             _state: State::Initial,
         }
     }
 
-    fn set_header(&mut self, (key, value): (&str, &str)) -> Result<(), OSMWriteError> {
+    /// The timestamp is the `timestamp` attribute of `<osm>`, like osmium. `Other` fields are
+    /// attributes of `<osm>` too. XML has nowhere for the replication sequence number or URL.
+    fn set_header(&mut self, field: HeaderField) -> Result<(), OSMWriteError> {
         match self._state {
-            State::Initial => {
-                self.headers.insert(key.into(), value.into());
-                Ok(())
+            State::Initial => {}
+            State::Closed => return Err(OSMWriteError::AlreadyClosed),
+            _ => return Err(OSMWriteError::AlreadyStarted),
+        }
+        match field {
+            HeaderField::Bbox(_)
+            | HeaderField::Generator(_)
+            | HeaderField::ReplicationTimestamp(_) => {}
+            HeaderField::Other(ref key, _)
+                if key == "version" || key == "generator" || key == "timestamp" =>
+            {
+                // Already attributes, so this would be invalid XML
+                return Err(OSMWriteError::UnsupportedHeaderField(field));
             }
-            State::Closed => Err(OSMWriteError::AlreadyClosed),
-            _ => Err(OSMWriteError::AlreadyStarted),
+            HeaderField::Other(..) => {}
+            _ => return Err(OSMWriteError::UnsupportedHeaderField(field)),
         }
+        self.header.set(field);
+        Ok(())
     }
 
     fn is_open(&self) -> bool {
@@ -380,9 +678,11 @@ impl<W: Write> OSMWriter<W> for XMLWriter<W> {
     fn close(&mut self) -> Result<(), OSMWriteError> {
         self.ensure_header()?;
 
-        write!(self.writer, "\n</osm>")?;
-
-        self._state = State::Closed;
+        if self._state != State::Closed {
+            write!(self.writer, "\n</osm>")?;
+            self._state = State::Closed;
+            self.writer.flush()?;
+        }
 
         Ok(())
     }
@@ -409,7 +709,9 @@ impl<W: Write> OSMWriter<W> for XMLWriter<W> {
             " visible=\"{}\"",
             if obj.deleted() { "false" } else { "true" }
         )?;
-        write!(self.writer, " version=\"{}\"", obj.version().unwrap())?;
+        if let Some(version) = obj.version() {
+            write!(self.writer, " version=\"{}\"", version)?;
+        }
         if let Some(user) = obj.user() {
             write!(self.writer, " user=\"")?;
             write_xml_escaped(&mut self.writer, user)?;
@@ -449,10 +751,10 @@ impl<W: Write> OSMWriter<W> for XMLWriter<W> {
                 write!(
                     self.writer,
                     "\n\t\t<member type=\"{}\" ref=\"{}\" role=\"",
-                    member.0, member.1
+                    member.mtype, member.id
                 )?;
-                if !member.2.is_empty() {
-                    write_xml_escaped(&mut self.writer, member.2)?;
+                if !member.role.is_empty() {
+                    write_xml_escaped(&mut self.writer, member.role)?;
                 }
                 write!(self.writer, "\"/>")?;
             }
@@ -485,6 +787,16 @@ impl<W: Write> OSMWriter<W> for XMLWriter<W> {
     }
 }
 
+impl<W: Write> DynWriter for XMLWriter<W> {
+    fn write_string_obj(&mut self, obj: &StringOSMObj) -> Result<(), OSMWriteError> {
+        self.write_obj(obj)
+    }
+
+    fn finish(&mut self) -> Result<(), OSMWriteError> {
+        self.close()
+    }
+}
+
 impl<W: Write> Drop for XMLWriter<W> {
     fn drop(&mut self) {
         self.close().unwrap();
@@ -495,6 +807,7 @@ impl<W: Write> Drop for XMLWriter<W> {
 mod tests {
     use super::*;
     use obj_types::StringNodeBuilder;
+    use {Lat, Lon, OSMObjBase};
 
     macro_rules! assert_escape {
         ( $name:ident, $input:expr, $output:expr ) => {
@@ -540,9 +853,150 @@ mod tests {
 			._timestamp(700.into())
 			._uid(1)
 			._user("&foo".to_string())
-			._lat_lon((0., 0.))
+			._lat_lon((Lat::from(0.), Lon::from(0.)))
 			.build()
 			.unwrap(),
-	    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<osm version=\"0.6\" generator=\"osmio/0.4.0\">\n\t<node id=\"1\" visible=\"true\" version=\"2\" user=\"&amp;foo\" uid=\"1\" changeset=\"1\" timestamp=\"1970-01-01T00:11:40Z\" lat=\"0\" lon=\"0\" />\n</osm>\n</osm>"
+	    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<osm version=\"0.6\" generator=\"osmio/0.4.0\">\n\t<node id=\"1\" visible=\"true\" version=\"2\" user=\"&amp;foo\" uid=\"1\" changeset=\"1\" timestamp=\"1970-01-01T00:11:40Z\" lat=\"0\" lon=\"0\" />\n</osm>"
 	);
+
+    #[test]
+    fn read_errors() {
+        let input = "<osm>\n<node id=\"1\" lat=\"1\" lon=\"2\"/>\n<node id=\"x\"/></osm>";
+        let mut reader = XMLReader::new(input.as_bytes());
+        let results: Vec<_> = reader.try_objects().collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        match results[1] {
+            Err(ReadError::XMLInvalidObject(ref name)) if name == "node" => {}
+            ref r => panic!("unexpected result {:?}", r),
+        }
+
+        let mut reader = XMLReader::new("<osm><node id=\"1\"></osm>".as_bytes());
+        match reader.try_next() {
+            Err(ReadError::XMLParseError(_)) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+
+        // deleted nodes have no location
+        let input = "<osm><node id=\"1\" version=\"2\" visible=\"false\"/></osm>";
+        let node = XMLReader::new(input.as_bytes())
+            .try_next()
+            .unwrap()
+            .unwrap();
+        assert!(node.deleted());
+    }
+
+    #[test]
+    fn read_with_options() {
+        let input = "<osm>\n<node id=\"x\"/>\n<node id=\"1\" uid=\"2\" user=\"a\" lat=\"1\" lon=\"2\"/></osm>";
+        let options = ReaderOptions {
+            strict: false,
+            decode_metadata: false,
+            ..Default::default()
+        };
+        let mut reader = XMLReader::new_with_options(input.as_bytes(), options);
+        let objs: Vec<_> = reader.try_objects().collect::<Result<_, _>>().unwrap();
+        assert_eq!(objs.len(), 1);
+        assert_eq!(objs[0].id(), 1);
+        assert_eq!(objs[0].uid(), None);
+        assert_eq!(objs[0].user(), None);
+    }
+
+    #[test]
+    fn headers() {
+        let mut res = Vec::new();
+        let mut xmlwr = XMLWriter::new(&mut res);
+        let bbox = BBox {
+            min_lat: Lat::from(-1.5),
+            min_lon: Lon::from(2.),
+            max_lat: Lat::from(3.),
+            max_lon: Lon::from(4.25),
+        };
+        xmlwr.set_header(HeaderField::Bbox(bbox)).unwrap();
+        xmlwr
+            .set_header(HeaderField::Generator("a&b".to_string()))
+            .unwrap();
+        xmlwr
+            .set_header(HeaderField::ReplicationTimestamp(700.into()))
+            .unwrap();
+        xmlwr
+            .set_header(HeaderField::Other("upload".into(), "false".into()))
+            .unwrap();
+        match xmlwr.set_header(HeaderField::ReplicationSequenceNumber(1)) {
+            Err(OSMWriteError::UnsupportedHeaderField(HeaderField::ReplicationSequenceNumber(
+                1,
+            ))) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        assert!(xmlwr
+            .set_header(HeaderField::Other("version".into(), "1".into()))
+            .is_err());
+        xmlwr.close().unwrap();
+        match xmlwr.set_header(HeaderField::Generator("x".to_string())) {
+            Err(OSMWriteError::AlreadyClosed) => {}
+            r => panic!("unexpected result {:?}", r),
+        }
+        drop(xmlwr);
+        assert_eq!(
+            std::str::from_utf8(&res).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<osm version=\"0.6\" generator=\"a&amp;b\" timestamp=\"1970-01-01T00:11:40Z\" upload=\"false\">\n\t<bounds minlat=\"-1.5\" minlon=\"2\" maxlat=\"3\" maxlon=\"4.25\"/>\n</osm>"
+        );
+    }
+
+    #[test]
+    fn read_header() {
+        let input = "<osm version=\"0.6\" generator=\"test\" timestamp=\"2020-01-01T00:00:00Z\" upload=\"false\">\n<bounds minlat=\"1\" minlon=\"-1.5\" maxlat=\"3\" maxlon=\"2\"/>\n<node id=\"1\" lat=\"1\" lon=\"2\"/></osm>";
+        let mut reader = XMLReader::new(input.as_bytes());
+        let header = reader.header().unwrap();
+        assert_eq!(header.generator.as_ref().unwrap(), "test");
+        assert_eq!(header.timestamp.unwrap().to_epoch_number(), 1577836800);
+        assert_eq!(
+            header.other,
+            vec![("upload".to_string(), "false".to_string())]
+        );
+        assert_eq!(
+            reader.bounds(),
+            Some(BBox {
+                min_lat: Lat::from(1.),
+                min_lon: Lon::from(-1.5),
+                max_lat: Lat::from(3.),
+                max_lon: Lon::from(2.),
+            })
+        );
+        assert_eq!(reader.objects().count(), 1);
+
+        let mut reader =
+            XMLReader::new("<osm><node id=\"1\" lat=\"1\" lon=\"2\"/></osm>".as_bytes());
+        assert_eq!(reader.bounds(), None);
+        assert_eq!(reader.objects().count(), 1);
+    }
+
+    #[test]
+    fn changesets() {
+        let input = "<osm><changeset id=\"5\" created_at=\"2020-01-01T00:00:00Z\" closed_at=\"2020-01-01T01:00:00Z\" open=\"false\" user=\"a&amp;b\" uid=\"2\" min_lat=\"1\" min_lon=\"2\" max_lat=\"3\" max_lon=\"4\" num_changes=\"10\" comments_count=\"0\"><tag k=\"comment\" v=\"fix\"/><discussion/></changeset>\n<changeset id=\"6\" open=\"true\"/><node id=\"1\" lat=\"1\" lon=\"2\"/></osm>";
+        let elements: Vec<_> = XMLReader::new(input.as_bytes()).elements().collect();
+        assert_eq!(elements.len(), 3);
+        let changeset = elements[0].clone().into_changeset().unwrap();
+        assert_eq!(changeset.id, 5);
+        assert!(!changeset.open);
+        assert_eq!(changeset.user.as_ref().unwrap(), "a&b");
+        assert_eq!(changeset.bbox.unwrap().max_lon, Lon::from(4.));
+        assert_eq!(changeset.num_changes, Some(10));
+        assert_eq!(changeset.tag("comment"), Some("fix"));
+        assert_eq!(elements[1], Element::Changeset(Changeset::new(6)));
+        assert!(elements[2].clone().into_object().unwrap().is_node());
+
+        // objects skip the changesets
+        assert_eq!(XMLReader::new(input.as_bytes()).objects().count(), 1);
+
+        let mut res = Vec::new();
+        {
+            let mut xmlwr = XMLWriter::new(&mut res);
+            xmlwr.write_changeset(&changeset).unwrap();
+            xmlwr.write_changeset(&Changeset::new(6)).unwrap();
+            xmlwr.close().unwrap();
+        }
+        let changesets: Vec<_> = XMLReader::new(res.as_slice()).changesets().collect();
+        assert_eq!(changesets, vec![changeset, Changeset::new(6)]);
+    }
 }
@@ -0,0 +1,290 @@
+//! Experimental multi-threaded XML reader.
+//!
+//! The input is read in chunks, each split just before the start of a `<node`, `<way`,
+//! `<relation` or `<changeset` element, and the chunks are parsed on `ReaderOptions::threads`
+//! threads. The objects are returned in file order. The split is found by scanning for those
+//! element names, so a file with them inside comments or CDATA can't be read this way.
+//!
+//! ```
+//! use osmio::xml::ParallelXMLReader;
+//! use osmio::{OSMReader, ReaderOptions};
+//!
+//! let xml = "<osm version=\"0.6\"><node id=\"1\" lat=\"1\" lon=\"2\"/><node id=\"2\" lat=\"1\" \
+//!            lon=\"2\"/></osm>";
+//! let options = ReaderOptions {
+//!     threads: 4,
+//!     ..Default::default()
+//! };
+//! let mut reader = ParallelXMLReader::new_with_options(xml.as_bytes(), options);
+//! assert_eq!(reader.objects().count(), 2);
+//! ```
+use super::XMLReader;
+use obj_types::StringOSMObj;
+use std::io::Read;
+use {Header, OSMReader, ReadError, ReaderOptions};
+
+/// How many bytes each thread parses at once, by default
+pub const CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The top level elements which the input can be split before
+const ELEMENTS: [&[u8]; 4] = [b"node", b"way", b"relation", b"changeset"];
+
+/// Reads an XML file on many threads, see the module docs
+pub struct ParallelXMLReader<R: Read> {
+    reader: R,
+    options: ReaderOptions,
+    chunk_size: usize,
+    /// Read from `reader`, but not yet parsed
+    unparsed: Vec<u8>,
+    eof: bool,
+    /// The next chunk is the first, with the `<osm>` element & header
+    first: bool,
+    header: Option<Header>,
+    /// Parsed objects, in reverse order
+    buffer: Vec<StringOSMObj>,
+}
+
+impl<R: Read> ParallelXMLReader<R> {
+    /// Parse this many bytes at a time (default `CHUNK_SIZE`). An element bigger than this is
+    /// still parsed whole.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// The next chunk of the file, and whether it's the last one
+    fn next_chunk(&mut self) -> Result<Option<(Vec<u8>, bool)>, ReadError> {
+        let mut target = self.chunk_size;
+        loop {
+            while !self.eof && self.unparsed.len() < target {
+                let start = self.unparsed.len();
+                self.unparsed.resize(target, 0);
+                let read = match self.reader.read(&mut self.unparsed[start..]) {
+                    Ok(read) => read,
+                    Err(ref e) if e.kind() == ::std::io::ErrorKind::Interrupted => {
+                        self.unparsed.truncate(start);
+                        continue;
+                    }
+                    Err(e) => {
+                        self.unparsed.truncate(start);
+                        return Err(e.into());
+                    }
+                };
+                self.unparsed.truncate(start + read);
+                if read == 0 {
+                    self.eof = true;
+                }
+            }
+            if self.eof {
+                if self.unparsed.is_empty() {
+                    return Ok(None);
+                }
+                return Ok(Some((::std::mem::take(&mut self.unparsed), true)));
+            }
+            // Not at the very start, so that every chunk has at least one element
+            if let Some(split) = last_element_start(&self.unparsed[1..]) {
+                let rest = self.unparsed.split_off(split + 1);
+                return Ok(Some((::std::mem::replace(&mut self.unparsed, rest), false)));
+            }
+            // One element is bigger than the chunk
+            target *= 2;
+        }
+    }
+
+    /// Parse the next chunks (one per thread) into `buffer`. False at the end of the file.
+    fn fill_buffer(&mut self) -> Result<bool, ReadError> {
+        let mut chunks = Vec::with_capacity(self.options.threads.max(1));
+        while chunks.len() < self.options.threads.max(1) {
+            match self.next_chunk()? {
+                None => break,
+                Some(chunk) => chunks.push(chunk),
+            }
+        }
+        if chunks.is_empty() {
+            return Ok(false);
+        }
+
+        // The chunks after the first need an `<osm>`, and all but the last need the `</osm>`
+        let first = self.first;
+        self.first = false;
+        let documents = chunks.into_iter().enumerate().map(|(i, (chunk, last))| {
+            let mut document = Vec::with_capacity(chunk.len() + 11);
+            if !(first && i == 0) {
+                document.extend_from_slice(b"<osm>");
+            }
+            document.extend(chunk);
+            if !last {
+                document.extend_from_slice(b"</osm>");
+            }
+            document
+        });
+        let options = &self.options;
+        let parsed: Vec<_> = ::std::thread::scope(|scope| {
+            let handles: Vec<_> = documents
+                .map(|document| scope.spawn(move || parse(&document, options)))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut objs = Vec::new();
+        for (i, result) in parsed.into_iter().enumerate() {
+            let (header, chunk_objs) = result?;
+            if first && i == 0 {
+                self.header = header;
+            }
+            objs.extend(chunk_objs);
+        }
+        // we reverse the Vec so that we can .pop from the buffer, as in the PBF reader
+        objs.reverse();
+        self.buffer = objs;
+        Ok(true)
+    }
+}
+
+/// Parse one chunk, as a whole XML document
+fn parse(
+    document: &[u8],
+    options: &ReaderOptions,
+) -> Result<(Option<Header>, Vec<StringOSMObj>), ReadError> {
+    let mut reader = XMLReader::new_with_options(document, options.clone());
+    let header = reader.try_header()?;
+    let mut objs = Vec::new();
+    while let Some(obj) = reader.try_next()? {
+        objs.push(obj);
+    }
+    Ok((header, objs))
+}
+
+/// The position of the last `<` which starts one of `ELEMENTS`
+fn last_element_start(bytes: &[u8]) -> Option<usize> {
+    (0..bytes.len()).rev().find(|&i| {
+        bytes[i] == b'<'
+            && ELEMENTS.iter().any(|name| {
+                let rest = &bytes[i + 1..];
+                rest.starts_with(name)
+                    && match rest.get(name.len()) {
+                        Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') | Some(b'/')
+                        | Some(b'>') => true,
+                        _ => false,
+                    }
+            })
+    })
+}
+
+impl<R: Read> OSMReader for ParallelXMLReader<R> {
+    type R = R;
+    type Obj = StringOSMObj;
+
+    fn new(reader: R) -> Self {
+        Self::new_with_options(reader, ReaderOptions::default())
+    }
+
+    fn new_with_options(reader: R, options: ReaderOptions) -> Self {
+        ParallelXMLReader {
+            reader: reader,
+            options: options,
+            chunk_size: CHUNK_SIZE,
+            unparsed: Vec::new(),
+            eof: false,
+            first: true,
+            header: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn set_sorted_assumption(&mut self, sorted_assumption: bool) {
+        self.options.sorted_assumption = sorted_assumption;
+    }
+    fn get_sorted_assumption(&mut self) -> bool {
+        self.options.sorted_assumption
+    }
+
+    fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn inner(&self) -> &R {
+        &self.reader
+    }
+
+    fn next(&mut self) -> Option<StringOSMObj> {
+        self.try_next().unwrap()
+    }
+
+    /// The next object. Changesets are skipped.
+    fn try_next(&mut self) -> Result<Option<StringOSMObj>, ReadError> {
+        while self.buffer.is_empty() {
+            if !self.fill_buffer()? {
+                return Ok(None);
+            }
+        }
+        Ok(self.buffer.pop())
+    }
+
+    /// The `<osm>` attributes & `<bounds>`, from the first chunk
+    fn try_header(&mut self) -> Result<Option<Header>, ReadError> {
+        if self.first {
+            self.fill_buffer()?;
+        }
+        Ok(self.header.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {OSMObj, OSMObjBase};
+
+    #[test]
+    fn element_starts() {
+        assert_eq!(
+            last_element_start(b"<node id=\"1\"/>\n<way id=\"2\">"),
+            Some(15)
+        );
+        assert_eq!(
+            last_element_start(b"<way id=\"2\"><nd ref=\"1\"/></way>"),
+            Some(0)
+        );
+        assert_eq!(last_element_start(b"<nodes/><wayside> <relation"), None);
+        assert_eq!(last_element_start(b"<relation\nid=\"1\">"), Some(0));
+    }
+
+    #[test]
+    fn read() {
+        let mut xml = "<?xml version=\"1.0\"?>\n<osm version=\"0.6\" generator=\"test\">\n\
+                       <bounds minlat=\"1\" minlon=\"2\" maxlat=\"3\" maxlon=\"4\"/>\n"
+            .to_string();
+        for i in 1..=100 {
+            xml.push_str(&format!(
+                "<node id=\"{}\" lat=\"1\" lon=\"2\"><tag k=\"n\" v=\"{}\"/></node>\n",
+                i, i
+            ));
+        }
+        xml.push_str("<changeset id=\"9\"/>\n<way id=\"1\"><nd ref=\"1\"/><nd ref=\"2\"/></way>\n");
+        xml.push_str("<relation id=\"1\"><member type=\"way\" ref=\"1\" role=\"\"/></relation>\n");
+        xml.push_str("</osm>\n");
+        let expected: Vec<_> = XMLReader::new(xml.as_bytes()).objects().collect();
+        assert_eq!(expected.len(), 102);
+
+        for &threads in &[1, 3] {
+            for &chunk_size in &[1, 50, 1000, CHUNK_SIZE] {
+                let options = ReaderOptions {
+                    threads: threads,
+                    ..Default::default()
+                };
+                let mut reader = ParallelXMLReader::new_with_options(xml.as_bytes(), options)
+                    .chunk_size(chunk_size);
+                let header = reader.header().unwrap();
+                assert_eq!(header.generator.as_ref().unwrap(), "test");
+                assert!(header.bbox.is_some());
+                let objs: Vec<_> = reader.objects().collect();
+                assert_eq!(objs, expected);
+            }
+        }
+        assert!(expected.last().unwrap().is_relation());
+        assert_eq!(expected[99].tag("n"), Some("100"));
+
+        let mut reader = ParallelXMLReader::new("<osm><node id=\"1\"".as_bytes()).chunk_size(1);
+        assert!(reader.try_next().is_err());
+    }
+}